@@ -0,0 +1,552 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::lex::{lex, Token};
+use crate::store::Store;
+
+/// requests longer than this are rejected without reading the rest
+const MAX_REQUEST_LEN: usize = 4096;
+
+/// whether a `GET` may be answered, given how it is shown to the user
+pub type Confirm = Box<dyn FnMut(&str) -> bool + Send>;
+
+/// answers other programs of the same user asking for single values, so that they
+/// don't need the master password. one request per line, one answer per line:
+///
+/// `GET <name> <attr>` is answered with `OK "<value>"`,
+/// `LIST` with `OK ["<name>", ...]` and anything else with `ERR <reason>`.
+/// values and names are json strings so that they stay on one line.
+/// names and attrs with spaces are quoted like in the prompt: `GET 'old mail' pass`
+pub struct Agent {
+    store: Store,
+    rate_limit: RateLimit,
+    idle_timeout: Option<Duration>,
+    last_request: Instant,
+    /// everything is answered without it
+    confirm: Option<Confirm>,
+    locked: bool,
+}
+
+impl Agent {
+    pub fn new(
+        store: Store,
+        rate_limit: RateLimit,
+        idle_timeout: Option<Duration>,
+        confirm: Option<Confirm>,
+        now: Instant,
+    ) -> Self {
+        Self {
+            store,
+            rate_limit,
+            idle_timeout,
+            last_request: now,
+            confirm,
+            locked: false,
+        }
+    }
+
+    pub fn answer(&mut self, request: &str, now: Instant) -> String {
+        if self.is_locked(now) {
+            return "ERR locked".into();
+        }
+        self.last_request = now;
+        if !self.rate_limit.allow(now) {
+            return "ERR too many requests".into();
+        }
+
+        let words = match lex(request.trim()) {
+            Ok(tokens) => tokens
+                .into_iter()
+                .map(|token| match token {
+                    Token::Value(word) | Token::Keyword(word) => Some(word),
+                    Token::Symbol(_) => None,
+                })
+                .collect::<Option<Vec<_>>>(),
+            Err(_) => None,
+        };
+
+        match words.as_deref() {
+            Some(["GET", name, attr]) => self.get(name, attr),
+            Some(["LIST"]) => json(&self.store.names()),
+            _ => "ERR unknown request".into(),
+        }
+    }
+
+    /// the error never contains any of the value
+    fn get(&mut self, name: &str, attr: &str) -> String {
+        let records = self.store.pick(name, None);
+        let record = match records.as_slice() {
+            [] => return format!("ERR '{}' not found", name),
+            [record] => record,
+            _ => return format!("ERR several records are named '{}'", name),
+        };
+        let Some(field) = record.fields.iter().find(|f| f.attr == attr) else {
+            return format!("ERR '{}' has no '{}'", name, attr);
+        };
+
+        if let Some(confirm) = self.confirm.as_mut() {
+            if !confirm(&format!("GET '{}' {}", name, attr)) {
+                return "ERR denied".into();
+            }
+        }
+        json(&field.value)
+    }
+
+    /// the store is dropped once nothing was asked for `idle_timeout`.
+    /// it can't be unlocked again, the agent has to be started again
+    pub fn is_locked(&mut self, now: Instant) -> bool {
+        let idle = self
+            .idle_timeout
+            .is_some_and(|timeout| now.duration_since(self.last_request) >= timeout);
+        if idle && !self.locked {
+            self.store = Store::new();
+            self.locked = true;
+        }
+        self.locked
+    }
+}
+
+fn json<T: serde::Serialize + ?Sized>(value: &T) -> String {
+    match serde_json::to_string(value) {
+        Ok(json) => format!("OK {}", json),
+        Err(_) => "ERR unable to encode the answer".into(),
+    }
+}
+
+/// at most `max` requests in any `per`
+pub struct RateLimit {
+    max: usize,
+    per: Duration,
+    recent: VecDeque<Instant>,
+}
+
+impl RateLimit {
+    pub fn new(max: usize, per: Duration) -> Self {
+        Self {
+            max,
+            per,
+            recent: VecDeque::new(),
+        }
+    }
+
+    fn allow(&mut self, now: Instant) -> bool {
+        while self
+            .recent
+            .front()
+            .is_some_and(|&at| now.duration_since(at) >= self.per)
+        {
+            self.recent.pop_front();
+        }
+        match self.recent.len() < self.max {
+            true => {
+                self.recent.push_back(now);
+                true
+            }
+            false => false,
+        }
+    }
+}
+
+/// asks on the controlling terminal, not on stdin, which might be redirected
+pub fn confirm_on_terminal(request: &str) -> bool {
+    use std::io::{BufRead, Write};
+
+    let Ok(mut tty) = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+    else {
+        return false;
+    };
+    if write!(tty, "allow {}? [y/N] ", request).is_err() {
+        return false;
+    }
+
+    let mut answer = String::new();
+    match std::io::BufReader::new(tty).read_line(&mut answer) {
+        Ok(_) => matches!(answer.trim(), "y" | "Y" | "yes"),
+        Err(_) => false,
+    }
+}
+
+#[cfg(unix)]
+pub use socket::{default_socket, serve};
+
+#[cfg(unix)]
+mod socket {
+    use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, Instant};
+
+    use anyhow::{bail, Context};
+    use ignorant::Ignore;
+
+    use super::{Agent, MAX_REQUEST_LEN};
+    use crate::cancel::Cancel;
+
+    /// how long a client may take to send a request before it is hung up on
+    const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// in the runtime directory that only the user can get into, if there is one
+    pub fn default_socket() -> anyhow::Result<PathBuf> {
+        let dir = dirs::runtime_dir()
+            .or_else(dirs::home_dir)
+            .context("unable to determine a directory for the socket. please provide one")?;
+        Ok(dir.join("royalguard-agent.sock"))
+    }
+
+    /// answers the clients connecting to `socket` one after the other
+    /// until `agent` locks or `cancel` is set
+    pub fn serve(agent: &mut Agent, socket: &Path, cancel: &Cancel) -> anyhow::Result<()> {
+        let listener = bind(socket)?;
+        listener.set_nonblocking(true)?;
+        let uid = unsafe { libc::geteuid() };
+
+        while !cancel.is_cancelled() && !agent.is_locked(Instant::now()) {
+            match listener.accept() {
+                Ok((stream, _)) => match peer_uid(&stream) {
+                    Ok(peer) if peer == uid => handle(agent, stream).ignore(),
+                    _ => reject(stream),
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    std::thread::sleep(Duration::from_millis(50))
+                }
+                Err(e) => {
+                    std::fs::remove_file(socket).ignore();
+                    return Err(e.into());
+                }
+            }
+        }
+
+        std::fs::remove_file(socket).ignore();
+        Ok(())
+    }
+
+    /// only the user can connect. a socket left behind by an agent
+    /// that didn't exit cleanly is replaced, one that is still answering is not
+    fn bind(socket: &Path) -> anyhow::Result<UnixListener> {
+        if socket.exists() {
+            if UnixStream::connect(socket).is_ok() {
+                bail!("an agent is already listening on '{}'", socket.display());
+            }
+            std::fs::remove_file(socket)
+                .with_context(|| format!("unable to replace '{}'", socket.display()))?;
+        }
+
+        let listener = UnixListener::bind(socket)
+            .with_context(|| format!("unable to listen on '{}'", socket.display()))?;
+        std::fs::set_permissions(socket, std::fs::Permissions::from_mode(0o600))?;
+        Ok(listener)
+    }
+
+    fn handle(agent: &mut Agent, stream: UnixStream) -> std::io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream);
+
+        loop {
+            let mut request = String::new();
+            let n = (&mut reader)
+                .take(MAX_REQUEST_LEN as u64 + 1)
+                .read_line(&mut request)?;
+            if n == 0 {
+                return Ok(());
+            }
+            if n > MAX_REQUEST_LEN {
+                return writeln!(writer, "ERR request too long");
+            }
+            writeln!(writer, "{}", agent.answer(&request, Instant::now()))?;
+        }
+    }
+
+    fn reject(mut stream: UnixStream) {
+        stream.set_nonblocking(false).ignore();
+        writeln!(stream, "ERR not the user the agent runs as").ignore();
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(super) fn peer_uid(stream: &UnixStream) -> std::io::Result<u32> {
+        use std::os::fd::AsRawFd;
+
+        let mut cred = libc::ucred {
+            pid: 0,
+            uid: 0,
+            gid: 0,
+        };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        let result = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut cred as *mut libc::ucred as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        match result {
+            0 => Ok(cred.uid),
+            _ => Err(std::io::Error::last_os_error()),
+        }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub(super) fn peer_uid(stream: &UnixStream) -> std::io::Result<u32> {
+        use std::os::fd::AsRawFd;
+
+        let (mut uid, mut gid) = (0, 0);
+        match unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } {
+            0 => Ok(uid),
+            _ => Err(std::io::Error::last_os_error()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::Assign;
+    use pretty_assertions::assert_eq;
+
+    fn store() -> Store {
+        let mut store = Store::new();
+        for (name, attr, value, sensitive) in [
+            ("gmail", "user", "zahash", false),
+            ("gmail", "pass", "ни шагу \"назад\"", true),
+            ("old mail", "pass", "line one\nline two", true),
+            ("all", "pin", "1234", true),
+        ] {
+            store.set(
+                name,
+                None,
+                vec![Assign {
+                    attr,
+                    value,
+                    sensitive,
+                    expires: None,
+                    kind: None,
+                }],
+            );
+        }
+        store
+    }
+
+    fn agent(now: Instant) -> Agent {
+        Agent::new(
+            store(),
+            RateLimit::new(100, Duration::from_secs(1)),
+            Some(Duration::from_secs(300)),
+            None,
+            now,
+        )
+    }
+
+    #[test]
+    fn test_answer() {
+        let now = Instant::now();
+        let mut agent = agent(now);
+
+        for (request, answer) in [
+            ("GET gmail user", r#"OK "zahash""#),
+            ("GET gmail pass\n", r#"OK "ни шагу \"назад\"""#),
+            ("GET 'old mail' pass", r#"OK "line one\nline two""#),
+            ("GET all pin", r#"OK "1234""#),
+            ("LIST", r#"OK ["all","gmail","old mail"]"#),
+            ("GET gmail url", "ERR 'gmail' has no 'url'"),
+            ("GET discord pass", "ERR 'discord' not found"),
+            ("GET gmail", "ERR unknown request"),
+            ("GET gmail pass again", "ERR unknown request"),
+            ("get gmail pass", "ERR unknown request"),
+            ("SET gmail pass = x", "ERR unknown request"),
+            ("LIST (all)", "ERR unknown request"),
+            ("GET 'gmail pass", "ERR unknown request"),
+            ("", "ERR unknown request"),
+        ] {
+            assert_eq!(agent.answer(request, now), answer, "{}", request);
+        }
+    }
+
+    #[test]
+    fn test_ambiguous() {
+        let now = Instant::now();
+        let mut store = store();
+        store.set_allow_duplicate_names(true).unwrap();
+        store.duplicate("gmail", "gmail");
+        let mut agent = Agent::new(
+            store,
+            RateLimit::new(100, Duration::from_secs(1)),
+            None,
+            None,
+            now,
+        );
+
+        assert_eq!(
+            agent.answer("GET gmail pass", now),
+            "ERR several records are named 'gmail'"
+        );
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let now = Instant::now();
+        let mut agent = Agent::new(
+            store(),
+            RateLimit::new(3, Duration::from_secs(1)),
+            None,
+            None,
+            now,
+        );
+
+        for _ in 0..3 {
+            assert_eq!(agent.answer("GET gmail user", now), r#"OK "zahash""#);
+        }
+        // rejected requests count as well
+        assert_eq!(agent.answer("LIST", now), "ERR too many requests");
+        assert_eq!(
+            agent.answer("GET gmail user", now + Duration::from_millis(999)),
+            "ERR too many requests"
+        );
+        assert_eq!(
+            agent.answer("GET gmail user", now + Duration::from_secs(1)),
+            r#"OK "zahash""#
+        );
+    }
+
+    #[test]
+    fn test_confirm() {
+        use std::sync::{Arc, Mutex};
+
+        let now = Instant::now();
+        let asked = Arc::new(Mutex::new(vec![]));
+        let confirm = {
+            let asked = Arc::clone(&asked);
+            move |request: &str| {
+                asked.lock().unwrap().push(request.to_string());
+                request.ends_with("user")
+            }
+        };
+        let mut agent = Agent::new(
+            store(),
+            RateLimit::new(100, Duration::from_secs(1)),
+            None,
+            Some(Box::new(confirm)),
+            now,
+        );
+
+        assert_eq!(agent.answer("GET gmail user", now), r#"OK "zahash""#);
+        assert_eq!(agent.answer("GET gmail pass", now), "ERR denied");
+        // only values are asked about
+        assert_eq!(
+            agent.answer("LIST", now),
+            r#"OK ["all","gmail","old mail"]"#
+        );
+        assert_eq!(
+            agent.answer("GET gmail url", now),
+            "ERR 'gmail' has no 'url'"
+        );
+        assert_eq!(
+            *asked.lock().unwrap(),
+            vec!["GET 'gmail' user", "GET 'gmail' pass"]
+        );
+    }
+
+    #[test]
+    fn test_lock() {
+        let now = Instant::now();
+        let mut agent = agent(now);
+
+        let later = now + Duration::from_secs(299);
+        assert!(!agent.is_locked(later));
+        // every request starts the timeout over
+        assert_eq!(agent.answer("GET gmail user", later), r#"OK "zahash""#);
+        assert!(!agent.is_locked(later + Duration::from_secs(299)));
+
+        let idle = later + Duration::from_secs(300);
+        assert!(agent.is_locked(idle));
+        assert_eq!(agent.answer("GET gmail user", idle), "ERR locked");
+        assert_eq!(agent.store.names(), Vec::<String>::new());
+        // locked for good
+        assert_eq!(agent.answer("LIST", idle), "ERR locked");
+
+        // never locks without a timeout
+        let mut agent = Agent::new(
+            store(),
+            RateLimit::new(100, Duration::from_secs(1)),
+            None,
+            None,
+            now,
+        );
+        assert!(!agent.is_locked(now + Duration::from_secs(60 * 60 * 24)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_serve() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::unix::fs::PermissionsExt;
+        use std::os::unix::net::UnixStream;
+        use std::path::Path;
+
+        use crate::cancel::Cancel;
+
+        /// what a client of the agent does
+        fn request(socket: &Path, requests: &[&str]) -> Vec<String> {
+            let mut stream = UnixStream::connect(socket).unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            requests
+                .iter()
+                .map(|request| {
+                    writeln!(stream, "{}", request).unwrap();
+                    let mut answer = String::new();
+                    reader.read_line(&mut answer).unwrap();
+                    answer.trim_end().to_string()
+                })
+                .collect()
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("agent.sock");
+        let cancel = Cancel::default();
+
+        let server = {
+            let (socket, cancel) = (socket.clone(), cancel.clone());
+            std::thread::spawn(move || serve(&mut agent(Instant::now()), &socket, &cancel))
+        };
+        while !socket.exists() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        assert_eq!(
+            request(&socket, &["GET gmail user", "LIST", "GET gmail url"]),
+            vec![
+                r#"OK "zahash""#,
+                r#"OK ["all","gmail","old mail"]"#,
+                "ERR 'gmail' has no 'url'"
+            ]
+        );
+        assert_eq!(
+            request(&socket, &[&"x".repeat(MAX_REQUEST_LEN + 1)]),
+            vec!["ERR request too long"]
+        );
+        // a second agent can't take over the socket
+        assert!(serve(&mut agent(Instant::now()), &socket, &Cancel::default()).is_err());
+
+        cancel.cancel();
+        server.join().unwrap().unwrap();
+        assert!(!socket.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_peer_uid() {
+        let (client, _) = std::os::unix::net::UnixStream::pair().unwrap();
+        assert_eq!(socket::peer_uid(&client).unwrap(), unsafe {
+            libc::geteuid()
+        });
+    }
+}
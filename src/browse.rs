@@ -0,0 +1,386 @@
+use std::io::Write;
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEventKind},
+    execute, queue,
+    terminal::{self, ClearType},
+};
+
+use crate::lex::{lex, Token};
+use crate::parse::{parse, Cmd};
+use crate::render::{fmt_history, fmt_history_change, fmt_history_expanded};
+use crate::store::{HistoryDiff, HistoryEntry, Store};
+
+const KEYS: &str = "↑/↓ move · enter expand · d diff · r reveal · q quit";
+
+/// the keys the browser knows. everything else is ignored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Up,
+    Down,
+    Enter,
+    Esc,
+    Char(char),
+}
+
+/// what is shown below the list for the selected entry
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum View {
+    List,
+    /// one field per line, sensitive values masked
+    Expanded,
+    /// what changed since the entry before it
+    Diff,
+    /// like `Expanded`, with the sensitive values
+    Revealed,
+}
+
+/// the history of one record, an entry at a time. only knows keys and lines,
+/// so that it can be driven without a terminal
+pub struct Browser {
+    name: String,
+    /// newest first, numbered from 0 like `history` and `restore`
+    entries: Vec<HistoryEntry>,
+    /// `diffs[i]` is what `entries[i]` changed
+    diffs: Vec<HistoryDiff>,
+    selected: usize,
+    view: View,
+    /// whether the master password was given again, which `r` needs
+    authorized: bool,
+}
+
+impl Browser {
+    pub fn new(store: &Store, name: &str) -> Self {
+        let mut entries = store.history(name);
+        entries.sort_by(|h1, h2| h1.datetime.cmp(&h2.datetime).reverse());
+        let mut diffs = store.history_diff(name);
+        diffs.reverse();
+
+        Self {
+            name: name.to_string(),
+            entries,
+            diffs,
+            selected: 0,
+            view: View::List,
+            authorized: false,
+        }
+    }
+
+    /// whether `key` needs the master password first
+    pub fn needs_reauth(&self, key: Key) -> bool {
+        key == Key::Char('r') && !self.authorized
+    }
+
+    /// lets `r` reveal from now on
+    pub fn authorize(&mut self) {
+        self.authorized = true;
+    }
+
+    /// `false` once the browser should close
+    pub fn handle(&mut self, key: Key) -> bool {
+        match (key, self.view) {
+            (Key::Char('q'), _) | (Key::Esc, View::List) => return false,
+            (Key::Up, _) => self.selected = self.selected.saturating_sub(1),
+            (Key::Down, _) => {
+                self.selected = (self.selected + 1).min(self.entries.len().saturating_sub(1))
+            }
+            (Key::Enter, View::List) => self.view = View::Expanded,
+            (Key::Enter | Key::Esc, _) => self.view = View::List,
+            (Key::Char('d'), _) => self.view = View::Diff,
+            (Key::Char('r'), _) if self.authorized => self.view = View::Revealed,
+            _ => {}
+        }
+        true
+    }
+
+    /// the whole screen: the list with the selected entry marked, its closer look and the keys.
+    /// a list too long for `height` lines shows the entries around the selected one
+    pub fn lines(&self, height: usize) -> Vec<String> {
+        if self.entries.is_empty() {
+            return vec![format!("'{}' has no history", self.name), KEYS.into()];
+        }
+
+        let (n, entry) = (self.selected, self.entries[self.selected].clone());
+        let detail = match self.view {
+            View::List => vec![],
+            View::Expanded => fmt_history_expanded(n, entry, true),
+            View::Revealed => fmt_history_expanded(n, entry, false),
+            View::Diff => match self.diffs.get(self.selected) {
+                Some(diff) => fmt_history_change(n, diff.clone(), true),
+                None => vec![],
+            },
+        };
+
+        // the title, the blank line and keys at the bottom and the closer look with its blank line
+        let fixed = 3 + if detail.is_empty() {
+            0
+        } else {
+            detail.len() + 1
+        };
+        let rows = height.saturating_sub(fixed).max(1);
+        let start =
+            (self.selected.saturating_sub(rows / 2)).min(self.entries.len().saturating_sub(rows));
+
+        let mut lines = match rows < self.entries.len() {
+            true => vec![format!(
+                "history of '{}' ({}/{})",
+                self.name,
+                self.selected + 1,
+                self.entries.len()
+            )],
+            false => vec![format!("history of '{}'", self.name)],
+        };
+        lines.extend(
+            (self.entries.iter().enumerate())
+                .skip(start)
+                .take(rows)
+                .map(|(i, entry)| {
+                    let marker = if i == self.selected { ">" } else { " " };
+                    format!("{} {}", marker, fmt_history(i, entry.clone(), true))
+                }),
+        );
+
+        if !detail.is_empty() {
+            lines.push(String::new());
+            lines.extend(detail);
+        }
+
+        lines.push(String::new());
+        lines.push(KEYS.into());
+        lines
+    }
+}
+
+/// the record `browse history <name>` is about
+pub fn target(line: &str) -> Option<String> {
+    let tokens = lex(line).ok()?;
+    let (Token::Value("browse"), rest) = tokens.split_first()? else {
+        return None;
+    };
+    match parse(rest).ok()? {
+        Cmd::History(name) => Some(name.to_string()),
+        _ => None,
+    }
+}
+
+/// shows `browser` on the alternate screen until it is closed.
+/// `reauth` asks for the master password before the first reveal, with the terminal as usual.
+/// the terminal is given back as it was, also when something fails
+pub fn run(mut browser: Browser, reauth: &mut dyn FnMut() -> bool) -> anyhow::Result<()> {
+    struct Restore;
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let _ = execute!(
+                std::io::stdout(),
+                cursor::Show,
+                terminal::LeaveAlternateScreen
+            );
+            let _ = terminal::disable_raw_mode();
+        }
+    }
+
+    terminal::enable_raw_mode()?;
+    let _restore = Restore;
+    let mut stdout = std::io::stdout();
+    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+    loop {
+        queue!(
+            stdout,
+            terminal::Clear(ClearType::All),
+            cursor::MoveTo(0, 0)
+        )?;
+        // raw mode doesn't return to the start of the line by itself
+        let (_, height) = terminal::size()?;
+        write!(stdout, "{}", browser.lines(height as usize).join("\r\n"))?;
+        stdout.flush()?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let key = match key.code {
+            KeyCode::Up | KeyCode::Char('k') => Key::Up,
+            KeyCode::Down | KeyCode::Char('j') => Key::Down,
+            KeyCode::Enter => Key::Enter,
+            KeyCode::Esc => Key::Esc,
+            KeyCode::Char(c) => Key::Char(c),
+            _ => continue,
+        };
+        if browser.needs_reauth(key) {
+            execute!(
+                stdout,
+                terminal::Clear(ClearType::All),
+                cursor::MoveTo(0, 0),
+                cursor::Show
+            )?;
+            terminal::disable_raw_mode()?;
+            let authorized = reauth();
+            terminal::enable_raw_mode()?;
+            execute!(stdout, cursor::Hide)?;
+            if authorized {
+                browser.authorize();
+            }
+        }
+        if !browser.handle(key) {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::eval::eval;
+    use pretty_assertions::assert_eq;
+
+    fn store() -> Store {
+        let mut store = Store::new();
+        for cmd in [
+            "set gmail user = zahash sensitive pass = first",
+            "set gmail sensitive pass = second",
+            "set gmail url = mail.google.com",
+        ] {
+            eval(cmd, &mut store).unwrap();
+        }
+        store
+    }
+
+    #[test]
+    fn test_keys() {
+        let mut browser = Browser::new(&store(), "gmail");
+        browser.authorize();
+        assert_eq!((browser.selected, browser.view), (0, View::List));
+
+        // stays within the three entries
+        assert!(browser.handle(Key::Up));
+        assert_eq!(browser.selected, 0);
+        for _ in 0..5 {
+            browser.handle(Key::Down);
+        }
+        assert_eq!(browser.selected, 2);
+        browser.handle(Key::Up);
+        assert_eq!(browser.selected, 1);
+
+        browser.handle(Key::Enter);
+        assert_eq!(browser.view, View::Expanded);
+        // moving keeps the closer look, on the newly selected entry
+        browser.handle(Key::Down);
+        assert_eq!((browser.selected, browser.view), (2, View::Expanded));
+        browser.handle(Key::Char('d'));
+        assert_eq!(browser.view, View::Diff);
+        browser.handle(Key::Char('r'));
+        assert_eq!(browser.view, View::Revealed);
+        browser.handle(Key::Char('x'));
+        assert_eq!(browser.view, View::Revealed);
+        browser.handle(Key::Enter);
+        assert_eq!(browser.view, View::List);
+        browser.handle(Key::Char('d'));
+        browser.handle(Key::Esc);
+        assert_eq!(browser.view, View::List);
+
+        // esc only closes from the list, q from anywhere
+        assert!(!browser.handle(Key::Esc));
+        browser.handle(Key::Enter);
+        assert!(!browser.handle(Key::Char('q')));
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut browser = Browser::new(&store(), "gmail");
+        browser.authorize();
+        let lines = browser.lines(100);
+        assert_eq!(lines[0], "history of 'gmail'");
+        assert!(lines[1].starts_with("> [0] ("));
+        assert!(lines[1].ends_with(" pass=***** url='mail.google.com' user='zahash'"));
+        assert!(lines[3].starts_with("  [2] ("));
+        assert_eq!(lines.last().unwrap(), KEYS);
+
+        browser.handle(Key::Down);
+        browser.handle(Key::Enter);
+        let lines = browser.lines(100);
+        assert!(lines[2].starts_with("> [1] ("));
+        assert_eq!(&lines[6..9], ["    pass=*****", "    user='zahash'", ""]);
+
+        browser.handle(Key::Char('d'));
+        assert_eq!(browser.lines(100)[6], "    ~ pass: ***** -> *****");
+
+        browser.handle(Key::Char('r'));
+        assert_eq!(browser.lines(100)[6], "    pass='second'");
+
+        let browser = Browser::new(&store(), "twitch");
+        assert_eq!(browser.lines(100)[0], "'twitch' has no history");
+    }
+
+    #[test]
+    fn test_reveal_needs_reauth() {
+        let mut browser = Browser::new(&store(), "gmail");
+        assert!(browser.needs_reauth(Key::Char('r')));
+        assert!(!browser.needs_reauth(Key::Char('d')));
+
+        browser.handle(Key::Char('r'));
+        assert_eq!(browser.view, View::List);
+
+        browser.authorize();
+        assert!(!browser.needs_reauth(Key::Char('r')));
+        browser.handle(Key::Char('r'));
+        assert_eq!(browser.view, View::Revealed);
+    }
+
+    #[test]
+    fn test_scrolling() {
+        let mut store = Store::new();
+        for i in 0..20 {
+            eval(&format!("set gmail n = {}", i), &mut store).unwrap();
+        }
+        let mut browser = Browser::new(&store, "gmail");
+
+        // the title, 5 entries, a blank line and the keys
+        let lines = browser.lines(8);
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[0], "history of 'gmail' (1/20)");
+        assert!(lines[1].starts_with("> [0] ("));
+        assert!(lines[5].starts_with("  [4] ("));
+
+        // the selected entry stays in the middle
+        for _ in 0..10 {
+            browser.handle(Key::Down);
+        }
+        let lines = browser.lines(8);
+        assert_eq!(lines[0], "history of 'gmail' (11/20)");
+        assert!(lines[1].starts_with("  [8] ("));
+        assert!(lines[3].starts_with("> [10] ("));
+
+        // until the end of the list
+        for _ in 0..10 {
+            browser.handle(Key::Down);
+        }
+        let lines = browser.lines(8);
+        assert!(lines[1].starts_with("  [15] ("));
+        assert!(lines[5].starts_with("> [19] ("));
+
+        // the closer look takes rows from the list
+        browser.handle(Key::Enter);
+        let lines = browser.lines(8);
+        assert!(lines[1].starts_with("  [18] ("));
+        assert!(lines[2].starts_with("> [19] ("));
+        assert_eq!(lines[5], "    n='0'");
+    }
+
+    #[test]
+    fn test_target() {
+        assert_eq!(target("browse history gmail").as_deref(), Some("gmail"));
+        assert_eq!(
+            target("browse history 'old mail'").as_deref(),
+            Some("old mail")
+        );
+        assert_eq!(target("browse history"), None);
+        assert_eq!(target("browse gmail"), None);
+        assert_eq!(target("history gmail"), None);
+    }
+}
@@ -0,0 +1,141 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
+use lazy_static::lazy_static;
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    flag, SigId,
+};
+
+/// set once a long running command should stop. the command checks it between records
+/// and either leaves the store as it was or reports what it got done
+#[derive(Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    /// what ctrl-c does through `interruptible`
+    #[cfg(test)]
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+lazy_static! {
+    /// whether ctrl-c ends the program like it does by default. the handler is installed once
+    /// and stays, since the previous one can't be put back after installing another
+    static ref DEFAULT: Option<Arc<AtomicBool>> = {
+        let default = Arc::new(AtomicBool::new(true));
+        flag::register_conditional_default(SIGINT, default.clone())
+            .ok()
+            .map(|_| default)
+    };
+    /// how many `Interruptible`s are alive
+    static ref ACTIVE: Mutex<usize> = Mutex::new(0);
+}
+
+/// set once `on_exit_signals` ends the program on ctrl-c instead of the default handler
+static HANDLED: AtomicBool = AtomicBool::new(false);
+
+/// on SIGTERM, and on SIGINT while nothing is `interruptible`, runs `cleanup` before
+/// ending the program with the code the signal would have ended it with
+#[cfg(unix)]
+pub fn on_exit_signals(cleanup: fn()) -> std::io::Result<()> {
+    let mut signals = signal_hook::iterator::Signals::new([SIGINT, SIGTERM])?;
+    HANDLED.store(true, Ordering::SeqCst);
+    if let Some(default) = DEFAULT.as_ref() {
+        default.store(false, Ordering::SeqCst);
+    }
+
+    std::thread::spawn(move || {
+        for signal in signals.forever() {
+            if exits(signal, *ACTIVE.lock().unwrap()) {
+                cleanup();
+                std::process::exit(128 + signal);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// there is no iterator over signals outside unix. ctrl-c ends the program by default there
+#[cfg(not(unix))]
+pub fn on_exit_signals(_cleanup: fn()) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// ctrl-c only cancels what is `interruptible`
+fn exits(signal: i32, active: usize) -> bool {
+    signal == SIGTERM || active == 0
+}
+
+/// ctrl-c cancels instead of ending the program until this is dropped
+pub struct Interruptible(Option<SigId>);
+
+pub fn interruptible(cancel: &Cancel) -> Interruptible {
+    let Some(default) = DEFAULT.as_ref() else {
+        return Interruptible(None);
+    };
+    let Ok(id) = flag::register(SIGINT, cancel.0.clone()) else {
+        return Interruptible(None);
+    };
+
+    let mut active = ACTIVE.lock().unwrap();
+    *active += 1;
+    default.store(false, Ordering::SeqCst);
+    Interruptible(Some(id))
+}
+
+impl Drop for Interruptible {
+    fn drop(&mut self) {
+        let (Some(id), Some(default)) = (self.0.take(), DEFAULT.as_ref()) else {
+            return;
+        };
+        signal_hook::low_level::unregister(id);
+
+        let mut active = ACTIVE.lock().unwrap();
+        *active -= 1;
+        if *active == 0 {
+            default.store(!HANDLED.load(Ordering::SeqCst), Ordering::SeqCst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interruptible() {
+        let (cancel, other) = (Cancel::default(), Cancel::default());
+
+        let _interruptible = interruptible(&cancel);
+        {
+            let _other = interruptible(&other);
+            signal_hook::low_level::raise(SIGINT).unwrap();
+            assert!(cancel.is_cancelled());
+            assert!(other.is_cancelled());
+        }
+
+        // the program doesn't end while any of them is alive
+        let other = Cancel::default();
+        drop(interruptible(&other));
+        signal_hook::low_level::raise(SIGINT).unwrap();
+        assert!(!other.is_cancelled());
+    }
+
+    #[test]
+    fn test_exits() {
+        assert!(exits(SIGINT, 0));
+        assert!(!exits(SIGINT, 1));
+        assert!(!exits(SIGINT, 2));
+        // SIGTERM isn't a ctrl-c, so it ends the program even during a long command
+        assert!(exits(SIGTERM, 0));
+        assert!(exits(SIGTERM, 1));
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    io::{IsTerminal, Write},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use arboard::Clipboard;
+use base64::Engine;
+use ignorant::Ignore;
+use lazy_static::lazy_static;
+use ring::digest::{digest, Digest, SHA256};
+
+/// when to copy through the terminal with an OSC 52 escape sequence instead of the
+/// system clipboard. over ssh there is no clipboard but terminals like iterm2,
+/// kitty and wezterm put what the sequence carries on the local clipboard
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Osc52 {
+    /// only if there is no system clipboard and the output is a terminal
+    Auto,
+    Always,
+    Never,
+}
+
+pub enum Copied {
+    /// on the system clipboard until it is cleared
+    Native { clear_after: Duration },
+    /// through the terminal. it can't be cleared from here
+    Terminal,
+}
+
+struct State {
+    clear_after: Duration,
+    osc52: Osc52,
+    generation: usize,
+    /// what was copied and the clipboard that holds it, until it is cleared.
+    /// on linux the copied text is gone once its `Clipboard` is dropped,
+    /// so the owner has to outlive the copy and be dropped before exiting.
+    pending: Option<(Digest, Clipboard)>,
+    /// when the pending copy is scheduled to be cleared
+    clears_at: Option<Instant>,
+}
+
+lazy_static! {
+    static ref STATE: Mutex<State> = Mutex::new(State {
+        clear_after: Duration::from_secs(30),
+        osc52: Osc52::Auto,
+        generation: 0,
+        pending: None,
+        clears_at: None,
+    });
+}
+
+pub fn set_clear_after(clear_after: Duration) {
+    STATE.lock().unwrap().clear_after = clear_after;
+}
+
+pub fn set_osc52(osc52: Osc52) {
+    STATE.lock().unwrap().osc52 = osc52;
+}
+
+/// copies `text` to the system clipboard or through the terminal (see `Osc52`)
+pub fn copy(text: String, clear_after: Option<Duration>) -> Option<Copied> {
+    let osc52 = STATE.lock().unwrap().osc52;
+    let terminal = || {
+        let mut stdout = std::io::stdout().lock();
+        write_osc52(&text, &mut stdout)
+            .ok()
+            .map(|_| Copied::Terminal)
+    };
+
+    match osc52 {
+        Osc52::Always => terminal(),
+        Osc52::Never => copy_native(&text, clear_after),
+        Osc52::Auto => match copy_native(&text, clear_after) {
+            Some(copied) => Some(copied),
+            None if std::io::stdout().is_terminal() => terminal(),
+            None => None,
+        },
+    }
+}
+
+/// copies `text` and schedules the clipboard to be cleared after `clear_after`
+/// (or the default set with `set_clear_after`).
+/// every copy cancels the clear scheduled by the previous one.
+/// the timer thread only knows the generation, never the copied text.
+fn copy_native(text: &str, clear_after: Option<Duration>) -> Option<Copied> {
+    let copied = digest(&SHA256, text.as_bytes());
+    let mut clipboard = Clipboard::new().ok()?;
+    clipboard.set_text(text).ok()?;
+
+    let mut state = STATE.lock().unwrap();
+    state.generation += 1;
+    state.pending = Some((copied, clipboard));
+    let generation = state.generation;
+    let clear_after = clear_after.unwrap_or(state.clear_after);
+    state.clears_at = Some(Instant::now() + clear_after);
+
+    thread::spawn(move || {
+        thread::sleep(clear_after);
+        let mut state = STATE.lock().unwrap();
+        if state.generation == generation {
+            clear(&mut state);
+        }
+    });
+
+    Some(Copied::Native { clear_after })
+}
+
+/// `ESC ] 52 ; c ; <base64> BEL` sets the clipboard (`c`) of the terminal to `text`
+fn write_osc52(text: &str, out: &mut impl Write) -> std::io::Result<()> {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+    write!(out, "\x1b]52;c;{}\x07", encoded)?;
+    out.flush()
+}
+
+/// the text currently on the clipboard
+pub fn paste() -> Option<String> {
+    Clipboard::new().ok()?.get_text().ok()
+}
+
+/// clears the clipboard right away if the scheduled clear hasn't happened yet.
+/// returns whether anything was cleared.
+pub fn clear_pending() -> bool {
+    clear(&mut STATE.lock().unwrap())
+}
+
+/// clears what is still pending once dropped, so that returning early with an error
+/// or panicking doesn't leave a copy on the clipboard
+pub struct ClearOnDrop;
+
+impl Drop for ClearOnDrop {
+    fn drop(&mut self) {
+        clear_pending();
+    }
+}
+
+/// blocks until the scheduled clear of the last copy has happened.
+/// for callers that would otherwise exit before the timer thread gets to it
+pub fn wait_pending() {
+    let Some(clears_at) = STATE.lock().unwrap().clears_at else {
+        return;
+    };
+    thread::sleep(clears_at.saturating_duration_since(Instant::now()));
+    clear_pending();
+}
+
+/// clears the clipboard unless something else has been copied since.
+/// returns whether it was cleared.
+fn clear(state: &mut State) -> bool {
+    state.clears_at = None;
+    let Some((copied, mut clipboard)) = state.pending.take() else {
+        return false;
+    };
+
+    match still_copied(&copied, clipboard.get_text().ok().as_deref()) {
+        true => {
+            clipboard.clear().ignore();
+            true
+        }
+        false => false,
+    }
+}
+
+/// whether the clipboard still holds the text with the `copied` digest.
+/// only the digest is compared so that the copied secret isn't kept around
+fn still_copied(copied: &Digest, current: Option<&str>) -> bool {
+    match current {
+        Some(current) => digest(&SHA256, current.as_bytes()).as_ref() == copied.as_ref(),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_still_copied() {
+        let copied = digest(&SHA256, "ни шагу назад".as_bytes());
+
+        assert!(still_copied(&copied, Some("ни шагу назад")));
+        assert!(!still_copied(&copied, Some("something copied afterwards")));
+        assert!(!still_copied(&copied, Some("")));
+        // images or an already empty clipboard have no text
+        assert!(!still_copied(&copied, None));
+    }
+
+    #[test]
+    fn test_osc52() {
+        let osc52 = |text| {
+            let mut out = vec![];
+            write_osc52(text, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(osc52("hunter2"), "\x1b]52;c;aHVudGVyMg==\x07");
+        assert_eq!(osc52(""), "\x1b]52;c;\x07");
+        // the payload never contains the terminator or another escape
+        assert_eq!(
+            osc52("ни шагу назад\x07\x1b"),
+            "\x1b]52;c;0L3QuCDRiNCw0LPRgyDQvdCw0LfQsNC0Bxs=\x07"
+        );
+    }
+}
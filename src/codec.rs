@@ -0,0 +1,183 @@
+use std::fmt::Display;
+
+use anyhow::{anyhow, bail};
+use base64::{engine::general_purpose, DecodeError, Engine};
+
+/// the longest value `decode` and `encode` produce, in bytes
+pub const MAX_LEN: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Base64,
+    Hex,
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Encoding::Base64 => write!(f, "b64"),
+            Encoding::Hex => write!(f, "hex"),
+        }
+    }
+}
+
+/// the text that `value` encodes. errors never contain any of `value`
+pub fn decode(value: &str, encoding: Encoding) -> anyhow::Result<String> {
+    let bytes = match encoding {
+        Encoding::Base64 => decode_base64(value)?,
+        Encoding::Hex => decode_hex(value)?,
+    };
+    check_len(bytes.len())?;
+    String::from_utf8(bytes).map_err(|_| anyhow!("the decoded value is binary, not text"))
+}
+
+pub fn encode(value: &str, encoding: Encoding) -> anyhow::Result<String> {
+    let encoded = match encoding {
+        Encoding::Base64 => general_purpose::STANDARD.encode(value),
+        Encoding::Hex => value.bytes().map(|b| format!("{:02x}", b)).collect(),
+    };
+    check_len(encoded.len())?;
+    Ok(encoded)
+}
+
+fn check_len(len: usize) -> anyhow::Result<()> {
+    match len > MAX_LEN {
+        true => bail!(
+            "the result is {} bytes, more than the {} allowed",
+            len,
+            MAX_LEN
+        ),
+        false => Ok(()),
+    }
+}
+
+/// standard or url safe, padded or not. keys are often wrapped, so whitespace is ignored
+fn decode_base64(value: &str) -> anyhow::Result<Vec<u8>> {
+    let value = value
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>();
+    let value = value.trim_end_matches('=');
+    let engine = match value.contains(['-', '_']) {
+        true => general_purpose::URL_SAFE_NO_PAD,
+        false => general_purpose::STANDARD_NO_PAD,
+    };
+
+    engine.decode(value).map_err(|e| match e {
+        DecodeError::InvalidByte(offset, _) | DecodeError::InvalidLastSymbol(offset, _) => {
+            anyhow!("not valid base64: unexpected character at {}", offset + 1)
+        }
+        _ => anyhow!("not valid base64: wrong length"),
+    })
+}
+
+/// upper or lower case digits. whitespace is ignored
+fn decode_hex(value: &str) -> anyhow::Result<Vec<u8>> {
+    let digits = value
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .enumerate()
+        .map(|(i, c)| {
+            c.to_digit(16)
+                .ok_or_else(|| anyhow!("not valid hex: unexpected character at {}", i + 1))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    if digits.len() % 2 != 0 {
+        bail!("not valid hex: odd number of digits");
+    }
+
+    Ok(digits
+        .chunks(2)
+        .map(|pair| (pair[0] * 16 + pair[1]) as u8)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_round_trip() {
+        for value in [
+            "",
+            "hunter2",
+            "ни шагу назад",
+            "a",
+            "ab",
+            "abc",
+            "{\"k\": 1}\n",
+        ] {
+            for encoding in [Encoding::Base64, Encoding::Hex] {
+                let encoded = encode(value, encoding).unwrap();
+                assert_eq!(decode(&encoded, encoding).unwrap(), value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(encode("hunter2", Encoding::Base64).unwrap(), "aHVudGVyMg==");
+        assert_eq!(encode("hunter2", Encoding::Hex).unwrap(), "68756e74657232");
+
+        for b64 in ["aHVudGVyMg==", "aHVudGVyMg", "aHVu\ndGVy Mg=="] {
+            assert_eq!(decode(b64, Encoding::Base64).unwrap(), "hunter2");
+        }
+        // url safe
+        assert_eq!(decode("Pz8_", Encoding::Base64).unwrap(), "???");
+        assert_eq!(decode("Pz8/", Encoding::Base64).unwrap(), "???");
+
+        for hex in ["68756e74657232", "68756E74657232", "6875 6e74 6572 32"] {
+            assert_eq!(decode(hex, Encoding::Hex).unwrap(), "hunter2");
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        let err = |value, encoding| decode(value, encoding).unwrap_err().to_string();
+
+        assert_eq!(
+            err("aHVu*GVyMg==", Encoding::Base64),
+            "not valid base64: unexpected character at 5"
+        );
+        assert_eq!(
+            err("aHVud", Encoding::Base64),
+            "not valid base64: wrong length"
+        );
+        assert_eq!(
+            err("6875zz", Encoding::Hex),
+            "not valid hex: unexpected character at 5"
+        );
+        assert_eq!(
+            err("687", Encoding::Hex),
+            "not valid hex: odd number of digits"
+        );
+        assert_eq!(
+            err("ff00", Encoding::Hex),
+            "the decoded value is binary, not text"
+        );
+        assert_eq!(
+            err("/w==", Encoding::Base64),
+            "the decoded value is binary, not text"
+        );
+    }
+
+    #[test]
+    fn test_max_len() {
+        let value = "a".repeat(MAX_LEN / 2);
+        assert!(encode(&value, Encoding::Hex).is_ok());
+        assert!(encode(&(value.clone() + "a"), Encoding::Hex).is_err());
+
+        let hex = "61".repeat(MAX_LEN + 1);
+        assert_eq!(
+            decode(&hex, Encoding::Hex).unwrap_err().to_string(),
+            format!(
+                "the result is {} bytes, more than the {} allowed",
+                MAX_LEN + 1,
+                MAX_LEN
+            )
+        );
+        assert!(decode(&hex[2..], Encoding::Hex).is_ok());
+    }
+}
@@ -0,0 +1,123 @@
+use std::num::{NonZeroU32, NonZeroU64};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// settings read from `config.toml` so that they don't have to be passed every time.
+/// anything left out falls back to the command line default. flags and environment
+/// variables given on the command line win over the file
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub fpath: Option<String>,
+    pub history_limit: Option<NonZeroU64>,
+    pub kdf: Option<KdfArg>,
+    pub pbkdf2_iterations: Option<NonZeroU32>,
+    pub auto_save: Option<bool>,
+    pub author: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KdfArg {
+    Pbkdf2,
+    #[value(alias = "argon2")]
+    #[serde(alias = "argon2")]
+    Argon2id,
+}
+
+impl Config {
+    /// a missing file is an empty config
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("unable to read '{}'", path.display()))
+            }
+        };
+        toml::from_str(&content).with_context(|| format!("invalid config '{}'", path.display()))
+    }
+
+    /// `config.toml` in the royalguard directory of the user's config directory
+    /// (`~/.config/royalguard` on linux), which is created if it doesn't exist yet
+    pub fn load_default() -> anyhow::Result<Self> {
+        let Some(path) = default_path() else {
+            return Ok(Self::default());
+        };
+        if let Some(dir) = path.parent() {
+            // without it there's just no config, which is no reason to stop
+            let _ = std::fs::create_dir_all(dir);
+        }
+        Self::load(&path)
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("the config only has plain values")
+    }
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("royalguard").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+
+        assert_eq!(Config::load(&path).unwrap(), Config::default());
+
+        std::fs::write(
+            &path,
+            "fpath = '/vaults/main'\nhistory_limit = 20\nkdf = 'argon2'\nauto_save = false\n",
+        )
+        .unwrap();
+        assert_eq!(
+            Config::load(&path).unwrap(),
+            Config {
+                fpath: Some("/vaults/main".into()),
+                history_limit: NonZeroU64::new(20),
+                kdf: Some(KdfArg::Argon2id),
+                pbkdf2_iterations: None,
+                auto_save: Some(false),
+                author: None,
+            }
+        );
+
+        for invalid in [
+            "history_limit = 0",
+            "pbkdf2_iterations = 0",
+            "kdf = 'scrypt'",
+            "fpaht = '/vaults/main'",
+            "auto_save = 'yes'",
+        ] {
+            std::fs::write(&path, invalid).unwrap();
+            assert!(Config::load(&path).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_to_toml() {
+        let config = Config {
+            fpath: Some("/vaults/main".into()),
+            history_limit: None,
+            kdf: Some(KdfArg::Pbkdf2),
+            pbkdf2_iterations: NonZeroU32::new(100_000),
+            auto_save: Some(true),
+            author: Some("alice".into()),
+        };
+        assert_eq!(
+            config.to_toml(),
+            "fpath = \"/vaults/main\"\nkdf = \"pbkdf2\"\npbkdf2_iterations = 100000\nauto_save = true\nauthor = \"alice\"\n"
+        );
+        assert_eq!(toml::from_str::<Config>(&config.to_toml()).unwrap(), config);
+    }
+}
@@ -7,56 +7,597 @@ use aes_gcm::{
     AeadCore, Aes256Gcm, KeyInit,
 };
 use ring::{
-    pbkdf2,
+    digest, pbkdf2,
     rand::{SecureRandom, SystemRandom},
 };
-use std::{num::NonZeroU32, path::Path};
+use std::{
+    fs::File,
+    io::{Read, Write},
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+};
 
-use crate::store::Store;
+use anyhow::Context;
+use ignorant::Ignore;
+use zeroize::Zeroizing;
 
-pub fn load<P: AsRef<Path>>(fpath: P, master_pass: &str) -> anyhow::Result<Store> {
-    create_new_file_if_not_exists(&fpath, master_pass)?;
-    let encrypted_file = std::fs::read(&fpath)?;
-    let salt = &encrypted_file[..16];
-    let cipher = get_cipher(master_pass, salt);
-    let nonce = &encrypted_file[16..28];
-    let encrypted_data = &encrypted_file[28..];
-    let plain_text = cipher
-        .decrypt(nonce.into(), encrypted_data.as_ref())
-        .map_err(|_| anyhow::anyhow!("Master password incorrect."))?;
-    let plain_text = String::from_utf8(plain_text)?;
-    Ok(serde_json::from_str::<Store>(&plain_text)?)
-}
-
-pub fn dump<P: AsRef<Path>>(fpath: P, master_pass: &str, store: &Store) -> anyhow::Result<()> {
-    create_new_file_if_not_exists(&fpath, master_pass)?;
+use crate::{keyring::Keyring, store::Store};
+
+/// marks files that start with the iteration count of PBKDF2.
+/// files written before it have no magic and are derived with `DEFAULT_ITERATIONS`.
+const MAGIC_V1: &[u8; 4] = b"RGv1";
+
+/// marks files that start with the kdf and its parameters
+pub const MAGIC_V2: &[u8; 4] = b"RGv2";
+
+/// marks files that start with the kdf, its parameters and `FLAG_*` bits.
+/// only written when one of the flags is set, so that other files stay readable
+/// by versions from before it
+const MAGIC_V3: &[u8; 4] = b"RGv3";
+
+/// the key is derived from the master password and a key file
+const FLAG_KEY_FILE: u8 = 1;
+
+/// the plain text was gzipped before it was encrypted
+const FLAG_GZIP: u8 = 2;
+
+/// plain text longer than this many bytes is compressed by `Compression::Auto`
+pub const COMPRESS_ABOVE: usize = 1024;
+
+/// whether the plain text is compressed before it is encrypted. files are read
+/// the same either way, the header tells whether they were
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Compression {
+    /// only above `COMPRESS_ABOVE`, where it is worth it
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl Compression {
+    fn applies(self, len: usize) -> bool {
+        match self {
+            Compression::Auto => len > COMPRESS_ABOVE,
+            Compression::Always => true,
+            Compression::Never => false,
+        }
+    }
+}
+
+/// where the secret that the kdf stretches into the encryption key comes from.
+/// the key `FLAG_*` bits in the header of a file record what it was sealed with,
+/// so that any source can tell whether it is able to open it
+pub trait KeySource {
+    /// the `FLAG_*` bits of files sealed with this source
+    fn flags(&self) -> u8;
+
+    /// the secret of a file whose header has `flags`.
+    /// `None` if the file needs something this source doesn't have
+    fn secret(&self, flags: u8) -> Option<Zeroizing<Vec<u8>>>;
+}
+
+/// just the master password
+pub struct Password<'a>(pub &'a str);
+
+impl KeySource for Password<'_> {
+    fn flags(&self) -> u8 {
+        0
+    }
+
+    fn secret(&self, flags: u8) -> Option<Zeroizing<Vec<u8>>> {
+        (flags == 0).then(|| Zeroizing::new(self.0.as_bytes().to_vec()))
+    }
+}
+
+/// the master password with the sha256 digest of the key file appended to it.
+/// files without the flag are opened with the password alone
+pub struct PasswordAndKeyFile<'a> {
+    pub pass: &'a str,
+    pub key_file: &'a [u8],
+}
+
+impl KeySource for PasswordAndKeyFile<'_> {
+    fn flags(&self) -> u8 {
+        FLAG_KEY_FILE
+    }
+
+    fn secret(&self, flags: u8) -> Option<Zeroizing<Vec<u8>>> {
+        if flags & !FLAG_KEY_FILE != 0 {
+            return None;
+        }
+        // allocated once, so no copy of the password is left behind by growing it
+        let mut secret = Zeroizing::new(Vec::with_capacity(self.pass.len() + 32));
+        secret.extend(self.pass.as_bytes());
+        if flags & FLAG_KEY_FILE != 0 {
+            secret.extend(digest::digest(&digest::SHA256, self.key_file).as_ref());
+        }
+        Some(secret)
+    }
+}
+
+/// the source for a master password and the content of an optional key file
+/// the master password kept in a keyring, with the key file if there is one.
+/// no secret while the keyring has nothing for `fpath`
+pub struct KeyringCached<'a> {
+    pub keyring: &'a dyn Keyring,
+    pub fpath: &'a str,
+    pub key_file: Option<&'a [u8]>,
+}
+
+impl KeySource for KeyringCached<'_> {
+    fn flags(&self) -> u8 {
+        match self.key_file {
+            Some(_) => FLAG_KEY_FILE,
+            None => 0,
+        }
+    }
+
+    fn secret(&self, flags: u8) -> Option<Zeroizing<Vec<u8>>> {
+        let pass = self.keyring.get(self.fpath)?;
+        let secret = key_source(&pass, self.key_file).secret(flags);
+        secret
+    }
+}
+
+pub fn key_source<'a>(pass: &'a str, key_file: Option<&'a [u8]>) -> Box<dyn KeySource + 'a> {
+    match key_file {
+        Some(key_file) => Box::new(PasswordAndKeyFile { pass, key_file }),
+        None => Box::new(Password(pass)),
+    }
+}
+
+pub const DEFAULT_ITERATIONS: u32 = 100_000;
+
+/// memory in KiB
+pub const ARGON2_M_COST: u32 = 65536;
+pub const ARGON2_T_COST: u32 = 3;
+pub const ARGON2_P_COST: u32 = 4;
+
+/// the most Argon2id may ask for, also from a file header. a crafted vault could
+/// otherwise take all the memory there is before the password is even checked
+pub const ARGON2_MAX_M_COST: u32 = 2 * 1024 * 1024;
+pub const ARGON2_MAX_T_COST: u32 = 100;
+pub const ARGON2_MAX_P_COST: u32 = 64;
+
+/// how the encryption key is derived from the master password
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kdf {
+    Pbkdf2 {
+        iterations: NonZeroU32,
+    },
+    Argon2id {
+        /// memory in KiB
+        m_cost: u32,
+        /// number of passes
+        t_cost: u32,
+        /// degree of parallelism
+        p_cost: u32,
+    },
+}
+
+impl Kdf {
+    /// kdf id followed by three u32 (little endian) parameters
+    pub const LEN: usize = 13;
+
+    pub fn argon2id() -> Self {
+        Kdf::Argon2id {
+            m_cost: ARGON2_M_COST,
+            t_cost: ARGON2_T_COST,
+            p_cost: ARGON2_P_COST,
+        }
+    }
+
+    /// refuses Argon2id parameters above `ARGON2_MAX_*`
+    pub fn check(self) -> anyhow::Result<Self> {
+        if let Kdf::Argon2id {
+            m_cost,
+            t_cost,
+            p_cost,
+        } = self
+        {
+            if m_cost > ARGON2_MAX_M_COST
+                || t_cost > ARGON2_MAX_T_COST
+                || p_cost > ARGON2_MAX_P_COST
+            {
+                anyhow::bail!(
+                    "Argon2id with {} KiB, {} passes and a parallelism of {} is more than the most allowed ({} KiB, {} passes, {})",
+                    m_cost,
+                    t_cost,
+                    p_cost,
+                    ARGON2_MAX_M_COST,
+                    ARGON2_MAX_T_COST,
+                    ARGON2_MAX_P_COST
+                );
+            }
+        }
+        Ok(self)
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let (id, params) = match self {
+            Kdf::Pbkdf2 { iterations } => (0, [iterations.get(), 0, 0]),
+            Kdf::Argon2id {
+                m_cost,
+                t_cost,
+                p_cost,
+            } => (1, [m_cost, t_cost, p_cost]),
+        };
+
+        let mut bytes = vec![id];
+        for param in params {
+            bytes.extend(param.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let param = |i: usize| {
+            let start = 1 + 4 * i;
+            Some(u32::from_le_bytes(
+                bytes.get(start..start + 4)?.try_into().ok()?,
+            ))
+        };
+
+        match bytes.first()? {
+            0 => Some(Kdf::Pbkdf2 {
+                iterations: NonZeroU32::new(param(0)?)?,
+            }),
+            1 => Some(Kdf::Argon2id {
+                m_cost: param(0)?,
+                t_cost: param(1)?,
+                p_cost: param(2)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// how hard a master password is to guess according to zxcvbn (its score of 0 to 4).
+/// only advice, any password is accepted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Strength {
+    VeryWeak,
+    Weak,
+    Fair,
+    Strong,
+    VeryStrong,
+}
+
+impl Strength {
+    /// weaker ones are warned about
+    pub const ADVISED: Strength = Strength::Strong;
+}
+
+impl std::fmt::Display for Strength {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Strength::VeryWeak => "very weak",
+            Strength::Weak => "weak",
+            Strength::Fair => "fair",
+            Strength::Strong => "strong",
+            Strength::VeryStrong => "very strong",
+        };
+        write!(f, "{}/4 ({})", *self as u8, name)
+    }
+}
+
+/// passwords too short for zxcvbn to judge are always `VeryWeak`
+pub fn password_strength(pass: &str) -> Strength {
+    if pass.chars().count() < 4 {
+        return Strength::VeryWeak;
+    }
+    match zxcvbn::zxcvbn(pass, &[]).map(|entropy| entropy.score()) {
+        Ok(0) | Err(_) => Strength::VeryWeak,
+        Ok(1) => Strength::Weak,
+        Ok(2) => Strength::Fair,
+        Ok(3) => Strength::Strong,
+        Ok(_) => Strength::VeryStrong,
+    }
+}
+
+/// `MAGIC_V2` | kdf (`Kdf::LEN`) | salt (16) | nonce (12) | encrypted data,
+/// or `MAGIC_V3` | kdf | flags (1) | salt | nonce | data for sources with flags
+/// and compressed data.
+/// older files are either `MAGIC_V1` | iterations (u32 little endian) | salt | nonce | data
+/// or just salt | nonce | data.
+struct Header<'file> {
+    kdf: Kdf,
+    flags: u8,
+    salt: &'file [u8],
+    nonce: &'file [u8],
+    data: &'file [u8],
+}
+
+impl<'file> Header<'file> {
+    fn new(kdf: Kdf, rest: &'file [u8]) -> Option<Self> {
+        match rest.len() >= 28 {
+            true => Some(Header {
+                kdf,
+                flags: 0,
+                salt: &rest[..16],
+                nonce: &rest[16..28],
+                data: &rest[28..],
+            }),
+            false => None,
+        }
+    }
+}
+
+/// every way `file` can be read. a legacy file whose salt happens to start with a magic
+/// is still readable because the legacy layout is always tried last.
+fn headers(file: &[u8]) -> Vec<Header<'_>> {
+    let v3 = file.strip_prefix(MAGIC_V3).and_then(|rest| {
+        let kdf = Kdf::from_bytes(rest.get(..Kdf::LEN)?)?;
+        let flags = *rest.get(Kdf::LEN)?;
+        let header = Header::new(kdf, &rest[Kdf::LEN + 1..])?;
+        Some(Header { flags, ..header })
+    });
+
+    let v2 = file.strip_prefix(MAGIC_V2).and_then(|rest| {
+        let kdf = Kdf::from_bytes(rest.get(..Kdf::LEN)?)?;
+        Header::new(kdf, &rest[Kdf::LEN..])
+    });
+
+    let v1 = file.strip_prefix(MAGIC_V1).and_then(|rest| {
+        let iterations = u32::from_le_bytes(rest.get(..4)?.try_into().ok()?);
+        let kdf = Kdf::Pbkdf2 {
+            iterations: NonZeroU32::new(iterations)?,
+        };
+        Header::new(kdf, &rest[4..])
+    });
+
+    let legacy = Header::new(
+        Kdf::Pbkdf2 {
+            iterations: NonZeroU32::new(DEFAULT_ITERATIONS).unwrap(),
+        },
+        file,
+    );
+
+    [v3, v2, v1, legacy].into_iter().flatten().collect()
+}
+
+/// whether the existing file at `fpath` can only be opened with a key file
+pub fn needs_key_file<P: AsRef<Path>>(fpath: P) -> bool {
+    std::fs::read(fpath)
+        .ok()
+        .and_then(|file| {
+            let header = headers(&file).into_iter().next()?;
+            Some(header.flags & FLAG_KEY_FILE != 0)
+        })
+        .unwrap_or(false)
+}
+
+/// `kdf` is only used if the file doesn't exist yet.
+/// existing files are decrypted with the kdf in their header, which is returned with the store.
+/// refuses vaults saved by versions that need a newer one than this to read all of them.
+/// saving them here would lose what this version doesn't know of
+pub fn load<P: AsRef<Path>>(
+    fpath: P,
+    source: &dyn KeySource,
+    kdf: Kdf,
+) -> anyhow::Result<(Store, Kdf)> {
+    let (store, kdf) = load_any_version(&fpath, source, kdf)?;
+    if let Some(required) = store.newer_reader_needed() {
+        anyhow::bail!(
+            "'{}' needs royalguard {} or newer to be opened without losing some of it. this is {}. update, or open it with --read-only",
+            fpath.as_ref().display(),
+            required,
+            env!("CARGO_PKG_VERSION")
+        );
+    }
+    Ok((store, kdf))
+}
+
+/// like `load`, also for vaults that need a newer version. only for reading them
+pub fn load_any_version<P: AsRef<Path>>(
+    fpath: P,
+    source: &dyn KeySource,
+    kdf: Kdf,
+) -> anyhow::Result<(Store, Kdf)> {
+    create_new_file_if_not_exists(&fpath, source, kdf)?;
     let encrypted_file = std::fs::read(&fpath)?;
-    let salt = &encrypted_file[..16];
-    let cipher = get_cipher(master_pass, salt);
-    let nonce = &encrypted_file[16..28];
-    let plain_text = serde_json::to_string(store)?;
-    let encrypted_text = cipher
-        .encrypt(nonce.into(), plain_text.as_ref())
-        .map_err(|_| anyhow::anyhow!("Failed to encrypt passwords."))?;
-    let mut content = salt.to_vec();
-    content.extend(nonce);
-    content.extend(encrypted_text);
-    std::fs::write(&fpath, content)?;
+    if let Some(header) = headers(&encrypted_file).first() {
+        header
+            .kdf
+            .check()
+            .with_context(|| format!("'{}' can't be opened", fpath.as_ref().display()))?;
+    }
+    let (plain_text, kdf) = match unseal(&encrypted_file, source) {
+        Some(unsealed) => unsealed,
+        None => match (needs_key_file(&fpath), source.flags() & FLAG_KEY_FILE != 0) {
+            (true, false) => anyhow::bail!(
+                "'{}' can only be opened with its key file",
+                fpath.as_ref().display()
+            ),
+            (true, true) => anyhow::bail!("Master password or key file incorrect."),
+            (false, _) => anyhow::bail!("Master password incorrect."),
+        },
+    };
+    // the whole vault in plain text, overwritten as soon as it is parsed
+    let plain_text = Zeroizing::new(plain_text);
+    let plain_text = std::str::from_utf8(&plain_text)?;
+    Ok((serde_json::from_str::<Store>(plain_text)?, kdf))
+}
+
+/// always writes the latest header with a fresh salt and nonce,
+/// so older files are upgraded on their first save.
+pub fn dump<P: AsRef<Path>>(
+    fpath: P,
+    source: &dyn KeySource,
+    kdf: Kdf,
+    compression: Compression,
+    store: &Store,
+) -> anyhow::Result<()> {
+    let plain_text = Zeroizing::new(serde_json::to_string(store)?);
+    let content = seal(&plain_text, source, kdf, compression)?;
+    write_atomic(fpath, |file| file.write_all(&content))
+}
+
+/// `contents` encrypted with a key derived from `source` behind the latest header.
+/// compressed contents are only readable by versions that know of `FLAG_GZIP`
+pub fn seal(
+    contents: &str,
+    source: &dyn KeySource,
+    kdf: Kdf,
+    compression: Compression,
+) -> anyhow::Result<Vec<u8>> {
+    let key_flags = source.flags();
+    let secret = source
+        .secret(key_flags)
+        .context("the key source can't open what it seals")?;
+    let (contents, flags) = match compression.applies(contents.len()) {
+        true => (gzip(contents.as_bytes())?, key_flags | FLAG_GZIP),
+        false => (Zeroizing::new(contents.as_bytes().to_vec()), key_flags),
+    };
+    let salt = get_random_salt()?;
+    let (encrypted_text, nonce) = encrypt_contents(&contents, &secret, &salt, kdf)?;
+    let mut sealed = match flags {
+        0 => MAGIC_V2.to_vec(),
+        _ => MAGIC_V3.to_vec(),
+    };
+    sealed.extend(kdf.to_bytes());
+    if flags != 0 {
+        sealed.push(flags);
+    }
+    sealed.extend(salt);
+    sealed.extend(nonce);
+    sealed.extend(encrypted_text);
+    Ok(sealed)
+}
+
+/// the decrypted contents of anything `seal`ed with any of the known headers
+/// and the kdf it was sealed with. `None` if `source` is wrong or can't open it
+/// or `sealed` was tampered with.
+pub fn unseal(sealed: &[u8], source: &dyn KeySource) -> Option<(Vec<u8>, Kdf)> {
+    headers(sealed).into_iter().find_map(|header| {
+        let secret = source.secret(header.flags & !FLAG_GZIP)?;
+        let contents = get_cipher(&secret, header.salt, header.kdf)
+            .ok()?
+            .decrypt(header.nonce.into(), header.data)
+            .ok()?;
+        match header.flags & FLAG_GZIP != 0 {
+            true => Some((gunzip(&Zeroizing::new(contents))?, header.kdf)),
+            false => Some((contents, header.kdf)),
+        }
+    })
+}
+
+fn gzip(contents: &[u8]) -> anyhow::Result<Zeroizing<Vec<u8>>> {
+    // room for the gzip header and incompressible contents, so that growing it leaves no copies
+    let mut encoder = flate2::write::GzEncoder::new(
+        Vec::with_capacity(contents.len() + 64),
+        flate2::Compression::default(),
+    );
+    encoder.write_all(contents)?;
+    Ok(Zeroizing::new(encoder.finish()?))
+}
+
+/// `None` if `compressed` isn't valid gzip, which can't happen to anything that decrypted
+fn gunzip(compressed: &[u8]) -> Option<Vec<u8>> {
+    let mut contents = Vec::with_capacity(compressed.len() * 4);
+    flate2::read::GzDecoder::new(compressed)
+        .read_to_end(&mut contents)
+        .ok()?;
+    Some(contents)
+}
+
+/// copies the file at `fpath` as it is (still encrypted) to `<fpath>.bak.<utc timestamp>`
+/// and deletes the oldest of those beyond the newest `keep`. nothing is done if `keep`
+/// is 0 or there is no file yet. returns the path of the new backup
+pub fn backup<P: AsRef<Path>>(fpath: P, keep: usize) -> anyhow::Result<Option<PathBuf>> {
+    let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ").to_string();
+    backup_as(fpath.as_ref(), keep, &stamp)
+}
+
+/// the timestamps sort the same as the strings, so the names tell which backups are newest
+fn backup_as(fpath: &Path, keep: usize, stamp: &str) -> anyhow::Result<Option<PathBuf>> {
+    if keep == 0 || !fpath.is_file() {
+        return Ok(None);
+    }
+    let fname = fpath
+        .file_name()
+        .with_context(|| format!("'{}' is not a file path", fpath.display()))?;
+    let prefix = format!("{}.bak.", fname.to_string_lossy());
+
+    let backup = fpath.with_file_name(format!("{}{}", prefix, stamp));
+    std::fs::copy(fpath, &backup)
+        .with_context(|| format!("unable to back up to '{}'", backup.display()))?;
+
+    let dir = match fpath.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut backups = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name.starts_with(&prefix))
+        .collect::<Vec<_>>();
+    backups.sort();
+    for old in &backups[..backups.len().saturating_sub(keep)] {
+        std::fs::remove_file(fpath.with_file_name(old))?;
+    }
+
+    Ok(Some(backup))
+}
+
+/// a new file that only the user can read and write. one that already exists is left alone
+pub fn create_private(fpath: &Path) -> std::io::Result<File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(fpath)
+}
+
+/// `write`s to a temporary file next to `fpath` and renames it over `fpath` once it
+/// is synced to disk, so a crash or a failed write leaves the previous file as it was.
+/// the file is only readable by the user afterwards. if `fpath` is a symlink,
+/// the file it points to is replaced and the symlink is kept
+fn write_atomic<P: AsRef<Path>>(
+    fpath: P,
+    write: impl FnOnce(&mut File) -> std::io::Result<()>,
+) -> anyhow::Result<()> {
+    let fpath = match std::fs::canonicalize(fpath.as_ref()) {
+        Ok(target) => target,
+        Err(_) => fpath.as_ref().to_path_buf(),
+    };
+    let fpath = fpath.as_path();
+    let fname = fpath
+        .file_name()
+        .with_context(|| format!("'{}' is not a file path", fpath.display()))?;
+    let tmp = fpath.with_file_name(format!(".{}.tmp", fname.to_string_lossy()));
+
+    // left over from a crash, and maybe made with other permissions
+    std::fs::remove_file(&tmp).ignore();
+    let written = create_private(&tmp)
+        .and_then(|mut file| {
+            write(&mut file)?;
+            file.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&tmp, fpath));
+
+    if let Err(e) = written {
+        std::fs::remove_file(&tmp).ignore();
+        return Err(e.into());
+    }
+
+    // the rename itself is only durable once the directory is synced
+    #[cfg(unix)]
+    if let Some(dir) = fpath.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        File::open(dir)?.sync_all()?;
+    }
+
     Ok(())
 }
 
 fn create_new_file_if_not_exists<P: AsRef<Path>>(
     fpath: P,
-    master_pass: &str,
+    source: &dyn KeySource,
+    kdf: Kdf,
 ) -> anyhow::Result<()> {
     if !fpath.as_ref().exists() {
-        let salt = get_random_salt()?;
-        let (empty_json, nonce) =
-            encrypt_contents(&serde_json::to_string(&Store::new())?, master_pass, &salt)?;
-        let mut content = salt.to_vec();
-        content.extend(nonce);
-        content.extend(empty_json);
-        std::fs::write(&fpath, content)?;
+        dump(fpath, source, kdf, Compression::Auto, &Store::new())?;
     }
     Ok(())
 }
@@ -69,33 +610,678 @@ fn get_random_salt() -> anyhow::Result<[u8; 16]> {
     Ok(salt)
 }
 
-fn derive_encryption_key(master_password: &str, salt: &[u8]) -> [u8; 32] {
+fn derive_encryption_key(secret: &[u8], salt: &[u8], kdf: Kdf) -> anyhow::Result<[u8; 32]> {
     let mut enc_key: [u8; 32] = [0u8; 32];
-    pbkdf2::derive(
-        pbkdf2::PBKDF2_HMAC_SHA256,
-        NonZeroU32::new(100_000).unwrap(),
-        salt,
-        master_password.as_bytes(),
-        &mut enc_key,
-    );
-    enc_key
+    match kdf.check()? {
+        Kdf::Pbkdf2 { iterations } => pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            salt,
+            secret,
+            &mut enc_key,
+        ),
+        Kdf::Argon2id {
+            m_cost,
+            t_cost,
+            p_cost,
+        } => {
+            let params = argon2::Params::new(m_cost, t_cost, p_cost, Some(enc_key.len()))
+                .map_err(|e| anyhow::anyhow!("Invalid Argon2 parameters. {}", e))?;
+            argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+                .hash_password_into(secret, salt, &mut enc_key)
+                .map_err(|e| anyhow::anyhow!("Key Derivation Error. {}", e))?;
+        }
+    }
+    Ok(enc_key)
 }
 
-fn get_cipher(master_password: &str, salt: &[u8]) -> Aes256Gcm {
-    let enc_key = derive_encryption_key(master_password, salt);
+fn get_cipher(secret: &[u8], salt: &[u8], kdf: Kdf) -> anyhow::Result<Aes256Gcm> {
+    let enc_key = derive_encryption_key(secret, salt, kdf)?;
     let cipher = Aes256Gcm::new(GenericArray::from_slice(&enc_key));
-    cipher
+    Ok(cipher)
 }
 
 fn encrypt_contents(
-    contents: &str,
-    master_password: &str,
+    contents: &[u8],
+    secret: &[u8],
     salt: &[u8],
+    kdf: Kdf,
 ) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
-    let cipher = get_cipher(master_password, salt);
+    let cipher = get_cipher(secret, salt, kdf)?;
     let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
     let encrypted_text = cipher
-        .encrypt(&nonce, contents.as_ref())
+        .encrypt(&nonce, contents)
         .map_err(|_| anyhow::anyhow!("Failed to encrypt passwords."))?;
     Ok((encrypted_text, nonce.to_vec()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::Assign;
+    use crate::store::MIN_READER_VERSION;
+    use pretty_assertions::assert_eq;
+
+    fn pbkdf2(iterations: u32) -> Kdf {
+        Kdf::Pbkdf2 {
+            iterations: NonZeroU32::new(iterations).unwrap(),
+        }
+    }
+
+    fn store() -> Store {
+        let mut store = Store::new();
+        store.set("gmail", None, vec![]);
+        store
+    }
+
+    #[test]
+    fn test_dump_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("royalguard");
+
+        let mut store = Store::new();
+        store.set(
+            "gmail",
+            None,
+            vec![
+                Assign {
+                    attr: "user",
+                    value: "zahash",
+                    sensitive: false,
+                    expires: None,
+                    kind: None,
+                },
+                Assign {
+                    attr: "pass",
+                    value: "ни шагу назад",
+                    sensitive: true,
+                    expires: None,
+                    kind: None,
+                },
+            ],
+        );
+        store.set("discord", None, vec![]);
+
+        dump(
+            &fpath,
+            &Password("master"),
+            pbkdf2(DEFAULT_ITERATIONS),
+            Compression::Auto,
+            &store,
+        )
+        .unwrap();
+        let (loaded, kdf) = load(&fpath, &Password("master"), pbkdf2(DEFAULT_ITERATIONS)).unwrap();
+
+        assert_eq!(kdf, pbkdf2(DEFAULT_ITERATIONS));
+        assert_eq!(
+            serde_json::to_vec(&loaded).unwrap(),
+            serde_json::to_vec(&store).unwrap()
+        );
+        assert!(loaded.contains("gmail") && loaded.contains("discord"));
+        assert!(load(&fpath, &Password("wrong"), pbkdf2(DEFAULT_ITERATIONS)).is_err());
+    }
+
+    #[test]
+    fn test_failed_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("royalguard");
+
+        dump(
+            &fpath,
+            &Password("master"),
+            pbkdf2(DEFAULT_ITERATIONS),
+            Compression::Auto,
+            &store(),
+        )
+        .unwrap();
+        let before = std::fs::read(&fpath).unwrap();
+
+        let written = write_atomic(&fpath, |file| {
+            file.write_all(&before[..before.len() / 2])?;
+            Err(std::io::Error::other("disk full"))
+        });
+
+        assert!(written.is_err());
+        assert_eq!(std::fs::read(&fpath).unwrap(), before);
+        // the temporary file is cleaned up
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+        assert!(
+            load(&fpath, &Password("master"), pbkdf2(DEFAULT_ITERATIONS))
+                .unwrap()
+                .0
+                .contains("gmail")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path().join("vault");
+        let link = dir.path().join("royalguard");
+        std::fs::write(&vault, "old").unwrap();
+        std::fs::set_permissions(&vault, std::fs::Permissions::from_mode(0o644)).unwrap();
+        std::os::unix::fs::symlink(&vault, &link).unwrap();
+
+        write_atomic(&link, |file| file.write_all(b"new")).unwrap();
+
+        assert!(std::fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(std::fs::read(&vault).unwrap(), b"new");
+        let mode = std::fs::metadata(&vault).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("royalguard");
+        let backups = || {
+            let mut backups = std::fs::read_dir(dir.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+                .filter(|name| name != "royalguard")
+                .collect::<Vec<_>>();
+            backups.sort();
+            backups
+        };
+
+        // nothing to back up yet
+        assert_eq!(backup(&fpath, 3).unwrap(), None);
+
+        let stamps = [
+            "20240101T000000.000Z",
+            "20240102T000000.000Z",
+            "20240103T000000.000Z",
+            "20240104T000000.000Z",
+            "20240105T000000.000Z",
+        ];
+        for (i, stamp) in stamps.into_iter().enumerate() {
+            std::fs::write(&fpath, format!("v{}", i + 1)).unwrap();
+            let backup = backup_as(&fpath, 3, stamp).unwrap().unwrap();
+            assert_eq!(
+                std::fs::read_to_string(backup).unwrap(),
+                format!("v{}", i + 1)
+            );
+        }
+
+        assert_eq!(
+            backups(),
+            [
+                "royalguard.bak.20240103T000000.000Z",
+                "royalguard.bak.20240104T000000.000Z",
+                "royalguard.bak.20240105T000000.000Z"
+            ]
+        );
+        let contents = backups()
+            .iter()
+            .map(|name| std::fs::read_to_string(dir.path().join(name)).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(contents, ["v3", "v4", "v5"]);
+
+        // 0 disables them, without touching the ones there are
+        assert_eq!(backup(&fpath, 0).unwrap(), None);
+        assert_eq!(backups().len(), 3);
+
+        // a real timestamp sorts after the ones above and pushes out the oldest
+        let newest = backup(&fpath, 3).unwrap().unwrap();
+        assert_eq!(backups().len(), 3);
+        assert_eq!(
+            backups().last().map(|name| dir.path().join(name)),
+            Some(newest)
+        );
+    }
+
+    #[test]
+    fn test_iterations() {
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("royalguard");
+
+        dump(
+            &fpath,
+            &Password("master"),
+            pbkdf2(200_000),
+            Compression::Auto,
+            &store(),
+        )
+        .unwrap();
+
+        let file = std::fs::read(&fpath).unwrap();
+        assert_eq!(&file[..4], MAGIC_V2);
+        assert_eq!(
+            Kdf::from_bytes(&file[4..4 + Kdf::LEN]),
+            Some(pbkdf2(200_000))
+        );
+
+        // the kdf of an existing file comes from its header
+        let (loaded, kdf) = load(&fpath, &Password("master"), pbkdf2(DEFAULT_ITERATIONS)).unwrap();
+        assert!(loaded.contains("gmail"));
+        assert_eq!(kdf, pbkdf2(200_000));
+        assert!(load(&fpath, &Password("wrong"), pbkdf2(200_000)).is_err());
+    }
+
+    #[test]
+    fn test_argon2id() {
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("royalguard");
+
+        dump(
+            &fpath,
+            &Password("master"),
+            pbkdf2(DEFAULT_ITERATIONS),
+            Compression::Auto,
+            &store(),
+        )
+        .unwrap();
+        let (loaded, kdf) = load(&fpath, &Password("master"), Kdf::argon2id()).unwrap();
+        assert_eq!(kdf, pbkdf2(DEFAULT_ITERATIONS));
+
+        // migrate by dumping again with the other kdf
+        dump(
+            &fpath,
+            &Password("master"),
+            Kdf::argon2id(),
+            Compression::Auto,
+            &loaded,
+        )
+        .unwrap();
+
+        let file = std::fs::read(&fpath).unwrap();
+        assert_eq!(&file[..4], MAGIC_V2);
+        assert_eq!(
+            Kdf::from_bytes(&file[4..4 + Kdf::LEN]),
+            Some(Kdf::argon2id())
+        );
+
+        let (loaded, kdf) = load(&fpath, &Password("master"), pbkdf2(DEFAULT_ITERATIONS)).unwrap();
+        assert!(loaded.contains("gmail"));
+        assert_eq!(kdf, Kdf::argon2id());
+        assert!(load(&fpath, &Password("wrong"), Kdf::argon2id()).is_err());
+    }
+
+    #[test]
+    fn test_argon2id_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("royalguard");
+        let greedy = Kdf::Argon2id {
+            m_cost: u32::MAX,
+            t_cost: 1,
+            p_cost: 1,
+        };
+        assert!(greedy.check().is_err());
+        assert!(Kdf::argon2id().check().is_ok());
+
+        // a header that asks for too much is refused before anything is derived
+        let sealed = seal("{}", &Password("master"), pbkdf2(1), Compression::Never).unwrap();
+        let mut crafted = MAGIC_V2.to_vec();
+        crafted.extend(greedy.to_bytes());
+        crafted.extend(&sealed[4 + Kdf::LEN..]);
+        std::fs::write(&fpath, crafted).unwrap();
+
+        let e = load(&fpath, &Password("master"), pbkdf2(1)).unwrap_err();
+        assert!(format!("{:#}", e).contains("is more than the most allowed"));
+        assert!(dump(
+            &fpath,
+            &Password("master"),
+            greedy,
+            Compression::Never,
+            &store()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_password_strength() {
+        assert_eq!(password_strength(""), Strength::VeryWeak);
+        assert_eq!(password_strength("a1!"), Strength::VeryWeak);
+        assert_eq!(password_strength("password"), Strength::VeryWeak);
+        assert!(password_strength("q8#Vz!r2Lp@w9Xk$uT4m") >= Strength::ADVISED);
+        assert_eq!(Strength::Weak.to_string(), "1/4 (weak)");
+    }
+
+    #[test]
+    fn test_key_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("royalguard");
+        let kdf = pbkdf2(1);
+        let with_key = PasswordAndKeyFile {
+            pass: "master",
+            key_file: b"key",
+        };
+        let err = |pass: &str, key_file: Option<&[u8]>| {
+            load(&fpath, &*key_source(pass, key_file), kdf)
+                .unwrap_err()
+                .to_string()
+        };
+
+        dump(
+            &fpath,
+            &Password("master"),
+            kdf,
+            Compression::Auto,
+            &store(),
+        )
+        .unwrap();
+        assert!(!needs_key_file(&fpath));
+        // ignored by files that don't need one
+        assert!(load(&fpath, &with_key, kdf).is_ok());
+        assert_eq!(err("wrong", Some(b"key")), "Master password incorrect.");
+
+        dump(&fpath, &with_key, kdf, Compression::Auto, &store()).unwrap();
+        let file = std::fs::read(&fpath).unwrap();
+        assert_eq!(&file[..4], MAGIC_V3);
+        assert_eq!(file[4 + Kdf::LEN], FLAG_KEY_FILE);
+        assert!(needs_key_file(&fpath));
+
+        let (loaded, _) = load(&fpath, &with_key, kdf).unwrap();
+        assert!(loaded.contains("gmail"));
+        assert_eq!(
+            err("master", None),
+            format!("'{}' can only be opened with its key file", fpath.display())
+        );
+        assert_eq!(
+            err("master", Some(b"other")),
+            "Master password or key file incorrect."
+        );
+        assert_eq!(
+            err("wrong", Some(b"key")),
+            "Master password or key file incorrect."
+        );
+
+        // new files are written with it
+        let fpath = dir.path().join("new");
+        load(&fpath, &with_key, kdf).unwrap();
+        assert!(needs_key_file(&fpath));
+    }
+
+    #[test]
+    fn test_key_sources() {
+        let with_key = PasswordAndKeyFile {
+            pass: "master",
+            key_file: b"key",
+        };
+        let keyring = MemoryKeyring::default();
+        keyring.set("vault", "master").unwrap();
+        let cached = KeyringCached {
+            keyring: &keyring,
+            fpath: "vault",
+            key_file: Some(b"key"),
+        };
+        let sources: [&dyn KeySource; 3] = [&Password("master"), &with_key, &cached];
+
+        for source in sources {
+            for kdf in [pbkdf2(1), Kdf::argon2id()] {
+                let sealed = seal("contents", source, kdf, Compression::Never).unwrap();
+                match source.flags() {
+                    0 => assert_eq!(&sealed[..4], MAGIC_V2),
+                    flags => {
+                        assert_eq!(&sealed[..4], MAGIC_V3);
+                        assert_eq!(sealed[4 + Kdf::LEN], flags);
+                    }
+                }
+                assert_eq!(unseal(&sealed, source), Some((b"contents".to_vec(), kdf)));
+            }
+        }
+
+        // a password alone can't open what needs a key file
+        let sealed = seal("contents", &with_key, pbkdf2(1), Compression::Never).unwrap();
+        assert_eq!(unseal(&sealed, &Password("master")), None);
+
+        // nor can anything open a file that needs a source it doesn't know of
+        let mut sealed = seal("contents", &with_key, pbkdf2(1), Compression::Never).unwrap();
+        sealed[4 + Kdf::LEN] |= 0b100;
+        assert_eq!(unseal(&sealed, &with_key), None);
+    }
+
+    #[derive(Default)]
+    struct MemoryKeyring(std::cell::RefCell<std::collections::HashMap<String, String>>);
+
+    impl Keyring for MemoryKeyring {
+        fn get(&self, fpath: &str) -> Option<Zeroizing<String>> {
+            self.0.borrow().get(fpath).cloned().map(Zeroizing::new)
+        }
+
+        fn set(&self, fpath: &str, pass: &str) -> anyhow::Result<()> {
+            self.0.borrow_mut().insert(fpath.into(), pass.into());
+            Ok(())
+        }
+
+        fn forget(&self, fpath: &str) -> anyhow::Result<()> {
+            self.0.borrow_mut().remove(fpath);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_keyring_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("royalguard");
+        let kdf = pbkdf2(1);
+        let keyring = MemoryKeyring::default();
+        let cached = KeyringCached {
+            keyring: &keyring,
+            fpath: "vault",
+            key_file: None,
+        };
+
+        dump(
+            &fpath,
+            &Password("master"),
+            kdf,
+            Compression::Auto,
+            &store(),
+        )
+        .unwrap();
+        // nothing kept yet
+        assert!(load(&fpath, &cached, kdf).is_err());
+
+        keyring.set("vault", "master").unwrap();
+        let (loaded, _) = load(&fpath, &cached, kdf).unwrap();
+        assert!(loaded.contains("gmail"));
+
+        // what it writes opens with the password as well
+        dump(&fpath, &cached, kdf, Compression::Auto, &store()).unwrap();
+        assert!(load(&fpath, &Password("master"), kdf).is_ok());
+
+        keyring.set("vault", "stale").unwrap();
+        assert!(load(&fpath, &cached, kdf).is_err());
+        keyring.forget("vault").unwrap();
+        assert!(load(&fpath, &cached, kdf).is_err());
+    }
+
+    /// records with users, urls, random passwords and a few changes each
+    fn realistic_store(records: usize) -> Store {
+        let mut store = Store::new();
+        for i in 0..records {
+            let name = format!("site{}", i);
+            let url = format!("https://login.site{}.example.com/account", i);
+            let user = format!("sussolini{}@gmail.com", i % 7);
+            for _ in 0..3 {
+                let pass = uuid::Uuid::new_v4().to_string();
+                store.set(
+                    &name,
+                    None,
+                    vec![
+                        Assign {
+                            attr: "url",
+                            value: &url,
+                            sensitive: false,
+                            expires: None,
+                            kind: None,
+                        },
+                        Assign {
+                            attr: "user",
+                            value: &user,
+                            sensitive: false,
+                            expires: None,
+                            kind: None,
+                        },
+                        Assign {
+                            attr: "pass",
+                            value: &pass,
+                            sensitive: true,
+                            expires: None,
+                            kind: None,
+                        },
+                    ],
+                );
+            }
+        }
+        store
+    }
+
+    #[test]
+    fn test_newer_reader_needed() {
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("royalguard");
+        let kdf = pbkdf2(1);
+        let write = |min_reader_version: &str| {
+            let contents = format!(
+                r#"{{"records":[],"version":"99.0.0","min_reader_version":"{}","history_limit":50}}"#,
+                min_reader_version
+            );
+            let sealed = seal(&contents, &Password("master"), kdf, Compression::Never).unwrap();
+            std::fs::write(&fpath, sealed).unwrap();
+        };
+
+        write(MIN_READER_VERSION);
+        assert!(load(&fpath, &Password("master"), kdf).is_ok());
+
+        write("99.0.0");
+        let e = load(&fpath, &Password("master"), kdf).unwrap_err();
+        assert!(e.to_string().contains(&format!(
+            "needs royalguard 99.0.0 or newer to be opened without losing some of it. this is {}",
+            env!("CARGO_PKG_VERSION")
+        )));
+        // still readable, for --read-only
+        let (store, _) = load_any_version(&fpath, &Password("master"), kdf).unwrap();
+        assert_eq!(store.newer_reader_needed(), Some("99.0.0"));
+    }
+
+    #[test]
+    fn test_compression() {
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("royalguard");
+        let kdf = pbkdf2(1);
+        let size = |compression: Compression, store: &Store| {
+            dump(&fpath, &Password("master"), kdf, compression, store).unwrap();
+            let (loaded, _) = load(&fpath, &Password("master"), kdf).unwrap();
+            assert_eq!(
+                serde_json::to_string(&loaded).unwrap(),
+                serde_json::to_string(store).unwrap()
+            );
+            std::fs::read(&fpath).unwrap().len()
+        };
+
+        for records in [5, 50, 500] {
+            let store = realistic_store(records);
+            let (compressed, plain) = (
+                size(Compression::Always, &store),
+                size(Compression::Never, &store),
+            );
+            assert!(
+                compressed < plain,
+                "{}: {} >= {}",
+                records,
+                compressed,
+                plain
+            );
+        }
+
+        // auto only compresses stores above the threshold
+        dump(
+            &fpath,
+            &Password("master"),
+            kdf,
+            Compression::Auto,
+            &store(),
+        )
+        .unwrap();
+        assert_eq!(&std::fs::read(&fpath).unwrap()[..4], MAGIC_V2);
+        let store = realistic_store(50);
+        assert!(serde_json::to_string(&store).unwrap().len() > COMPRESS_ABOVE);
+        dump(&fpath, &Password("master"), kdf, Compression::Auto, &store).unwrap();
+        let file = std::fs::read(&fpath).unwrap();
+        assert_eq!(&file[..4], MAGIC_V3);
+        assert_eq!(file[4 + Kdf::LEN], FLAG_GZIP);
+
+        // with a key file too
+        let with_key = PasswordAndKeyFile {
+            pass: "master",
+            key_file: b"key",
+        };
+        let sealed = seal("contents", &with_key, kdf, Compression::Always).unwrap();
+        assert_eq!(sealed[4 + Kdf::LEN], FLAG_KEY_FILE | FLAG_GZIP);
+        assert_eq!(
+            unseal(&sealed, &with_key),
+            Some((b"contents".to_vec(), kdf))
+        );
+        assert_eq!(unseal(&sealed, &Password("master")), None);
+    }
+
+    #[test]
+    fn test_old_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("royalguard");
+
+        let salt = get_random_salt().unwrap();
+        let contents = serde_json::to_string(&store()).unwrap();
+
+        let (encrypted_text, nonce) = encrypt_contents(
+            contents.as_bytes(),
+            b"master",
+            &salt,
+            pbkdf2(DEFAULT_ITERATIONS),
+        )
+        .unwrap();
+        std::fs::write(
+            &fpath,
+            [&salt[..], &nonce[..], &encrypted_text[..]].concat(),
+        )
+        .unwrap();
+        assert_eq!(
+            load(&fpath, &Password("master"), Kdf::argon2id())
+                .unwrap()
+                .1,
+            pbkdf2(DEFAULT_ITERATIONS)
+        );
+
+        let (encrypted_text, nonce) =
+            encrypt_contents(contents.as_bytes(), b"master", &salt, pbkdf2(200_000)).unwrap();
+        std::fs::write(
+            &fpath,
+            [
+                &MAGIC_V1[..],
+                &200_000u32.to_le_bytes()[..],
+                &salt[..],
+                &nonce[..],
+                &encrypted_text[..],
+            ]
+            .concat(),
+        )
+        .unwrap();
+        let (loaded, kdf) = load(&fpath, &Password("master"), Kdf::argon2id()).unwrap();
+        assert!(loaded.contains("gmail"));
+        assert_eq!(kdf, pbkdf2(200_000));
+
+        dump(
+            &fpath,
+            &Password("master"),
+            pbkdf2(300_000),
+            Compression::Auto,
+            &loaded,
+        )
+        .unwrap();
+        assert_eq!(&std::fs::read(&fpath).unwrap()[..4], MAGIC_V2);
+        assert_eq!(
+            load(&fpath, &Password("master"), pbkdf2(DEFAULT_ITERATIONS))
+                .unwrap()
+                .1,
+            pbkdf2(300_000)
+        );
+    }
+}
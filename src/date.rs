@@ -0,0 +1,380 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, Local, Months, NaiveDate, NaiveTime, TimeZone};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::store::Clock;
+
+/// what every command that takes a date or a length of time accepts, for its errors
+const DATE_FORMS: &str =
+    "2024-03-04, 2024-03-04T09:30:00+01:00, today, yesterday, 7d or '3 months ago'";
+const SPAN_FORMS: &str = "90 (days), 90d, 12w, 6m or 1y";
+
+lazy_static! {
+    static ref SPAN_REGEX: Regex =
+        Regex::new(r"^(\d+) ?(d|days?|w|weeks?|m|months?|y|years?)?$").unwrap();
+    static ref DAY_REGEX: Regex = Regex::new(r"^\d{4}-\d{2}-\d{2}$").unwrap();
+    /// 03/04/2024 is the 3rd of april in some places and the 4th of march in others
+    static ref AMBIGUOUS_REGEX: Regex = Regex::new(r"^\d{1,2}[/.-]\d{1,2}[/.-]\d{2,4}$").unwrap();
+}
+
+/// a length of time. months and years are calendar months and years, not a number of days
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Span {
+    Days(u32),
+    Weeks(u32),
+    Months(u32),
+    Years(u32),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DateError<'text> {
+    Ambiguous(&'text str),
+    NotADate(&'text str),
+    NotASpan(&'text str),
+    OutOfRange(&'text str),
+}
+
+impl Span {
+    /// `at` moved forward by the span. a month or year later is the same day of the month,
+    /// or the last day of a shorter month (a year after 2024-02-29 is 2025-02-28)
+    pub fn after<Tz: TimeZone>(self, at: DateTime<Tz>) -> Option<DateTime<Tz>> {
+        match self {
+            Span::Days(days) => at.checked_add_signed(chrono::Duration::days(days.into())),
+            Span::Weeks(weeks) => at.checked_add_signed(chrono::Duration::weeks(weeks.into())),
+            Span::Months(months) => at.checked_add_months(Months::new(months)),
+            Span::Years(years) => at.checked_add_months(Months::new(years.checked_mul(12)?)),
+        }
+    }
+
+    /// `at` moved back by the span, the other way around from `after`
+    pub fn before<Tz: TimeZone>(self, at: DateTime<Tz>) -> Option<DateTime<Tz>> {
+        match self {
+            Span::Days(days) => at.checked_sub_signed(chrono::Duration::days(days.into())),
+            Span::Weeks(weeks) => at.checked_sub_signed(chrono::Duration::weeks(weeks.into())),
+            Span::Months(months) => at.checked_sub_months(Months::new(months)),
+            Span::Years(years) => at.checked_sub_months(Months::new(years.checked_mul(12)?)),
+        }
+    }
+}
+
+/// `90` (days), `90d`, `12w`, `6m`, `1y` or spelled out like `3 months`. never 0
+pub fn parse_span(text: &str) -> Result<Span, DateError<'_>> {
+    let caps = SPAN_REGEX
+        .captures(text.trim())
+        .ok_or(DateError::NotASpan(text))?;
+    let n = match caps[1].parse::<u32>() {
+        Ok(0) => return Err(DateError::NotASpan(text)),
+        Ok(n) => n,
+        Err(_) => return Err(DateError::OutOfRange(text)),
+    };
+    Ok(match caps.get(2).map(|unit| &unit.as_str()[..1]) {
+        Some("w") => Span::Weeks(n),
+        Some("m") => Span::Months(n),
+        Some("y") => Span::Years(n),
+        _ => Span::Days(n),
+    })
+}
+
+/// a date as of the time of `clock`. see `parse_date_at`
+pub fn parse_date<'text>(
+    text: &'text str,
+    clock: &dyn Clock,
+) -> Result<DateTime<Local>, DateError<'text>> {
+    parse_date_at(text, clock.now())
+}
+
+/// `2024-03-04` (the start of that day where `now` is), RFC 3339, `today`, `yesterday`,
+/// or a span back from `now` like `7d` and `3 months ago`. a bare number isn't a date
+pub fn parse_date_at<Tz: TimeZone>(
+    text: &str,
+    now: DateTime<Tz>,
+) -> Result<DateTime<Tz>, DateError<'_>> {
+    let trimmed = text.trim();
+    let out_of_range = || DateError::OutOfRange(text);
+    let tz = now.timezone();
+
+    match trimmed {
+        "today" => return start_of_day(now.date_naive(), &tz).ok_or_else(out_of_range),
+        "yesterday" => {
+            let yesterday = now.date_naive().pred_opt().ok_or_else(out_of_range)?;
+            return start_of_day(yesterday, &tz).ok_or_else(out_of_range);
+        }
+        _ => {}
+    }
+    if let Some(span) = trimmed.strip_suffix("ago") {
+        let span = parse_span(span).map_err(|_| DateError::NotADate(text))?;
+        return span.before(now).ok_or_else(out_of_range);
+    }
+    if DAY_REGEX.is_match(trimmed) {
+        let day = NaiveDate::parse_from_str(trimmed, "%Y-%m-%d")
+            .map_err(|_| DateError::NotADate(text))?;
+        return start_of_day(day, &tz).ok_or_else(out_of_range);
+    }
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(trimmed) {
+        return Ok(datetime.with_timezone(&tz));
+    }
+    if !trimmed.chars().all(|c| c.is_ascii_digit()) {
+        if let Ok(span) = parse_span(trimmed) {
+            return span.before(now).ok_or_else(out_of_range);
+        }
+    }
+
+    match AMBIGUOUS_REGEX.is_match(trimmed) {
+        true => Err(DateError::Ambiguous(text)),
+        false => Err(DateError::NotADate(text)),
+    }
+}
+
+/// midnight, or the first moment of the day where clocks skip midnight
+fn start_of_day<Tz: TimeZone>(day: NaiveDate, tz: &Tz) -> Option<DateTime<Tz>> {
+    let midnight = day.and_time(NaiveTime::MIN);
+    (0..=2)
+        .map(|hours| midnight + chrono::Duration::hours(hours))
+        .find_map(|time| tz.from_local_datetime(&time).earliest())
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Span::Days(days) => write!(f, "{}", days),
+            Span::Weeks(weeks) => write!(f, "{}w", weeks),
+            Span::Months(months) => write!(f, "{}m", months),
+            Span::Years(years) => write!(f, "{}y", years),
+        }
+    }
+}
+
+impl<'text> Display for DateError<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DateError::Ambiguous(text) => write!(
+                f,
+                "'{}' could be day/month or month/day. write dates like {}",
+                text, DATE_FORMS
+            ),
+            DateError::NotADate(text) => {
+                write!(
+                    f,
+                    "'{}' is not a date. write dates like {}",
+                    text, DATE_FORMS
+                )
+            }
+            DateError::NotASpan(text) => write!(
+                f,
+                "'{}' is not a length of time. write lengths like {}",
+                text, SPAN_FORMS
+            ),
+            DateError::OutOfRange(text) => write!(f, "'{}' is too far away", text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, Utc};
+    use pretty_assertions::assert_eq;
+
+    fn at(rfc3339: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap()
+    }
+
+    #[test]
+    fn test_parse_span() {
+        assert_eq!(parse_span("90"), Ok(Span::Days(90)));
+        assert_eq!(parse_span("90d"), Ok(Span::Days(90)));
+        assert_eq!(parse_span("1 day"), Ok(Span::Days(1)));
+        assert_eq!(parse_span("12w"), Ok(Span::Weeks(12)));
+        assert_eq!(parse_span("2 weeks"), Ok(Span::Weeks(2)));
+        assert_eq!(parse_span("6m"), Ok(Span::Months(6)));
+        assert_eq!(parse_span("3 months"), Ok(Span::Months(3)));
+        assert_eq!(parse_span("1y"), Ok(Span::Years(1)));
+        assert_eq!(parse_span("2years"), Ok(Span::Years(2)));
+
+        assert_eq!(parse_span("0"), Err(DateError::NotASpan("0")));
+        assert_eq!(parse_span("0d"), Err(DateError::NotASpan("0d")));
+        assert_eq!(parse_span("-5"), Err(DateError::NotASpan("-5")));
+        assert_eq!(parse_span("5x"), Err(DateError::NotASpan("5x")));
+        assert_eq!(parse_span("d"), Err(DateError::NotASpan("d")));
+        assert_eq!(parse_span(""), Err(DateError::NotASpan("")));
+        assert_eq!(
+            parse_span("99999999999"),
+            Err(DateError::OutOfRange("99999999999"))
+        );
+    }
+
+    #[test]
+    fn test_span_after_before() {
+        let now = at("2024-01-31T10:00:00+00:00");
+        assert_eq!(
+            Span::Days(1).after(now),
+            Some(at("2024-02-01T10:00:00+00:00"))
+        );
+        assert_eq!(
+            Span::Weeks(1).before(now),
+            Some(at("2024-01-24T10:00:00+00:00"))
+        );
+        // the end of a shorter month
+        assert_eq!(
+            Span::Months(1).after(now),
+            Some(at("2024-02-29T10:00:00+00:00"))
+        );
+        assert_eq!(
+            Span::Months(1).before(at("2024-03-31T10:00:00+00:00")),
+            Some(at("2024-02-29T10:00:00+00:00"))
+        );
+
+        // leap days
+        let leap = at("2024-02-29T12:00:00+00:00");
+        assert_eq!(
+            Span::Years(1).after(leap),
+            Some(at("2025-02-28T12:00:00+00:00"))
+        );
+        assert_eq!(
+            Span::Years(4).after(leap),
+            Some(at("2028-02-29T12:00:00+00:00"))
+        );
+        assert_eq!(
+            Span::Years(1).before(leap),
+            Some(at("2023-02-28T12:00:00+00:00"))
+        );
+        assert_eq!(
+            Span::Days(365).after(leap),
+            Some(at("2025-02-28T12:00:00+00:00"))
+        );
+        assert_eq!(
+            Span::Days(1).before(at("2024-03-01T00:00:00+00:00")),
+            Some(leap - chrono::Duration::hours(12))
+        );
+
+        assert_eq!(Span::Years(u32::MAX).after(now), None);
+    }
+
+    #[test]
+    fn test_parse_date() {
+        let now = at("2024-03-01T08:00:00+01:00");
+
+        assert_eq!(
+            parse_date_at("2024-02-29", now),
+            Ok(at("2024-02-29T00:00:00+01:00"))
+        );
+        assert_eq!(
+            parse_date_at(" 2023-12-31 ", now),
+            Ok(at("2023-12-31T00:00:00+01:00"))
+        );
+        assert_eq!(
+            parse_date_at("2024-02-29T23:30:00+01:00", now),
+            Ok(at("2024-02-29T23:30:00+01:00"))
+        );
+        assert_eq!(
+            parse_date_at("today", now),
+            Ok(at("2024-03-01T00:00:00+01:00"))
+        );
+        // the day before the 1st of march in a leap year
+        assert_eq!(
+            parse_date_at("yesterday", now),
+            Ok(at("2024-02-29T00:00:00+01:00"))
+        );
+        assert_eq!(
+            parse_date_at("7d", now),
+            Ok(at("2024-02-23T08:00:00+01:00"))
+        );
+        assert_eq!(
+            parse_date_at("7d ago", now),
+            Ok(at("2024-02-23T08:00:00+01:00"))
+        );
+        assert_eq!(
+            parse_date_at("3 months ago", now),
+            Ok(at("2023-12-01T08:00:00+01:00"))
+        );
+        assert_eq!(
+            parse_date_at("1y", now),
+            Ok(at("2023-03-01T08:00:00+01:00"))
+        );
+
+        // not leap years
+        assert_eq!(
+            parse_date_at("2023-02-29", now),
+            Err(DateError::NotADate("2023-02-29"))
+        );
+        assert_eq!(
+            parse_date_at("1900-02-29", now),
+            Err(DateError::NotADate("1900-02-29"))
+        );
+        assert!(parse_date_at("2000-02-29", now).is_ok());
+
+        for ambiguous in ["03/04/2024", "3/4/24", "03.04.2024", "03-04-2024"] {
+            assert_eq!(
+                parse_date_at(ambiguous, now),
+                Err(DateError::Ambiguous(ambiguous))
+            );
+        }
+        for wrong in [
+            "7",
+            "tomorrow",
+            "ago",
+            "7x ago",
+            "2024-3-4",
+            "2024-13-01",
+            "2024/03/04",
+            "",
+        ] {
+            assert_eq!(parse_date_at(wrong, now), Err(DateError::NotADate(wrong)));
+        }
+    }
+
+    #[test]
+    fn test_parse_date_timezones() {
+        // the same moment, wherever `now` is
+        let utc = Utc.with_ymd_and_hms(2024, 2, 29, 23, 30, 0).unwrap();
+        let tokyo = utc.with_timezone(&FixedOffset::east_opt(9 * 3600).unwrap());
+        assert_eq!(
+            parse_date_at("2024-02-29T23:30:00Z", tokyo),
+            Ok(at("2024-03-01T08:30:00+09:00"))
+        );
+        assert_eq!(parse_date_at("2024-02-29T23:30:00Z", utc), Ok(utc));
+
+        // days start where `now` is. it is already the 1st of march in tokyo
+        assert_eq!(
+            parse_date_at("today", tokyo),
+            Ok(at("2024-03-01T00:00:00+09:00"))
+        );
+        assert_eq!(
+            parse_date_at("today", utc),
+            Ok(Utc.with_ymd_and_hms(2024, 2, 29, 0, 0, 0).unwrap())
+        );
+        assert_eq!(
+            parse_date_at("yesterday", tokyo),
+            Ok(at("2024-02-29T00:00:00+09:00"))
+        );
+        assert_eq!(
+            parse_date_at("2024-03-01", tokyo),
+            Ok(at("2024-02-29T15:00:00+00:00"))
+        );
+
+        // the new year comes earlier east
+        let new_year = at("2024-01-01T00:30:00+05:30");
+        assert_eq!(
+            parse_date_at("yesterday", new_year),
+            Ok(at("2023-12-31T00:00:00+05:30"))
+        );
+        assert_eq!(
+            parse_date_at("1d", new_year),
+            Ok(at("2023-12-31T00:30:00+05:30"))
+        );
+    }
+
+    #[test]
+    fn test_date_errors() {
+        assert_eq!(
+            DateError::Ambiguous("03/04/2024").to_string(),
+            "'03/04/2024' could be day/month or month/day. write dates like 2024-03-04, 2024-03-04T09:30:00+01:00, today, yesterday, 7d or '3 months ago'"
+        );
+        assert_eq!(
+            DateError::NotASpan("5x").to_string(),
+            "'5x' is not a length of time. write lengths like 90 (days), 90d, 12w, 6m or 1y"
+        );
+    }
+}
@@ -0,0 +1,55 @@
+/// the fewest single character insertions, deletions and substitutions that turn `a` into `b`.
+/// counts chars, not bytes
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+
+    // the distances from the start of `a` so far to every start of `b`
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    let mut curr = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        curr[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != cb);
+            curr[j + 1] = substitution.min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[b.len()]
+}
+
+/// whether `a` and `b` are at most `max` apart. cheaper than `levenshtein`
+/// for the many values that differ in length by more than that
+pub fn within(a: &str, b: &str, max: usize) -> bool {
+    a.chars().count().abs_diff(b.chars().count()) <= max && levenshtein(a, b) <= max
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("", ""), 0);
+        assert_eq!(levenshtein("github", "github"), 0);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("abc", ""), 3);
+        assert_eq!(levenshtein("gitub", "github"), 1);
+        assert_eq!(levenshtein("github", "gitub"), 1);
+        assert_eq!(levenshtein("gihtub", "github"), 2);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("flaw", "lawn"), 2);
+        // chars, not bytes
+        assert_eq!(levenshtein("назад", "назат"), 1);
+        assert_eq!(levenshtein("ñ", "n"), 1);
+    }
+
+    #[test]
+    fn test_within() {
+        assert!(within("gitub", "github", 1));
+        assert!(!within("gihtub", "github", 1));
+        assert!(within("gihtub", "github", 2));
+        assert!(!within("git", "github", 2));
+        assert!(within("назад", "назат", 1));
+    }
+}
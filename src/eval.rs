@@ -1,606 +1,6399 @@
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::time::Duration;
+
 use anyhow::anyhow;
-use arboard::Clipboard;
+use chrono::{DateTime, Local};
 use ignorant::Ignore;
+use serde::Deserialize;
+use serde::Serialize;
+use uuid::Uuid;
+use zeroize::Zeroizing;
 
+use crate::cancel;
+use crate::cancel::Cancel;
+use crate::clipboard;
+use crate::clipboard::Copied;
+use crate::codec;
+use crate::crypt;
+use crate::crypt::Kdf;
+use crate::crypt::DEFAULT_ITERATIONS;
+use crate::date::Span;
+use crate::distance;
 use crate::lex::*;
+use crate::parse::CheckKind;
 use crate::parse::*;
+use crate::render::fmt_record_lines;
+use crate::render::PlainText;
+use crate::render::Renderer;
+use crate::share;
+use crate::store::DupStatus;
+use crate::store::DuplicateGroup;
 use crate::store::Field;
+use crate::store::FieldKind;
+use crate::store::FieldRenameStatus;
+use crate::store::HistoryDiff;
 use crate::store::HistoryEntry;
+use crate::store::KindMismatch;
+use crate::store::MergeConflict;
 use crate::store::Record;
 use crate::store::RenameStatus;
+use crate::store::SizeReport;
 use crate::store::Store;
+use crate::store::UndeleteStatus;
+use crate::store::TAGS;
 
 #[derive(Debug)]
 pub enum EvalError<'text> {
     Lex(LexError),
     Parse(ParseError<'text>),
     Import(anyhow::Error),
+    Export(anyhow::Error),
+    Totp(anyhow::Error),
+    Share(anyhow::Error),
+    Rollback(anyhow::Error),
+    Tag(anyhow::Error),
+    Codec(anyhow::Error),
+    Ambiguous(anyhow::Error),
+    Merge(anyhow::Error),
+    /// the command that would have changed a read-only store
+    ReadOnly(&'text str),
+    Gen,
 }
 
 pub enum Evaluation<'text> {
     Set,
+    SetWithWarnings(Vec<String>),
+    /// the whole record that went to the trash, history and all
     Del(Option<Record>),
+    /// the record that is left after deleting some of its fields
+    DelFields(Option<Record>),
+    DelQuery(Vec<Record>),
+    /// how many records a `set where` changed
+    SetWhere {
+        updated: usize,
+        warnings: Vec<String>,
+    },
+    /// in the order they are shown in
     Show(Vec<Record>),
     Reveal(Vec<Record>),
-    Copy(bool),
+    Count(usize),
+    List(Vec<String>),
+    /// a decoded or encoded value that was asked to be shown
+    Coded(String),
+    /// like `Show` and `Reveal`, but always rendered as json
+    ShowJson(Vec<Record>),
+    RevealJson(Vec<Record>),
+    Copy(Option<Copied>),
+    Gen(Option<String>),
+    Totp {
+        code: String,
+        remaining: Duration,
+        copied: Option<Copied>,
+    },
+    Share {
+        passphrase: String,
+        copied: Option<Copied>,
+    },
+    Receive {
+        name: String,
+        warnings: Vec<String>,
+    },
+    /// the command that was taken back, if any
+    Undo(Option<String>),
+    Redo(Option<String>),
+    /// the deleted records, oldest first
+    Trash(Vec<Record>),
+    Undelete((UndeleteStatus, &'text str)),
+    /// how many records were deleted for good
+    EmptyTrash(usize),
+    Merge {
+        added: usize,
+        updated: usize,
+        conflicts: Vec<MergeConflict>,
+    },
+    Watch {
+        query: String,
+        /// false if it was watched already
+        added: bool,
+        matching: usize,
+    },
+    Unwatch {
+        query: String,
+        /// false if it wasn't watched
+        removed: bool,
+    },
+    /// each watched query and how many records it matches
+    Watches(Vec<(String, usize)>),
     History(Vec<HistoryEntry>),
     RevealHistory(Vec<HistoryEntry>),
-    Import(usize),
+    /// oldest first
+    HistoryDiff(Vec<HistoryDiff>),
+    RevealHistoryDiff(Vec<HistoryDiff>),
+    Import {
+        imported: usize,
+        skipped: usize,
+        renamed: usize,
+        warnings: Vec<String>,
+    },
+    Export {
+        exported: usize,
+        warnings: Vec<String>,
+    },
+    SizeReport(SizeReport),
+    Duplicates {
+        sensitive: Vec<DuplicateGroup>,
+        plain: Vec<DuplicateGroup>,
+    },
+    KindMismatches(Vec<KindMismatch>),
+    /// records named like a keyword and a free name to rename each to
+    KeywordNames(Vec<(String, String)>),
     Rename((RenameStatus, &'text str, &'text str)),
+    /// the old name, new name and status of each matching record
+    BatchRename {
+        renames: Vec<(String, String, RenameStatus)>,
+        dry_run: bool,
+    },
+    /// the status, record, old attr and new attr
+    RenameField((FieldRenameStatus, &'text str, &'text str, &'text str)),
+    Rollback(Record),
+    Tag(Record),
+    AllowDuplicateNames(bool),
+    Dup {
+        status: DupStatus,
+        src: &'text str,
+        dst: &'text str,
+        /// the new record if it was `Successful`
+        record: Option<Record>,
+    },
+    HistoryLimit(Option<usize>),
+    /// how many history entries were dropped
+    PruneHistory(usize),
 }
 
 impl<'text> Evaluation<'text> {
-    fn fmt_record(record: Record, sensitize: bool) -> String {
-        use std::fmt::Write;
+    pub fn render(self, renderer: &dyn Renderer) -> Vec<String> {
+        renderer.render(self)
+    }
+}
+
+/// what whoever runs the commands decides for all of them
+#[derive(Debug, Clone, Copy)]
+pub struct EvalOptions {
+    /// how the master password of the vault to `merge` is asked for, given its path
+    pub merge_pass: fn(&str) -> Option<String>,
+    /// warn about sensitive values that are easy to guess
+    pub strength_warnings: bool,
+    /// how `set` asks whether to strip what `untidy` found around a sensitive value,
+    /// given the warning about it. without asking, the value is kept as it is
+    pub trim_untidy: fn(&str) -> bool,
+    /// store `otpauth://` values as sensitive even when not told to
+    pub sensitive_otpauth: bool,
+    pub import_limits: ImportLimits,
+}
+
+impl Default for EvalOptions {
+    fn default() -> Self {
+        Self {
+            merge_pass: |_| None,
+            strength_warnings: true,
+            trim_untidy: |_| false,
+            sensitive_otpauth: true,
+            import_limits: ImportLimits::default(),
+        }
+    }
+}
+
+/// `eval_with` the default options, which is all the tests need
+#[cfg(test)]
+pub fn eval<'text>(
+    text: &'text str,
+    store: &mut Store,
+) -> Result<Evaluation<'text>, EvalError<'text>> {
+    eval_with(text, store, &EvalOptions::default())
+}
 
-        let mut buf = String::new();
-        write!(buf, "'{}'", record.name).ignore();
-        Self::fmt_fields(record.fields, sensitize, &mut buf);
+pub fn eval_with<'text>(
+    text: &'text str,
+    store: &mut Store,
+    options: &EvalOptions,
+) -> Result<Evaluation<'text>, EvalError<'text>> {
+    let tokens = lex(text)?;
+    let cmd = parse(&tokens)?;
 
-        buf
+    if store.is_read_only() && cmd.changes_store() {
+        return Err(EvalError::ReadOnly(
+            text.split_whitespace().next().unwrap_or(text),
+        ));
     }
 
-    fn fmt_history(history: HistoryEntry, sensitize: bool) -> String {
-        use std::fmt::Write;
+    if let Some((name, filter)) = cmd.target() {
+        check_target(store, matches!(cmd, Cmd::Set { .. }), name, filter)?;
+    }
 
-        let mut buf = String::new();
-        write!(buf, "({})", history.datetime.format("%Y-%m-%d %H:%M %:z")).ignore();
-        Self::fmt_fields(history.fields, sensitize, &mut buf);
+    let eval = match cmd.changes_store() {
+        true => {
+            // the values of a `set` might be sensitive, so they aren't repeated by `undo`
+            store.checkpoint(match &cmd {
+                Cmd::Set { name, .. } => format!("set '{}'", name),
+                Cmd::SetWhere { filter, .. } => format!("set where {}", filter),
+                cmd => cmd.to_string(),
+            });
+            let eval = evaluate(cmd, store, options);
+            store.forget_unchanged();
+            eval
+        }
+        false => evaluate(cmd, store, options),
+    };
+    check_watches(store);
+    eval
+}
 
-        buf
+/// tells what changed about the matches of each watched query since it was last checked
+fn check_watches(store: &mut Store) {
+    let mut changes = vec![];
+    for (i, watch) in store.watches().iter().enumerate() {
+        let Ok(tokens) = lex(&watch.query) else {
+            continue;
+        };
+        let Ok(query) = parse_whole_query(&tokens) else {
+            continue;
+        };
+        let matched = store.get(query);
+        if let Some(notice) = watch_notice(&watch.query, &watch.matched, &matched) {
+            changes.push((i, matched, notice));
+        }
     }
 
-    fn fmt_fields(mut fields: Vec<Field>, sensitize: bool, buf: &mut String) {
-        use std::fmt::Write;
+    for (i, matched, notice) in changes {
+        store.rewatch(i, matched, notice);
+    }
+}
 
+/// `None` unless records started or stopped matching, or a matching one changed
+fn watch_notice(query: &str, before: &[Record], after: &[Record]) -> Option<String> {
+    let sorted = |fields: &[Field]| {
+        let mut fields = fields.to_vec();
         fields.sort_by(|f1, f2| f1.attr.cmp(&f2.attr));
+        fields
+    };
+    let find =
+        |records: &[Record], record: &Record| records.iter().find(|r| r.id == record.id).cloned();
 
-        for field in fields {
-            match sensitize && field.sensitive {
-                true => write!(buf, " {}=*****", field.attr),
-                false => write!(buf, " {}='{}'", field.attr, field.value),
+    let mut changes = vec![];
+    for record in after {
+        match find(before, record) {
+            None => changes.push((record.name.clone(), "+")),
+            Some(old)
+                if old.name != record.name || sorted(&old.fields) != sorted(&record.fields) =>
+            {
+                changes.push((record.name.clone(), "~"))
             }
-            .ignore()
+            Some(_) => {}
         }
     }
-
-    pub fn lines(self) -> Vec<String> {
-        match self {
-            Evaluation::Set => vec![],
-            Evaluation::Del(record) => match record {
-                Some(record) => vec![Evaluation::fmt_record(record, true)],
-                None => vec![],
-            },
-            Evaluation::Show(mut records) => {
-                records.sort_by(|r1, r2| r1.name.cmp(&r2.name));
-                records
-                    .into_iter()
-                    .map(|record| Evaluation::fmt_record(record, true))
-                    .collect()
-            }
-            Evaluation::Reveal(mut records) => {
-                records.sort_by(|r1, r2| r1.name.cmp(&r2.name));
-                records
-                    .into_iter()
-                    .map(|record| Evaluation::fmt_record(record, false))
-                    .collect()
-            }
-            Evaluation::Copy(status) => match status {
-                true => vec!["Copied!".into()],
-                false => vec!["Unable to Copy! Try Again!".into()],
-            },
-            Evaluation::History(mut history) => {
-                history.sort_by(|h1, h2| h1.datetime.cmp(&h2.datetime).reverse());
-                history
-                    .into_iter()
-                    .map(|h| Evaluation::fmt_history(h, true))
-                    .collect()
-            }
-            Evaluation::RevealHistory(mut history) => {
-                history.sort_by(|h1, h2| h1.datetime.cmp(&h2.datetime).reverse());
-                history
-                    .into_iter()
-                    .map(|h| Evaluation::fmt_history(h, false))
-                    .collect()
-            }
-            Evaluation::Rename((status, old, new)) => match status {
-                RenameStatus::OldNameNotFound => vec![format!("'{}' not found!", old)],
-                RenameStatus::NewNameAlreadyExists => vec![format!("'{}' already exists!", new)],
-                RenameStatus::Successful => vec!["Renamed!".into()],
-            },
-            Evaluation::Import(nrecords) => vec![format!("imported {} records", nrecords)],
+    for record in before {
+        if find(after, record).is_none() {
+            changes.push((record.name.clone(), "-"));
         }
     }
+    if changes.is_empty() {
+        return None;
+    }
+    changes.sort();
+
+    let count = match after.len() == before.len() {
+        true => format!("{} matching", after.len()),
+        false => format!("{} matching, was {}", after.len(), before.len()),
+    };
+    let changes = changes
+        .into_iter()
+        .map(|(name, change)| format!("{} '{}'", change, name))
+        .collect::<Vec<_>>();
+    Some(format!(
+        "watch {}: {} ({})",
+        query,
+        count,
+        changes.join(", ")
+    ))
 }
 
-pub fn eval<'text>(
-    text: &'text str,
+fn check_not_tags<'text>(
+    mut attrs: impl Iterator<Item = &'text str>,
+) -> Result<(), EvalError<'text>> {
+    match attrs.find(|attr| attr.starts_with(TAGS)) {
+        Some(attr) => Err(EvalError::Tag(anyhow!(
+            "'{}' is reserved for tags. use 'tag' and 'untag' instead",
+            attr
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// about the sensitive values that are easy to guess, if the warnings are on
+fn strength_warnings(name: &str, assignments: &[Assign], options: &EvalOptions) -> Vec<String> {
+    match options.strength_warnings {
+        true => assignments
+            .iter()
+            .filter(|assign| assign.sensitive)
+            .filter_map(|assign| strength_warning(name, assign.attr, assign.value))
+            .collect(),
+        false => vec![],
+    }
+}
+
+/// values that don't look like the kind of their field: the one given with `type`,
+/// or else the one the field already had or its attr tells
+fn kind_warnings(assignments: &[Assign], before: Option<&Record>) -> Vec<String> {
+    let kept = |attr: &str| {
+        (before?.fields.iter())
+            .find(|f| f.attr == attr)
+            .map(|f| f.kind)
+            .filter(|kind| !kind.is_unknown())
+    };
+    (assignments.iter())
+        .filter_map(|assign| {
+            let kind = (assign.kind.or_else(|| kept(assign.attr)))
+                .unwrap_or_else(|| FieldKind::infer(assign.attr));
+            (!kind.accepts(assign.value))
+                .then(|| format!("⚠ {}: doesn't look like {}", assign.attr, kind.describe()))
+        })
+        .collect()
+}
+
+/// the secret of an `otpauth://` uri is as good as a password, so such values are made
+/// sensitive unless `options.sensitive_otpauth` is off. each one made sensitive is warned about
+fn sensitize_otpauth<'text>(
+    assignments: Vec<Assign<'text>>,
+    options: &EvalOptions,
+) -> (Vec<Assign<'text>>, Vec<String>) {
+    let mut warnings = vec![];
+    let assignments = (assignments.into_iter())
+        .map(|assign| {
+            match options.sensitive_otpauth
+                && !assign.sensitive
+                && assign.value.starts_with("otpauth://")
+            {
+                true => {
+                    warnings.push(format!(
+                        "⚠ {}: stored as sensitive since it is an otpauth:// uri (--plain-otpauth keeps it as typed)",
+                        assign.attr
+                    ));
+                    Assign {
+                        sensitive: true,
+                        ..assign
+                    }
+                }
+                false => assign,
+            }
+        })
+        .collect();
+    (assignments, warnings)
+}
+
+fn evaluate<'text>(
+    cmd: Cmd<'text>,
     store: &mut Store,
+    options: &EvalOptions,
 ) -> Result<Evaluation<'text>, EvalError<'text>> {
-    let tokens = lex(text)?;
-    let cmd = parse(&tokens)?;
-
     match cmd {
-        Cmd::Set { name, assignments } => {
-            store.set(name, assignments);
-            Ok(Evaluation::Set)
+        Cmd::Set {
+            name,
+            filter,
+            assignments,
+            generated,
+        } => {
+            let attrs = assignments.iter().map(|a| a.attr);
+            check_not_tags(attrs.chain(generated.iter().map(|g| g.attr)))?;
+            let values = generate_values(&generated).ok_or(EvalError::Gen)?;
+            let (tidied, mut untidy_warnings) = tidy(&assignments, options.trim_untidy);
+            let assignments = with_tidied(assignments, &tidied);
+            // generated values are strong enough and aren't checked
+            let mut warnings = strength_warnings(name, &assignments, options);
+            warnings.append(&mut untidy_warnings);
+            let before = store.pick(name, filter.as_ref()).pop();
+            warnings.append(&mut kind_warnings(&assignments, before.as_ref()));
+            let (assignments, mut otpauth_warnings) = sensitize_otpauth(assignments, options);
+            warnings.append(&mut otpauth_warnings);
+
+            let assignments = with_generated(assignments, &generated, &values);
+            store.set(name, filter.as_ref(), assignments);
+            match warnings.is_empty() {
+                true => Ok(Evaluation::Set),
+                false => Ok(Evaluation::SetWithWarnings(warnings)),
+            }
         }
-        Cmd::Del { name, attrs } => match attrs.as_slice() {
-            [] => Ok(Evaluation::Del(store.remove(name))),
-            attrs => Ok(Evaluation::Del(store.remove_attrs(name, attrs))),
+        Cmd::Del {
+            name,
+            filter,
+            attrs,
+        } => match attrs.as_slice() {
+            [] => Ok(Evaluation::Del(store.remove(name, filter.as_ref()))),
+            attrs => Ok(Evaluation::DelFields(store.remove_attrs(
+                name,
+                filter.as_ref(),
+                attrs,
+            ))),
         },
-        Cmd::Show(query) => Ok(Evaluation::Show(store.get(query))),
-        Cmd::Reveal(query) => Ok(Evaluation::Reveal(store.get(query))),
-        Cmd::Copy { name, attr } => {
-            if let Some(record) = store.get(Query::Name(name)).pop() {
-                if let Some(field) = record.fields.iter().find(|f| f.attr == attr) {
-                    if let Ok(mut clipboard) = Clipboard::new() {
-                        return Ok(Evaluation::Copy(
-                            clipboard.set_text(field.value.clone()).is_ok(),
-                        ));
-                    }
-                }
-            }
-            Ok(Evaluation::Copy(false))
+        Cmd::SetWhere {
+            filter,
+            assignments,
+        } => {
+            check_not_tags(assignments.iter().map(|a| a.attr))?;
+            let (tidied, mut untidy_warnings) = tidy(&assignments, options.trim_untidy);
+            let assignments = with_tidied(assignments, &tidied);
+            let mut warnings = strength_warnings("", &assignments, options);
+            warnings.append(&mut untidy_warnings);
+            warnings.append(&mut kind_warnings(&assignments, None));
+            let (assignments, mut otpauth_warnings) = sensitize_otpauth(assignments, options);
+            warnings.append(&mut otpauth_warnings);
+            let updated = store.set_where(&filter, assignments);
+            Ok(Evaluation::SetWhere { updated, warnings })
+        }
+        Cmd::DelQuery(query) => Ok(Evaluation::DelQuery(store.remove_query(query))),
+        Cmd::Show(query, order) => Ok(Evaluation::Show(ordered(store.get(query), &order, true))),
+        Cmd::Reveal(query, order) => {
+            Ok(Evaluation::Reveal(ordered(store.get(query), &order, false)))
+        }
+        Cmd::ShowJson(query, order) => Ok(Evaluation::ShowJson(ordered(
+            store.get(query),
+            &order,
+            true,
+        ))),
+        Cmd::RevealJson(query, order) => Ok(Evaluation::RevealJson(ordered(
+            store.get(query),
+            &order,
+            false,
+        ))),
+        Cmd::Count(query) => Ok(Evaluation::Count(store.count(query))),
+        Cmd::List(query) => Ok(Evaluation::List(store.names_matching(query))),
+        Cmd::Copy {
+            name,
+            filter,
+            attr,
+            clear_after,
+        } => {
+            let clear_after = clear_after.map(Duration::from_secs);
+            let text = (store.pick(name, filter.as_ref()).pop())
+                .and_then(|record| clipboard_text(record, attr));
+            Ok(Evaluation::Copy(
+                text.and_then(|text| clipboard::copy(text, clear_after)),
+            ))
+        }
+        Cmd::CopyTotp {
+            name,
+            attr,
+            clear_after,
+        } => {
+            let code = totp(store, name, Some(attr), None, None)
+                .and_then(|totp| Ok(totp.generate_current()?))
+                .map_err(EvalError::Totp)?;
+            let clear_after = clear_after.map(Duration::from_secs);
+            Ok(Evaluation::Copy(clipboard::copy(code, clear_after)))
+        }
+        Cmd::Totp {
+            name,
+            digits,
+            period,
+        } => {
+            let (code, remaining) = totp(store, name, None, digits, period)
+                .and_then(|totp| Ok((totp.generate_current()?, totp.ttl()?)))
+                .map_err(EvalError::Totp)?;
+            // the code is short lived, so it is cleared once it expires
+            let copied = clipboard::copy(code.clone(), Some(Duration::from_secs(remaining)));
+            Ok(Evaluation::Totp {
+                code,
+                remaining: Duration::from_secs(remaining),
+                copied,
+            })
+        }
+        Cmd::Share(name) => {
+            let record = store
+                .get(Query::Name(name))
+                .pop()
+                .ok_or_else(|| EvalError::Share(anyhow!("'{}' not found", name)))?;
+            let (armored, passphrase) =
+                share::share(record, store.now()).map_err(EvalError::Share)?;
+            Ok(Evaluation::Share {
+                passphrase,
+                copied: clipboard::copy(armored, None),
+            })
+        }
+        Cmd::Decode {
+            name,
+            attr,
+            encoding,
+            reveal,
+        } => {
+            let value = field_value(store, name, attr).map_err(EvalError::Codec)?;
+            let decoded = codec::decode(&value, encoding).map_err(EvalError::Codec)?;
+            Ok(coded(decoded, reveal))
+        }
+        Cmd::Encode {
+            name,
+            attr,
+            encoding,
+            reveal,
+        } => {
+            let value = field_value(store, name, attr).map_err(EvalError::Codec)?;
+            let encoded = codec::encode(&value, encoding).map_err(EvalError::Codec)?;
+            Ok(coded(encoded, reveal))
         }
         Cmd::History(name) => Ok(Evaluation::History(store.history(name))),
         Cmd::RevealHistory(name) => Ok(Evaluation::RevealHistory(store.history(name))),
-        Cmd::Rename(old, new) => {
-            let status = store.rename(old, new);
+        Cmd::HistoryDiff(name) => Ok(Evaluation::HistoryDiff(store.history_diff(name))),
+        Cmd::RevealHistoryDiff(name) => Ok(Evaluation::RevealHistoryDiff(store.history_diff(name))),
+        Cmd::Rename { old, filter, new } => {
+            let status = store.rename(old, filter.as_ref(), new);
             Ok(Evaluation::Rename((status, old, new)))
         }
-        Cmd::Import(fpath) => {
-            let content =
-                std::fs::read_to_string(fpath).map_err(|e| EvalError::Import(anyhow!(e)))?;
+        Cmd::BatchRename {
+            pattern,
+            replacement,
+            dry_run,
+        } => {
+            let renames = match dry_run {
+                true => store.preview_batch_rename(&pattern, replacement),
+                false => store.batch_rename(&pattern, replacement),
+            };
+            Ok(Evaluation::BatchRename { renames, dry_run })
+        }
+        Cmd::RenameField {
+            record,
+            filter,
+            old_attr,
+            new_attr,
+        } => {
+            if let Some(attr) = [old_attr, new_attr]
+                .into_iter()
+                .find(|attr| attr.starts_with(TAGS))
+            {
+                return Err(EvalError::Tag(anyhow!(
+                    "'{}' is reserved for tags. use 'tag' and 'untag' instead",
+                    attr
+                )));
+            }
+            let status = store.rename_field(record, filter.as_ref(), old_attr, new_attr);
+            Ok(Evaluation::RenameField((
+                status, record, old_attr, new_attr,
+            )))
+        }
+        Cmd::Dup { src, dst } => {
+            let status = store.duplicate(src, dst);
+            let record = match status {
+                DupStatus::Successful => store.get(Query::Name(dst)).pop(),
+                _ => None,
+            };
+            Ok(Evaluation::Dup {
+                status,
+                src,
+                dst,
+                record,
+            })
+        }
+        Cmd::AllowDuplicateNames(Some(allow)) => match store.set_allow_duplicate_names(allow) {
+            Ok(()) => Ok(Evaluation::AllowDuplicateNames(allow)),
+            Err(shared) => Err(EvalError::Ambiguous(anyhow!(
+                "several records are still named '{}'. rename them apart first",
+                shared.join("', '")
+            ))),
+        },
+        Cmd::AllowDuplicateNames(None) => Ok(Evaluation::AllowDuplicateNames(
+            store.allow_duplicate_names(),
+        )),
+        Cmd::SetHistoryLimit(limit) => {
+            store.set_history_limit(limit);
+            Ok(Evaluation::HistoryLimit(limit))
+        }
+        Cmd::ShowHistoryLimit => Ok(Evaluation::HistoryLimit(store.history_limit())),
+        Cmd::ClearHistory(name) => prune_history(store, name, 1),
+        Cmd::PruneHistory { name, keep } => prune_history(store, name, keep),
+        Cmd::Rollback { name, steps } => {
+            if !store.contains(name) {
+                return Err(EvalError::Rollback(anyhow!("'{}' not found", name)));
+            }
+            let entries = store.history(name).len();
+            match store.rollback(name, steps) {
+                Some(record) => Ok(Evaluation::Rollback(record)),
+                None => Err(EvalError::Rollback(anyhow!(
+                    "cannot roll back to entry {}. the history of '{}' has only {}",
+                    steps,
+                    name,
+                    entries
+                ))),
+            }
+        }
+        Cmd::Restore { name, index } => {
+            if !store.contains(name) {
+                return Err(EvalError::Rollback(anyhow!("'{}' not found", name)));
+            }
+            let entries = store.history(name).len();
+            match store.restore(name, index) {
+                Some(record) => Ok(Evaluation::Rollback(record)),
+                None => Err(EvalError::Rollback(anyhow!(
+                    "cannot restore entry [{}]. the history of '{}' goes from [0] to [{}]",
+                    index,
+                    name,
+                    entries.saturating_sub(1)
+                ))),
+            }
+        }
+        Cmd::Tag { name, tags } => match store.tag(name, &tags) {
+            Some(record) => Ok(Evaluation::Tag(record)),
+            None => Err(EvalError::Tag(anyhow!("'{}' not found", name))),
+        },
+        Cmd::Untag { name, tags } => match store.untag(name, &tags) {
+            Some(record) => Ok(Evaluation::Tag(record)),
+            None => Err(EvalError::Tag(anyhow!("'{}' not found", name))),
+        },
+        Cmd::Import(fpath, merge) => import(store, merge, options, |importer| {
+            import_lines(fpath, importer)
+        }),
+        Cmd::ImportLastPass(fpath, merge) => import(store, merge, options, |importer| {
+            import_lastpass(fpath, importer)
+        }),
+        Cmd::ImportCsv(fpath, merge) => import(store, merge, options, |importer| {
+            import_csv(fpath, importer)
+        }),
+        Cmd::ImportKeePass(fpath, merge) => import(store, merge, options, |importer| {
+            import_keepass(fpath, importer)
+        }),
+        Cmd::Export(fpath) => export_all(store, |records, cancel| export(fpath, records, cancel)),
+        Cmd::ExportCsv(fpath) => {
+            export_all(store, |records, cancel| export_csv(fpath, records, cancel))
+        }
+        Cmd::ExportCsvFields(fpath) => export_all(store, |records, cancel| {
+            export_csv_fields(fpath, records, cancel)
+        }),
+        Cmd::ExportJson(fpath) => {
+            export_all(store, |records, cancel| export_json(fpath, records, cancel))
+        }
+        Cmd::SizeReport => Ok(Evaluation::SizeReport(store.size_report())),
+        Cmd::Check(CheckKind::Duplicates) => Ok(Evaluation::Duplicates {
+            sensitive: store.find_duplicate_sensitive_values(),
+            plain: store.find_duplicate_values(),
+        }),
+        Cmd::Check(CheckKind::Kinds) => {
+            Ok(Evaluation::KindMismatches(store.find_kind_mismatches()))
+        }
+        Cmd::Check(CheckKind::Names) => Ok(Evaluation::KeywordNames(keyword_names(store))),
+        Cmd::Gen {
+            len,
+            symbols,
+            digits,
+            ambiguous,
+        } => {
+            let charset = charset(symbols, digits, ambiguous);
+            Ok(Evaluation::Gen(generate(len, &charset)))
+        }
+        Cmd::GenWords { count, sep } => Ok(Evaluation::Gen(passphrase(count, sep))),
+        Cmd::Undo => Ok(Evaluation::Undo(store.undo())),
+        Cmd::Redo => Ok(Evaluation::Redo(store.redo())),
+        Cmd::Trash => Ok(Evaluation::Trash(store.trash())),
+        Cmd::Undelete(name) => Ok(Evaluation::Undelete((store.undelete(name), name))),
+        Cmd::EmptyTrash => Ok(Evaluation::EmptyTrash(store.empty_trash())),
+        Cmd::Merge(fpath) => merge(store, fpath, options),
+        Cmd::Watch(query) => {
+            let text = query.to_string();
+            let matched = store.get(query);
+            let matching = matched.len();
+            Ok(Evaluation::Watch {
+                added: store.watch(text.clone(), matched),
+                query: text,
+                matching,
+            })
+        }
+        Cmd::Unwatch(query) => {
+            let query = query.to_string();
+            Ok(Evaluation::Unwatch {
+                removed: store.unwatch(&query),
+                query,
+            })
+        }
+        Cmd::Watches => Ok(Evaluation::Watches(
+            (store.watches().iter())
+                .map(|watch| (watch.query.clone(), watch.matched.len()))
+                .collect(),
+        )),
+    }
+}
 
-            for (line_idx, line) in content.lines().enumerate() {
-                if line.trim().is_empty() {
-                    continue;
-                }
+fn merge<'text>(
+    store: &mut Store,
+    fpath: &str,
+    options: &EvalOptions,
+) -> Result<Evaluation<'text>, EvalError<'text>> {
+    // `load` would create a missing file
+    if !std::path::Path::new(fpath).is_file() {
+        return Err(EvalError::Merge(anyhow!("'{}' not found", fpath)));
+    }
+    let master_pass = (options.merge_pass)(fpath)
+        .map(Zeroizing::new)
+        .ok_or_else(|| EvalError::Merge(anyhow!("no master password for '{}'", fpath)))?;
 
-                let cmd = String::from("set ") + line;
+    // the kdf is read from the file, this one would only be used for a new file
+    let kdf = Kdf::Pbkdf2 {
+        iterations: std::num::NonZeroU32::new(DEFAULT_ITERATIONS).unwrap(),
+    };
+    // ctrl-c while the file is decrypted leaves the store as it was. not while
+    // the password is asked for, the prompt has to end on its own
+    let cancel = Cancel::default();
+    let _interruptible = cancel::interruptible(&cancel);
+    let (other, _) =
+        crypt::load(fpath, &crypt::Password(&master_pass), kdf).map_err(EvalError::Merge)?;
+    merge_loaded(store, other, &cancel)
+}
 
-                if let Err(e) = eval(&cmd, store) {
-                    return Err(EvalError::Import(anyhow!(
-                        "{:?} line number: [{}] {}",
-                        e,
-                        line_idx + 1,
-                        line,
-                    )));
-                }
-            }
+fn merge_loaded<'text>(
+    store: &mut Store,
+    other: Store,
+    cancel: &Cancel,
+) -> Result<Evaluation<'text>, EvalError<'text>> {
+    if cancel.is_cancelled() {
+        return Err(EvalError::Merge(anyhow!("cancelled. nothing was merged")));
+    }
+    let report = store.merge(other);
+    Ok(Evaluation::Merge {
+        added: report.added,
+        updated: report.updated,
+        conflicts: report.conflicts,
+    })
+}
+
+/// adds the record `share`d by someone else. needs the passphrase they were shown,
+/// which is why it is asked for by the prompt instead of being part of a command
+pub fn receive<'text>(
+    armored: &str,
+    passphrase: &str,
+    store: &mut Store,
+) -> Result<Evaluation<'text>, EvalError<'text>> {
+    if store.is_read_only() {
+        return Err(EvalError::ReadOnly("receive"));
+    }
+    let shared = share::receive(armored, passphrase).map_err(EvalError::Share)?;
 
-            Ok(Evaluation::Import(content.lines().count()))
+    let mut warnings = vec![];
+    if shared.is_stale(store.now()) {
+        warnings.push(format!(
+            "this share was created on {}. ask for a new one if it wasn't expected",
+            shared.created.format("%Y-%m-%d %H:%M %:z")
+        ));
+    }
+
+    // the user's own record is never changed by what someone else sent
+    let name = match store.pick(&shared.name, None).is_empty() {
+        true => shared.name.clone(),
+        false => {
+            let name = free_name(&shared.name, |name| store.contains(name));
+            warnings.push(format!(
+                "'{}' already exists, so it was received as '{}'",
+                shared.name, name
+            ));
+            name
         }
+    };
+    check_target(store, true, &name, None)?;
+
+    let assignments = shared
+        .fields
+        .iter()
+        .map(|field| Assign {
+            attr: &field.attr,
+            value: &field.value,
+            sensitive: field.sensitive,
+            expires: field.expires.map(|days| Expiry::In(Span::Days(days))),
+            kind: Some(field.kind).filter(|kind| !kind.is_unknown()),
+        })
+        .collect();
+    store.checkpoint(format!("receive '{}'", name));
+    store.set(&name, None, assignments);
+    store.forget_unchanged();
+    check_watches(store);
+
+    Ok(Evaluation::Receive { name, warnings })
+}
+
+/// by name and then by the `sort` attr, numerically if both values are numbers.
+/// records without it come last. sensitive values don't decide the order when
+/// `sensitize`d so that it doesn't give them away
+fn ordered(mut records: Vec<Record>, order: &Order, sensitize: bool) -> Vec<Record> {
+    records.sort_by(|r1, r2| r1.name.cmp(&r2.name));
+
+    if let Some(attr) = order.sort {
+        let key = |record: &Record| match attr {
+            "." => Some(record.name.clone()),
+            attr => record
+                .fields
+                .iter()
+                .find(|f| f.attr == attr && !(sensitize && f.sensitive))
+                .map(|f| f.value.clone()),
+        };
+        records.sort_by(|r1, r2| match (key(r1), key(r2)) {
+            (Some(v1), Some(v2)) => match (v1.trim().parse::<f64>(), v2.trim().parse::<f64>()) {
+                (Ok(n1), Ok(n2)) => n1.total_cmp(&n2),
+                _ => v1.to_lowercase().cmp(&v2.to_lowercase()),
+            },
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        });
+    }
+
+    if let Some(limit) = order.limit {
+        records.truncate(limit);
     }
+    records
 }
 
-pub trait Cond<'text> {
-    fn test(&self, data: &Record) -> bool;
+fn field_value(store: &Store, name: &str, attr: &str) -> anyhow::Result<String> {
+    let record = store
+        .get(Query::Name(name))
+        .pop()
+        .ok_or_else(|| anyhow!("'{}' not found", name))?;
+    let mut field = record
+        .fields
+        .into_iter()
+        .find(|f| f.attr == attr)
+        .ok_or_else(|| anyhow!("'{}' has no '{}'", name, attr))?;
+    Ok(std::mem::take(&mut field.value))
 }
 
-impl<'text> Cond<'text> for Query<'text> {
-    fn test(&self, data: &Record) -> bool {
-        match self {
-            Query::Or(cond) => cond.test(data),
-            Query::Name(name) => data.name == *name,
-            Query::All => true,
-        }
+/// what `copy` puts on the clipboard: the value of the attr, or the whole record
+/// revealed (asking for all of it is explicit enough)
+fn clipboard_text(record: Record, attr: Option<&str>) -> Option<String> {
+    match attr {
+        Some(attr) => (record.fields.into_iter())
+            .find(|f| f.attr == attr)
+            .map(|mut field| std::mem::take(&mut field.value)),
+        None => Some(fmt_record_lines(record, false).join("\n")),
     }
 }
 
-impl<'text> Cond<'text> for Or<'text> {
-    fn test(&self, data: &Record) -> bool {
-        match self {
-            Or::And(cond) => cond.test(data),
-            Or::Or(lhs, rhs) => lhs.test(data) || rhs.test(data),
-        }
+/// copied unless it was asked to be shown
+fn coded<'text>(value: String, reveal: bool) -> Evaluation<'text> {
+    match reveal {
+        true => Evaluation::Coded(value),
+        false => Evaluation::Copy(clipboard::copy(value, None)),
     }
 }
 
-impl<'text> Cond<'text> for And<'text> {
-    fn test(&self, data: &Record) -> bool {
-        match self {
-            And::Filter(cond) => cond.test(data),
-            And::And(lhs, rhs) => lhs.test(data) && rhs.test(data),
+/// records that can only be named in quotes, since bare their name is a keyword.
+/// each with the first of `<name>1`, `<name>2`.. that no record has
+fn keyword_names(store: &Store) -> Vec<(String, String)> {
+    (store.names().into_iter())
+        .filter(|name| is_keyword(name))
+        .map(|name| {
+            let rename = (1..)
+                .map(|i| format!("{}{}", name, i))
+                .find(|rename| !store.contains(rename))
+                .expect("some number is free");
+            (name, rename)
+        })
+        .collect()
+}
+
+fn prune_history<'text>(
+    store: &mut Store,
+    name: &str,
+    keep: usize,
+) -> Result<Evaluation<'text>, EvalError<'text>> {
+    match store.prune_history(name, keep) {
+        Some(removed) => Ok(Evaluation::PruneHistory(removed)),
+        None => Err(EvalError::Rollback(anyhow!("'{}' not found", name))),
+    }
+}
+
+/// with duplicate names a name alone may not say which record is meant.
+/// `set` needs `where` for any existing name, since without it a new record is added
+fn check_target<'text>(
+    store: &Store,
+    set: bool,
+    name: &str,
+    filter: Option<&Or<'text>>,
+) -> Result<(), EvalError<'text>> {
+    let candidates = |records: Vec<Record>| {
+        PlainText
+            .render(Evaluation::Show(records))
+            .into_iter()
+            .map(|line| format!("\n    {}", line))
+            .collect::<String>()
+    };
+
+    if !store.allow_duplicate_names() {
+        return match filter {
+            Some(_) => Err(EvalError::Ambiguous(anyhow!(
+                "names are unique, so 'where' is only needed with 'allow-duplicate-names on'"
+            ))),
+            None => Ok(()),
+        };
+    }
+
+    let picked = store.pick(name, filter);
+    match (set, filter) {
+        (true, None) if !picked.is_empty() => Err(EvalError::Ambiguous(anyhow!(
+            "'{}' already exists. add `where <filter>` to update one or to add another:{}",
+            name,
+            candidates(picked)
+        ))),
+        _ if picked.len() > 1 => Err(EvalError::Ambiguous(anyhow!(
+            "{} records are named '{}'. pick one with `where <filter>` \
+            (set, del, copy and rename) or rename them apart:{}",
+            picked.len(),
+            name,
+            candidates(picked)
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// the generator of `name`'s field `attr`, or else of its first otp field
+/// ('totp', 'otp' or one of `type otp`). the value is either an `otpauth://` uri
+/// (with its own digits, period and algorithm) or a base32 secret (SHA1, 6 digits every 30s).
+/// `digits` and `period` override both
+fn totp(
+    store: &Store,
+    name: &str,
+    attr: Option<&str>,
+    digits: Option<usize>,
+    period: Option<u64>,
+) -> anyhow::Result<totp_rs::TOTP> {
+    let record = store
+        .get(Query::Name(name))
+        .pop()
+        .ok_or_else(|| anyhow!("'{}' not found", name))?;
+    let field = match attr {
+        Some(attr) => (record.fields.iter())
+            .find(|f| f.attr == attr)
+            .ok_or_else(|| anyhow!("'{}' has no '{}'", name, attr))?,
+        None => (["totp", "otp"].iter())
+            .find_map(|attr| record.fields.iter().find(|f| f.attr == *attr))
+            .or_else(|| (record.fields.iter()).find(|f| f.kind() == FieldKind::Otp))
+            .ok_or_else(|| anyhow!("'{}' has no 'totp' or 'otp' (or a field of type otp)", name))?,
+    };
+
+    let mut totp = match field.value.starts_with("otpauth://") {
+        true => totp_rs::TOTP::from_url_unchecked(&field.value).map_err(|e| {
+            anyhow!(
+                "'{}' of '{}' is not a valid otpauth://totp/ uri. {}",
+                field.attr,
+                name,
+                e
+            )
+        })?,
+        false => {
+            // authenticators show secrets in groups of four and lowercase is common too
+            let secret = field.value.replace(' ', "").to_uppercase();
+            let secret = totp_rs::Secret::Encoded(secret.trim_end_matches('=').into())
+                .to_bytes()
+                .ok()
+                // 80 bits is the shortest secret in common use. shorter ones are typos
+                .filter(|secret| secret.len() >= 10)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "'{}' of '{}' is neither an otpauth:// uri nor a base32 secret",
+                        field.attr,
+                        name
+                    )
+                })?;
+            totp_rs::TOTP::new_unchecked(
+                totp_rs::Algorithm::SHA1,
+                6,
+                1,
+                30,
+                secret,
+                None,
+                String::new(),
+            )
         }
+    };
+
+    if let Some(digits) = digits {
+        totp.digits = digits;
     }
+    if let Some(period) = period {
+        totp.step = period;
+    }
+    if !(6..=8).contains(&totp.digits) {
+        return Err(anyhow!(
+            "totp codes have 6 to 8 digits, not {}",
+            totp.digits
+        ));
+    }
+
+    Ok(totp)
 }
 
-impl<'text> Cond<'text> for Filter<'text> {
-    fn test(&self, data: &Record) -> bool {
+/// what was most likely captured by accident along with a pasted value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Untidy {
+    TrailingNewline,
+    SurroundingWhitespace,
+    ControlCharacters,
+}
+
+impl Display for Untidy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Filter::Contains(cond) => cond.test(data),
-            Filter::Matches(cond) => cond.test(data),
-            Filter::Cmp(cond) => cond.test(data),
-            Filter::Parens(q) => q.test(data),
+            Untidy::TrailingNewline => write!(f, "a trailing newline"),
+            Untidy::SurroundingWhitespace => write!(f, "leading or trailing whitespace"),
+            Untidy::ControlCharacters => write!(f, "control characters"),
         }
     }
 }
 
-impl<'text> Cond<'text> for Contains<'text> {
-    fn test(&self, data: &Record) -> bool {
-        match self.attr {
-            "." => data
-                .name
-                .to_lowercase()
-                .contains(&self.substr.to_lowercase()),
-            attr => data
-                .fields
-                .iter()
-                .find(|f| f.attr == attr)
-                .map_or(false, |f| {
-                    f.value.to_lowercase().contains(&self.substr.to_lowercase())
-                }),
-        }
+/// `None` if `value` looks as intended, otherwise what is off about it and the value without it.
+/// newlines and tabs within the value are kept, multi-line values like keys have them
+pub fn untidy(value: &str) -> Option<(Untidy, String)> {
+    let untidy = if value.ends_with('\n') {
+        Untidy::TrailingNewline
+    } else if value.trim() != value {
+        Untidy::SurroundingWhitespace
+    } else if strip_control(value, &['\n', '\t']) != value {
+        Untidy::ControlCharacters
+    } else {
+        return None;
+    };
+    Some((untidy, strip_control(value.trim(), &['\n', '\t'])))
+}
+
+/// the stripped value of each untidy sensitive value the user agreed to strip
+/// and warnings about the ones that were kept. the value itself is never part of a warning
+fn tidy(assignments: &[Assign], ask: fn(&str) -> bool) -> (Vec<Option<String>>, Vec<String>) {
+    let mut warnings = vec![];
+    let tidied = assignments
+        .iter()
+        .map(|assign| {
+            let (untidy, tidy) = untidy(assign.value).filter(|_| assign.sensitive)?;
+            let warning = format!(
+                "⚠ {}: {} characters with {}",
+                assign.attr,
+                assign.value.chars().count(),
+                untidy
+            );
+            match ask(&warning) {
+                true => Some(tidy),
+                false => {
+                    warnings.push(warning);
+                    None
+                }
+            }
+        })
+        .collect();
+    (tidied, warnings)
+}
+
+fn with_tidied<'text>(
+    assignments: Vec<Assign<'text>>,
+    tidied: &'text [Option<String>],
+) -> Vec<Assign<'text>> {
+    assignments
+        .into_iter()
+        .zip(tidied)
+        .map(|(assign, tidy)| Assign {
+            value: tidy.as_deref().unwrap_or(assign.value),
+            ..assign
+        })
+        .collect()
+}
+
+/// `None` unless zxcvbn thinks `value` is weaker than "strong" (3/4).
+/// values too short for zxcvbn to judge always get a warning.
+fn strength_warning(name: &str, attr: &str, value: &str) -> Option<String> {
+    const STRENGTHS: [&str; 5] = ["very weak", "weak", "fair", "strong", "very strong"];
+
+    if value.chars().count() < 4 {
+        return Some(format!(
+            "⚠ {}: strength 0/4 (very weak) – shorter than 4 characters",
+            attr
+        ));
+    }
+
+    let entropy = zxcvbn::zxcvbn(value, &[name]).ok()?;
+    let score = entropy.score().min(4);
+    if score >= 3 {
+        return None;
     }
+
+    let mut warning = format!(
+        "⚠ {}: strength {}/4 ({})",
+        attr, score, STRENGTHS[score as usize]
+    );
+    if let Some(reason) = entropy.feedback().as_ref().and_then(|f| f.warning()) {
+        warning.push_str(&format!(" – {}", reason));
+    }
+    Some(warning)
 }
 
-impl<'text> Cond<'text> for Matches<'text> {
-    fn test(&self, data: &Record) -> bool {
-        match self.attr {
-            "." => self.pat.find(&data.name).is_some(),
-            attr => data
-                .fields
-                .iter()
-                .find(|f| f.attr == attr)
-                .and_then(|f| self.pat.find(&f.value))
-                .is_some(),
-        }
+/// letters are always included. the ambiguous characters are `0`, `O`, `l` and `1`.
+/// `'` is never included since it can't be used in a value.
+fn charset(symbols: bool, digits: bool, ambiguous: bool) -> Vec<u8> {
+    let mut charset = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ".to_vec();
+    if digits {
+        charset.extend_from_slice(b"0123456789");
     }
+    if symbols {
+        charset.extend_from_slice(b"!@#$%^&*()-_=+[]{};:,.<>?/~");
+    }
+
+    if !ambiguous {
+        charset.retain(|c| !b"0Ol1".contains(c));
+    }
+
+    charset
 }
 
-impl<'text> Cond<'text> for Is<'text> {
-    fn test(&self, data: &Record) -> bool {
-        match self.attr {
-            "." => data.name == self.value,
-            attr => data
-                .fields
-                .iter()
-                .find(|f| f.attr == attr)
-                .map_or(false, |f| f.value == self.value),
+/// picks `len` characters from `charset` uniformly using the system's secure rng
+fn generate(len: usize, charset: &[u8]) -> Option<String> {
+    use ring::rand::{SecureRandom, SystemRandom};
+
+    let rng = SystemRandom::new();
+    // bytes at or above `limit` are rejected so that no character is more likely than another
+    let limit = 256 - 256 % charset.len();
+
+    let mut password = String::with_capacity(len);
+    let mut byte = [0u8; 1];
+    while password.len() < len {
+        rng.fill(&mut byte).ok()?;
+        let byte = byte[0] as usize;
+        if byte < limit {
+            password.push(charset[byte % charset.len()] as char);
         }
     }
+
+    Some(password)
 }
 
-impl<'text> From<LexError> for EvalError<'text> {
-    fn from(value: LexError) -> Self {
-        EvalError::Lex(value)
+/// a value for each of `generated`, in the same order
+fn generate_values(generated: &[Generated]) -> Option<Vec<String>> {
+    generated
+        .iter()
+        .map(|generated| match generated.generator {
+            Generator::Password(len) => generate(len, &charset(true, true, true)),
+            Generator::Phrase(count) => passphrase(count, "-"),
+        })
+        .collect()
+}
+
+/// `assignments` followed by the `generated` attrs set to `values` as sensitive
+fn with_generated<'text>(
+    mut assignments: Vec<Assign<'text>>,
+    generated: &[Generated<'text>],
+    values: &'text [String],
+) -> Vec<Assign<'text>> {
+    assignments.extend(
+        generated
+            .iter()
+            .zip(values)
+            .map(|(generated, value)| Assign {
+                attr: generated.attr,
+                value,
+                sensitive: true,
+                expires: generated.expires,
+                kind: None,
+            }),
+    );
+    assignments
+}
+
+/// the BIP-39 english word list. 2048 words, so every word is 11 bits of a passphrase.
+/// any list of up to 65536 words, one per line, can take its place
+pub const WORDS: &str = include_str!("words.txt");
+
+/// `count` words picked uniformly from `WORDS` using the system's secure rng
+pub fn passphrase(count: usize, sep: &str) -> Option<String> {
+    use ring::rand::{SecureRandom, SystemRandom};
+
+    let words = WORDS.lines().collect::<Vec<_>>();
+    let rng = SystemRandom::new();
+
+    // the draws past the last whole multiple of the list's length are thrown away,
+    // so that every word is equally likely whatever the length
+    let limit = 65536 - 65536 % words.len();
+
+    let mut passphrase = Vec::with_capacity(count);
+    let mut bytes = [0u8; 2];
+    while passphrase.len() < count {
+        rng.fill(&mut bytes).ok()?;
+        let draw = u16::from_le_bytes(bytes) as usize;
+        if draw < limit {
+            passphrase.push(words[draw % words.len()]);
+        }
     }
+
+    Some(passphrase.join(sep))
 }
 
-impl<'text> From<ParseError<'text>> for EvalError<'text> {
-    fn from(value: ParseError<'text>) -> Self {
-        EvalError::Parse(value)
+/// safeguards applied to every import so that a crafted file
+/// cannot exhaust memory, bloat the vault or smuggle in terminal escape sequences.
+#[derive(Debug, Clone, Copy)]
+pub struct ImportLimits {
+    /// longest line in bytes that is read from the file
+    pub max_line_len: usize,
+    /// most records a single import may add or update
+    pub max_records: usize,
+    /// strip control characters from names, attrs and values
+    pub sanitize: bool,
+}
+
+impl Default for ImportLimits {
+    fn default() -> Self {
+        Self {
+            max_line_len: 1 << 20,
+            max_records: 10_000,
+            sanitize: true,
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use pretty_assertions::assert_eq;
+/// attr, value and whether it is sensitive
+type ImportedField = (String, String, bool, Option<FieldKind>);
 
-    macro_rules! check {
-        ($store:expr, $cmd:expr, $expected:expr) => {
-            let eval = eval($cmd, &mut $store).expect(&format!("unable to eval {}", $cmd));
-            assert_eq!(eval.lines(), $expected);
-        };
+/// collects the records of an import so that nothing is written
+/// to the store unless the whole file passes the safeguards.
+struct Importer {
+    limits: ImportLimits,
+    records: Vec<(String, Vec<ImportedField>)>,
+    sanitized: usize,
+    /// sensitive values that `untidy` finds something off about. kept as they are
+    untidy: usize,
+    cancel: Cancel,
+}
+
+impl Importer {
+    fn new(limits: ImportLimits, cancel: Cancel) -> Self {
+        Self {
+            limits,
+            records: vec![],
+            sanitized: 0,
+            untidy: 0,
+            cancel,
+        }
     }
 
-    macro_rules! eval {
-        ($store:expr, $($cmd:expr),*) => {
-            $ ( eval($cmd, $store).expect(&format!("unable to eval {}", $cmd)); )*
-        };
+    /// reads the whole file but gives up on the first line longer than `max_line_len`
+    /// without buffering the rest of it.
+    fn read(&self, fpath: &str) -> anyhow::Result<String> {
+        use std::io::{BufRead, Read};
+
+        let max_line_len = self.limits.max_line_len;
+        let mut reader = std::io::BufReader::new(std::fs::File::open(fpath)?);
+        let mut content = vec![];
+
+        for line_number in 1.. {
+            let mut line = vec![];
+            let n = reader
+                .by_ref()
+                .take(max_line_len as u64 + 1)
+                .read_until(b'\n', &mut line)?;
+
+            if n == 0 {
+                break;
+            }
+            if n > max_line_len && line.last() != Some(&b'\n') {
+                return Err(anyhow!(
+                    "line is longer than {} bytes line number: [{}]",
+                    max_line_len,
+                    line_number
+                ));
+            }
+
+            content.extend(line);
+        }
+
+        Ok(String::from_utf8(content)?)
+    }
+
+    fn add(&mut self, name: &str, assignments: Vec<Assign>) -> anyhow::Result<()> {
+        if self.cancel.is_cancelled() {
+            return Err(anyhow!(
+                "cancelled after {} records. nothing was imported",
+                self.records.len()
+            ));
+        }
+        if self.records.len() == self.limits.max_records {
+            return Err(anyhow!(
+                "more than {} records. nothing was imported",
+                self.limits.max_records
+            ));
+        }
+
+        let name = self.sanitize(name, &[]);
+        let fields = assignments
+            .into_iter()
+            .map(|assign| {
+                let value = self.sanitize(assign.value, &['\n', '\t']);
+                if assign.sensitive && untidy(&value).is_some() {
+                    self.untidy += 1;
+                }
+                let attr = self.sanitize(assign.attr, &[]);
+                (attr, value, assign.sensitive, assign.kind)
+            })
+            .collect();
+
+        self.records.push((name, fields));
+        Ok(())
+    }
+
+    fn sanitize(&mut self, text: &str, keep: &[char]) -> String {
+        if !self.limits.sanitize {
+            return text.to_string();
+        }
+
+        let sanitized = strip_control(text, keep);
+        if sanitized.len() != text.len() {
+            self.sanitized += 1;
+        }
+        sanitized
+    }
+
+    /// writes the records to the store. `merge` decides what happens to the ones
+    /// whose name existed before the import. repeated names within the import
+    /// are still merged into one record like repeated `set`s.
+    /// nothing is written if one of them would be as ambiguous as it is to `set`
+    fn commit<'text>(
+        self,
+        store: &mut Store,
+        merge: Merge,
+        mut warnings: Vec<String>,
+    ) -> Result<Evaluation<'text>, EvalError<'text>> {
+        let existing = self
+            .records
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .filter(|name| store.contains(name))
+            .collect::<HashSet<_>>();
+
+        if merge == Merge::Overwrite {
+            for name in &existing {
+                check_target(store, true, name, None)?;
+            }
+        }
+
+        let mut renames = HashMap::new();
+        // records, not lines. the lines of a name all go to the same record
+        let (mut imported, mut skipped) = (HashSet::new(), HashSet::new());
+
+        for (name, fields) in &self.records {
+            let name = match (merge, existing.contains(name.as_str())) {
+                (Merge::Skip, true) => {
+                    skipped.insert(name.as_str());
+                    continue;
+                }
+                (Merge::Rename, true) => renames
+                    .entry(name)
+                    .or_insert_with(|| self.free_name(store, name))
+                    .clone(),
+                _ => {
+                    imported.insert(name.as_str());
+                    name.clone()
+                }
+            };
+
+            let assignments = fields
+                .iter()
+                .map(|(attr, value, sensitive, kind)| Assign {
+                    attr,
+                    value,
+                    sensitive: *sensitive,
+                    expires: None,
+                    kind: *kind,
+                })
+                .collect();
+            store.set(&name, None, assignments);
+        }
+
+        if self.sanitized > 0 {
+            warnings.push(format!(
+                "removed control characters from {} names, attrs or values",
+                self.sanitized
+            ));
+        }
+        if self.untidy > 0 {
+            warnings.push(format!(
+                "⚠ {} sensitive values have surrounding whitespace, a trailing newline or control characters",
+                self.untidy
+            ));
+        }
+
+        Ok(Evaluation::Import {
+            imported: imported.len(),
+            skipped: skipped.len(),
+            renamed: renames.len(),
+            warnings,
+        })
+    }
+
+    /// neither in the store nor in the import
+    fn free_name(&self, store: &Store, name: &str) -> String {
+        free_name(name, |candidate| {
+            store.contains(candidate) || self.records.iter().any(|(other, _)| other == candidate)
+        })
+    }
+}
+
+/// the first `<name>-2`, `<name>-3`, ... that isn't `taken`
+fn free_name(name: &str, taken: impl Fn(&str) -> bool) -> String {
+    let mut n = 2;
+    while taken(&format!("{}-{}", name, n)) {
+        n += 1;
+    }
+    format!("{}-{}", name, n)
+}
+
+fn strip_control(text: &str, keep: &[char]) -> String {
+    text.chars()
+        .filter(|c| !c.is_control() || keep.contains(c))
+        .collect()
+}
+
+fn import<'text>(
+    store: &mut Store,
+    merge: Merge,
+    options: &EvalOptions,
+    f: impl FnOnce(&mut Importer) -> anyhow::Result<Vec<String>>,
+) -> Result<Evaluation<'text>, EvalError<'text>> {
+    // ctrl-c stops reading the file, before anything is written to the store
+    let cancel = Cancel::default();
+    let _interruptible = cancel::interruptible(&cancel);
+    let mut importer = Importer::new(options.import_limits, cancel);
+    let warnings = f(&mut importer).map_err(EvalError::Import)?;
+    importer.commit(store, merge, warnings)
+}
+
+/// reads one record per line in the same format as `set` without the keyword.
+/// `gen(24)` and `gen(phrase 5)` values are generated for every line on its own.
+fn import_lines(fpath: &str, importer: &mut Importer) -> anyhow::Result<Vec<String>> {
+    let content = importer.read(fpath)?;
+    let mut ngenerated = 0;
+
+    for (line_idx, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let error = |e: String| {
+            anyhow!(
+                "{} line number: [{}] {}",
+                e,
+                line_idx + 1,
+                strip_control(line, &[])
+            )
+        };
+
+        let cmd = String::from("set ") + line;
+        let tokens = lex(&cmd).map_err(|e| error(e.to_string()))?;
+        let Cmd::Set {
+            name,
+            filter: None,
+            assignments,
+            generated,
+        } = parse(&tokens).map_err(|e| error(e.to_string()))?
+        else {
+            return Err(error("expected a record".into()));
+        };
+
+        let values = generate_values(&generated)
+            .ok_or_else(|| error("unable to generate values.".into()))?;
+        ngenerated += values.len();
+        importer.add(name, with_generated(assignments, &generated, &values))?;
+    }
+
+    Ok(match ngenerated {
+        0 => vec![],
+        n => vec![format!("generated {} sensitive values", n)],
+    })
+}
+
+/// reads the csv exported by LastPass with the columns
+/// `url,username,password,totp,extra,name,grouping,fav`.
+/// rows sharing a name are merged into one record just like repeated `set`s.
+fn import_lastpass(fpath: &str, importer: &mut Importer) -> anyhow::Result<Vec<String>> {
+    #[derive(Deserialize)]
+    struct Row {
+        url: String,
+        username: String,
+        password: String,
+        #[serde(default)]
+        totp: String,
+        extra: String,
+        name: String,
+        grouping: String,
+    }
+
+    let content = importer.read(fpath)?;
+    let mut reader = csv::Reader::from_reader(content.as_bytes());
+    let mut warnings = vec![];
+
+    for row in reader.deserialize::<Row>() {
+        let row = row.map_err(|e| match e.position() {
+            Some(p) => anyhow!("{} line number: [{}]", e, p.line()),
+            None => anyhow!(e),
+        })?;
+
+        if row.name.trim().is_empty() {
+            warnings.push(format!("skipped a row without a name. url='{}'", row.url));
+            continue;
+        }
+
+        let assignments = [
+            ("user", &row.username, false),
+            ("pass", &row.password, true),
+            ("url", &row.url, false),
+            ("totp", &row.totp, true),
+            ("note", &row.extra, false),
+            ("group", &row.grouping, false),
+        ]
+        .into_iter()
+        .filter(|(_, value, _)| !value.is_empty())
+        .map(|(attr, value, sensitive)| Assign {
+            attr,
+            value,
+            sensitive,
+            expires: None,
+            kind: None,
+        })
+        .collect();
+
+        importer.add(&row.name, assignments)?;
+    }
+
+    Ok(warnings)
+}
+
+/// reads the csv exported by browsers. chrome uses the columns `name,url,username,password`
+/// while firefox has no `name` column, in which case the url is used as the name.
+/// malformed rows are skipped and reported instead of aborting the whole import.
+fn import_csv(fpath: &str, importer: &mut Importer) -> anyhow::Result<Vec<String>> {
+    let content = importer.read(fpath)?;
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let headers = reader.headers()?.clone();
+    let column = |header: &str| headers.iter().position(|h| h == header);
+
+    let name_column = column("name");
+    let url_column = column("url");
+    let user_column = column("username");
+    let pass_column = column("password");
+    let note_column = column("note");
+
+    if name_column.is_none() && url_column.is_none() {
+        return Err(anyhow!("expected a 'name' or 'url' column"));
+    }
+
+    let mut skipped = 0;
+    let mut warnings = vec![];
+
+    if pass_column.is_none() {
+        warnings.push("no 'password' column found. importing without passwords".into());
+    }
+
+    for row in reader.records() {
+        let row = match row {
+            Ok(row) if row.len() == headers.len() => row,
+            Ok(row) => {
+                warnings.push(format!(
+                    "line {}: expected {} columns but found {}",
+                    row.position().map_or(0, |p| p.line()),
+                    headers.len(),
+                    row.len()
+                ));
+                skipped += 1;
+                continue;
+            }
+            Err(e) => {
+                warnings.push(format!(
+                    "line {}: {}",
+                    e.position().map_or(0, |p| p.line()),
+                    e
+                ));
+                skipped += 1;
+                continue;
+            }
+        };
+
+        let get = |column: Option<usize>| column.and_then(|i| row.get(i)).unwrap_or("");
+
+        let name = match get(name_column) {
+            "" => get(url_column),
+            name => name,
+        };
+
+        if name.is_empty() {
+            warnings.push(format!(
+                "line {}: row without a name or url",
+                row.position().map_or(0, |p| p.line())
+            ));
+            skipped += 1;
+            continue;
+        }
+
+        let assignments = [
+            ("user", get(user_column), false),
+            ("pass", get(pass_column), true),
+            ("url", get(url_column), false),
+            ("note", get(note_column), false),
+        ]
+        .into_iter()
+        .filter(|(_, value, _)| !value.is_empty())
+        .map(|(attr, value, sensitive)| Assign {
+            attr,
+            value,
+            sensitive,
+            expires: None,
+            kind: None,
+        })
+        .collect();
+
+        importer.add(name, assignments)?;
+    }
+
+    if skipped > 0 {
+        warnings.push(format!("skipped {} rows", skipped));
+    }
+
+    Ok(warnings)
+}
+
+/// reads the unencrypted xml exported by KeePass 2.x.
+/// the names of the groups below the root group are joined into a `group` attr
+/// and entries without a title are named after their uuid.
+/// KeePass allows the same title in many entries, so all but the first get a `-2`, `-3`, ...
+fn import_keepass(fpath: &str, importer: &mut Importer) -> anyhow::Result<Vec<String>> {
+    fn walk<'a, 'input>(
+        group: roxmltree::Node<'a, 'input>,
+        path: &[&'a str],
+        entries: &mut Vec<(String, roxmltree::Node<'a, 'input>)>,
+    ) {
+        for child in group.children() {
+            match child.tag_name().name() {
+                "Entry" => entries.push((path.join("/"), child)),
+                "Group" => {
+                    let name = child
+                        .children()
+                        .find(|n| n.has_tag_name("Name"))
+                        .and_then(|n| n.text())
+                        .unwrap_or_default();
+                    walk(child, &[path, &[name]].concat(), entries);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn string<'a>(entry: roxmltree::Node<'a, '_>, key: &str) -> &'a str {
+        entry
+            .children()
+            .filter(|n| n.has_tag_name("String"))
+            .find(|n| {
+                n.children()
+                    .any(|k| k.has_tag_name("Key") && k.text() == Some(key))
+            })
+            .and_then(|n| n.children().find(|v| v.has_tag_name("Value")))
+            .and_then(|v| v.text())
+            .unwrap_or_default()
+    }
+
+    let content = importer.read(fpath)?;
+    let doc = roxmltree::Document::parse(&content)?;
+
+    let root = doc
+        .descendants()
+        .find(|n| n.has_tag_name("Root"))
+        .and_then(|root| root.children().find(|n| n.has_tag_name("Group")))
+        .ok_or_else(|| anyhow!("expected a <Root> with a <Group>"))?;
+
+    let mut entries = vec![];
+    walk(root, &[], &mut entries);
+
+    let named = entries
+        .into_iter()
+        .map(|(group, entry)| {
+            let uuid = entry
+                .children()
+                .find(|n| n.has_tag_name("UUID"))
+                .and_then(|n| n.text())
+                .unwrap_or_default();
+
+            let name = match string(entry, "Title") {
+                "" => uuid,
+                title => title,
+            };
+
+            (group, entry, name)
+        })
+        .collect::<Vec<_>>();
+
+    let mut taken = named
+        .iter()
+        .map(|(_, _, name)| name.to_string())
+        .collect::<HashSet<_>>();
+    let mut seen = HashSet::new();
+    let mut warnings = vec![];
+    let mut skipped = 0;
+
+    for (group, entry, name) in named {
+        if name.is_empty() {
+            skipped += 1;
+            continue;
+        }
+
+        let name = match seen.insert(name) {
+            true => name.to_string(),
+            false => {
+                let renamed = free_name(name, |candidate| taken.contains(candidate));
+                let place = match group.as_str() {
+                    "" => "the root group".to_string(),
+                    group => format!("'{}'", group),
+                };
+                warnings.push(format!(
+                    "'{}' in {} has the title of an earlier entry, so it was imported as '{}'",
+                    name, place, renamed
+                ));
+                taken.insert(renamed.clone());
+                renamed
+            }
+        };
+
+        let assignments = [
+            ("user", string(entry, "UserName"), false),
+            ("pass", string(entry, "Password"), true),
+            ("url", string(entry, "URL"), false),
+            ("note", string(entry, "Notes"), false),
+            ("group", group.as_str(), false),
+        ]
+        .into_iter()
+        .filter(|(_, value, _)| !value.is_empty())
+        .map(|(attr, value, sensitive)| Assign {
+            attr,
+            value,
+            sensitive,
+            expires: None,
+            kind: None,
+        })
+        .collect();
+
+        importer.add(&name, assignments)?;
+    }
+
+    if skipped > 0 {
+        warnings.push(format!(
+            "skipped {} entries without a title or uuid",
+            skipped
+        ));
+    }
+
+    Ok(warnings)
+}
+
+/// exports are plain text, so only the user may read them.
+/// an existing file is never replaced, the prompt asks and removes it first
+fn create_export(fpath: &str) -> anyhow::Result<std::fs::File> {
+    crypt::create_private(std::path::Path::new(fpath)).map_err(|e| match e.kind() {
+        std::io::ErrorKind::AlreadyExists => anyhow!("'{}' already exists", fpath),
+        _ => e.into(),
+    })
+}
+
+/// exports every record. values are written as they are, so sensitive ones get a warning.
+/// ctrl-c stops the export between records, before anything is written to the file
+fn export_all<'text>(
+    store: &Store,
+    f: impl FnOnce(Vec<Record>, &Cancel) -> anyhow::Result<usize>,
+) -> Result<Evaluation<'text>, EvalError<'text>> {
+    let records = store.get(Query::All);
+    let nsensitive = records
+        .iter()
+        .flat_map(|record| &record.fields)
+        .filter(|field| field.sensitive)
+        .count();
+
+    let cancel = Cancel::default();
+    let _interruptible = cancel::interruptible(&cancel);
+    let exported = f(records, &cancel).map_err(EvalError::Export)?;
+    let warnings = match nsensitive {
+        0 => vec![],
+        1 => vec!["⚠ 1 sensitive value was written in plain text".into()],
+        n => vec![format!(
+            "⚠ {} sensitive values were written in plain text",
+            n
+        )],
+    };
+    Ok(Evaluation::Export { exported, warnings })
+}
+
+/// ends an export once it is cancelled. `done` records were exported so far
+fn check_cancel(cancel: &Cancel, done: usize) -> anyhow::Result<()> {
+    match cancel.is_cancelled() {
+        true => Err(anyhow!(
+            "cancelled after {} records. nothing was exported",
+            done
+        )),
+        false => Ok(()),
+    }
+}
+
+/// the file is only created once everything is written to `buf`
+fn write_export(fpath: &str, buf: &[u8]) -> anyhow::Result<()> {
+    std::io::Write::write_all(&mut create_export(fpath)?, buf)?;
+    Ok(())
+}
+
+/// writes one line per record in the format expected by `import`.
+/// sensitive fields are prefixed with the `sensitive` keyword so they round-trip.
+/// tags are left out, `import` can't set them.
+fn export(fpath: &str, mut records: Vec<Record>, cancel: &Cancel) -> anyhow::Result<usize> {
+    use std::fmt::Write;
+
+    fn quote(record: &Record, text: &str) -> anyhow::Result<String> {
+        match text.contains(['\'', '\n']) {
+            true => Err(anyhow!(
+                "'{}' cannot be exported. single quotes and newlines are not importable",
+                record.name
+            )),
+            false => Ok(format!("'{}'", text)),
+        }
+    }
+
+    records.sort_by(|r1, r2| r1.name.cmp(&r2.name));
+
+    let mut buf = String::new();
+    for (done, record) in records.iter().enumerate() {
+        check_cancel(cancel, done)?;
+        write!(buf, "{}", quote(record, &record.name)?).ignore();
+        for field in record.fields.iter().filter(|f| f.attr != TAGS) {
+            if field.sensitive {
+                write!(buf, " sensitive").ignore();
+            }
+            write!(
+                buf,
+                " {} = {}",
+                quote(record, &field.attr)?,
+                quote(record, &field.value)?
+            )
+            .ignore();
+            if !field.kind.is_unknown() {
+                write!(buf, " type {}", field.kind).ignore();
+            }
+        }
+        writeln!(buf).ignore();
+    }
+
+    write_export(fpath, buf.as_bytes())?;
+    Ok(records.len())
+}
+
+/// writes one row per record with the columns `name` followed by the union of all attrs.
+/// tags are left out. sensitive values are written in plain text.
+fn export_csv(fpath: &str, mut records: Vec<Record>, cancel: &Cancel) -> anyhow::Result<usize> {
+    records.sort_by(|r1, r2| r1.name.cmp(&r2.name));
+
+    let attrs = records
+        .iter()
+        .flat_map(|r| r.fields.iter().map(|f| f.attr.as_str()))
+        .filter(|attr| *attr != TAGS)
+        .collect::<BTreeSet<_>>();
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(std::iter::once("name").chain(attrs.iter().copied()))?;
+
+    for (done, record) in records.iter().enumerate() {
+        check_cancel(cancel, done)?;
+        let values = attrs.iter().map(|attr| {
+            record
+                .fields
+                .iter()
+                .find(|f| f.attr == *attr)
+                .map_or("", |f| f.value.as_str())
+        });
+        writer.write_record(std::iter::once(record.name.as_str()).chain(values))?;
+    }
+
+    write_export(fpath, &writer.into_inner()?)?;
+    Ok(records.len())
+}
+
+/// writes one row per field with the columns `name,attr,value,sensitive`,
+/// records sorted by name and their fields by attr. tags are left out.
+/// sensitive values are written in plain text.
+fn export_csv_fields(
+    fpath: &str,
+    mut records: Vec<Record>,
+    cancel: &Cancel,
+) -> anyhow::Result<usize> {
+    records.sort_by(|r1, r2| r1.name.cmp(&r2.name));
+    for record in &mut records {
+        record.fields.sort_by(|f1, f2| f1.attr.cmp(&f2.attr));
+    }
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(["name", "attr", "value", "sensitive"])?;
+
+    for (done, record) in records.iter().enumerate() {
+        check_cancel(cancel, done)?;
+        for field in record.fields.iter().filter(|f| f.attr != TAGS) {
+            let sensitive = if field.sensitive { "true" } else { "false" };
+            writer.write_record([&record.name, &field.attr, &field.value, sensitive])?;
+        }
+    }
+
+    write_export(fpath, &writer.into_inner()?)?;
+    Ok(records.len())
+}
+
+/// writes the current fields of the records as a json array sorted by name.
+/// the history stays in the vault, it holds old sensitive values nobody asked for.
+/// sensitive values are written in plain text.
+fn export_json(fpath: &str, mut records: Vec<Record>, cancel: &Cancel) -> anyhow::Result<usize> {
+    #[derive(Serialize)]
+    struct Exported<'a> {
+        id: Uuid,
+        name: &'a str,
+        fields: &'a [Field],
+    }
+
+    records.sort_by(|r1, r2| r1.name.cmp(&r2.name));
+    let mut exported = Vec::with_capacity(records.len());
+    for (done, record) in records.iter().enumerate() {
+        check_cancel(cancel, done)?;
+        exported.push(Exported {
+            id: record.id,
+            name: &record.name,
+            fields: &record.fields,
+        });
+    }
+
+    write_export(fpath, &serde_json::to_vec_pretty(&exported)?)?;
+    Ok(records.len())
+}
+
+pub trait Cond<'text> {
+    fn test(&self, data: &Record, now: DateTime<Local>) -> bool;
+}
+
+impl<'text> Cond<'text> for Query<'text> {
+    fn test(&self, data: &Record, now: DateTime<Local>) -> bool {
+        match self {
+            Query::Or(cond) => cond.test(data, now),
+            Query::Name(name) => data.name == *name,
+            Query::All => true,
+        }
+    }
+}
+
+impl<'text> Cond<'text> for Or<'text> {
+    fn test(&self, data: &Record, now: DateTime<Local>) -> bool {
+        match self {
+            Or::And(cond) => cond.test(data, now),
+            Or::Or(lhs, rhs) => lhs.test(data, now) || rhs.test(data, now),
+        }
+    }
+}
+
+impl<'text> Cond<'text> for And<'text> {
+    fn test(&self, data: &Record, now: DateTime<Local>) -> bool {
+        match self {
+            And::Filter(cond) => cond.test(data, now),
+            And::And(lhs, rhs) => lhs.test(data, now) && rhs.test(data, now),
+        }
+    }
+}
+
+impl<'text> Cond<'text> for Filter<'text> {
+    fn test(&self, data: &Record, now: DateTime<Local>) -> bool {
+        match self {
+            Filter::Contains(cond) => cond.test(data, now),
+            Filter::StartsWith(cond) => cond.test(data, now),
+            Filter::EndsWith(cond) => cond.test(data, now),
+            Filter::Matches(cond) => cond.test(data, now),
+            Filter::Fuzzy(cond) => cond.test(data, now),
+            Filter::Cmp(cond) => cond.test(data, now),
+            Filter::Compare(cond) => cond.test(data, now),
+            Filter::Parens(q) => q.test(data, now),
+            Filter::Not(cond) => !cond.test(data, now),
+            Filter::Has(cond) => cond.test(data, now),
+            Filter::Missing(cond) => cond.test(data, now),
+            Filter::TagContains(cond) => cond.test(data, now),
+            Filter::FieldCount(cond) => cond.test(data, now),
+            Filter::Expired(cond) => cond.test(data, now),
+        }
+    }
+}
+
+impl<'text> Cond<'text> for Has<'text> {
+    fn test(&self, data: &Record, _now: DateTime<Local>) -> bool {
+        data.fields.iter().any(|f| f.attr == self.attr)
+    }
+}
+
+impl<'text> Cond<'text> for TagContains<'text> {
+    fn test(&self, data: &Record, _now: DateTime<Local>) -> bool {
+        data.tags().contains(&self.tag)
+    }
+}
+
+impl<'text> Cond<'text> for Missing<'text> {
+    fn test(&self, data: &Record, _now: DateTime<Local>) -> bool {
+        !data.fields.iter().any(|f| f.attr == self.attr)
+    }
+}
+
+impl<'text> Cond<'text> for Contains<'text> {
+    fn test(&self, data: &Record, _now: DateTime<Local>) -> bool {
+        let contains = |text: &str| match self.case_sensitive {
+            true => text.contains(self.substr),
+            false => text.to_lowercase().contains(&self.substr.to_lowercase()),
+        };
+
+        match self.attr {
+            "." => contains(&data.name),
+            attr => data
+                .fields
+                .iter()
+                .find(|f| f.attr == attr)
+                .is_some_and(|f| contains(&f.value)),
+        }
+    }
+}
+
+impl<'text> Cond<'text> for StartsWith<'text> {
+    fn test(&self, data: &Record, _now: DateTime<Local>) -> bool {
+        let starts_with = |text: &str| match self.case_sensitive {
+            true => text.starts_with(self.prefix),
+            false => text.to_lowercase().starts_with(&self.prefix.to_lowercase()),
+        };
+
+        match self.attr {
+            "." => starts_with(&data.name),
+            attr => data
+                .fields
+                .iter()
+                .find(|f| f.attr == attr)
+                .is_some_and(|f| starts_with(&f.value)),
+        }
+    }
+}
+
+impl<'text> Cond<'text> for EndsWith<'text> {
+    fn test(&self, data: &Record, _now: DateTime<Local>) -> bool {
+        let ends_with = |text: &str| match self.case_sensitive {
+            true => text.ends_with(self.suffix),
+            false => text.to_lowercase().ends_with(&self.suffix.to_lowercase()),
+        };
+
+        match self.attr {
+            "." => ends_with(&data.name),
+            attr => data
+                .fields
+                .iter()
+                .find(|f| f.attr == attr)
+                .is_some_and(|f| ends_with(&f.value)),
+        }
+    }
+}
+
+impl<'text> Cond<'text> for Matches<'text> {
+    fn test(&self, data: &Record, _now: DateTime<Local>) -> bool {
+        match self.attr {
+            "." => self.pat.find(&data.name).is_some(),
+            attr => data
+                .fields
+                .iter()
+                .find(|f| f.attr == attr)
+                .and_then(|f| self.pat.find(&f.value))
+                .is_some(),
+        }
+    }
+}
+
+/// exact match, except `true`/`false` which are compared case-insensitively
+impl<'text> Cond<'text> for Is<'text> {
+    fn test(&self, data: &Record, _now: DateTime<Local>) -> bool {
+        fn parse_bool(text: &str) -> Option<bool> {
+            match text {
+                t if t.eq_ignore_ascii_case("true") => Some(true),
+                t if t.eq_ignore_ascii_case("false") => Some(false),
+                _ => None,
+            }
+        }
+
+        let is = |value: &str| match (parse_bool(value), parse_bool(self.value)) {
+            (Some(lhs), Some(rhs)) => lhs == rhs,
+            _ => value == self.value,
+        };
+
+        match self.attr {
+            "." => is(&data.name),
+            attr => data
+                .fields
+                .iter()
+                .find(|f| f.attr == attr)
+                .is_some_and(|f| is(&f.value)),
+        }
+    }
+}
+
+/// both sides are parsed as `f64`. if either of them isn't a number
+/// (or the attr doesn't exist) the record simply doesn't match.
+impl<'text> Cond<'text> for Compare<'text> {
+    fn test(&self, data: &Record, _now: DateTime<Local>) -> bool {
+        let lhs = match self.attr {
+            "." => Some(data.name.as_str()),
+            attr => data
+                .fields
+                .iter()
+                .find(|f| f.attr == attr)
+                .map(|f| f.value.as_str()),
+        };
+
+        let (Some(Ok(lhs)), Ok(rhs)) = (
+            lhs.map(|lhs| lhs.trim().parse::<f64>()),
+            self.value.trim().parse::<f64>(),
+        ) else {
+            return false;
+        };
+
+        compare(&self.op, lhs, rhs)
+    }
+}
+
+impl<'text> Cond<'text> for FieldCount {
+    fn test(&self, data: &Record, _now: DateTime<Local>) -> bool {
+        let count = data.fields.iter().filter(|f| f.attr != TAGS).count();
+        compare(&self.op, count, self.count)
+    }
+}
+
+impl<'text> Cond<'text> for Fuzzy<'text> {
+    fn test(&self, data: &Record, _now: DateTime<Local>) -> bool {
+        let target = self.target.to_lowercase();
+        let close = |text: &str| distance::within(&text.to_lowercase(), &target, self.max_distance);
+
+        match self.attr {
+            "." => close(&data.name),
+            attr => data
+                .fields
+                .iter()
+                .find(|f| f.attr == attr)
+                .is_some_and(|f| close(&f.value)),
+        }
+    }
+}
+
+impl<'text> Cond<'text> for Expired {
+    fn test(&self, data: &Record, now: DateTime<Local>) -> bool {
+        expired(data, now)
+    }
+}
+
+/// whether a field of the record has had its value for longer than its `expires`
+fn expired(record: &Record, now: DateTime<Local>) -> bool {
+    let history = record.history.entries();
+    record.fields.iter().any(|field| {
+        let Some(days) = field.expires else {
+            return false;
+        };
+        // the oldest entry of the newest run that has the current value
+        let since = (history.iter().rev())
+            .take_while(|h| {
+                (h.fields.iter()).any(|f| f.attr == field.attr && f.value == field.value)
+            })
+            .last()
+            .map(|h| h.datetime);
+        let until = since.and_then(|since| Span::Days(days).after(since));
+        until.is_some_and(|until| now > until)
+    })
+}
+
+fn compare<T: PartialOrd>(op: &CompareOp, lhs: T, rhs: T) -> bool {
+    match op {
+        CompareOp::Gt => lhs > rhs,
+        CompareOp::Lt => lhs < rhs,
+        CompareOp::Gte => lhs >= rhs,
+        CompareOp::Lte => lhs <= rhs,
+    }
+}
+
+impl<'text> Display for EvalError<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalError::Lex(e) => write!(f, "{}", e),
+            EvalError::Parse(e) => write!(f, "{}", e),
+            EvalError::Import(e) => write!(f, "{}", e),
+            EvalError::Export(e) => write!(f, "{}", e),
+            EvalError::Totp(e) => write!(f, "{}", e),
+            EvalError::Share(e) => write!(f, "{}", e),
+            EvalError::Rollback(e) => write!(f, "{}", e),
+            EvalError::Tag(e) => write!(f, "{}", e),
+            EvalError::Codec(e) => write!(f, "{}", e),
+            EvalError::Ambiguous(e) => write!(f, "{}", e),
+            EvalError::Merge(e) => write!(f, "{}", e),
+            EvalError::ReadOnly(cmd) => {
+                write!(f, "the vault is open read-only. '{}' would change it", cmd)
+            }
+            EvalError::Gen => write!(f, "Unable to Generate! Try Again!"),
+        }
+    }
+}
+
+impl<'text> From<LexError> for EvalError<'text> {
+    fn from(value: LexError) -> Self {
+        EvalError::Lex(value)
+    }
+}
+
+impl<'text> From<ParseError<'text>> for EvalError<'text> {
+    fn from(value: ParseError<'text>) -> Self {
+        EvalError::Parse(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::to_json;
+    use crate::render::Json;
+    use crate::store::MockClock;
+    use crate::store::UNDO_LIMIT;
+    use pretty_assertions::assert_eq;
+
+    macro_rules! check {
+        ($store:expr, $cmd:expr, $expected:expr) => {
+            let cmd = $cmd;
+            let eval = eval(cmd, &mut $store).expect(&format!("unable to eval {}", cmd));
+            assert_eq!(eval.render(&PlainText), $expected);
+        };
+    }
+
+    macro_rules! eval {
+        ($store:expr, $($cmd:expr),*) => {
+            $ ( eval($cmd, $store).expect(&format!("unable to eval {}", $cmd)); )*
+        };
+    }
+
+    #[test]
+    fn test_set() {
+        let mut store = Store::new();
+
+        eval!(&mut store, "set gmail");
+        check!(&mut store, "show all", ["'gmail'"]);
+
+        eval!(&mut store, "set gmail user = zahash pass = supersecretpass");
+        check!(
+            &mut store,
+            "show all",
+            ["'gmail' pass='supersecretpass' user='zahash'"]
+        );
+
+        eval!(&mut store, "set gmail url = mail.google.com");
+        check!(
+            &mut store,
+            "show all",
+            ["'gmail' pass='supersecretpass' url='mail.google.com' user='zahash'"]
+        );
+
+        eval!(&mut store, "set gmail pass = updatedpass");
+        check!(
+            &mut store,
+            "show all",
+            ["'gmail' pass='updatedpass' url='mail.google.com' user='zahash'"]
+        );
+
+        eval!(&mut store, "set discord url = discord.com tags = chat,call");
+        check!(
+            &mut store,
+            "show all",
+            [
+                "'discord' tags='chat,call' url='discord.com'",
+                "'gmail' pass='updatedpass' url='mail.google.com' user='zahash'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_del() {
+        let mut store = Store::new();
+
+        check!(&mut store, "delete gmail", [] as [String; 0]);
+
+        eval!(
+            &mut store,
+            "set gmail url = mail.google.com sensitive pass = gpass"
+        );
+
+        check!(&mut store, "delete discord", [] as [String; 0]);
+
+        eval!(
+            &mut store,
+            "set discord user = doubledragon url = discord.com"
+        );
+
+        eval!(
+            &mut store,
+            "set gmail sensitive pass = gpass2",
+            "set gmail sensitive pass = gpass3"
+        );
+        check!(
+            &mut store,
+            "delete gmail",
+            ["'gmail' pass=***** url='mail.google.com' — 2 earlier versions in its history go with it"]
+        );
+
+        check!(
+            &mut store,
+            "show all",
+            ["'discord' url='discord.com' user='doubledragon'"]
+        );
+
+        check!(&mut store, "delete gmail user pass", [] as [String; 0]);
+        // the rest of the record stays, and so does its history
+        check!(
+            &mut store,
+            "delete discord user pass",
+            ["'discord' url='discord.com'"]
+        );
+
+        // nothing to mention once the history is cleared
+        eval!(
+            &mut store,
+            "set discord url = discord.gg",
+            "history clear discord"
+        );
+        check!(&mut store, "delete discord", ["'discord' url='discord.gg'"]);
+    }
+
+    #[test]
+    fn test_del_query() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set gmail user = zahash sensitive pass = gpass",
+            "set github user = zahash url = github.com",
+            "set discord user = doubledragon"
+        );
+
+        // nothing matches
+        check!(
+            &mut store,
+            "del user contains pablo",
+            ["nothing matched, nothing deleted"]
+        );
+
+        check!(
+            &mut store,
+            "del user contains zahash",
+            [
+                "'github' url='github.com' user='zahash'",
+                "'gmail' pass=***** user='zahash'",
+                "deleted 2 records"
+            ]
+        );
+        check!(&mut store, "show all", ["'discord' user='doubledragon'"]);
+
+        eval!(&mut store, "set example url = example.org");
+        check!(
+            &mut store,
+            "del where url contains EXAMPLE.org",
+            ["'example' url='example.org'", "deleted 1 record"]
+        );
+        check!(&mut store, "show all", ["'discord' user='doubledragon'"]);
+
+        // the rest of the store is still reachable by name
+        eval!(&mut store, "set twitch user = zahash");
+        check!(&mut store, "show twitch", ["'twitch' user='zahash'"]);
+
+        check!(
+            &mut store,
+            "del all",
+            [
+                "'discord' user='doubledragon'",
+                "'twitch' user='zahash'",
+                "deleted 2 records"
+            ]
+        );
+        check!(&mut store, "show all", [] as [String; 0]);
+    }
+
+    #[test]
+    fn test_expired() {
+        let clock = MockClock::at("2024-01-01T09:00:00+00:00");
+        let mut store = Store::new();
+        store.set_clock(clock.clone());
+        eval!(
+            &mut store,
+            "set gmail user = zahash sensitive pass = gpass expires 90",
+            "set discord user = hazash pass = dpass"
+        );
+        let record = |store: &Store| store.get(Query::Name("gmail")).pop().unwrap();
+        let start = store.now();
+        let days = |days| start + chrono::Duration::days(days);
+        let expires = |store: &Store| {
+            let record = record(store);
+            record
+                .fields
+                .iter()
+                .find(|f| f.attr == "pass")
+                .unwrap()
+                .expires
+        };
+
+        assert!(!expired(&record(&store), days(89)));
+        assert!(expired(&record(&store), days(91)));
+        // records without an expiry never expire
+        let discord = store.get(Query::Name("discord")).pop().unwrap();
+        assert!(!expired(&discord, days(10000)));
+
+        // other fields changing doesn't make the value newer
+        clock.advance(chrono::Duration::days(30));
+        eval!(&mut store, "set gmail user = amogus");
+        assert!(expired(&record(&store), days(91)));
+
+        // a new value does, and keeps the expiry
+        clock.advance(chrono::Duration::days(30));
+        let set = store.now();
+        eval!(&mut store, "set gmail sensitive pass = newpass");
+        assert_eq!(expires(&store), Some(90));
+        assert!(!expired(&record(&store), set + chrono::Duration::days(90)));
+        assert!(expired(&record(&store), set + chrono::Duration::days(91)));
+
+        eval!(
+            &mut store,
+            "set gmail sensitive pass = newpass expires never"
+        );
+        assert_eq!(expires(&store), None);
+        assert!(!expired(&record(&store), days(10000)));
+    }
+
+    #[test]
+    fn test_expired_uses_store_clock() {
+        let clock = MockClock::at("2024-01-01T09:00:00+00:00");
+        let mut store = Store::new();
+        store.set_clock(clock.clone());
+        eval!(
+            &mut store,
+            "set gmail sensitive pass = gpass expires 90",
+            "set discord sensitive pass = dpass expires 365"
+        );
+
+        check!(&mut store, "show expired", [] as [String; 0]);
+        clock.advance(chrono::Duration::days(91));
+        check!(&mut store, "show expired", ["'gmail' pass=*****"]);
+        clock.advance(chrono::Duration::days(365));
+        check!(
+            &mut store,
+            "show expired",
+            ["'discord' pass=*****", "'gmail' pass=*****"]
+        );
+    }
+
+    #[test]
+    fn test_stale_share_uses_store_clock() {
+        let clock = MockClock::at("2024-01-01T09:00:00+00:00");
+        let mut store = Store::new();
+        store.set_clock(clock.clone());
+        eval!(&mut store, "set gmail sensitive pass = gpass");
+        let record = store.get(Query::Name("gmail")).pop().unwrap();
+        let (armored, passphrase) = share::share(record, store.now()).unwrap();
+
+        let mut other = Store::new();
+        other.set_clock(clock.clone());
+        clock.advance(chrono::Duration::hours(23));
+        assert_eq!(
+            receive(&armored, &passphrase, &mut other)
+                .unwrap()
+                .render(&PlainText),
+            ["received 'gmail'"]
+        );
+
+        let mut other = Store::new();
+        other.set_clock(clock.clone());
+        clock.advance(chrono::Duration::hours(2));
+        let received = receive(&armored, &passphrase, &mut other)
+            .unwrap()
+            .render(&PlainText);
+        assert!(received[0].starts_with("this share was created on 2024-01-01"));
+    }
+
+    #[test]
+    fn test_expires_attr() {
+        let mut store = Store::new();
+        eval!(&mut store, "set x a = 1 expires = 90");
+
+        let record = store.get(Query::Name("x")).pop().unwrap();
+        let field = record.fields.iter().find(|f| f.attr == "expires").unwrap();
+        assert_eq!(field.value, "90");
+        assert!(record.fields.iter().all(|f| f.expires.is_none()));
+    }
+
+    #[test]
+    fn test_show_expired() {
+        let mut store = serde_json::from_str::<Store>(
+            r#"{"records":[{"id":"6c3a4a8e-4d2b-4b7e-9d55-1f6f0d7c2a11","name":"gmail","fields":[{"attr":"pass","value":"gpass","sensitive":true,"expires":30}],"history":[{"datetime":"2023-11-04T10:00:00+05:30","fields":[{"attr":"pass","value":"gpass","sensitive":true,"expires":30}]}]}],"version":"0.4.9"}"#,
+        )
+        .unwrap();
+        eval!(&mut store, "set discord pass = dpass expires 30");
+
+        check!(&mut store, "show expired", ["'gmail' pass=*****"]);
+        check!(&mut store, "list not expired", ["discord"]);
+    }
+
+    #[test]
+    fn test_set_where() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set gmail user = old@oldcorp.com url = mail.oldcorp.com",
+            "set wiki user = old@oldcorp.com url = wiki.oldcorp.com",
+            "set discord user = doubledragon url = discord.com"
+        );
+
+        check!(
+            &mut store,
+            "set where url contains oldcorp.com user = 'me@newcorp.com'",
+            ["updated 2 records"]
+        );
+        check!(
+            &mut store,
+            "show all",
+            [
+                "'discord' url='discord.com' user='doubledragon'",
+                "'gmail' url='mail.oldcorp.com' user='me@newcorp.com'",
+                "'wiki' url='wiki.oldcorp.com' user='me@newcorp.com'"
+            ]
+        );
+        // every record gets its own history entry
+        for name in ["gmail", "wiki"] {
+            let history = eval(&format!("history {}", name), &mut store)
+                .unwrap()
+                .render(&PlainText);
+            assert_eq!(history.len(), 2, "{:?}", history);
+            assert!(history[0].ends_with("user='me@newcorp.com'"));
+        }
+
+        // a record matching both sides of the query is updated once
+        check!(
+            &mut store,
+            "set where user contains newcorp or url contains discord team = infra",
+            ["updated 3 records"]
+        );
+        check!(
+            &mut store,
+            "set where team is infra and url contains wiki team = docs",
+            ["updated 1 record"]
+        );
+        check!(&mut store, "list team is infra", ["discord", "gmail"]);
+        check!(&mut store, "list team is docs", ["wiki"]);
+
+        check!(
+            &mut store,
+            "set where url contains pablo user = x",
+            ["nothing matched, nothing updated"]
+        );
+
+        let updated = eval(
+            "set where url contains oldcorp.com sensitive pass = 'tr0ub4dor&3 horse battery'",
+            &mut store,
+        )
+        .unwrap()
+        .render(&PlainText);
+        assert_eq!(updated.last().unwrap(), "updated 2 records");
+        check!(
+            &mut store,
+            "show has pass",
+            [
+                "'gmail' pass=***** team='infra' url='mail.oldcorp.com' user='me@newcorp.com'",
+                "'wiki' pass=***** team='docs' url='wiki.oldcorp.com' user='me@newcorp.com'"
+            ]
+        );
+
+        // the values aren't repeated by undo
+        check!(
+            &mut store,
+            "undo",
+            ["undo: set where url contains 'oldcorp.com'"]
+        );
+        check!(&mut store, "count has pass", ["0"]);
+
+        assert!(matches!(
+            eval("set where has url _tags = x", &mut store),
+            Err(EvalError::Tag(_))
+        ));
+    }
+
+    #[test]
+    fn test_del_query_trash() {
+        let mut store = Store::new();
+        eval!(
+            &mut store,
+            "set gmail user = zahash",
+            "set github user = zahash",
+            "set discord user = pablo"
+        );
+
+        eval!(&mut store, "del user is zahash");
+        check!(&mut store, "list", ["discord"]);
+        check!(
+            &mut store,
+            "trash",
+            ["'gmail' user='zahash'", "'github' user='zahash'"]
+        );
+        check!(&mut store, "undelete gmail", ["Restored!"]);
+        check!(&mut store, "list", ["discord", "gmail"]);
+    }
+
+    #[test]
+    fn test_trash() {
+        let mut store = Store::new();
+        check!(&mut store, "trash", ["trash is empty"]);
+        check!(
+            &mut store,
+            "undelete gmail",
+            ["'gmail' is not in the trash!"]
+        );
+
+        eval!(
+            &mut store,
+            "set gmail user = zahash sensitive pass = first",
+            "set gmail sensitive pass = second",
+            "set discord user = pablo",
+            "set github user = zahash"
+        );
+        let gmail = store.pick("gmail", None)[0].clone();
+
+        eval!(&mut store, "del gmail", "del user is pablo");
+        check!(&mut store, "list", ["github"]);
+        check!(
+            &mut store,
+            "trash",
+            ["'gmail' pass=***** user='zahash'", "'discord' user='pablo'"]
+        );
+
+        // deleting fields doesn't trash anything
+        eval!(&mut store, "del github user");
+        check!(
+            &mut store,
+            "trash",
+            ["'gmail' pass=***** user='zahash'", "'discord' user='pablo'"]
+        );
+
+        check!(&mut store, "undelete gmail", ["Restored!"]);
+        check!(
+            &mut store,
+            "reveal gmail",
+            ["'gmail' pass='second' user='zahash'"]
+        );
+        let restored = store.pick("gmail", None)[0].clone();
+        assert_eq!(restored.id, gmail.id);
+        assert_eq!(store.history("gmail").len(), 2);
+        check!(&mut store, "trash", ["'discord' user='pablo'"]);
+        check!(
+            &mut store,
+            "undelete gmail",
+            ["'gmail' is not in the trash!"]
+        );
+
+        // the latest of several with the same name comes back first
+        eval!(
+            &mut store,
+            "set discord user = pablo2",
+            "del discord",
+            "set discord user = pablo3"
+        );
+        check!(
+            &mut store,
+            "undelete discord",
+            ["'discord' already exists!"]
+        );
+        eval!(&mut store, "del discord");
+        check!(&mut store, "undelete discord", ["Restored!"]);
+        check!(&mut store, "show discord", ["'discord' user='pablo3'"]);
+
+        check!(&mut store, "undo", ["undo: undelete 'discord'"]);
+        check!(&mut store, "list", ["github", "gmail"]);
+    }
+
+    #[test]
+    fn test_restore_is_rollback_from_0() {
+        let store = || {
+            let mut store = Store::new();
+            eval!(
+                &mut store,
+                "set gmail user = zahash",
+                "set gmail user = sussolini",
+                "set gmail user = pablo"
+            );
+            store
+        };
+
+        for (restore, rollback, user) in [(0, 1, "pablo"), (1, 2, "sussolini"), (2, 3, "zahash")] {
+            let expected = [format!("'gmail' user='{}'", user)];
+            check!(
+                store(),
+                &format!("restore gmail {}", restore),
+                expected.clone()
+            );
+            check!(store(), &format!("rollback gmail {}", rollback), expected);
+        }
+    }
+
+    #[test]
+    fn test_restore_from_trash() {
+        let mut store = Store::new();
+        eval!(
+            &mut store,
+            "set gmail user = zahash",
+            "set gmail user = sussolini",
+            "del gmail"
+        );
+
+        // a name alone brings the record back from the trash, with an index it's the history
+        check!(&mut store, "restore gmail", ["Restored!"]);
+        check!(&mut store, "show gmail", ["'gmail' user='sussolini'"]);
+        eval!(&mut store, "restore gmail 1");
+        check!(&mut store, "show gmail", ["'gmail' user='zahash'"]);
+        check!(
+            &mut store,
+            "restore gmail",
+            ["'gmail' is not in the trash!"]
+        );
+    }
+
+    #[test]
+    fn test_empty_trash_one() {
+        let mut store = Store::new();
+        eval!(&mut store, "set gmail user = zahash", "del gmail");
+        check!(&mut store, "empty trash", ["deleted 1 record for good"]);
+    }
+
+    #[test]
+    fn test_empty_trash() {
+        let mut store = Store::new();
+        check!(&mut store, "empty trash", ["trash is empty"]);
+
+        eval!(
+            &mut store,
+            "set gmail user = zahash",
+            "set discord user = pablo",
+            "del all"
+        );
+        check!(&mut store, "empty trash", ["deleted 2 records for good"]);
+        check!(&mut store, "trash", ["trash is empty"]);
+        check!(
+            &mut store,
+            "undelete gmail",
+            ["'gmail' is not in the trash!"]
+        );
+        check!(&mut store, "show all", [] as [String; 0]);
+
+        // the trash is saved with the vault, but only if there is something in it
+        assert!(!serde_json::to_string(&store).unwrap().contains("trash"));
+        eval!(&mut store, "set gmail user = zahash", "del gmail");
+        let mut loaded: Store =
+            serde_json::from_str(&serde_json::to_string(&store).unwrap()).unwrap();
+        check!(&mut loaded, "undelete gmail", ["Restored!"]);
+        check!(&mut loaded, "show all", ["'gmail' user='zahash'"]);
+
+        check!(&mut store, "undo", ["undo: del 'gmail'"]);
+        eval!(&mut store, "del gmail", "empty trash");
+        check!(&mut store, "undo", ["undo: empty trash"]);
+        check!(&mut store, "trash", ["'gmail' user='zahash'"]);
+    }
+
+    /// writes `store` encrypted with `master_pass` to a new file in `dir`
+    fn vault(dir: &tempfile::TempDir, store: &Store, master_pass: &str) -> String {
+        let fpath = dir.path().join(uuid::Uuid::new_v4().to_string());
+        let kdf = Kdf::Pbkdf2 {
+            iterations: std::num::NonZeroU32::new(1).unwrap(),
+        };
+        crypt::dump(
+            &fpath,
+            &crypt::Password(master_pass),
+            kdf,
+            crypt::Compression::Auto,
+            store,
+        )
+        .unwrap();
+        fpath.to_str().unwrap().to_string()
+    }
+
+    /// `eval` that knows the master password of the vaults to merge
+    fn eval_merge<'text>(
+        cmd: &'text str,
+        store: &mut Store,
+    ) -> Result<Evaluation<'text>, EvalError<'text>> {
+        let options = EvalOptions {
+            merge_pass: |_| Some("other".into()),
+            ..Default::default()
+        };
+        eval_with(cmd, store, &options)
+    }
+
+    #[test]
+    fn test_merge() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut ours = Store::new();
+        eval!(
+            &mut ours,
+            "set gmail user = zahash sensitive pass = first",
+            "set discord user = pablo"
+        );
+        let mut theirs =
+            serde_json::from_str::<Store>(&serde_json::to_string(&ours).unwrap()).unwrap();
+        ours.set_author(Some("alice".into()));
+        theirs.set_author(Some("bob".into()));
+
+        eval!(&mut ours, "set gmail sensitive pass = alice");
+        eval!(
+            &mut theirs,
+            "set gmail sensitive pass = bob url = mail.google.com",
+            "set github user = zahash",
+            "tag discord chat"
+        );
+        eval!(&mut ours, "set discord user = pablo2");
+
+        let other = vault(&dir, &theirs, "other");
+        assert_eq!(
+            eval_merge(&format!("merge {}", other), &mut ours)
+                .unwrap()
+                .render(&PlainText),
+            [
+                "added 1 record, updated 2",
+                "kept the newest of 2 conflicting values:",
+                "    alice: 'discord' user",
+                "    bob: 'gmail' pass",
+            ]
+        );
+        check!(
+            &mut ours,
+            "reveal all",
+            [
+                "'discord' [chat] user='pablo2'",
+                "'github' user='zahash'",
+                "'gmail' pass='bob' url='mail.google.com' user='zahash'",
+            ]
+        );
+
+        // both histories, oldest first. a new entry only if the fields are a mix of both
+        let authors = |store: &Store, name| {
+            store
+                .history(name)
+                .into_iter()
+                .map(|h| h.author)
+                .collect::<Vec<_>>()
+        };
+        let (alice, bob) = (Some("alice".to_string()), Some("bob".to_string()));
+        assert_eq!(authors(&ours, "gmail"), [None, alice.clone(), bob.clone()]);
+        assert_eq!(
+            authors(&ours, "discord"),
+            [None, bob.clone(), alice.clone(), alice.clone()]
+        );
+        assert_eq!(authors(&ours, "github"), [bob]);
+
+        // merging again only finds the conflict that was resolved the other way
+        assert_eq!(
+            eval_merge(&format!("merge {}", other), &mut ours)
+                .unwrap()
+                .render(&PlainText),
+            [
+                "added 0 records, updated 0",
+                "kept the newest of 1 conflicting values:",
+                "    alice: 'discord' user",
+            ]
+        );
+
+        check!(&mut ours, "undo", [format!("undo: merge '{}'", other)]);
+        check!(&mut ours, "list", ["discord", "gmail"]);
+        check!(
+            &mut ours,
+            "reveal gmail",
+            ["'gmail' pass='alice' user='zahash'"]
+        );
+    }
+
+    #[test]
+    fn test_merge_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // different vaults, so different ids
+        let mut ours = Store::new();
+        let mut theirs = Store::new();
+        eval!(&mut ours, "set gmail user = zahash", "tag gmail work");
+        eval!(
+            &mut theirs,
+            "set gmail sensitive pass = hunter2",
+            "tag gmail social work",
+            "set discord user = pablo"
+        );
+        theirs.set_allow_duplicate_names(true).unwrap();
+        eval!(&mut theirs, "dup discord discord");
+
+        let other = vault(&dir, &theirs, "other");
+        // the second 'discord' is matched by name to the first one
+        assert_eq!(
+            eval_merge(&format!("merge {}", other), &mut ours)
+                .unwrap()
+                .render(&PlainText),
+            ["added 1 record, updated 1"]
+        );
+        check!(
+            &mut ours,
+            "reveal all",
+            [
+                "'discord' user='pablo'",
+                "'gmail' [work, social] pass='hunter2' user='zahash'",
+            ]
+        );
+
+        // several records share the name, so which one is meant is unclear
+        let mut ours = Store::new();
+        ours.set_allow_duplicate_names(true).unwrap();
+        eval!(&mut ours, "set discord user = pablo", "dup discord discord");
+        let mut theirs = Store::new();
+        eval!(&mut theirs, "set discord user = pablo2");
+        let other = vault(&dir, &theirs, "other");
+        assert_eq!(
+            eval_merge(&format!("merge {}", other), &mut ours)
+                .unwrap()
+                .render(&PlainText),
+            ["added 1 record, updated 0"]
+        );
+        check!(&mut ours, "count discord", ["3"]);
+    }
+
+    #[test]
+    fn test_merge_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new();
+
+        let missing = dir.path().join("missing");
+        let missing = missing.to_str().unwrap();
+        let err = |cmd: &str, store: &mut Store| match eval_merge(cmd, store) {
+            Ok(_) => panic!("evaluated {}", cmd),
+            Err(e) => e.to_string(),
+        };
+        assert_eq!(
+            err(&format!("merge {}", missing), &mut store),
+            format!("'{}' not found", missing)
+        );
+        // and it wasn't created either
+        assert!(!std::path::Path::new(missing).exists());
+
+        let other = vault(&dir, &Store::new(), "not the other");
+        assert_eq!(
+            err(&format!("merge {}", other), &mut store),
+            "Master password incorrect."
+        );
+        check!(&mut store, "undo", ["nothing to undo"]);
+    }
+
+    #[test]
+    fn test_watch() {
+        let mut store = Store::new();
+        check!(&mut store, "watches", ["nothing is watched"]);
+
+        eval!(
+            &mut store,
+            "set gmail user = zahash",
+            "set github user = zahash totp = JBSWY3DPEHPK3PXP",
+            "set discord user = pablo"
+        );
+        check!(
+            &mut store,
+            "watch missing totp",
+            ["watching missing totp: 2 matching"]
+        );
+        check!(
+            &mut store,
+            "watch missing totp",
+            ["already watching missing totp"]
+        );
+        check!(&mut store, "watch gmail", ["watching 'gmail': 1 matching"]);
+        assert_eq!(store.take_notices(), Vec::<String>::new());
+
+        // only when the matches change
+        eval!(&mut store, "show all", "set github url = github.com");
+        assert_eq!(store.take_notices(), Vec::<String>::new());
+        eval!(&mut store, "set gmail user = zahash");
+        assert_eq!(store.take_notices(), Vec::<String>::new());
+
+        eval!(&mut store, "set gmail totp = JBSWY3DPEHPK3PXP");
+        assert_eq!(
+            store.take_notices(),
+            [
+                "watch missing totp: 1 matching, was 2 (- 'gmail')",
+                "watch 'gmail': 1 matching (~ 'gmail')",
+            ]
+        );
+        // taken only once
+        assert_eq!(store.take_notices(), Vec::<String>::new());
+
+        eval!(&mut store, "set discord2 user = pablo", "del github totp");
+        assert_eq!(
+            store.take_notices(),
+            [
+                "watch missing totp: 2 matching, was 1 (+ 'discord2')",
+                "watch missing totp: 3 matching, was 2 (+ 'github')",
+            ]
+        );
+
+        eval!(&mut store, "rename discord chat");
+        assert_eq!(
+            store.take_notices(),
+            ["watch missing totp: 3 matching (~ 'chat')"]
+        );
+
+        // undo, redo and commands that fail are changes like any other
+        eval!(&mut store, "undo");
+        assert_eq!(
+            store.take_notices(),
+            ["watch missing totp: 3 matching (~ 'discord')"]
+        );
+        eval("rename discord github", &mut store).unwrap();
+        assert_eq!(store.take_notices(), Vec::<String>::new());
+
+        check!(
+            &mut store,
+            "watches",
+            ["missing totp: 3 matching", "'gmail': 1 matching"]
+        );
+        check!(&mut store, "unwatch gmail", ["stopped watching 'gmail'"]);
+        check!(&mut store, "unwatch gmail", ["not watching 'gmail'"]);
+        eval!(&mut store, "del gmail", "del all");
+        assert_eq!(
+            store.take_notices(),
+            ["watch missing totp: 0 matching, was 3 (- 'discord', - 'discord2', - 'github')"]
+        );
+        check!(&mut store, "watches", ["missing totp: 0 matching"]);
+    }
+
+    #[test]
+    fn test_duplicate_names() {
+        let mut store = Store::new();
+
+        eval!(&mut store, "set gmail user = zahash");
+        check!(
+            &mut store,
+            "allow-duplicate-names",
+            ["allow-duplicate-names: off"]
+        );
+        // unique names don't need to be told apart
+        assert!(matches!(
+            eval("set gmail where user is zahash pass = x", &mut store),
+            Err(EvalError::Ambiguous(_))
+        ));
+
+        check!(
+            &mut store,
+            "allow-duplicate-names on",
+            ["allow-duplicate-names: on"]
+        );
+        // a set without 'where' would be ambiguous between updating and adding
+        assert!(matches!(
+            eval("set gmail user = pablo", &mut store),
+            Err(EvalError::Ambiguous(_))
+        ));
+        eval!(
+            &mut store,
+            "set gmail where user is pablo user = pablo sensitive pass = ppass",
+            "set gmail where user is zahash sensitive pass = zpass"
+        );
+        check!(
+            &mut store,
+            "show gmail",
+            [
+                "'gmail' pass=***** user='zahash'",
+                "'gmail' pass=***** user='pablo'"
+            ]
+        );
+
+        for cmd in [
+            "del gmail",
+            "del gmail pass",
+            "copy gmail pass",
+            "rename gmail gmail2",
+            "history gmail",
+            "set gmail where has pass pass = x",
+            "allow-duplicate-names off",
+        ] {
+            assert!(
+                matches!(eval(cmd, &mut store), Err(EvalError::Ambiguous(_))),
+                "{}",
+                cmd
+            );
+        }
+
+        eval!(&mut store, "del gmail where user is pablo pass");
+        check!(
+            &mut store,
+            "reveal gmail",
+            ["'gmail' pass='zpass' user='zahash'", "'gmail' user='pablo'"]
+        );
+        check!(
+            &mut store,
+            "rename gmail where user is pablo gmail2",
+            ["Renamed!"]
+        );
+        // unique again, so no 'where' needed
+        check!(
+            &mut store,
+            "tag gmail2 personal",
+            ["'gmail2' [personal] user='pablo'"]
+        );
+        check!(
+            &mut store,
+            "allow-duplicate-names off",
+            ["allow-duplicate-names: off"]
+        );
+        check!(
+            &mut store,
+            "show all",
+            [
+                "'gmail' pass=***** user='zahash'",
+                "'gmail2' [personal] user='pablo'"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_count() {
+        let mut store = Store::new();
+        check!(&mut store, "count all", ["0"]);
+
+        eval!(
+            &mut store,
+            "set gmail user = zahash url = mail.google.com",
+            "set gdrive user = benito url = drive.google.com",
+            "set discord user = zahash"
+        );
+
+        check!(&mut store, "count all", ["3"]);
+        check!(&mut store, "count gmail", ["1"]);
+        check!(&mut store, "count twitch", ["0"]);
+        check!(&mut store, "count url contains google", ["2"]);
+        check!(&mut store, "count user is zahash", ["2"]);
+        check!(
+            &mut store,
+            "count url contains google and not user is zahash",
+            ["1"]
+        );
+        check!(&mut store, "count missing url or user is benito", ["2"]);
+
+        // every record sharing a name is counted
+        eval!(
+            &mut store,
+            "allow-duplicate-names on",
+            "set gmail where user is benito user = benito"
+        );
+        check!(&mut store, "count gmail", ["2"]);
+        assert_eq!(
+            eval("count gmail", &mut store).unwrap().render(&Json),
+            ["2"]
+        );
+    }
+
+    #[test]
+    fn test_codec() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set aws sensitive key = 'aHVudGVyMg==' token = '68756e74657232' raw = '//8=' user = zahash"
+        );
+
+        check!(&mut store, "decode aws key b64 reveal", ["hunter2"]);
+        check!(&mut store, "decode aws token hex reveal", ["hunter2"]);
+        check!(&mut store, "encode aws user b64 reveal", ["emFoYXNo"]);
+        check!(&mut store, "encode aws user hex reveal", ["7a6168617368"]);
+
+        for (cmd, err) in [
+            (
+                "decode aws user hex reveal",
+                "not valid hex: unexpected character at 1",
+            ),
+            (
+                "decode aws raw b64 reveal",
+                "the decoded value is binary, not text",
+            ),
+            (
+                "decode aws token b64 reveal",
+                "not valid base64: unexpected character at 14",
+            ),
+            ("decode aws pass b64 reveal", "'aws' has no 'pass'"),
+            ("decode gmail key b64 reveal", "'gmail' not found"),
+        ] {
+            match eval(cmd, &mut store) {
+                Err(e @ EvalError::Codec(_)) => assert_eq!(e.to_string(), err),
+                _ => panic!("{}", cmd),
+            }
+        }
+        // nothing was changed
+        check!(
+            &mut store,
+            "reveal aws",
+            ["'aws' key='aHVudGVyMg==' raw='//8=' token='68756e74657232' user='zahash'"]
+        );
+    }
+
+    #[test]
+    fn test_sort_limit() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set web port = 8080 user = Zed",
+            "set ssh port = 22 user = amy",
+            "set db port = 'not a port' user = bob",
+            "set cache",
+            "set mail port = 587 sensitive user = aaron"
+        );
+
+        check!(
+            &mut store,
+            "show all limit 2",
+            ["'cache'", "'db' port='not a port' user='bob'"]
+        );
+        // numbers by value, text without case and records without it last
+        check!(
+            &mut store,
+            "show has port sort port",
+            [
+                "'ssh' port='22' user='amy'",
+                "'mail' port='587' user=*****",
+                "'web' port='8080' user='Zed'",
+                "'db' port='not a port' user='bob'"
+            ]
+        );
+        // sensitive values don't give away where they would be
+        check!(
+            &mut store,
+            "show all sort user limit 3",
+            [
+                "'ssh' port='22' user='amy'",
+                "'db' port='not a port' user='bob'",
+                "'web' port='8080' user='Zed'"
+            ]
+        );
+        check!(
+            &mut store,
+            "reveal all sort user limit 1",
+            ["'mail' port='587' user='aaron'"]
+        );
+        // ties stay by name
+        check!(
+            &mut store,
+            "show all sort nothing",
+            [
+                "'cache'",
+                "'db' port='not a port' user='bob'",
+                "'mail' port='587' user=*****",
+                "'ssh' port='22' user='amy'",
+                "'web' port='8080' user='Zed'"
+            ]
+        );
+        // more than there are
+        check!(
+            &mut store,
+            "show port > 1000 limit 50",
+            ["'web' port='8080' user='Zed'"]
+        );
+        check!(&mut store, "show twitch limit 3", [] as [String; 0]);
+        assert_eq!(
+            eval("show --json all sort port limit 1", &mut store)
+                .unwrap()
+                .render(&Json)[0]
+                .matches("\"name\":\"ssh\"")
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_list() {
+        let mut store = Store::new();
+        check!(&mut store, "list", [] as [String; 0]);
+
+        eval!(
+            &mut store,
+            "set twitch user = amogus url = twitch.tv",
+            "set gmail user = zahash sensitive pass = gpass url = mail.google.com",
+            "set 'bank of sus' sensitive pin = 1234 sensitive pass = bpass"
+        );
+
+        check!(&mut store, "list", ["bank of sus", "gmail", "twitch"]);
+        check!(&mut store, "list all", ["bank of sus", "gmail", "twitch"]);
+        check!(&mut store, "list gmail", ["gmail"]);
+        check!(&mut store, "list discord", [] as [String; 0]);
+        check!(&mut store, "list has url and not user is amogus", ["gmail"]);
+
+        // not even records with nothing but sensitive fields show a value
+        let listed = eval("list has pass", &mut store)
+            .unwrap()
+            .render(&PlainText);
+        assert_eq!(listed, ["bank of sus", "gmail"]);
+        for secret in ["1234", "bpass", "gpass"] {
+            assert!(listed.iter().all(|line| !line.contains(secret)));
+        }
+    }
+
+    #[test]
+    fn test_count_agrees_with_show() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set gmail user = zahash pass = pass123 url = mail.google.com",
+            "set discord user = hazash pass = dpass123 url = discord.com",
+            "set twitch user = amogus pass = tpass123",
+            "set sus user = sussolini name = potatus"
+        );
+
+        for query in [
+            "all",
+            "discord",
+            "twitch2",
+            r#"user contains AsH and url matches '\.com'"#,
+            "url contains google or user is amogus",
+            "pass matches '[a-z]+123' and ( user is amogus or user contains 'ash' )",
+            "url startswith MAIL. or . endswith CH",
+            "user endswith ash and . startswith disc",
+            "pass startswith 123",
+            "name is sus",
+            "not has url",
+        ] {
+            let shown = eval(&format!("show {}", query), &mut store)
+                .unwrap()
+                .render(&PlainText)
+                .len();
+            check!(&mut store, &format!("count {}", query), [shown.to_string()]);
+        }
+    }
+
+    #[test]
+    fn test_startswith_endswith() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set gmail user = zahash pass = pass123 url = mail.google.com",
+            "set discord user = hazash pass = dpass123 url = discord.com",
+            "set twitch user = amogus pass = tpass123"
+        );
+
+        check!(
+            &mut store,
+            "show url startswith MAIL. or . endswith CH",
+            [
+                "'gmail' pass='pass123' url='mail.google.com' user='zahash'",
+                "'twitch' pass='tpass123' user='amogus'"
+            ]
+        );
+        check!(
+            &mut store,
+            "show user endswith ash and . startswith disc",
+            ["'discord' pass='dpass123' url='discord.com' user='hazash'"]
+        );
+        check!(&mut store, "show pass startswith 123", [] as [String; 0]);
+    }
+
+    #[test]
+    fn test_startswith_endswith_cs() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set gmail user = zahash pass = pass123 url = mail.google.com",
+            "set discord user = hazash pass = dpass123 url = discord.com",
+            "set twitch user = amogus pass = tpass123"
+        );
+
+        check!(
+            &mut store,
+            "show url cs startswith MAIL. or . cs endswith CH",
+            [] as [String; 0]
+        );
+        check!(
+            &mut store,
+            "show url cs startswith mail. or . cs endswith ch",
+            [
+                "'gmail' pass='pass123' url='mail.google.com' user='zahash'",
+                "'twitch' pass='tpass123' user='amogus'"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_show_reveal() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set gmail user = zahash pass = pass123 url = mail.google.com",
+            "set discord user = hazash pass = dpass123 url = discord.com",
+            "set twitch user = amogus pass = tpass123"
+        );
+
+        check!(
+            &mut store,
+            "show discord",
+            ["'discord' pass='dpass123' url='discord.com' user='hazash'"]
+        );
+        check!(
+            &mut store,
+            "show all",
+            [
+                "'discord' pass='dpass123' url='discord.com' user='hazash'",
+                "'gmail' pass='pass123' url='mail.google.com' user='zahash'",
+                "'twitch' pass='tpass123' user='amogus'"
+            ]
+        );
+        check!(
+            &mut store,
+            r#"show user contains AsH and url matches '\.com'"#,
+            [
+                "'discord' pass='dpass123' url='discord.com' user='hazash'",
+                "'gmail' pass='pass123' url='mail.google.com' user='zahash'"
+            ]
+        );
+        check!(
+            &mut store,
+            r#"show url contains google or user is amogus"#,
+            [
+                "'gmail' pass='pass123' url='mail.google.com' user='zahash'",
+                "'twitch' pass='tpass123' user='amogus'"
+            ]
+        );
+        check!(
+            &mut store,
+            "show pass matches '[a-z]+123' and ( user is amogus or user contains 'ash' )",
+            [
+                "'discord' pass='dpass123' url='discord.com' user='hazash'",
+                "'gmail' pass='pass123' url='mail.google.com' user='zahash'",
+                "'twitch' pass='tpass123' user='amogus'"
+            ]
+        );
+
+        eval!(&mut store, "set sus user = sussolini name = potatus");
+        check!(&mut store, "show name is sus", [] as [String; 0]);
+        check!(
+            &mut store,
+            "show name is potatus",
+            ["'sus' name='potatus' user='sussolini'"]
+        );
+        check!(
+            &mut store,
+            "show . is sus",
+            ["'sus' name='potatus' user='sussolini'"]
+        );
+        check!(
+            &mut store,
+            "show . contains SUS",
+            ["'sus' name='potatus' user='sussolini'"]
+        );
+
+        eval!(&mut store, "set sus secret pass = supahotfire");
+        check!(
+            &mut store,
+            "show sus",
+            ["'sus' name='potatus' pass=***** user='sussolini'"]
+        );
+
+        eval!(&mut store, "set sus sensitive user = sussolini");
+        check!(
+            &mut store,
+            "show sus",
+            ["'sus' name='potatus' pass=***** user=*****"]
+        );
+        check!(
+            &mut store,
+            "reveal sus",
+            ["'sus' name='potatus' pass='supahotfire' user='sussolini'"]
+        );
+    }
+
+    #[test]
+    fn test_not() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set gmail user = zahash pass = pass123 url = mail.google.com",
+            "set discord user = zahash pass = dpass url = discord.com"
+        );
+
+        check!(
+            &mut store,
+            "show not (url contains google)",
+            ["'discord' pass='dpass' url='discord.com' user='zahash'"]
+        );
+        check!(
+            &mut store,
+            "show user is zahash and not (pass contains 123)",
+            ["'discord' pass='dpass' url='discord.com' user='zahash'"]
+        );
+        check!(
+            &mut store,
+            "show not not url contains google",
+            ["'gmail' pass='pass123' url='mail.google.com' user='zahash'"]
+        );
+        check!(&mut store, "show not all", [] as [String; 0]);
+    }
+
+    #[test]
+    fn test_has_missing() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set gmail user = zahash url = mail.google.com",
+            "set github user = zahash totp = JBSWY3DPEHPK3PXP",
+            "set twitch"
+        );
+
+        check!(
+            &mut store,
+            "show missing url",
+            ["'github' totp='JBSWY3DPEHPK3PXP' user='zahash'", "'twitch'"]
+        );
+        check!(
+            &mut store,
+            "show has totp",
+            ["'github' totp='JBSWY3DPEHPK3PXP' user='zahash'"]
+        );
+        check!(
+            &mut store,
+            "show has user and not has totp",
+            ["'gmail' url='mail.google.com' user='zahash'"]
+        );
+        check!(
+            &mut store,
+            "show missing user or has url",
+            ["'gmail' url='mail.google.com' user='zahash'", "'twitch'"]
+        );
+        check!(&mut store, "show lacks user", ["'twitch'"]);
+        check!(
+            &mut store,
+            "show has user and lacks url",
+            ["'github' totp='JBSWY3DPEHPK3PXP' user='zahash'"]
+        );
+    }
+
+    #[test]
+    fn test_case_sensitive() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set gmail user = Bob",
+            "set GitHub user = bobby"
+        );
+
+        check!(&mut store, "list user contains BOB", ["GitHub", "gmail"]);
+        check!(&mut store, "list user cs contains Bob", ["gmail"]);
+        check!(&mut store, "list user cs contains BOB", [] as [String; 0]);
+        check!(&mut store, "list user matches '^bob$'", ["gmail"]);
+        check!(&mut store, "list user cs matches '^bob'", ["GitHub"]);
+        check!(&mut store, "list . matches '^g'", ["GitHub", "gmail"]);
+        check!(&mut store, "list . cs matches '^G'", ["GitHub"]);
+        check!(&mut store, "list . cs contains git", [] as [String; 0]);
+    }
+
+    #[test]
+    fn test_fuzzy() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set GitHub url = github.com",
+            "set gitlab url = gitlab.com",
+            "set gmail user = Zahash",
+            "set twitch"
+        );
+
+        check!(&mut store, "list . fuzzy gitub", ["GitHub"]);
+        check!(&mut store, "list . fuzzy GITUB 1", ["GitHub"]);
+        check!(&mut store, "list . fuzzy gitub 2", ["GitHub", "gitlab"]);
+        check!(&mut store, "list . fuzzy gmial 2", ["gmail"]);
+        check!(&mut store, "list url fuzzy gitlab.co", ["gitlab"]);
+        check!(&mut store, "list user fuzzy zahsh", ["gmail"]);
+        check!(
+            &mut store,
+            "list user fuzzy zahsh and . fuzzy gmial 2",
+            ["gmail"]
+        );
+        check!(&mut store, "list pass fuzzy twitch 5", [] as [String; 0]);
+    }
+
+    #[test]
+    fn test_compare() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set web port = 8080",
+            "set ssh port = 22",
+            "set db port = 'not a port'",
+            "set cache",
+            "set 10 amount = 100.5"
+        );
+
+        check!(&mut store, "show port > 8000", ["'web' port='8080'"]);
+        check!(&mut store, "show port < 8080", ["'ssh' port='22'"]);
+        check!(
+            &mut store,
+            "show port >= 22",
+            ["'ssh' port='22'", "'web' port='8080'"]
+        );
+        check!(
+            &mut store,
+            "show port <= 8080 and port >= 8080",
+            ["'web' port='8080'"]
+        );
+        check!(&mut store, "show amount <= 100.5", ["'10' amount='100.5'"]);
+        check!(&mut store, "show amount > 1e2", ["'10' amount='100.5'"]);
+        check!(&mut store, "show . >= 10", ["'10' amount='100.5'"]);
+        check!(&mut store, "show port gt 8000", ["'web' port='8080'"]);
+        check!(
+            &mut store,
+            "show port lte 8080 and port gte 8080",
+            ["'web' port='8080'"]
+        );
+        check!(
+            &mut store,
+            "show port gte 0",
+            ["'ssh' port='22'", "'web' port='8080'"]
+        );
+    }
+
+    #[test]
+    fn test_compare_not_a_number() {
+        let mut store = Store::new();
+        eval!(&mut store, "set db port = 'not a port'");
+
+        for cmd in ["show port < abc", "show port lt 'not a port'"] {
+            assert!(matches!(
+                eval(cmd, &mut store),
+                Err(EvalError::Parse(ParseError::SyntaxError(3, "not a number")))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_field_count() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set empty",
+            "set one user = zahash",
+            "set two user = zahash sensitive pass = x",
+            "set tagged user = zahash",
+            "tag tagged work social",
+            "set odd fields = many"
+        );
+
+        check!(&mut store, "list fields lt 1", ["empty"]);
+        // exactly at the threshold
+        check!(
+            &mut store,
+            "list fields < 2",
+            ["empty", "odd", "one", "tagged"]
+        );
+        check!(
+            &mut store,
+            "list fields <= 2",
+            ["empty", "odd", "one", "tagged", "two"]
+        );
+        check!(&mut store, "list fields >= 2", ["two"]);
+        check!(&mut store, "list fields > 2", [] as [String; 0]);
+        check!(&mut store, "list fields gte 0 and fields lte 0", ["empty"]);
+        check!(&mut store, "list not fields > 0", ["empty"]);
+        // the attr called 'fields' is still reachable
+        check!(&mut store, "list fields is many", ["odd"]);
+        check!(&mut store, "list has fields", ["odd"]);
+    }
+
+    #[test]
+    fn test_is_bool() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set github mfa = TRUE",
+            "set gitlab mfa = false",
+            "set twitch mfa = yes"
+        );
+
+        check!(&mut store, "show mfa is true", ["'github' mfa='TRUE'"]);
+        check!(&mut store, "show mfa is False", ["'gitlab' mfa='false'"]);
+        check!(&mut store, "show mfa is yes", ["'twitch' mfa='yes'"]);
+        check!(&mut store, "show mfa is YES", [] as [String; 0]);
+    }
+
+    #[test]
+    fn test_json() {
+        fn json(store: &mut Store, cmd: &str) -> serde_json::Value {
+            match eval(cmd, store).unwrap().render(&PlainText).as_slice() {
+                [line] => serde_json::from_str(line).unwrap(),
+                lines => panic!("expected a single line of json but got {:?}", lines),
+            }
+        }
+
+        let mut store = Store::new();
+        eval!(
+            &mut store,
+            "set gmail user = zahash sensitive pass = gpass",
+            "set gmail sensitive pass = newpass",
+            "set discord user = hazash"
+        );
+
+        let shown = json(&mut store, "show --json all");
+        assert_eq!(shown[0]["name"], "discord");
+        assert_eq!(shown[1]["name"], "gmail");
+        assert_eq!(
+            shown[1]["fields"],
+            serde_json::json!([
+                { "attr": "pass", "value": null, "sensitive": true },
+                { "attr": "user", "value": "zahash", "sensitive": false }
+            ])
+        );
+        assert_eq!(shown[1]["history"].as_array().unwrap().len(), 2);
+        assert!(shown[1]["history"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|h| h["fields"][0]["value"].is_null()));
+
+        let revealed = json(&mut store, "reveal --json gmail");
+        assert_eq!(revealed.as_array().unwrap().len(), 1);
+        assert_eq!(revealed[0]["fields"][0]["value"], "newpass");
+        assert_eq!(revealed[0]["history"][0]["fields"][0]["value"], "gpass");
+
+        assert_eq!(
+            json(&mut store, "show --json twitch"),
+            serde_json::json!([])
+        );
+        assert_eq!(
+            to_json(&eval("show user is hazash", &mut store).unwrap()),
+            serde_json::json!([shown[0]])
+        );
+        assert!(to_json(&eval("set twitch", &mut store).unwrap()).is_null());
+    }
+
+    #[test]
+    fn test_history() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set sus user = 'benito sussolini' sensitive pass = amogus"
+        );
+        eval!(&mut store, "set sus user = 'pablo susscobar'");
+        eval!(&mut store, "set sus user = 'pablo susscobar'");
+        eval!(&mut store, "del sus user");
+        eval!(&mut store, "set sus pass = potatus");
+        eval!(&mut store, "set sus note = 'this is the latest'");
+
+        check!(
+            &mut store,
+            "show sus",
+            ["'sus' note='this is the latest' pass='potatus'"]
+        );
+        match eval("history sus", &mut store)
+            .unwrap()
+            .render(&PlainText)
+            .as_slice()
+        {
+            [h1, h2, h3, h4, h5] => {
+                assert!(h1.ends_with("note='this is the latest' pass='potatus'"));
+                assert!(h2.ends_with("pass='potatus'"));
+                assert!(h3.ends_with("pass=*****"));
+                assert!(h4.ends_with("pass=***** user='pablo susscobar'"));
+                assert!(h5.ends_with("pass=***** user='benito sussolini'"));
+            }
+            _ => panic!(),
+        }
+
+        check!(&mut store, "history blah", [] as [String; 0]);
+    }
+
+    #[test]
+    fn test_history_author() {
+        let mut store = Store::new();
+
+        eval!(&mut store, "set sus user = 'benito sussolini'");
+        store.set_author(Some("pablo".into()));
+        eval!(&mut store, "set sus user = 'pablo susscobar'");
+        store.set_author(Some("joseph".into()));
+        eval!(&mut store, "del sus user");
+        // unchanged fields add no entry, so nothing to attribute
+        eval!(&mut store, "del sus user");
+
+        match eval("history sus", &mut store)
+            .unwrap()
+            .render(&PlainText)
+            .as_slice()
+        {
+            [h1, h2, h3] => {
+                assert!(h1.ends_with(") (joseph)"));
+                assert!(h2.ends_with(") (pablo) user='pablo susscobar'"));
+                assert!(h3.ends_with(") user='benito sussolini'"));
+                assert!(!h3.contains("(pablo)") && !h3.contains("(joseph)"));
+            }
+            lines => panic!("{:?}", lines),
+        }
+
+        // the author is a setting of the session and not part of the vault
+        let json = serde_json::to_value(&store).unwrap();
+        assert!(json.get("author").is_none());
+        assert!(json["records"][0]["history"][0].get("author").is_none());
+        assert_eq!(json["records"][0]["history"][1]["author"], "pablo");
+
+        let loaded = serde_json::from_str::<Store>(&json.to_string()).unwrap();
+        let authors = loaded
+            .history("sus")
+            .into_iter()
+            .map(|h| h.author)
+            .collect::<Vec<_>>();
+        assert_eq!(authors, [None, Some("pablo".into()), Some("joseph".into())]);
+    }
+
+    #[test]
+    fn test_reveal_history() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set sus user = 'benito sussolini' sensitive pass = amogus"
+        );
+        match eval("reveal history sus", &mut store)
+            .unwrap()
+            .render(&PlainText)
+            .as_slice()
+        {
+            [h1] => assert!(h1.ends_with("pass='amogus' user='benito sussolini'")),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_history_limit() {
+        let mut store = Store::new();
+
+        check!(
+            &mut store,
+            "history limit show",
+            ["history limit: 50 entries per record"]
+        );
+        check!(
+            &mut store,
+            "history limit set 3",
+            ["history limit: 3 entries per record"]
+        );
+
+        for pass in ["a", "b", "c", "d", "e"] {
+            eval!(&mut store, &format!("set gmail pass = {}", pass));
+        }
+        match eval("history gmail", &mut store)
+            .unwrap()
+            .render(&PlainText)
+            .as_slice()
+        {
+            [h1, h2, h3] => {
+                assert!(h1.ends_with("pass='e'"));
+                assert!(h2.ends_with("pass='d'"));
+                assert!(h3.ends_with("pass='c'"));
+            }
+            lines => panic!("{:?}", lines),
+        }
+
+        // saved with the vault
+        let loaded =
+            serde_json::from_str::<Store>(&serde_json::to_string(&store).unwrap()).unwrap();
+        assert_eq!(loaded.history_limit(), Some(3));
+
+        check!(
+            &mut store,
+            "history limit clear",
+            ["history limit: none, every entry is kept"]
+        );
+        for pass in ["f", "g"] {
+            eval!(&mut store, &format!("set gmail pass = {}", pass));
+        }
+        assert_eq!(store.history("gmail").len(), 5);
+    }
+
+    #[test]
+    fn test_tags() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set gmail user = zahash sensitive pass = ninja",
+            "set discord user = pablo",
+            "set github user = zahash"
+        );
+
+        check!(
+            &mut store,
+            "tag gmail work social",
+            ["'gmail' [work, social] pass=***** user='zahash'"]
+        );
+        check!(
+            &mut store,
+            "tag discord social social",
+            ["'discord' [social] user='pablo'"]
+        );
+        check!(
+            &mut store,
+            "tag gmail work",
+            ["'gmail' [work, social] pass=***** user='zahash'"]
+        );
+        assert_eq!(store.history("gmail").len(), 2);
+
+        check!(
+            &mut store,
+            "show tag contains social",
+            [
+                "'discord' [social] user='pablo'",
+                "'gmail' [work, social] pass=***** user='zahash'"
+            ]
+        );
+        check!(
+            &mut store,
+            "show tag:work",
+            ["'gmail' [work, social] pass=***** user='zahash'"]
+        );
+        // membership, not a substring
+        check!(&mut store, "show tag:soc", [] as [String; 0]);
+        check!(
+            &mut store,
+            "show tagged social and user is zahash",
+            ["'gmail' [work, social] pass=***** user='zahash'"]
+        );
+        check!(
+            &mut store,
+            "show tagged work or user is pablo",
+            [
+                "'discord' [social] user='pablo'",
+                "'gmail' [work, social] pass=***** user='zahash'"
+            ]
+        );
+        check!(
+            &mut store,
+            "show user is zahash and not tag:work",
+            ["'github' user='zahash'"]
+        );
+
+        check!(
+            &mut store,
+            "untag gmail social",
+            ["'gmail' [work] pass=***** user='zahash'"]
+        );
+        // removing the last tag removes the field
+        check!(
+            &mut store,
+            "untag discord social",
+            ["'discord' user='pablo'"]
+        );
+        check!(
+            &mut store,
+            "show has _tags",
+            ["'gmail' [work] pass=***** user='zahash'"]
+        );
+
+        for cmd in [
+            "tag twitch work",
+            "untag twitch work",
+            "set gmail _tags = hacked",
+            "set gmail sensitive _tags_2 = hacked",
+            "set gmail _tags = gen(10)",
+        ] {
+            assert!(
+                matches!(eval(cmd, &mut store), Err(EvalError::Tag(_))),
+                "{}",
+                cmd
+            );
+        }
+        check!(
+            &mut store,
+            "reveal gmail",
+            ["'gmail' [work] pass='ninja' user='zahash'"]
+        );
+    }
+
+    #[test]
+    fn test_undo() {
+        use std::io::Write;
+
+        let mut store = Store::new();
+        check!(&mut store, "undo", ["nothing to undo"]);
+
+        eval!(
+            &mut store,
+            "set gmail user = zahash sensitive pass = hunter2",
+            "set discord user = pablo"
+        );
+
+        eval!(&mut store, "set gmail sensitive pass = changed");
+        check!(&mut store, "undo", ["undo: set 'gmail'"]);
+        check!(
+            &mut store,
+            "reveal all",
+            [
+                "'discord' user='pablo'",
+                "'gmail' pass='hunter2' user='zahash'"
+            ]
+        );
+        assert_eq!(store.history("gmail").len(), 1);
+
+        eval!(&mut store, "del gmail");
+        check!(&mut store, "undo", ["undo: del 'gmail'"]);
+        check!(&mut store, "count gmail", ["1"]);
+        assert_eq!(store.history("gmail").len(), 1);
+
+        eval!(&mut store, "rename discord discord2", "tag discord2 work");
+        check!(&mut store, "undo", ["undo: tag 'discord2' 'work'"]);
+        check!(&mut store, "undo", ["undo: rename 'discord' 'discord2'"]);
+        check!(&mut store, "list", ["discord", "gmail"]);
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "'github' user = zahash").unwrap();
+        let import = format!("import {}", file.path().to_str().unwrap());
+        eval!(&mut store, &import);
+        check!(
+            &mut store,
+            "undo",
+            [format!("undo: import '{}'", file.path().display())]
+        );
+        check!(&mut store, "list", ["discord", "gmail"]);
+
+        // nothing to take back from commands that didn't change anything
+        eval!(
+            &mut store,
+            "show all",
+            "set gmail user = zahash",
+            "rename --dry-run all gmail gmail2"
+        );
+        check!(&mut store, "del github", [] as [String; 0]);
+        check!(&mut store, "undo", ["undo: set 'discord'"]);
+        check!(&mut store, "undo", ["undo: set 'gmail'"]);
+        check!(&mut store, "undo", ["nothing to undo"]);
+        check!(&mut store, "show all", [] as [String; 0]);
+
+        // only the latest changes are kept
+        for i in 0..UNDO_LIMIT + 5 {
+            eval!(&mut store, &format!("set site{}", i));
+        }
+        for _ in 0..UNDO_LIMIT {
+            assert!(store.undo().is_some());
+        }
+        check!(&mut store, "undo", ["nothing to undo"]);
+        check!(&mut store, "count all", ["5"]);
+    }
+
+    #[test]
+    fn test_undo_restores_earlier_state() {
+        fn state(store: &mut Store) -> (Vec<String>, Vec<String>) {
+            let shown = eval("reveal all", store).unwrap().render(&PlainText);
+            let histories = store
+                .names()
+                .iter()
+                .map(|name| serde_json::to_string(&store.history(name)).unwrap())
+                .collect();
+            (shown, histories)
+        }
+
+        let mut store = Store::new();
+        eval!(
+            &mut store,
+            "set gmail user = zahash sensitive pass = hunter2",
+            "set gmail sensitive pass = ninja",
+            "set discord user = pablo"
+        );
+        let before = state(&mut store);
+
+        eval!(
+            &mut store,
+            "del gmail",
+            "rename discord chat",
+            "set chat user = pablo2 url = discord.com",
+            "del chat url",
+            "set gmail user = 'someone else'"
+        );
+        for cmd in [
+            "set 'gmail'",
+            "del 'chat' 'url'",
+            "set 'chat'",
+            "rename 'discord' 'chat'",
+            "del 'gmail'",
+        ] {
+            check!(&mut store, "undo", [format!("undo: {}", cmd)]);
+        }
+
+        assert_eq!(state(&mut store), before);
+        assert_eq!(store.history("gmail").len(), 2);
+    }
+
+    #[test]
+    fn test_redo() {
+        let mut store = Store::new();
+        check!(&mut store, "redo", ["nothing to redo"]);
+
+        eval!(
+            &mut store,
+            "set gmail user = zahash sensitive pass = hunter2",
+            "set gmail sensitive pass = changed"
+        );
+        let changed = serde_json::to_string(&store).unwrap();
+
+        check!(&mut store, "undo", ["undo: set 'gmail'"]);
+        check!(&mut store, "redo", ["redo: set 'gmail'"]);
+        assert_eq!(serde_json::to_string(&store).unwrap(), changed);
+        check!(&mut store, "redo", ["nothing to redo"]);
+
+        // redone changes can be undone again, several in a row
+        eval!(&mut store, "rename gmail gmail2", "tag gmail2 work");
+        check!(&mut store, "undo", ["undo: tag 'gmail2' 'work'"]);
+        check!(&mut store, "undo", ["undo: rename 'gmail' 'gmail2'"]);
+        check!(&mut store, "undo", ["undo: set 'gmail'"]);
+        check!(&mut store, "redo", ["redo: set 'gmail'"]);
+        check!(&mut store, "redo", ["redo: rename 'gmail' 'gmail2'"]);
+        check!(&mut store, "undo", ["undo: rename 'gmail' 'gmail2'"]);
+        check!(&mut store, "redo", ["redo: rename 'gmail' 'gmail2'"]);
+        check!(&mut store, "redo", ["redo: tag 'gmail2' 'work'"]);
+        check!(
+            &mut store,
+            "reveal gmail2",
+            ["'gmail2' [work] pass='changed' user='zahash'"]
+        );
+
+        // commands that change nothing keep what can be redone
+        check!(&mut store, "undo", ["undo: tag 'gmail2' 'work'"]);
+        eval!(&mut store, "show all", "set gmail2 user = zahash");
+        check!(&mut store, "redo", ["redo: tag 'gmail2' 'work'"]);
+
+        // a new change can't be followed by a redo
+        check!(&mut store, "undo", ["undo: tag 'gmail2' 'work'"]);
+        eval!(&mut store, "set discord user = pablo");
+        check!(&mut store, "redo", ["nothing to redo"]);
+        check!(&mut store, "undo", ["undo: set 'discord'"]);
+        check!(&mut store, "list", ["gmail2"]);
+    }
+
+    #[test]
+    fn test_rollback() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set gmail user = zahash sensitive pass = first",
+            "set gmail sensitive pass = second",
+            "set gmail sensitive pass = third url = mail.google.com"
+        );
+
+        // the newest entry is the current state
+        check!(
+            &mut store,
+            "rollback gmail 1",
+            ["'gmail' pass=***** url='mail.google.com' user='zahash'"]
+        );
+        assert_eq!(store.history("gmail").len(), 3);
+
+        check!(
+            &mut store,
+            "rollback gmail 3",
+            ["'gmail' pass=***** user='zahash'"]
+        );
+        check!(
+            &mut store,
+            "reveal gmail",
+            ["'gmail' pass='first' user='zahash'"]
+        );
+        assert_eq!(store.history("gmail").len(), 4);
+
+        // the rollback is an entry of its own and can be undone
+        check!(
+            &mut store,
+            "rollback gmail 2",
+            ["'gmail' pass=***** url='mail.google.com' user='zahash'"]
+        );
+        check!(
+            &mut store,
+            "reveal gmail",
+            ["'gmail' pass='third' url='mail.google.com' user='zahash'"]
+        );
+
+        assert!(matches!(
+            eval("rollback gmail 6", &mut store),
+            Err(EvalError::Rollback(e)) if e.to_string() == "cannot roll back to entry 6. the history of 'gmail' has only 5"
+        ));
+        assert!(matches!(
+            eval("rollback twitch 1", &mut store),
+            Err(EvalError::Rollback(e)) if e.to_string() == "'twitch' not found"
+        ));
+        assert_eq!(store.history("gmail").len(), 5);
+    }
+
+    #[test]
+    fn test_history_diff() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set gmail user = a url = old.com sensitive pass = first",
+            "set gmail user = b",
+            "del gmail url",
+            "set gmail sensitive pass = second note = hi"
+        );
+
+        let diff = |store: &mut Store, cmd| {
+            eval(cmd, store)
+                .unwrap()
+                .render(&PlainText)
+                .into_iter()
+                // the dates differ from run to run
+                .map(|line| match line.starts_with('[') {
+                    true => line[..3].to_string(),
+                    false => line,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            diff(&mut store, "history diff gmail"),
+            [
+                "[0]",
+                "    + note='hi'",
+                "    ~ pass: ***** -> *****",
+                "[1]",
+                "    - url='old.com'",
+                "[2]",
+                "    ~ user: 'a' -> 'b'",
+                "[3]",
+                "    + pass=*****",
+                "    + url='old.com'",
+                "    + user='a'",
+            ]
+        );
+        assert_eq!(
+            diff(&mut store, "reveal history diff gmail")[..3],
+            ["[0]", "    + note='hi'", "    ~ pass: 'first' -> 'second'"]
+        );
+
+        // a value that becomes sensitive is masked from then on
+        eval!(&mut store, "set gmail sensitive user = b");
+        assert_eq!(
+            diff(&mut store, "history diff gmail")[..2],
+            ["[0]", "    ~ user: 'b' -> *****"]
+        );
+
+        check!(&mut store, "history diff twitch", [] as [String; 0]);
+    }
+
+    #[test]
+    fn test_prune_history() {
+        let mut store = Store::new();
+
+        for pass in ["a", "b", "c", "d", "e", "f"] {
+            eval!(&mut store, &format!("set gmail pass = {}", pass));
+        }
+        eval!(&mut store, "set github pass = x", "set github pass = y");
+
+        check!(
+            &mut store,
+            "history prune gmail 4",
+            ["removed 2 history entries"]
+        );
+        match eval("history gmail", &mut store)
+            .unwrap()
+            .render(&PlainText)
+            .as_slice()
+        {
+            [h1, h2, h3, h4] => {
+                assert!(h1.starts_with("[0] ") && h1.ends_with("pass='f'"));
+                assert!(h2.starts_with("[1] ") && h2.ends_with("pass='e'"));
+                assert!(h3.starts_with("[2] ") && h3.ends_with("pass='d'"));
+                assert!(h4.starts_with("[3] ") && h4.ends_with("pass='c'"));
+            }
+            lines => panic!("{:?}", lines),
+        }
+        check!(
+            &mut store,
+            "history prune gmail 10",
+            ["nothing to remove from the history"]
+        );
+
+        check!(
+            &mut store,
+            "history clear gmail",
+            ["removed 3 history entries"]
+        );
+        match eval("history gmail", &mut store)
+            .unwrap()
+            .render(&PlainText)
+            .as_slice()
+        {
+            [h1] => assert!(h1.starts_with("[0] ") && h1.ends_with("pass='f'")),
+            lines => panic!("{:?}", lines),
+        }
+        check!(&mut store, "reveal gmail", ["'gmail' pass='f'"]);
+        assert_eq!(store.history("github").len(), 2);
+
+        // and it can be undone like any other change
+        check!(&mut store, "undo", ["undo: history clear 'gmail'"]);
+        assert_eq!(store.history("gmail").len(), 4);
+
+        assert!(matches!(
+            eval("history clear twitch", &mut store),
+            Err(EvalError::Rollback(e)) if e.to_string() == "'twitch' not found"
+        ));
+    }
+
+    #[test]
+    fn test_read_only() {
+        let mut store = Store::new();
+        eval!(&mut store, "set gmail user = zahash sensitive pass = first");
+        store.set_read_only(true);
+        let before = serde_json::to_string(&store).unwrap();
+
+        for cmd in [
+            "set gmail pass = other",
+            "set github user = zahash",
+            "del gmail",
+            "del name contains gm",
+            "rename gmail google",
+            "rollback gmail 1",
+            "tag gmail work",
+            "history clear gmail",
+            "import /tmp/royalguard-read-only.txt",
+            "merge /tmp/royalguard-read-only",
+        ] {
+            match eval(cmd, &mut store) {
+                Err(e @ EvalError::ReadOnly(_)) => assert_eq!(
+                    e.to_string(),
+                    format!(
+                        "the vault is open read-only. '{}' would change it",
+                        cmd.split_whitespace().next().unwrap()
+                    )
+                ),
+                _ => panic!("{}", cmd),
+            }
+        }
+        assert_eq!(serde_json::to_string(&store).unwrap(), before);
+
+        // looking things up still works
+        check!(
+            &mut store,
+            "show gmail",
+            ["'gmail' pass=***** user='zahash'"]
+        );
+        check!(&mut store, "list", ["gmail"]);
+        assert_eq!(store.history("gmail").len(), 1);
+    }
+
+    #[test]
+    fn test_restore_history_entry() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set gmail user = zahash sensitive pass = first",
+            "set gmail pass = second url = mail.google.com"
+        );
+        match eval("history gmail", &mut store)
+            .unwrap()
+            .render(&PlainText)
+            .as_slice()
+        {
+            [h1, h2] => {
+                assert!(h1.starts_with("[0] ("));
+                assert!(h1.ends_with("pass='second' url='mail.google.com' user='zahash'"));
+                assert!(h2.starts_with("[1] ("));
+                assert!(h2.ends_with("pass=***** user='zahash'"));
+            }
+            _ => panic!(),
+        }
+
+        // the index 'history' shows, sensitive again like it was back then
+        check!(
+            &mut store,
+            "restore gmail 1",
+            ["'gmail' pass=***** user='zahash'"]
+        );
+        let record = store.pick("gmail", None)[0].clone();
+        let pass = record.fields.iter().find(|f| f.attr == "pass").unwrap();
+        assert_eq!((pass.value.as_str(), pass.sensitive), ("first", true));
+        assert!(!record.fields.iter().any(|f| f.attr == "url"));
+        assert_eq!(store.history("gmail").len(), 3);
+
+        let lines = eval("reveal history gmail", &mut store)
+            .unwrap()
+            .render(&PlainText);
+        assert!(lines[0].starts_with("[0] ("));
+        assert!(lines[0].ends_with(") pass='first' user='zahash'"));
+        assert!(lines[2].starts_with("[2] ("));
+
+        assert!(matches!(
+            eval("restore gmail 3", &mut store),
+            Err(EvalError::Rollback(e)) if e.to_string() == "cannot restore entry [3]. the history of 'gmail' goes from [0] to [2]"
+        ));
+        assert!(matches!(
+            eval("restore twitch 0", &mut store),
+            Err(EvalError::Rollback(e)) if e.to_string() == "'twitch' not found"
+        ));
+        assert_eq!(store.history("gmail").len(), 3);
+    }
+
+    #[test]
+    fn test_rename() {
+        let mut store = Store::new();
+
+        check!(&mut store, "rename gmail discord", ["'gmail' not found!"]);
+
+        eval!(&mut store, "set discord");
+        check!(
+            &mut store,
+            "rename gmail discord",
+            ["'discord' already exists!"]
+        );
+
+        check!(&mut store, "rename discord discord2", ["Renamed!"]);
+    }
+
+    #[test]
+    fn test_rename_reindexes() {
+        let mut store = Store::new();
+
+        eval!(&mut store, "set discord", "rename discord discord2");
+        check!(&mut store, "show discord", [] as [String; 0]);
+        check!(&mut store, "show discord2", ["'discord2'"]);
+
+        eval!(&mut store, "set discord user = zahash");
+        check!(
+            &mut store,
+            "show all",
+            ["'discord' user='zahash'", "'discord2'"]
+        );
+    }
+
+    #[test]
+    fn test_rename_leaves_nothing_behind() {
+        let mut store = Store::new();
+
+        // everything that refers to a record lives on it, so nothing is left behind
+        eval!(&mut store, "set gmail user = zahash", "tag gmail work");
+        let id = store.pick("gmail", None)[0].id;
+        check!(&mut store, "rename gmail gmail2", ["Renamed!"]);
+        check!(&mut store, "count gmail", ["0"]);
+        assert!(matches!(
+            eval("tag gmail work", &mut store),
+            Err(EvalError::Tag(_))
+        ));
+        check!(
+            &mut store,
+            "show tagged work",
+            ["'gmail2' [work] user='zahash'"]
+        );
+        assert_eq!(store.history("gmail").len(), 0);
+        assert_eq!(store.history("gmail2").len(), 2);
+        assert_eq!(store.pick("gmail2", None)[0].id, id);
+    }
+
+    #[test]
+    fn test_batch_rename() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set a.com user = a",
+            "set b.com user = b",
+            "set b.io",
+            "set c.org"
+        );
+
+        check!(
+            &mut store,
+            "rename --dry-run all '\\.com$' .io",
+            [
+                "would rename 'a.com' to 'a.io'",
+                "'b.com' not renamed, 'b.io' already exists!"
+            ]
+        );
+        check!(&mut store, "list", ["a.com", "b.com", "b.io", "c.org"]);
+
+        // a conflict doesn't stop the others
+        check!(
+            &mut store,
+            "rename all '\\.com$' .io",
+            [
+                "renamed 'a.com' to 'a.io'",
+                "'b.com' not renamed, 'b.io' already exists!"
+            ]
+        );
+        check!(&mut store, "list", ["a.io", "b.com", "b.io", "c.org"]);
+        check!(&mut store, "show a.io", ["'a.io' user='a'"]);
+        check!(&mut store, "count a.com", ["0"]);
+
+        check!(
+            &mut store,
+            "rename all '^(.)\\.(.*)$' $2-$1",
+            [
+                "renamed 'a.io' to 'io-a'",
+                "renamed 'b.com' to 'com-b'",
+                "renamed 'b.io' to 'io-b'",
+                "renamed 'c.org' to 'org-c'"
+            ]
+        );
+        check!(&mut store, "rename all xyz abc", ["nothing to rename"]);
+
+        // two records can't end up with the same name
+        check!(
+            &mut store,
+            "rename all '-.$' -x",
+            [
+                "renamed 'com-b' to 'com-x'",
+                "renamed 'io-a' to 'io-x'",
+                "'io-b' not renamed, 'io-x' already exists!",
+                "renamed 'org-c' to 'org-x'"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rename_field() {
+        let mut store = Store::new();
+
+        check!(
+            &mut store,
+            "rename gmail pass password",
+            ["'gmail' not found!"]
+        );
+
+        eval!(&mut store, "set gmail user = zahash sensitive pass = gpass");
+        check!(
+            &mut store,
+            "rename gmail url link",
+            ["'gmail' has no 'url'!"]
+        );
+        check!(
+            &mut store,
+            "rename gmail pass user",
+            ["'gmail' already has 'user'!"]
+        );
+        check!(&mut store, "rename gmail pass password", ["Renamed!"]);
+
+        // the value and its sensitivity are kept
+        check!(
+            &mut store,
+            "show gmail",
+            ["'gmail' password=***** user='zahash'"]
+        );
+        check!(
+            &mut store,
+            "reveal gmail",
+            ["'gmail' password='gpass' user='zahash'"]
+        );
+        let history = store.history("gmail");
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].fields[0].attr, "pass");
+        assert_eq!(history[1].fields[0].attr, "password");
+        assert!(history[1].fields[0].sensitive);
+
+        for cmd in ["rename gmail user _tags", "rename gmail _tags user"] {
+            assert!(
+                matches!(eval(cmd, &mut store), Err(EvalError::Tag(_))),
+                "{}",
+                cmd
+            );
+        }
+    }
+
+    #[test]
+    fn test_dup() {
+        let mut store = Store::new();
+
+        check!(&mut store, "dup gmail gmail_backup", ["'gmail' not found!"]);
+
+        eval!(
+            &mut store,
+            "set gmail user = zahash sensitive pass = gpass",
+            "set gmail sensitive pass = gpass2",
+            "tag gmail work",
+            "set discord"
+        );
+        check!(
+            &mut store,
+            "dup gmail discord",
+            ["'discord' already exists!"]
+        );
+        check!(
+            &mut store,
+            "dup gmail gmail_backup",
+            ["'gmail_backup' [work] pass=***** user='zahash'"]
+        );
+
+        // same fields and sensitivity, but its own record with a history of one entry
+        eval!(&mut store, "set gmail_backup pass = changed");
+        check!(
+            &mut store,
+            "reveal gmail",
+            ["'gmail' [work] pass='gpass2' user='zahash'"]
+        );
+        check!(
+            &mut store,
+            "reveal gmail_backup",
+            ["'gmail_backup' [work] pass='changed' user='zahash'"]
+        );
+        assert_eq!(store.history("gmail").len(), 3);
+        assert_eq!(store.history("gmail_backup").len(), 2);
+        assert_ne!(
+            store.get(Query::Name("gmail")).pop().unwrap().id,
+            store.get(Query::Name("gmail_backup")).pop().unwrap().id
+        );
+
+        // clone is the same command
+        check!(
+            &mut store,
+            "clone twitch twitch-alt",
+            ["'twitch' not found!"]
+        );
+        check!(
+            &mut store,
+            "clone gmail gmail_backup",
+            ["'gmail_backup' already exists!"]
+        );
+        check!(
+            &mut store,
+            "clone gmail gmail-alt",
+            ["'gmail-alt' [work] pass=***** user='zahash'"]
+        );
+        assert_eq!(store.history("gmail-alt").len(), 1);
+        check!(&mut store, "undo", ["undo: dup 'gmail' 'gmail-alt'"]);
+    }
+
+    #[test]
+    fn test_totp() {
+        let mut store = Store::new();
+        eval!(
+            &mut store,
+            "set gmail user = zahash totp = 'otpauth://totp/Google:zahash?secret=JBSWY3DPEHPK3PXP&period=30&digits=6'",
+            "set discord totp = 'not a uri'"
+        );
+
+        check!(
+            &mut store,
+            "show gmail",
+            ["'gmail' totp=***** user='zahash'"]
+        );
+        check!(&mut store, "show discord", ["'discord' totp='not a uri'"]);
+
+        match eval("totp gmail", &mut store)
+            .unwrap()
+            .render(&PlainText)
+            .as_slice()
+        {
+            // followed by whether it was copied when there is a clipboard
+            [line, ..] => {
+                let (code, remaining) = line.split_once(' ').unwrap();
+                assert_eq!(code.len(), 6);
+                assert!(code.chars().all(|c| c.is_ascii_digit()));
+                assert!(remaining.starts_with("(valid for "));
+            }
+            lines => panic!("expected a code but got {:?}", lines),
+        }
+
+        // a plain base32 secret in 'otp' when there is no 'totp'
+        eval!(
+            &mut store,
+            "set twitch sensitive otp = 'jbsw y3dp ehpk 3pxp'",
+            "set amazon sensitive otp = 'not base32!'"
+        );
+        let mut code = |cmd| match eval(cmd, &mut store).unwrap().render(&PlainText).as_slice() {
+            [line, ..] => line.split_once(' ').unwrap().0.to_string(),
+            lines => panic!("expected a code but got {:?}", lines),
+        };
+        assert_eq!(code("totp twitch").len(), 6);
+        assert_eq!(code("totp twitch digits = 8").len(), 8);
+        assert_eq!(code("totp gmail digits = 7 period = 60").len(), 7);
+
+        for cmd in [
+            "totp discord",
+            "totp amazon",
+            "totp blah",
+            "totp twitch digits = 9",
+            "copy totp gmail user",
+            "copy totp twitch totp",
+        ] {
+            assert!(
+                matches!(eval(cmd, &mut store), Err(EvalError::Totp(_))),
+                "{}",
+                cmd
+            );
+        }
+    }
+
+    #[test]
+    fn test_totp_rfc6238() {
+        // the test vectors of https://www.rfc-editor.org/rfc/rfc6238#appendix-B
+        // with the ascii secrets "1234567890" repeated to 20, 32 and 64 bytes
+        let mut store = Store::new();
+        eval!(
+            &mut store,
+            "set sha1 totp = 'otpauth://totp/rfc?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&digits=8'",
+            "set sha256 totp = 'otpauth://totp/rfc?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZA&digits=8&algorithm=SHA256'",
+            "set sha512 totp = 'otpauth://totp/rfc?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNA&digits=8&algorithm=SHA512'",
+            "set plain sensitive totp = GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"
+        );
+
+        for (time, sha1, sha256, sha512) in [
+            (59, "94287082", "46119246", "90693936"),
+            (1111111109, "07081804", "68084774", "25091201"),
+            (1111111111, "14050471", "67062674", "99943326"),
+            (1234567890, "89005924", "91819424", "93441116"),
+            (2000000000, "69279037", "90698825", "38618901"),
+            (20000000000, "65353130", "77737706", "47863826"),
+        ] {
+            let code = |name| {
+                totp(&store, name, Some("totp"), None, None)
+                    .unwrap()
+                    .generate(time)
+            };
+            assert_eq!(code("sha1"), sha1);
+            assert_eq!(code("sha256"), sha256);
+            assert_eq!(code("sha512"), sha512);
+            // a plain secret is SHA1 with 6 digits, the last 6 of the 8
+            assert_eq!(code("plain"), sha1[2..]);
+        }
+    }
+
+    #[test]
+    fn test_receive() {
+        let mut store = Store::new();
+        eval!(
+            &mut store,
+            "set gmail user = zahash sensitive pass = gpass",
+            "set gmail sensitive pass = newpass"
+        );
+
+        let record = store.get(Query::Name("gmail")).pop().unwrap();
+        let (armored, passphrase) = share::share(record, store.now()).unwrap();
+
+        let mut other = Store::new();
+        assert!(matches!(
+            receive(&armored, "wrong passphrase", &mut other),
+            Err(EvalError::Share(_))
+        ));
+        assert_eq!(
+            receive(&armored, &passphrase, &mut other)
+                .unwrap()
+                .render(&PlainText),
+            ["received 'gmail'"]
+        );
+        check!(
+            &mut other,
+            "reveal all",
+            ["'gmail' pass='newpass' user='zahash'"]
+        );
+        assert_eq!(other.history("gmail").len(), 1);
+
+        assert!(matches!(
+            eval("share twitch", &mut store),
+            Err(EvalError::Share(_))
+        ));
+    }
+
+    #[test]
+    fn test_receive_existing() {
+        let mut store = Store::new();
+        eval!(&mut store, "set card sensitive number = 4111 type pin");
+        let record = store.get(Query::Name("card")).pop().unwrap();
+        let (armored, passphrase) = share::share(record, store.now()).unwrap();
+
+        let mut other = Store::new();
+        eval!(&mut other, "set card sensitive number = 5500");
+        assert_eq!(
+            receive(&armored, &passphrase, &mut other)
+                .unwrap()
+                .render(&PlainText),
+            [
+                "'card' already exists, so it was received as 'card-2'",
+                "received 'card-2'"
+            ]
+        );
+        check!(
+            &mut other,
+            "reveal all",
+            ["'card' number='5500'", "'card-2' number='4111'"]
+        );
+        let received = other.get(Query::Name("card-2")).pop().unwrap();
+        assert_eq!(received.fields[0].kind, FieldKind::Pin);
+
+        // a received record isn't added next to the ones that share its name either
+        let mut other = Store::new();
+        eval!(
+            &mut other,
+            "allow-duplicate-names on",
+            "set card number = 1",
+            "set card where missing number number = 2",
+            "set card-2 number = 3"
+        );
+        receive(&armored, &passphrase, &mut other).unwrap();
+        check!(&mut other, "list all", ["card", "card", "card-2", "card-3"]);
+    }
+
+    #[test]
+    fn test_field_kinds() {
+        let mut store = Store::new();
+
+        // told by the attr
+        check!(
+            &mut store,
+            "set gmail email = zahash url = 'mail google' pin = 12ab",
+            [
+                "⚠ email: doesn't look like an email address",
+                "⚠ url: doesn't look like a url",
+                "⚠ pin: doesn't look like a pin (digits only)"
+            ]
+        );
+        check!(
+            &mut store,
+            "set gmail email = zahash@gmail.com url = mail.google.com pin = 1234",
+            [] as [String; 0]
+        );
+
+        // given, and kept when the value changes
+        check!(
+            &mut store,
+            "set card number = '4111 1111 1111 1111' type pin limit = 5k type number",
+            ["⚠ limit: doesn't look like a number"]
+        );
+        check!(
+            &mut store,
+            "set card number = unknown",
+            ["⚠ number: doesn't look like a pin (digits only)"]
+        );
+        let card = store.get(Query::Name("card")).pop().unwrap();
+        let number = card.fields.iter().find(|f| f.attr == "number").unwrap();
+        assert_eq!(number.kind, FieldKind::Pin);
+
+        check!(
+            &mut store,
+            "set where . is card limit = 5000",
+            ["updated 1 record"]
+        );
+        check!(
+            &mut store,
+            "set where . is card limit = lots type number",
+            ["⚠ limit: doesn't look like a number", "updated 1 record"]
+        );
+
+        // a secret of type otp makes the record `totp`-able
+        eval!(
+            &mut store,
+            "set github sensitive code = 'JBSW Y3DP EHPK 3PXP' type otp"
+        );
+        assert!(totp(&store, "github", None, None, None).is_ok());
+        assert!(totp(&store, "gmail", None, None, None).is_err());
+    }
+
+    #[test]
+    fn test_strength_warnings() {
+        let mut store = Store::new();
+
+        match eval(
+            "set gmail user = zahash sensitive pass = password",
+            &mut store,
+        )
+        .unwrap()
+        .render(&PlainText)
+        .as_slice()
+        {
+            [warning] => assert!(warning.starts_with("⚠ pass: strength 0/4 (very weak)")),
+            lines => panic!("expected a single warning but got {:?}", lines),
+        }
+
+        check!(
+            &mut store,
+            "set gmail sensitive pin = 123 sensitive pass = ''",
+            [
+                "⚠ pin: strength 0/4 (very weak) – shorter than 4 characters",
+                "⚠ pass: strength 0/4 (very weak) – shorter than 4 characters"
+            ]
+        );
+        check!(&mut store, "set gmail pass = password", [] as [String; 0]);
+        check!(
+            &mut store,
+            "set gmail sensitive pass = 'vK7#qz!Lw2@xR9$mTn4&'",
+            [] as [String; 0]
+        );
+    }
+
+    #[test]
+    fn test_sensitive_otpauth() {
+        let mut store = Store::new();
+        let uri = "otpauth://totp/Google:zahash?secret=JBSWY3DPEHPK3PXP";
+
+        check!(
+            &mut store,
+            &format!("set gmail totp = '{}'", uri),
+            ["⚠ totp: stored as sensitive since it is an otpauth:// uri (--plain-otpauth keeps it as typed)"]
+        );
+        check!(&mut store, "show gmail", ["'gmail' totp=*****"]);
+        check!(
+            &mut store,
+            &format!("set where totp startswith otpauth backup = '{}'", uri),
+            [
+                "⚠ backup: stored as sensitive since it is an otpauth:// uri (--plain-otpauth keeps it as typed)",
+                "updated 1 record"
+            ]
+        );
+
+        let plain = EvalOptions {
+            sensitive_otpauth: false,
+            ..Default::default()
+        };
+        let cmd = format!("set github totp = '{}'", uri);
+        let eval = eval_with(&cmd, &mut store, &plain);
+        assert_eq!(eval.unwrap().render(&PlainText), [] as [String; 0]);
+        check!(
+            &mut store,
+            "show github",
+            [format!("'github' totp='{}'", uri)]
+        );
+    }
+
+    #[test]
+    fn test_eval_options() {
+        let mut store = Store::new();
+        let quiet = EvalOptions {
+            strength_warnings: false,
+            ..Default::default()
+        };
+        let eval = eval_with("set gmail sensitive pass = password", &mut store, &quiet);
+        assert_eq!(eval.unwrap().render(&PlainText), [] as [String; 0]);
+
+        let trim = EvalOptions {
+            trim_untidy: |_| true,
+            ..quiet
+        };
+        let eval = eval_with(
+            "set gmail sensitive pass = ' vK7#qz!Lw2 '",
+            &mut store,
+            &trim,
+        );
+        assert_eq!(eval.unwrap().render(&PlainText), [] as [String; 0]);
+        check!(&mut store, "reveal gmail", ["'gmail' pass='vK7#qz!Lw2'"]);
+    }
+
+    #[test]
+    fn test_untidy() {
+        assert_eq!(untidy("vK7#qz!Lw2"), None);
+        assert_eq!(untidy("-----BEGIN KEY-----\nabc\n\tdef"), None);
+        assert_eq!(
+            untidy("vK7#qz!Lw2\n"),
+            Some((Untidy::TrailingNewline, "vK7#qz!Lw2".into()))
+        );
+        assert_eq!(
+            untidy(" vK7#qz!Lw2\r\n"),
+            Some((Untidy::TrailingNewline, "vK7#qz!Lw2".into()))
+        );
+        assert_eq!(
+            untidy("vK7#qz!Lw2 "),
+            Some((Untidy::SurroundingWhitespace, "vK7#qz!Lw2".into()))
+        );
+        assert_eq!(
+            untidy("vK7#qz\u{200b}!Lw2\x08"),
+            Some((Untidy::ControlCharacters, "vK7#qz\u{200b}!Lw2".into()))
+        );
+
+        let mut store = Store::new();
+        check!(
+            &mut store,
+            "set gmail user = ' zahash ' sensitive pass = ' vK7#qz!Lw2@xR9$mTn4& '",
+            ["⚠ pass: 22 characters with leading or trailing whitespace"]
+        );
+        // kept as it is unless the prompt is told to strip it
+        check!(
+            &mut store,
+            "reveal gmail",
+            ["'gmail' pass=' vK7#qz!Lw2@xR9$mTn4& ' user=' zahash '"]
+        );
+
+        let assignments = [
+            Assign {
+                attr: "pass",
+                value: "vK7#qz!Lw2\n",
+                sensitive: true,
+                expires: None,
+                kind: None,
+            },
+            Assign {
+                attr: "pin",
+                value: " 1234",
+                sensitive: true,
+                expires: None,
+                kind: None,
+            },
+        ];
+        assert_eq!(
+            tidy(&assignments, |_| true),
+            (vec![Some("vK7#qz!Lw2".into()), Some("1234".into())], vec![])
+        );
+        assert_eq!(
+            tidy(&assignments, |warning| warning.starts_with("⚠ pin")),
+            (
+                vec![None, Some("1234".into())],
+                vec!["⚠ pass: 11 characters with a trailing newline".into()]
+            )
+        );
+    }
+
+    #[test]
+    fn test_gen() {
+        fn generate(cmd: &str) -> String {
+            match eval(cmd, &mut Store::new())
+                .unwrap()
+                .render(&PlainText)
+                .as_slice()
+            {
+                [password] => password.clone(),
+                lines => panic!("expected a single password but got {:?}", lines),
+            }
+        }
+
+        let password = generate("gen 24");
+        assert_eq!(password.len(), 24);
+        assert!(password
+            .bytes()
+            .all(|c| charset(true, true, true).contains(&c)));
+        assert_ne!(password, generate("gen 24"));
+
+        let password = generate("gen 200 nosymbols");
+        assert_eq!(password.len(), 200);
+        assert!(password.chars().all(|c| c.is_ascii_alphanumeric()));
+
+        let password = generate("gen 200 nodigits noambiguous");
+        assert_eq!(password.len(), 200);
+        assert!(password.chars().all(|c| !c.is_ascii_digit()));
+        assert!(password.chars().all(|c| !"0Ol1".contains(c)));
+
+        assert_eq!(charset(false, false, false).len(), 50);
+        assert!(!charset(true, true, true).contains(&b'\''));
+
+        let words = WORDS.lines().collect::<HashSet<_>>();
+        assert_eq!(words.len(), 2048);
+        assert_eq!(words.len(), WORDS.lines().count());
+
+        let passphrase = generate("gen words 6");
+        assert_eq!(passphrase.split('-').count(), 6);
+        assert!(passphrase.split('-').all(|word| words.contains(word)));
+        assert_ne!(passphrase, generate("gen words 6"));
+
+        let passphrase = generate("gen words 8 sep '_'");
+        assert_eq!(passphrase.split('_').count(), 8);
+        assert!(passphrase.split('_').all(|word| words.contains(word)));
+    }
+
+    #[test]
+    fn test_quoted_keywords() {
+        let mut store = Store::new();
+
+        eval!(
+            &mut store,
+            "set 'all' user = zahash",
+            "set 'prev' user = hazash",
+            "set gmail user = amogus 'all' = everything"
+        );
+
+        check!(&mut store, "show 'all'", ["'all' user='zahash'"]);
+        check!(&mut store, "reveal 'prev'", ["'prev' user='hazash'"]);
+        check!(
+            &mut store,
+            "show all",
+            [
+                "'all' user='zahash'",
+                "'gmail' all='everything' user='amogus'",
+                "'prev' user='hazash'"
+            ]
+        );
+        check!(
+            &mut store,
+            "show 'all' is everything",
+            ["'gmail' all='everything' user='amogus'"]
+        );
+        assert_eq!(
+            eval("history 'all'", &mut store)
+                .unwrap()
+                .render(&PlainText)
+                .len(),
+            1
+        );
+        assert_eq!(
+            eval("reveal history 'prev'", &mut store)
+                .unwrap()
+                .render(&PlainText)
+                .len(),
+            1
+        );
+
+        check!(&mut store, "rename 'prev' 'all'", ["'all' already exists!"]);
+        check!(&mut store, "rename 'prev' previous", ["Renamed!"]);
+        check!(&mut store, "del gmail 'all'", ["'gmail' user='amogus'"]);
+        check!(&mut store, "del 'all'", ["'all' user='zahash'"]);
+        check!(
+            &mut store,
+            "show all",
+            ["'gmail' user='amogus'", "'previous' user='hazash'"]
+        );
+    }
+
+    #[test]
+    fn test_copy() {
+        let mut store = Store::new();
+
+        check!(&mut store, "copy gmail pass", ["Unable to Copy! Try Again!"]);
+
+        eval!(&mut store, "set gmail");
+        check!(&mut store, "copy gmail pass", ["Unable to Copy! Try Again!"]);
+
+        eval!(&mut store, "set gmail url = mail.google.com");
+        check!(&mut store, "copy gmail pass", ["Unable to Copy! Try Again!"]);
+
+        eval!(&mut store, "set gmail pass = gpass");
+        check!(&mut store, "copy gmail pass", ["Copied! will clear in 30s"]);
+
+        eval!(&mut store, "set gmail sensitive pass = gpass");
+        check!(&mut store, "copy gmail pass", ["Copied! will clear in 30s"]);
+
+        check!(&mut store, "copy gmail", ["Copied! will clear in 30s"]);
+        check!(&mut store, "copy twitch", ["Unable to Copy! Try Again!"]);
+    }
+
+    #[test]
+    fn test_clipboard_text() {
+        let mut store = Store::new();
+        eval!(
+            &mut store,
+            "set gmail user = zahash sensitive pass = gpass url = mail.google.com"
+        );
+        eval!(&mut store, "tag gmail work");
+        let record = || store.get(Query::Name("gmail")).pop().unwrap();
+
+        assert_eq!(
+            clipboard_text(record(), Some("pass")).as_deref(),
+            Some("gpass")
+        );
+        assert_eq!(clipboard_text(record(), Some("totp")), None);
+        // revealed, one field per line
+        assert_eq!(
+            clipboard_text(record(), None).as_deref(),
+            Some("'gmail' [work]\n    pass='gpass'\n    url='mail.google.com'\n    user='zahash'")
+        );
+    }
+
+    #[test]
+    fn test_import_generated() {
+        use std::io::Write;
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            file,
+            "'gmail' user = zahash pass = gen(24)\n\
+             'discord' user = hazash pass = gen(phrase 5)\n\
+             'twitch' pass = gen(24)\n\
+             'github' user = zahash sensitive pass = given\n"
+        )
+        .unwrap();
+
+        let mut store = Store::new();
+        let cmd = format!("import {}", file.path().to_str().unwrap());
+        check!(
+            &mut store,
+            &cmd,
+            ["generated 3 sensitive values", "imported 4 records"]
+        );
+        check!(&mut store, "show missing pass", [] as [String; 0]);
+        check!(
+            &mut store,
+            "show gmail",
+            ["'gmail' pass=***** user='zahash'"]
+        );
+
+        let pass = |store: &Store, name| {
+            let record = store.get(Query::Name(name)).pop().unwrap();
+            let field = record.fields.into_iter().find(|f| f.attr == "pass");
+            field.unwrap().value.clone()
+        };
+        let (gmail, twitch) = (pass(&store, "gmail"), pass(&store, "twitch"));
+        assert_eq!(gmail.chars().count(), 24);
+        assert_ne!(gmail, twitch);
+        assert_eq!(pass(&store, "discord").split('-').count(), 5);
+
+        eval!(
+            &mut store,
+            "set gmail pass = gen(phrase 3) url = mail.google.com"
+        );
+        assert_eq!(pass(&store, "gmail").split('-').count(), 3);
+        check!(
+            &mut store,
+            "show gmail",
+            ["'gmail' pass=***** url='mail.google.com' user='zahash'"]
+        );
+    }
+
+    #[test]
+    fn test_import() {
+        use std::io::Write;
+
+        fn import(store: &mut Store, contents: &'static str) {
+            let mut file = tempfile::NamedTempFile::new().unwrap();
+            write!(file, "{}", contents).unwrap();
+            let cmd = format!("import {}", file.path().to_str().unwrap());
+            eval!(store, &cmd);
+        }
+
+        let mut store = Store::new();
+
+        import(&mut store, "");
+        check!(&mut store, "show all", [] as [String; 0]);
+
+        import(
+            &mut store,
+            r#"
+            'gmail' user = ligma pass = balls
+            'gmail' user = ligma pass = balls
+            'gmail' user = ligma pass = balls
+            'gmail' user = ligma pass = balls
+            'gmail' user = 'benito sussolini' pass = 'joseph ballin'
+            'gmail' user = 'benito sussolini' pass = 'joseph ballin'
+            'gmail' user = 'benito sussolini' pass = 'joseph ballin'
+            'discord' user = 'dorito breath' pass = 'kitten'
+            'discord' user = 'dorito breath' pass = 'kitten'
+            "#,
+        );
+        check!(
+            &mut store,
+            "show all",
+            [
+                "'discord' pass='kitten' user='dorito breath'",
+                "'gmail' pass='joseph ballin' user='benito sussolini'"
+            ]
+        );
+        match eval("history gmail", &mut store)
+            .unwrap()
+            .render(&PlainText)
+            .as_slice()
+        {
+            [h1, h2] => {
+                assert!(h1.ends_with("pass='joseph ballin' user='benito sussolini'"));
+                assert!(h2.ends_with("pass='balls' user='ligma'"));
+            }
+            _ => panic!(),
+        }
+        match eval("history discord", &mut store)
+            .unwrap()
+            .render(&PlainText)
+            .as_slice()
+        {
+            [h1] => assert!(h1.ends_with("pass='kitten' user='dorito breath'")),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_import_merge() {
+        use std::io::Write;
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            file,
+            "'gmail' user = hazash pass = newpass\n'gmail' url = mail.google.com\n'discord' user = hazash\n"
+        )
+        .unwrap();
+        let fpath = file.path().to_str().unwrap();
+
+        fn with_gmail() -> Store {
+            let mut store = Store::new();
+            eval!(&mut store, "set gmail user = zahash pass = oldpass");
+            store
+        }
+
+        let mut store = with_gmail();
+        check!(
+            &mut store,
+            &format!("import {} skip", fpath),
+            ["skipped 1 existing record", "imported 1 record"]
+        );
+        check!(
+            &mut store,
+            "reveal all",
+            [
+                "'discord' user='hazash'",
+                "'gmail' pass='oldpass' user='zahash'"
+            ]
+        );
+
+        let mut store = with_gmail();
+        check!(
+            &mut store,
+            &format!("import {} overwrite", fpath),
+            ["imported 2 records"]
+        );
+        check!(
+            &mut store,
+            "reveal all",
+            [
+                "'discord' user='hazash'",
+                "'gmail' pass='newpass' url='mail.google.com' user='hazash'"
+            ]
+        );
+
+        let mut store = with_gmail();
+        check!(
+            &mut store,
+            &format!("import {}", fpath),
+            ["imported 2 records"]
+        );
+        check!(
+            &mut store,
+            "reveal gmail",
+            ["'gmail' pass='newpass' url='mail.google.com' user='hazash'"]
+        );
+
+        let mut store = with_gmail();
+        check!(
+            &mut store,
+            &format!("import {} rename", fpath),
+            ["renamed 1 colliding record", "imported 1 record"]
+        );
+        check!(
+            &mut store,
+            "reveal all",
+            [
+                "'discord' user='hazash'",
+                "'gmail' pass='oldpass' user='zahash'",
+                "'gmail-2' pass='newpass' url='mail.google.com' user='hazash'"
+            ]
+        );
+        check!(
+            &mut store,
+            &format!("import {} rename", fpath),
+            ["renamed 2 colliding records", "imported 0 records"]
+        );
+        check!(
+            &mut store,
+            "show . startswith gmail",
+            [
+                "'gmail' pass='oldpass' user='zahash'",
+                "'gmail-2' pass='newpass' url='mail.google.com' user='hazash'",
+                "'gmail-3' pass='newpass' url='mail.google.com' user='hazash'"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import_duplicate_names() {
+        use std::io::Write;
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(file, "'gmail' user = hazash\n'discord' user = hazash\n").unwrap();
+        let fpath = file.path().to_str().unwrap();
+
+        let mut store = Store::new();
+        eval!(
+            &mut store,
+            "allow-duplicate-names on",
+            "set gmail user = zahash",
+            "set gmail where has url url = mail.google.com"
+        );
+
+        // like `set`, without knowing which one is meant
+        for cmd in [
+            format!("import {}", fpath),
+            format!("import {} overwrite", fpath),
+        ] {
+            assert!(matches!(
+                eval(&cmd, &mut store),
+                Err(EvalError::Ambiguous(_))
+            ));
+            check!(&mut store, "list all", ["gmail", "gmail"]);
+        }
+
+        eval!(&mut store, &format!("import {} rename", fpath));
+        check!(
+            &mut store,
+            "list all",
+            ["discord", "gmail", "gmail", "gmail-2"]
+        );
+        eval!(&mut store, &format!("import {} skip", fpath));
+        check!(
+            &mut store,
+            "list all",
+            ["discord", "gmail", "gmail", "gmail-2"]
+        );
+    }
+
+    #[test]
+    fn test_import_cancel() {
+        use std::io::Write;
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "'gmail' user = zahash\n'discord' user = pablo").unwrap();
+        let fpath = file.path().to_str().unwrap();
+
+        let cancel = Cancel::default();
+        let mut importer = Importer::new(ImportLimits::default(), cancel.clone());
+        import_lines(fpath, &mut importer).unwrap();
+        assert_eq!(importer.records.len(), 2);
+
+        // stops at the next record
+        cancel.cancel();
+        assert_eq!(
+            import_lines(fpath, &mut importer).unwrap_err().to_string(),
+            "cancelled after 2 records. nothing was imported"
+        );
+        assert_eq!(importer.records.len(), 2);
+
+        // the store is only written to once the whole file is read
+        let mut store = Store::new();
+        eval!(&mut store, &format!("import {}", fpath));
+        check!(
+            &mut store,
+            "show all",
+            ["'discord' user='pablo'", "'gmail' user='zahash'"]
+        );
+    }
+
+    #[test]
+    fn test_export_cancel() {
+        let mut store = Store::new();
+        eval!(
+            &mut store,
+            "set gmail user = zahash",
+            "set discord user = pablo"
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("export");
+        let fpath = fpath.to_str().unwrap();
+
+        let cancel = Cancel::default();
+        cancel.cancel();
+        type Export = fn(&str, Vec<Record>, &Cancel) -> anyhow::Result<usize>;
+        let exports: [Export; 4] = [export, export_csv, export_csv_fields, export_json];
+        for export in exports {
+            let records = store.get(Query::All);
+            assert_eq!(
+                export(fpath, records, &cancel).unwrap_err().to_string(),
+                "cancelled after 0 records. nothing was exported"
+            );
+            // nothing is left behind
+            assert!(!std::path::Path::new(fpath).exists());
+        }
+
+        let records = store.get(Query::All);
+        assert_eq!(export(fpath, records, &Cancel::default()).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_merge_cancel() {
+        let mut store = Store::new();
+        eval!(&mut store, "set gmail user = zahash");
+        let mut other = Store::new();
+        eval!(&mut other, "set discord user = pablo");
+
+        let cancel = Cancel::default();
+        cancel.cancel();
+        match merge_loaded(&mut store, other.clone(), &cancel) {
+            Err(EvalError::Merge(e)) => assert_eq!(e.to_string(), "cancelled. nothing was merged"),
+            _ => panic!(),
+        }
+        check!(&mut store, "list", ["gmail"]);
+
+        merge_loaded(&mut store, other, &Cancel::default()).unwrap();
+        check!(&mut store, "list", ["discord", "gmail"]);
+    }
+
+    #[test]
+    fn test_import_safeguards() {
+        use std::io::Write;
+
+        fn import(
+            store: &mut Store,
+            limits: ImportLimits,
+            contents: &str,
+            f: fn(&str, &mut Importer) -> anyhow::Result<Vec<String>>,
+        ) -> Result<Vec<String>, String> {
+            let mut file = tempfile::NamedTempFile::new().unwrap();
+            file.write_all(contents.as_bytes()).unwrap();
+            let mut importer = Importer::new(limits, Cancel::default());
+            let warnings =
+                f(file.path().to_str().unwrap(), &mut importer).map_err(|e| e.to_string())?;
+            let eval = importer
+                .commit(store, Merge::Overwrite, warnings)
+                .map_err(|e| e.to_string())?;
+            Ok(eval.render(&PlainText))
+        }
+
+        let limits = ImportLimits {
+            max_line_len: 64,
+            ..Default::default()
+        };
+
+        let mut store = Store::new();
+        assert_eq!(
+            import(
+                &mut store,
+                limits,
+                &format!("'gmail' user = zahash\n'a' note = {}\n", "x".repeat(100)),
+                import_lines
+            ),
+            Err("line is longer than 64 bytes line number: [2]".into())
+        );
+        assert_eq!(
+            import(
+                &mut store,
+                limits,
+                &format!("name,password\ngmail,{}\n", "x".repeat(100)),
+                import_csv
+            ),
+            Err("line is longer than 64 bytes line number: [2]".into())
+        );
+        check!(&mut store, "show all", [] as [String; 0]);
+
+        assert_eq!(
+            import(
+                &mut store,
+                limits,
+                &format!("'a' note = {}", "x".repeat(53)),
+                import_lines
+            ),
+            Ok(vec!["imported 1 record".into()])
+        );
+
+        let limits = ImportLimits {
+            max_records: 2,
+            ..Default::default()
+        };
+
+        let mut store = Store::new();
+        assert_eq!(
+            import(
+                &mut store,
+                limits,
+                "'gmail' user = zahash\n'discord' user = hazash\n'twitch' user = amogus\n",
+                import_lines
+            ),
+            Err("more than 2 records. nothing was imported".into())
+        );
+        check!(&mut store, "show all", [] as [String; 0]);
+
+        assert_eq!(
+            import(
+                &mut store,
+                limits,
+                "'gmail' user = zahash\n'discord' user = hazash\n",
+                import_lines
+            ),
+            Ok(vec!["imported 2 records".into()])
+        );
+
+        let mut store = Store::new();
+        assert_eq!(
+            import(
+                &mut store,
+                ImportLimits::default(),
+                "'gmail' sensitive pass = ' p1 ' note = ' fine '\n'github' sensitive pass = 'p2 '\n",
+                import_lines
+            ),
+            Ok(vec![
+                "⚠ 2 sensitive values have surrounding whitespace, a trailing newline or control characters".into(),
+                "imported 2 records".into()
+            ])
+        );
+
+        let contents = "'\x1b[2Jgmail' user = '\x1b[31mzahash\x07' note = fine\n";
+
+        let mut store = Store::new();
+        assert_eq!(
+            import(&mut store, ImportLimits::default(), contents, import_lines),
+            Ok(vec![
+                "removed control characters from 2 names, attrs or values".into(),
+                "imported 1 record".into()
+            ])
+        );
+        check!(
+            &mut store,
+            "show all",
+            ["'[2Jgmail' note='fine' user='[31mzahash'"]
+        );
+
+        let limits = ImportLimits {
+            sanitize: false,
+            ..Default::default()
+        };
+
+        let mut store = Store::new();
+        assert_eq!(
+            import(&mut store, limits, contents, import_lines),
+            Ok(vec!["imported 1 record".into()])
+        );
+        check!(
+            &mut store,
+            "show all",
+            ["'\x1b[2Jgmail' note='fine' user='\x1b[31mzahash\x07'"]
+        );
+    }
+
+    #[test]
+    fn test_import_lastpass() {
+        use std::io::Write;
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(
+            br#"url,username,password,totp,extra,name,grouping,fav
+https://mail.google.com,zahash,"pass, with comma",,,gmail,Email,1
+https://discord.com,hazash,dpass,,"multi
+line note",discord,Social/Chat,0
+https://mail.google.com,zahash,newpass,JBSWY3DPEHPK3PXP,,gmail,Email,1
+https://nameless.com,nobody,npass,,,,,0
+"#,
+        )
+        .unwrap();
+
+        let mut store = Store::new();
+        let cmd = format!("import lastpass {}", file.path().to_str().unwrap());
+        check!(
+            &mut store,
+            &cmd,
+            [
+                "skipped a row without a name. url='https://nameless.com'",
+                "imported 2 records"
+            ]
+        );
+
+        check!(
+            &mut store,
+            "show all",
+            [
+                "'discord' group='Social/Chat' note='multi\nline note' pass=***** url='https://discord.com' user='hazash'",
+                "'gmail' group='Email' pass=***** totp=***** url='https://mail.google.com' user='zahash'"
+            ]
+        );
+        check!(
+            &mut store,
+            "reveal gmail",
+            ["'gmail' group='Email' pass='newpass' totp='JBSWY3DPEHPK3PXP' url='https://mail.google.com' user='zahash'"]
+        );
+        assert_eq!(
+            eval("history gmail", &mut store)
+                .unwrap()
+                .render(&PlainText)
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_import_csv() {
+        use std::io::Write;
+
+        fn import(store: &mut Store, contents: &'static str) -> Vec<String> {
+            let mut file = tempfile::NamedTempFile::new().unwrap();
+            file.write_all(contents.as_bytes()).unwrap();
+            let cmd = format!("import csv {}", file.path().to_str().unwrap());
+            eval(&cmd, store).unwrap().render(&PlainText)
+        }
+
+        let mut store = Store::new();
+        assert_eq!(
+            import(
+                &mut store,
+                r#"name,url,username,password,note
+accounts.google.com,https://accounts.google.com/signin,zahash,"g,pass",
+discord.com,https://discord.com/login,hazash,dpass,second account
+broken.com,https://broken.com,nobody
+,,ghost,gpass,
+accounts.google.com,https://accounts.google.com/signin,zahash,newpass,
+"#
+            ),
+            [
+                "line 4: expected 5 columns but found 3",
+                "line 5: row without a name or url",
+                "skipped 2 rows",
+                "imported 2 records"
+            ]
+        );
+        check!(
+            &mut store,
+            "reveal all",
+            [
+                "'accounts.google.com' pass='newpass' url='https://accounts.google.com/signin' user='zahash'",
+                "'discord.com' note='second account' pass='dpass' url='https://discord.com/login' user='hazash'"
+            ]
+        );
+        assert_eq!(
+            eval("history accounts.google.com", &mut store)
+                .unwrap()
+                .render(&PlainText)
+                .len(),
+            2
+        );
+
+        let mut store = Store::new();
+        assert_eq!(
+            import(
+                &mut store,
+                r#"url,username,httpRealm,formActionOrigin,guid
+https://twitch.tv,amogus,,https://twitch.tv,{abc}
+"#
+            ),
+            [
+                "no 'password' column found. importing without passwords",
+                "imported 1 record"
+            ]
+        );
+        check!(
+            &mut store,
+            "show all",
+            ["'https://twitch.tv' url='https://twitch.tv' user='amogus'"]
+        );
+    }
+
+    #[test]
+    fn test_import_keepass() {
+        use std::io::Write;
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(
+            r#"<?xml version="1.0" encoding="utf-8" standalone="yes"?>
+<KeePassFile>
+    <Meta><Generator>KeePass</Generator></Meta>
+    <Root>
+        <Group>
+            <UUID>cm9vdA==</UUID>
+            <Name>Database</Name>
+            <Entry>
+                <UUID>dG9w</UUID>
+                <String><Key>Title</Key><Value>top</Value></String>
+                <String><Key>UserName</Key><Value>zahash</Value></String>
+            </Entry>
+            <Group>
+                <Name>Internet</Name>
+                <Group>
+                    <Name>Email</Name>
+                    <Entry>
+                        <UUID>Z21haWw=</UUID>
+                        <String><Key>Title</Key><Value>gmail</Value></String>
+                        <String><Key>UserName</Key><Value>zahash</Value></String>
+                        <String><Key>Password</Key><Value ProtectInMemory="True">&lt;p&amp;ss&gt; "ни шагу" 🦀</Value></String>
+                        <String><Key>URL</Key><Value>https://mail.google.com</Value></String>
+                        <String><Key>Notes</Key><Value>personal</Value></String>
+                        <History>
+                            <Entry>
+                                <UUID>Z21haWw=</UUID>
+                                <String><Key>Title</Key><Value>old gmail</Value></String>
+                            </Entry>
+                        </History>
+                    </Entry>
+                    <Entry>
+                        <UUID>bm90aXRsZQ==</UUID>
+                        <String><Key>Title</Key><Value></Value></String>
+                        <String><Key>Password</Key><Value>untitled</Value></String>
+                    </Entry>
+                    <Entry>
+                        <String><Key>Title</Key><Value></Value></String>
+                    </Entry>
+                </Group>
+            </Group>
+        </Group>
+    </Root>
+</KeePassFile>
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let mut store = Store::new();
+        let cmd = format!("import keepass {}", file.path().to_str().unwrap());
+        check!(
+            &mut store,
+            &cmd,
+            [
+                "skipped 1 entries without a title or uuid",
+                "imported 3 records"
+            ]
+        );
+        check!(
+            &mut store,
+            "reveal all",
+            [
+                "'bm90aXRsZQ==' group='Internet/Email' pass='untitled'",
+                "'gmail' group='Internet/Email' note='personal' pass='<p&ss> \"ни шагу\" 🦀' url='https://mail.google.com' user='zahash'",
+                "'top' user='zahash'"
+            ]
+        );
+        check!(
+            &mut store,
+            "show gmail",
+            ["'gmail' group='Internet/Email' note='personal' pass=***** url='https://mail.google.com' user='zahash'"]
+        );
+    }
+
+    #[test]
+    fn test_import_keepass_same_title() {
+        use std::io::Write;
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(
+            r#"<KeePassFile>
+    <Root>
+        <Group>
+            <Name>Database</Name>
+            <Entry>
+                <String><Key>Title</Key><Value>gmail</Value></String>
+                <String><Key>UserName</Key><Value>zahash</Value></String>
+            </Entry>
+            <Entry>
+                <String><Key>Title</Key><Value>gmail-2</Value></String>
+                <String><Key>UserName</Key><Value>hazash</Value></String>
+            </Entry>
+            <Group>
+                <Name>Work</Name>
+                <Entry>
+                    <String><Key>Title</Key><Value>gmail</Value></String>
+                    <String><Key>UserName</Key><Value>zahash@work</Value></String>
+                </Entry>
+            </Group>
+        </Group>
+    </Root>
+</KeePassFile>
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let mut store = Store::new();
+        let cmd = format!("import keepass {}", file.path().to_str().unwrap());
+        check!(
+            &mut store,
+            &cmd,
+            [
+                "'gmail' in 'Work' has the title of an earlier entry, so it was imported as 'gmail-3'",
+                "imported 3 records"
+            ]
+        );
+        check!(
+            &mut store,
+            "show all",
+            [
+                "'gmail' user='zahash'",
+                "'gmail-2' user='hazash'",
+                "'gmail-3' group='Work' user='zahash@work'"
+            ]
+        );
     }
 
     #[test]
-    fn test_set() {
+    fn test_check_duplicates() {
         let mut store = Store::new();
 
-        eval!(&mut store, "set gmail");
-        check!(&mut store, "show all", ["'gmail'"]);
+        check!(&mut store, "check duplicates", ["no duplicate values"]);
 
-        eval!(&mut store, "set gmail user = zahash pass = supersecretpass");
-        check!(
+        eval!(
             &mut store,
-            "show all",
-            ["'gmail' pass='supersecretpass' user='zahash'"]
+            "set gmail user = zahash sensitive pass = hunter2",
+            "set yahoo user = zahash sensitive pass = hunter2 sensitive pin = 1234",
+            "set bank user = pablo sensitive pin = 1234 sensitive pass = hunter2",
+            "set github user = pablo sensitive pass = ninja",
+            // the same value, but not sensitive in both
+            "set notes text = ninja",
+            "tag gmail work",
+            "tag github work"
         );
 
-        eval!(&mut store, "set gmail url = mail.google.com");
         check!(
             &mut store,
-            "show all",
-            ["'gmail' pass='supersecretpass' url='mail.google.com' user='zahash'"]
+            "check duplicates",
+            [
+                "sensitive values used more than once:",
+                "***** in 3 fields: 'bank' pass, 'gmail' pass, 'yahoo' pass",
+                "***** in 2 fields: 'bank' pin, 'yahoo' pin",
+                "other values used more than once:",
+                "'pablo' in 2 fields: 'bank' user, 'github' user",
+                "'zahash' in 2 fields: 'gmail' user, 'yahoo' user"
+            ]
         );
 
-        eval!(&mut store, "set gmail pass = updatedpass");
-        check!(
+        let groups = store.find_duplicate_sensitive_values();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].count, 3);
+        assert!(groups.iter().all(|g| g.value == "*****"));
+
+        eval!(
             &mut store,
-            "show all",
-            ["'gmail' pass='updatedpass' url='mail.google.com' user='zahash'"]
+            "set yahoo sensitive pass = changed",
+            "del bank",
+            "del github"
         );
-
-        eval!(&mut store, "set discord url = discord.com tags = chat,call");
         check!(
             &mut store,
-            "show all",
+            "check duplicates",
             [
-                "'discord' tags='chat,call' url='discord.com'",
-                "'gmail' pass='updatedpass' url='mail.google.com' user='zahash'",
+                "other values used more than once:",
+                "'zahash' in 2 fields: 'gmail' user, 'yahoo' user"
             ]
         );
     }
 
     #[test]
-    fn test_del() {
+    fn test_check_kinds() {
         let mut store = Store::new();
 
-        check!(&mut store, "delete gmail", [] as [String; 0]);
-
-        eval!(
+        check!(
             &mut store,
-            "set gmail url = mail.google.com sensitive pass = gpass"
+            "check kinds",
+            ["every value looks like its kind"]
         );
 
-        check!(&mut store, "delete discord", [] as [String; 0]);
-
         eval!(
             &mut store,
-            "set discord user = doubledragon url = discord.com"
-        );
-
-        check!(
-            &mut store,
-            "delete gmail",
-            ["'gmail' pass=***** url='mail.google.com'"]
+            "set card number = 4111 type number sensitive pin = 12a4",
+            "set gmail email = zahash url = mail.google.com",
+            "set notes text = anything",
+            "set bank code = 'not digits' type pin"
         );
 
         check!(
             &mut store,
-            "show all",
-            ["'discord' url='discord.com' user='doubledragon'"]
+            "check kinds",
+            [
+                "'bank' code: doesn't look like a pin (digits only)",
+                "'card' pin: doesn't look like a pin (digits only)",
+                "'gmail' email: doesn't look like an email address"
+            ]
         );
 
-        check!(&mut store, "delete gmail user pass", [] as [String; 0]);
+        eval!(&mut store, "set gmail email = zahash@gmail.com", "del bank");
         check!(
             &mut store,
-            "delete discord user pass",
-            ["'discord' url='discord.com'"]
+            "check kinds",
+            ["'card' pin: doesn't look like a pin (digits only)"]
         );
     }
 
     #[test]
-    fn test_show_reveal() {
+    fn test_check_names() {
         let mut store = Store::new();
 
-        eval!(
+        check!(
             &mut store,
-            "set gmail user = zahash pass = pass123 url = mail.google.com",
-            "set discord user = hazash pass = dpass123 url = discord.com",
-            "set twitch user = amogus pass = tpass123"
+            "check names",
+            ["no record is named like a keyword"]
         );
 
-        check!(
+        eval!(
             &mut store,
-            "show discord",
-            ["'discord' pass='dpass123' url='discord.com' user='hazash'"]
+            "set 'all' user = zahash",
+            "set 'all1' user = zahash",
+            "set 'prev' user = zahash",
+            "set gmail user = zahash"
         );
         check!(
             &mut store,
-            "show all",
+            "check names",
             [
-                "'discord' pass='dpass123' url='discord.com' user='hazash'",
-                "'gmail' pass='pass123' url='mail.google.com' user='zahash'",
-                "'twitch' pass='tpass123' user='amogus'"
+                "'all' is the keyword all without quotes. rename 'all' all2",
+                "'prev' is the keyword prev without quotes. rename 'prev' prev1"
             ]
         );
+
+        eval!(&mut store, "rename 'all' all2", "rename 'prev' prev1");
         check!(
             &mut store,
-            r#"show user contains AsH and url matches '\.com'"#,
-            [
-                "'discord' pass='dpass123' url='discord.com' user='hazash'",
-                "'gmail' pass='pass123' url='mail.google.com' user='zahash'"
-            ]
+            "check names",
+            ["no record is named like a keyword"]
         );
-        check!(
+    }
+
+    #[test]
+    fn test_size_report() {
+        let mut store = Store::new();
+        for i in 0..12 {
+            eval!(&mut store, &format!("set site{} user = zahash", i));
+        }
+        eval!(
             &mut store,
-            r#"show url contains google or user is amogus"#,
-            [
-                "'gmail' pass='pass123' url='mail.google.com' user='zahash'",
-                "'twitch' pass='tpass123' user='amogus'"
-            ]
+            "set gmail pass = 1",
+            "set gmail pass = 2",
+            "set gmail pass = 3"
         );
-        check!(
+
+        let report = store.size_report();
+        assert_eq!(
+            report.total,
+            serde_json::to_vec(&store).unwrap().len(),
+            "the report must measure the same bytes as the vault file"
+        );
+        assert_eq!(
+            report.records.iter().map(|r| r.total).sum::<usize>() + report.overhead,
+            report.total
+        );
+        assert!(report
+            .records
+            .iter()
+            .all(|r| r.fields + r.history < r.total));
+        assert!(report.records.windows(2).all(|w| w[0].total >= w[1].total));
+        assert_eq!(report.records[0].name, "gmail");
+
+        let lines = eval("size report", &mut store).unwrap().render(&PlainText);
+        assert_eq!(lines.len(), 16);
+        assert_eq!(lines[0], format!("{} bytes uncompressed", report.total));
+        assert!(lines[1].starts_with("'gmail' "));
+        assert!(lines[11].starts_with("3 other records "));
+    }
+
+    #[test]
+    fn test_size_report_prune() {
+        let mut store = Store::new();
+        eval!(
             &mut store,
-            "show pass matches '[a-z]+123' and ( user is amogus or user contains 'ash' )",
+            "set gmail pass = 1",
+            "set gmail pass = 2",
+            "set gmail pass = 3",
+            "set discord pass = 1",
+            "set discord pass = 2",
+            "set github user = zahash"
+        );
+
+        let report = store.size_report();
+        let prunable = |name: &str| {
+            let record = report.records.iter().find(|r| r.name == name).unwrap();
+            record.prunable
+        };
+        assert!(prunable("gmail") > prunable("discord"));
+        assert_eq!(prunable("github"), 0);
+
+        let lines = eval("size report", &mut store).unwrap().render(&PlainText);
+        let total = prunable("gmail") + prunable("discord");
+        assert_eq!(
+            lines[lines.len() - 3..],
             [
-                "'discord' pass='dpass123' url='discord.com' user='hazash'",
-                "'gmail' pass='pass123' url='mail.google.com' user='zahash'",
-                "'twitch' pass='tpass123' user='amogus'"
+                format!(
+                    "pruning every history to the current state saves {} bytes",
+                    total
+                ),
+                format!(
+                    "    history prune 'gmail' 1 saves {} bytes",
+                    prunable("gmail")
+                ),
+                format!(
+                    "    history prune 'discord' 1 saves {} bytes",
+                    prunable("discord")
+                ),
             ]
         );
 
-        eval!(&mut store, "set sus user = sussolini name = potatus");
-        check!(&mut store, "show name is sus", [] as [String; 0]);
-        check!(
-            &mut store,
-            "show name is potatus",
-            ["'sus' name='potatus' user='sussolini'"]
-        );
-        check!(
+        // the estimate is exactly what the suggested commands save
+        eval!(
             &mut store,
-            "show . is sus",
-            ["'sus' name='potatus' user='sussolini'"]
+            "history prune gmail 1",
+            "history prune discord 1"
         );
-        check!(
+        assert_eq!(store.size_report().total, report.total - total);
+        assert!(!eval("size report", &mut store)
+            .unwrap()
+            .render(&PlainText)
+            .iter()
+            .any(|line| line.contains("prune")));
+    }
+
+    #[test]
+    fn test_export() {
+        let mut store = Store::new();
+
+        eval!(
             &mut store,
-            "show . contains SUS",
-            ["'sus' name='potatus' user='sussolini'"]
+            "set gmail user = 'benito sussolini' sensitive pass = 'ни шагу назад' url = mail.google.com",
+            "set discord user = hazash code = 1234 type pin",
+            "set 'all' 'is' = 'keyword (lookalikes)'"
         );
 
-        eval!(&mut store, "set sus secret pass = supahotfire");
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("export.txt");
+        let cmd = format!("export {}", fpath.to_str().unwrap());
         check!(
             &mut store,
-            "show sus",
-            ["'sus' name='potatus' pass=***** user='sussolini'"]
+            &cmd,
+            [
+                "⚠ 1 sensitive value was written in plain text",
+                "exported 3 records"
+            ]
         );
 
-        eval!(&mut store, "set sus sensitive user = sussolini");
-        check!(
-            &mut store,
-            "show sus",
-            ["'sus' name='potatus' pass=***** user=*****"]
+        let mut imported = Store::new();
+        let cmd = format!("import {}", fpath.to_str().unwrap());
+        eval!(&mut imported, &cmd);
+        // the kinds that were given
+        let kinds = |store: &Store| {
+            let mut kinds = (store.get(Query::All).iter())
+                .flat_map(|r| {
+                    r.fields
+                        .iter()
+                        .map(|f| format!("{}.{}: {}", r.name, f.attr, f.kind))
+                })
+                .collect::<Vec<_>>();
+            kinds.sort();
+            kinds
+        };
+        assert_eq!(kinds(&imported), kinds(&store));
+
+        assert_eq!(
+            eval("show all", &mut imported).unwrap().render(&PlainText),
+            eval("show all", &mut store).unwrap().render(&PlainText)
         );
-        check!(
-            &mut store,
-            "reveal sus",
-            ["'sus' name='potatus' pass='supahotfire' user='sussolini'"]
+        assert_eq!(
+            eval("reveal all", &mut imported)
+                .unwrap()
+                .render(&PlainText),
+            eval("reveal all", &mut store).unwrap().render(&PlainText)
+        );
+
+        store.set(
+            "twitch",
+            None,
+            vec![Assign {
+                attr: "note",
+                value: "it's",
+                sensitive: false,
+                expires: None,
+                kind: None,
+            }],
         );
+        let cmd = format!("export {}", dir.path().join("quote.txt").to_str().unwrap());
+        assert!(matches!(eval(&cmd, &mut store), Err(EvalError::Export(_))));
+        assert!(!dir.path().join("quote.txt").exists());
     }
 
     #[test]
-    fn test_history() {
+    fn test_export_file() {
+        let mut store = Store::new();
+        eval!(&mut store, "set gmail sensitive pass = hunter2");
+
+        let dir = tempfile::tempdir().unwrap();
+        for (cmd, fname) in [
+            ("export", "export.txt"),
+            ("export csv", "export.csv"),
+            ("export json", "export.json"),
+        ] {
+            let fpath = dir.path().join(fname);
+            let cmd = format!("{} {}", cmd, fpath.to_str().unwrap());
+            eval!(&mut store, &cmd);
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = std::fs::metadata(&fpath).unwrap().permissions().mode();
+                assert_eq!(mode & 0o777, 0o600, "{}", cmd);
+            }
+
+            // never replaced without asking
+            let before = std::fs::read(&fpath).unwrap();
+            eval!(&mut store, "set gmail sensitive pass = changed");
+            assert!(matches!(eval(&cmd, &mut store), Err(EvalError::Export(_))));
+            assert_eq!(std::fs::read(&fpath).unwrap(), before, "{}", cmd);
+        }
+    }
+
+    #[test]
+    fn test_export_csv() {
         let mut store = Store::new();
 
         eval!(
             &mut store,
-            "set sus user = 'benito sussolini' sensitive pass = amogus"
+            "set gmail user = zahash sensitive pass = 'sus, amogus' url = mail.google.com",
+            "set discord user = hazash"
+        );
+        store.set(
+            "twitch",
+            None,
+            vec![Assign {
+                attr: "note",
+                value: "he said \"hi\"\nit's 'fine'",
+                sensitive: false,
+                expires: None,
+                kind: None,
+            }],
         );
-        eval!(&mut store, "set sus user = 'pablo susscobar'");
-        eval!(&mut store, "set sus user = 'pablo susscobar'");
-        eval!(&mut store, "del sus user");
-        eval!(&mut store, "set sus pass = potatus");
-        eval!(&mut store, "set sus note = 'this is the latest'");
 
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("export.csv");
+        let cmd = format!("export csv {}", fpath.to_str().unwrap());
         check!(
             &mut store,
-            "show sus",
-            ["'sus' note='this is the latest' pass='potatus'"]
+            &cmd,
+            [
+                "⚠ 1 sensitive value was written in plain text",
+                "exported 3 records"
+            ]
         );
-        match eval("history sus", &mut store).unwrap().lines().as_slice() {
-            [h1, h2, h3, h4, h5] => {
-                assert!(h1.ends_with("note='this is the latest' pass='potatus'"));
-                assert!(h2.ends_with("pass='potatus'"));
-                assert!(h3.ends_with("pass=*****"));
-                assert!(h4.ends_with("pass=***** user='pablo susscobar'"));
-                assert!(h5.ends_with("pass=***** user='benito sussolini'"));
-            }
-            _ => assert!(false),
-        }
 
-        check!(&mut store, "history blah", [] as [String; 0]);
+        let mut reader = csv::Reader::from_path(&fpath).unwrap();
+        assert_eq!(
+            reader.headers().unwrap(),
+            vec!["name", "note", "pass", "url", "user"]
+        );
+        let rows = reader
+            .records()
+            .map(|row| row.unwrap().iter().map(String::from).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["discord", "", "", "", "hazash"],
+                vec!["gmail", "", "sus, amogus", "mail.google.com", "zahash"],
+                vec!["twitch", "he said \"hi\"\nit's 'fine'", "", "", ""],
+            ]
+        );
     }
 
     #[test]
-    fn test_reveal_history() {
+    fn test_export_tagged() {
         let mut store = Store::new();
+        eval!(&mut store, "set gmail user = zahash", "tag gmail work");
 
-        eval!(
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("export.txt");
+        check!(
             &mut store,
-            "set sus user = 'benito sussolini' sensitive pass = amogus"
+            &format!("export {}", fpath.to_str().unwrap()),
+            ["exported 1 record"]
         );
-        match eval("reveal history sus", &mut store)
-            .unwrap()
-            .lines()
-            .as_slice()
-        {
-            [h1] => assert!(h1.ends_with("pass='amogus' user='benito sussolini'")),
-            _ => assert!(false),
-        }
+        assert_eq!(
+            std::fs::read_to_string(&fpath).unwrap(),
+            "'gmail' 'user' = 'zahash'\n"
+        );
+
+        let mut imported = Store::new();
+        let cmd = format!("import {}", fpath.to_str().unwrap());
+        eval!(&mut imported, &cmd);
+        check!(&mut imported, "show all", ["'gmail' user='zahash'"]);
     }
 
     #[test]
-    fn test_rename() {
+    fn test_export_csv_fields() {
         let mut store = Store::new();
 
-        check!(&mut store, "rename gmail discord", ["'gmail' not found!"]);
+        eval!(
+            &mut store,
+            "set gmail user = zahash sensitive pass = 'sus, amogus'",
+            "set discord user = hazash",
+            "tag discord work"
+        );
 
-        eval!(&mut store, "set discord");
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("fields.csv");
+        let cmd = format!("export csv fields {}", fpath.to_str().unwrap());
         check!(
             &mut store,
-            "rename gmail discord",
-            ["'discord' already exists!"]
+            &cmd,
+            [
+                "⚠ 1 sensitive value was written in plain text",
+                "exported 2 records"
+            ]
         );
 
-        check!(&mut store, "rename discord discord2", ["Renamed!"]);
+        let mut reader = csv::Reader::from_path(&fpath).unwrap();
+        assert_eq!(
+            reader.headers().unwrap(),
+            vec!["name", "attr", "value", "sensitive"]
+        );
+        let rows = reader
+            .records()
+            .map(|row| row.unwrap().iter().map(String::from).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["discord", "user", "hazash", "false"],
+                vec!["gmail", "pass", "sus, amogus", "true"],
+                vec!["gmail", "user", "zahash", "false"],
+            ]
+        );
     }
 
     #[test]
-    fn test_copy() {
+    fn test_export_csv_tagged() {
         let mut store = Store::new();
+        eval!(&mut store, "set gmail user = zahash", "tag gmail work");
 
-        check!(&mut store, "copy gmail pass", ["Unable to Copy! Try Again!"]);
-
-        eval!(&mut store, "set gmail");
-        check!(&mut store, "copy gmail pass", ["Unable to Copy! Try Again!"]);
-
-        eval!(&mut store, "set gmail url = mail.google.com");
-        check!(&mut store, "copy gmail pass", ["Unable to Copy! Try Again!"]);
-
-        eval!(&mut store, "set gmail pass = gpass");
-        check!(&mut store, "copy gmail pass", ["Copied!"]);
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("export.csv");
+        eval!(
+            &mut store,
+            &format!("export csv {}", fpath.to_str().unwrap())
+        );
 
-        eval!(&mut store, "set gmail sensitive pass = gpass");
-        check!(&mut store, "copy gmail pass", ["Copied!"]);
+        let mut reader = csv::Reader::from_path(&fpath).unwrap();
+        assert_eq!(reader.headers().unwrap(), vec!["name", "user"]);
     }
 
     #[test]
-    fn test_import() {
-        use std::io::Write;
-
-        fn import(store: &mut Store, contents: &'static str) {
-            let mut file = tempfile::NamedTempFile::new().unwrap();
-            write!(file, "{}", contents).unwrap();
-            let cmd = format!("import {}", file.path().to_str().unwrap());
-            eval!(store, &cmd);
-        }
-
+    fn test_export_json() {
         let mut store = Store::new();
 
-        import(&mut store, "");
-        check!(&mut store, "show all", [] as [String; 0]);
-
-        import(
+        eval!(
             &mut store,
-            r#"
-            'gmail' user = ligma pass = balls
-            'gmail' user = ligma pass = balls
-            'gmail' user = ligma pass = balls
-            'gmail' user = ligma pass = balls
-            'gmail' user = 'benito sussolini' pass = 'joseph ballin'
-            'gmail' user = 'benito sussolini' pass = 'joseph ballin'
-            'gmail' user = 'benito sussolini' pass = 'joseph ballin'
-            'discord' user = 'dorito breath' pass = 'kitten'
-            'discord' user = 'dorito breath' pass = 'kitten'
-            "#,
+            "set gmail user = zahash sensitive pass = 'ни шагу назад'",
+            "set discord user = hazash code = 1234 type pin"
         );
+
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("export.json");
+        let cmd = format!("export json {}", fpath.to_str().unwrap());
         check!(
             &mut store,
-            "show all",
+            &cmd,
             [
-                "'discord' pass='kitten' user='dorito breath'",
-                "'gmail' pass='joseph ballin' user='benito sussolini'"
+                "⚠ 1 sensitive value was written in plain text",
+                "exported 2 records"
             ]
         );
-        match eval("history gmail", &mut store)
-            .unwrap()
-            .lines()
-            .as_slice()
-        {
-            [h1, h2] => {
-                assert!(h1.ends_with("pass='joseph ballin' user='benito sussolini'"));
-                assert!(h2.ends_with("pass='balls' user='ligma'"));
-            }
-            _ => assert!(false),
-        }
-        match eval("history discord", &mut store)
+
+        let json =
+            serde_json::from_reader::<_, serde_json::Value>(std::fs::File::open(&fpath).unwrap())
+                .unwrap();
+        assert_eq!(json[0]["name"], "discord");
+        assert_eq!(json[1]["name"], "gmail");
+        assert!(json[1]["fields"]
+            .as_array()
             .unwrap()
-            .lines()
-            .as_slice()
-        {
-            [h1] => assert!(h1.ends_with("pass='kitten' user='dorito breath'")),
-            _ => assert!(false),
-        }
+            .iter()
+            .any(|field| field["attr"] == "pass"
+                && field["value"] == "ни шагу назад"
+                && field["sensitive"] == true));
+        // old values of sensitive fields aren't written
+        assert_eq!(json[1].get("history"), None);
+        // only given kinds are written
+        let kind = |json: &serde_json::Value, attr: &str| {
+            let fields = json["fields"].as_array().unwrap();
+            fields.iter().find(|f| f["attr"] == attr).unwrap()["kind"].clone()
+        };
+        assert_eq!(kind(&json[0], "code"), "pin");
+        assert_eq!(kind(&json[0], "user"), serde_json::Value::Null);
+        let records = serde_json::from_value::<Vec<Record>>(json.clone()).unwrap();
+        assert_eq!(records[0].fields[1].kind, FieldKind::Pin);
+
+        eval!(&mut store, "del gmail");
+        let cmd = format!(
+            "export json {}",
+            dir.path().join("one.json").to_str().unwrap()
+        );
+        check!(&mut store, &cmd, ["exported 1 record"]);
     }
 }
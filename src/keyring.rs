@@ -0,0 +1,86 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::Context;
+use zeroize::Zeroizing;
+
+/// where the master password of a vault can be kept between sessions, so that
+/// nobody has to type it
+pub trait Keyring {
+    /// the master password kept for the vault at `fpath`
+    fn get(&self, fpath: &str) -> Option<Zeroizing<String>>;
+
+    fn set(&self, fpath: &str, pass: &str) -> anyhow::Result<()>;
+
+    fn forget(&self, fpath: &str) -> anyhow::Result<()>;
+}
+
+/// the freedesktop secret service (gnome keyring, kwallet..) through libsecret's `secret-tool`
+pub struct SecretService;
+
+const SERVICE: &str = "royalguard";
+
+/// the same vault under another relative path is still the same entry
+fn vault(fpath: &str) -> String {
+    std::fs::canonicalize(fpath)
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|_| fpath.to_string())
+}
+
+const MISSING: &str = "the keyring needs secret-tool (libsecret)";
+
+fn check(action: &str, output: std::process::Output) -> anyhow::Result<()> {
+    match output.status.success() {
+        true => Ok(()),
+        false => anyhow::bail!(
+            "unable to {} the master password. {}",
+            action,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+    }
+}
+
+impl Keyring for SecretService {
+    fn get(&self, fpath: &str) -> Option<Zeroizing<String>> {
+        let output = Command::new("secret-tool")
+            .args(["lookup", "service", SERVICE, "vault", &vault(fpath)])
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        // nothing kept is an empty output and a failure
+        let pass = Zeroizing::new(String::from_utf8(output.stdout).ok()?);
+        (output.status.success() && !pass.is_empty()).then_some(pass)
+    }
+
+    fn set(&self, fpath: &str, pass: &str) -> anyhow::Result<()> {
+        let vault = vault(fpath);
+        // the password goes through stdin, since arguments can be seen by other processes
+        let mut child = Command::new("secret-tool")
+            .args(["store", "--label", &format!("royalguard {}", vault)])
+            .args(["service", SERVICE, "vault", &vault])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context(MISSING)?;
+        // dropped right away, so that secret-tool sees the end of the password
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(pass.as_bytes())?;
+        check("keep", child.wait_with_output()?)
+    }
+
+    fn forget(&self, fpath: &str) -> anyhow::Result<()> {
+        let output = Command::new("secret-tool")
+            .args(["clear", "service", SERVICE, "vault", &vault(fpath)])
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .output()
+            .context(MISSING)?;
+        check("forget", output)
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt::Display;
+
 use lazy_static::lazy_static;
 use regex::Regex;
 
@@ -20,7 +22,7 @@ pub enum LexError {
     InvalidToken { pos: usize },
 }
 
-pub fn lex(text: &str) -> Result<Vec<Token>, LexError> {
+pub fn lex(text: &str) -> Result<Vec<Token<'_>>, LexError> {
     match text.is_empty() {
         true => Ok(vec![]),
         false => {
@@ -46,8 +48,17 @@ pub fn lex(text: &str) -> Result<Vec<Token>, LexError> {
     }
 }
 
-fn lex_token(text: &str, pos: usize) -> Result<(Token, usize), LexError> {
+/// whether `text` typed without quotes is a keyword, like `all` or `prev`
+pub fn is_keyword(text: &str) -> bool {
+    matches!(lex(text).as_deref(), Ok([Token::Keyword(keyword)]) if *keyword == text)
+}
+
+fn lex_token(text: &str, pos: usize) -> Result<(Token<'_>, usize), LexError> {
     lex_keyword(text, pos)
+        .or(lex_standalone_symbol(text, pos, ">="))
+        .or(lex_standalone_symbol(text, pos, "<="))
+        .or(lex_standalone_symbol(text, pos, ">"))
+        .or(lex_standalone_symbol(text, pos, "<"))
         .or(lex_symbol(text, pos, "="))
         .or(lex_symbol(text, pos, "("))
         .or(lex_symbol(text, pos, ")"))
@@ -55,12 +66,12 @@ fn lex_token(text: &str, pos: usize) -> Result<(Token, usize), LexError> {
         .ok_or(LexError::InvalidToken { pos })
 }
 
-fn lex_keyword(text: &str, pos: usize) -> Option<(Token, usize)> {
+fn lex_keyword(text: &str, pos: usize) -> Option<(Token<'_>, usize)> {
     let (token, pos) = lex_with_pattern(text, pos, &KEYWORD_REGEX)?;
     Some((Token::Keyword(token), pos))
 }
 
-fn lex_value(text: &str, pos: usize) -> Option<(Token, usize)> {
+fn lex_value(text: &str, pos: usize) -> Option<(Token<'_>, usize)> {
     let (mut token, pos) = lex_with_pattern(text, pos, &VALUE_REGEX)?;
     if let Some(stripped) = token.strip_prefix('\'') {
         token = stripped;
@@ -100,6 +111,39 @@ fn lex_symbol(text: &str, pos: usize, symbol: &'static str) -> Option<(Token<'st
     None
 }
 
+/// a symbol that is only a symbol when whitespace or the end of the text follows it,
+/// so values like `<abc` or `>1` stay values
+fn lex_standalone_symbol(
+    text: &str,
+    pos: usize,
+    symbol: &'static str,
+) -> Option<(Token<'static>, usize)> {
+    let (token, next_pos) = lex_symbol(text, pos, symbol)?;
+    match text[next_pos..].chars().next() {
+        None => Some((token, next_pos)),
+        Some(c) if c.is_whitespace() => Some((token, next_pos)),
+        Some(_) => None,
+    }
+}
+
+impl<'text> Display for Token<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Keyword(keyword) => write!(f, "{}", keyword),
+            Token::Symbol(symbol) => write!(f, "{}", symbol),
+            Token::Value(value) => write!(f, "'{}'", value),
+        }
+    }
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LexError::InvalidToken { pos } => write!(f, "invalid token at char {}", pos),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -117,6 +161,7 @@ mod tests {
         name user pass url
         (=)'🦀🦀🦀''كلمة عربية مخيفة''N''' look_mom   no_spaces   'oh wow spaces'
         (zahash)('zahash')
+        > < >= <= '>'
         "#;
 
         use Token::*;
@@ -166,10 +211,61 @@ mod tests {
                     Symbol("("),
                     Value("zahash"),
                     Symbol(")"),
+                    Symbol(">"),
+                    Symbol("<"),
+                    Symbol(">="),
+                    Symbol("<="),
+                    Value(">"),
                 ]
             ),
 
-            Err(LexError::InvalidToken { pos }) => assert!(false, "{}", &src[pos..]),
+            Err(LexError::InvalidToken { pos }) => panic!("{}", &src[pos..]),
         }
     }
+
+    #[test]
+    fn test_comparison_symbols_in_values() {
+        use Token::*;
+
+        assert_eq!(
+            lex("<abc >1 >=x <=> a>b > 1").unwrap(),
+            vec![
+                Value("<abc"),
+                Value(">1"),
+                Value(">=x"),
+                Value("<=>"),
+                Value("a>b"),
+                Symbol(">"),
+                Value("1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_keyword() {
+        assert!(is_keyword("all"));
+        assert!(is_keyword("prev"));
+        assert!(is_keyword("show"));
+        assert!(!is_keyword("'all'"));
+        assert!(!is_keyword("allx"));
+        assert!(!is_keyword("all x"));
+        assert!(!is_keyword("gmail"));
+    }
+
+    #[test]
+    fn test_quoted_keywords() {
+        use Token::*;
+
+        assert_eq!(
+            lex("all 'all' prev 'prev' set 'set'").unwrap(),
+            vec![
+                Keyword("all"),
+                Value("all"),
+                Keyword("prev"),
+                Value("prev"),
+                Keyword("set"),
+                Value("set"),
+            ]
+        );
+    }
 }
@@ -1,10 +1,21 @@
+mod agent;
+mod browse;
+mod cancel;
+mod clipboard;
+mod codec;
+mod config;
 mod crypt;
+mod date;
+mod distance;
 mod eval;
+mod keyring;
 mod lex;
 mod parse;
 mod prompt;
+mod render;
+mod share;
 mod store;
 
-fn main() -> anyhow::Result<()> {
+fn main() -> anyhow::Result<std::process::ExitCode> {
     prompt::run()
 }
@@ -1,29 +1,93 @@
 use std::{collections::HashSet, fmt::Display};
 
-use chainchomp::ctx_free::{combine_parsers, many};
-use regex::Regex;
+use chainchomp::ctx_free::{many, Parser};
+use regex::{Regex, RegexBuilder};
 
+use crate::codec::Encoding;
+use crate::date::{self, DateError, Span};
 use crate::lex::*;
+use crate::store::FieldKind;
 
-// <cmd> ::= set <name> {<assign>}*
-//         | del <name> {<attr>}*
-//         | show <query>
-//         | reveal <query>
-//         | copy <name> <attr>
+// <cmd> ::= set <name> <where>? {<assign> | <generated>}*
+//         | del <name> <where>? {<attr>}*
+//         | del <query>
+//         | show --json? <query> <order>
+//         | reveal --json? <query> <order>
+//         | count <query>
+//         | list <query>?
+//         | copy <name> <where>? <attr> <seconds>?
+//         | copy totp <name> <attr> <seconds>?
+//         | totp <name> {digits = <value> | period = <value>}*
+//         | share <name>
+//         | decode <name> <attr> <encoding> reveal?
+//         | encode <name> <attr> <encoding> reveal?
+//         | tag <name> {<value>}+
+//         | untag <name> {<value>}+
 //         | reveal? history <name>
-//         | rename <value> <value>
-//         | import <value>
+//         | reveal? history diff <name>
+//         | history limit {set <value> | clear | show}
+//         | history clear <name>
+//         | history prune <name> <value>
+//         | rename <value> <where>? <value>
+//         | rename <name> <where>? <attr> <attr>
+//         | rename --dry-run? all <value> <value>
+//         | dup <name> <value>
+//         | allow-duplicate-names {on | off}?
+//         | rollback <name> <value>
+//         | undo
+//         | redo
+//         | trash
+//         | undelete <name>
+//         | restore <name> {<index>}?
+//         | empty trash
+//         | merge <fpath>
+//         | watch <query>
+//         | unwatch <query>
+//         | watches
+//         | import <value> <merge>?
+//         | import lastpass <value> <merge>?
+//         | import csv <value> <merge>?
+//         | import keepass <value> <merge>?
+//         | export <value>
+//         | export csv <value>
+//         | export csv fields <value>
+//         | export json <value>
+//         | size report
+//         | check duplicates
+//         | check kinds
+//         | check names
+//         | gen <value> {nosymbols | nodigits | noambiguous}*
+//         | gen words <value> {sep <value>}?
 
-// <assign> ::= sensitive? <attr> = <value>
+// <where> ::= where <or>
+// <order> ::= {sort <attr>}? {limit <value>}?
+// <assign> ::= sensitive? <attr> = <value> {expires {<value> | never}}? {type <kind>}?
+// <generated> ::= sensitive? <attr> = gen ( phrase? <value> ) {expires {<value> | never}}?
+// <kind> ::= pin | otp | url | email | number
+// <merge> ::= skip | overwrite | rename
+// <encoding> ::= b64 | hex
 // <name> ::= <attr> ::= <value> ::= [^'\n\s\t\(\)]+|'[^'\n]+'
 
 // <query> ::= <or> | <value> | all
 // <or> ::= <and> | <or> or <and>
 // <and> ::= <filter> | <and> and <filter>
-// <filter> ::= ( <query> ) | <contains> | <matches> | <is>
-// <contains> ::= <attr> contains <value>
-// <matches> ::= <attr> matches <value>
+// <filter> ::= ( <query> ) | <not> | <has> | <missing> | <tag> | <contains> | <startswith> | <endswith> | <matches> | <fuzzy> | <is> | <compare>
+// <not> ::= not <filter> | not all
+// <has> ::= has <attr>
+// <missing> ::= ( missing | lacks ) <attr>
+// <tag> ::= tag contains <value> | tag:<value> | tagged <value>
+// <contains> ::= <attr> [cs] contains <value>
+// <startswith> ::= <attr> [cs] startswith <value>
+// <endswith> ::= <attr> [cs] endswith <value>
+// <matches> ::= <attr> [cs] matches <value>
+// <fuzzy> ::= <attr> fuzzy <value> <value>?
 // <is> ::= <attr> is <value>
+// <compare> ::= <attr> {> | < | >= | <= | gt | lt | gte | lte} <value>
+
+/// the longest password `gen` and `gen(..)` make
+pub const MAX_GEN_LEN: usize = 1024;
+/// the most words `gen words` and `gen(phrase ..)` pick
+pub const MAX_GEN_WORDS: usize = 64;
 
 #[derive(Debug)]
 pub enum ParseError<'text> {
@@ -34,10 +98,54 @@ pub enum ParseError<'text> {
     Expected(Token<'static>, usize),
     ExpectedOneOf(Vec<Token<'static>>, usize),
     InvalidRegex(usize),
+    Date(DateError<'text>, usize),
     DuplicateAssignments(&'text str, usize),
+    ExpectedFilter(usize),
+    NameAlwaysExists(usize),
     IncompleteParse(usize),
 }
 
+impl<'text> ParseError<'text> {
+    fn pos(&self) -> usize {
+        match self {
+            ParseError::SyntaxError(pos, _)
+            | ParseError::ExpectedName(pos)
+            | ParseError::ExpectedAttr(pos)
+            | ParseError::ExpectedValue(pos)
+            | ParseError::Expected(_, pos)
+            | ParseError::ExpectedOneOf(_, pos)
+            | ParseError::InvalidRegex(pos)
+            | ParseError::Date(_, pos)
+            | ParseError::DuplicateAssignments(_, pos)
+            | ParseError::ExpectedFilter(pos)
+            | ParseError::NameAlwaysExists(pos)
+            | ParseError::IncompleteParse(pos) => *pos,
+        }
+    }
+}
+
+/// same as `chainchomp::ctx_free::combine_parsers` but instead of discarding
+/// the individual failures, returns the error of the parser that got furthest.
+/// falls back to `e` if none of them made it past `pos`.
+fn combine_parsers<'text, Ast>(
+    tokens: &[Token<'text>],
+    pos: usize,
+    parsers: &[&dyn Parser<Token<'text>, ParseError<'text>, Ast>],
+    e: ParseError<'text>,
+) -> Result<(Ast, usize), ParseError<'text>> {
+    let mut furthest = e;
+
+    for parser in parsers {
+        match parser.parse(tokens, pos) {
+            Ok((ast, pos)) => return Ok((ast, pos)),
+            Err(e) if e.pos() > furthest.pos() => furthest = e,
+            Err(_) => continue,
+        }
+    }
+
+    Err(furthest)
+}
+
 pub fn parse<'text>(tokens: &[Token<'text>]) -> Result<Cmd<'text>, ParseError<'text>> {
     let (cmd, pos) = parse_cmd(tokens, 0)?;
     match pos < tokens.len() {
@@ -46,25 +154,259 @@ pub fn parse<'text>(tokens: &[Token<'text>]) -> Result<Cmd<'text>, ParseError<'t
     }
 }
 
+/// a query on its own, like the ones `watch` keeps
+pub fn parse_whole_query<'text>(
+    tokens: &[Token<'text>],
+) -> Result<Query<'text>, ParseError<'text>> {
+    let (query, pos) = parse_query(tokens, 0)?;
+    match pos < tokens.len() {
+        true => Err(ParseError::IncompleteParse(pos)),
+        false => Ok(query),
+    }
+}
+
 pub enum Cmd<'text> {
     Set {
         name: &'text str,
+        filter: Option<Or<'text>>,
+        assignments: Vec<Assign<'text>>,
+        generated: Vec<Generated<'text>>,
+    },
+    /// the same assignments on every record that matches
+    SetWhere {
+        filter: Or<'text>,
         assignments: Vec<Assign<'text>>,
     },
     Del {
         name: &'text str,
+        filter: Option<Or<'text>>,
         attrs: Vec<&'text str>,
     },
-    Show(Query<'text>),
-    Reveal(Query<'text>),
+    DelQuery(Query<'text>),
+    Show(Query<'text>, Order<'text>),
+    ShowJson(Query<'text>, Order<'text>),
+    Reveal(Query<'text>, Order<'text>),
+    RevealJson(Query<'text>, Order<'text>),
+    Count(Query<'text>),
+    List(Query<'text>),
     Copy {
+        name: &'text str,
+        filter: Option<Or<'text>>,
+        /// the whole record, revealed, if `None`
+        attr: Option<&'text str>,
+        /// seconds until the clipboard is cleared. the global default if `None`
+        clear_after: Option<u64>,
+    },
+    CopyTotp {
+        name: &'text str,
+        attr: &'text str,
+        clear_after: Option<u64>,
+    },
+    Totp {
+        name: &'text str,
+        /// override the digits and period (in seconds) of the secret
+        digits: Option<usize>,
+        period: Option<u64>,
+    },
+    Share(&'text str),
+    /// copies the decoded value of `attr`. shown instead if `reveal`
+    Decode {
+        name: &'text str,
+        attr: &'text str,
+        encoding: Encoding,
+        reveal: bool,
+    },
+    /// copies the encoded value of `attr`. shown instead if `reveal`
+    Encode {
         name: &'text str,
         attr: &'text str,
+        encoding: Encoding,
+        reveal: bool,
     },
     History(&'text str),
     RevealHistory(&'text str),
-    Rename(&'text str, &'text str),
-    Import(&'text str),
+    HistoryDiff(&'text str),
+    RevealHistoryDiff(&'text str),
+    /// `None` keeps every entry
+    SetHistoryLimit(Option<usize>),
+    ShowHistoryLimit,
+    /// keeps only the current state in the history
+    ClearHistory(&'text str),
+    /// keeps only the `keep` newest history entries
+    PruneHistory {
+        name: &'text str,
+        keep: usize,
+    },
+    Rename {
+        old: &'text str,
+        filter: Option<Or<'text>>,
+        new: &'text str,
+    },
+    Dup {
+        src: &'text str,
+        dst: &'text str,
+    },
+    /// renames the attr of a field, keeping its value and sensitivity
+    RenameField {
+        record: &'text str,
+        filter: Option<Or<'text>>,
+        old_attr: &'text str,
+        new_attr: &'text str,
+    },
+    /// renames every record whose name matches `pattern`, replacing the match
+    BatchRename {
+        pattern: Regex,
+        replacement: &'text str,
+        dry_run: bool,
+    },
+    /// `None` shows the setting
+    AllowDuplicateNames(Option<bool>),
+    Rollback {
+        name: &'text str,
+        /// 1 is the newest history entry
+        steps: usize,
+    },
+    Tag {
+        name: &'text str,
+        tags: Vec<&'text str>,
+    },
+    Untag {
+        name: &'text str,
+        tags: Vec<&'text str>,
+    },
+    Import(&'text str, Merge),
+    ImportLastPass(&'text str, Merge),
+    ImportCsv(&'text str, Merge),
+    ImportKeePass(&'text str, Merge),
+    Export(&'text str),
+    ExportCsv(&'text str),
+    /// a row per field rather than per record
+    ExportCsvFields(&'text str),
+    ExportJson(&'text str),
+    SizeReport,
+    Check(CheckKind),
+    Gen {
+        len: usize,
+        symbols: bool,
+        digits: bool,
+        ambiguous: bool,
+    },
+    GenWords {
+        count: usize,
+        sep: &'text str,
+    },
+    /// takes back the latest change
+    Undo,
+    Redo,
+    Trash,
+    Undelete(&'text str),
+    /// `index` counts from the newest history entry, which is 0
+    Restore {
+        name: &'text str,
+        index: usize,
+    },
+    EmptyTrash,
+    Merge(&'text str),
+    Watch(Query<'text>),
+    Unwatch(Query<'text>),
+    Watches,
+}
+
+impl<'text> Cmd<'text> {
+    /// whether `undo` can take back what the command does
+    pub fn changes_store(&self) -> bool {
+        match self {
+            Cmd::BatchRename { dry_run, .. } => !dry_run,
+            Cmd::AllowDuplicateNames(allow) => allow.is_some(),
+            Cmd::Set { .. }
+            | Cmd::SetWhere { .. }
+            | Cmd::Del { .. }
+            | Cmd::DelQuery(_)
+            | Cmd::SetHistoryLimit(_)
+            | Cmd::ClearHistory(_)
+            | Cmd::PruneHistory { .. }
+            | Cmd::Rename { .. }
+            | Cmd::Dup { .. }
+            | Cmd::RenameField { .. }
+            | Cmd::Rollback { .. }
+            | Cmd::Tag { .. }
+            | Cmd::Untag { .. }
+            | Cmd::Undelete(_)
+            | Cmd::Restore { .. }
+            | Cmd::EmptyTrash
+            | Cmd::Merge(_)
+            | Cmd::Import(..)
+            | Cmd::ImportLastPass(..)
+            | Cmd::ImportCsv(..)
+            | Cmd::ImportKeePass(..) => true,
+            Cmd::Show(..)
+            | Cmd::ShowJson(..)
+            | Cmd::Reveal(..)
+            | Cmd::RevealJson(..)
+            | Cmd::Count(_)
+            | Cmd::List(_)
+            | Cmd::Copy { .. }
+            | Cmd::CopyTotp { .. }
+            | Cmd::Totp { .. }
+            | Cmd::Share(_)
+            | Cmd::Decode { .. }
+            | Cmd::Encode { .. }
+            | Cmd::History(_)
+            | Cmd::RevealHistory(_)
+            | Cmd::HistoryDiff(_)
+            | Cmd::RevealHistoryDiff(_)
+            | Cmd::ShowHistoryLimit
+            | Cmd::Export(_)
+            | Cmd::ExportCsv(_)
+            | Cmd::ExportCsvFields(_)
+            | Cmd::ExportJson(_)
+            | Cmd::SizeReport
+            | Cmd::Check(..)
+            | Cmd::Gen { .. }
+            | Cmd::GenWords { .. }
+            | Cmd::Undo
+            | Cmd::Redo
+            | Cmd::Trash
+            | Cmd::Watch(_)
+            | Cmd::Unwatch(_)
+            | Cmd::Watches => false,
+        }
+    }
+
+    /// the name of the record the command works on, with the `where` filter
+    /// that picks one of several records sharing the name
+    pub fn target(&self) -> Option<(&'text str, Option<&Or<'text>>)> {
+        match self {
+            Cmd::Set { name, filter, .. }
+            | Cmd::Del { name, filter, .. }
+            | Cmd::Copy { name, filter, .. }
+            | Cmd::Rename {
+                old: name, filter, ..
+            }
+            | Cmd::RenameField {
+                record: name,
+                filter,
+                ..
+            } => Some((name, filter.as_ref())),
+            Cmd::CopyTotp { name, .. }
+            | Cmd::Totp { name, .. }
+            | Cmd::Dup { src: name, .. }
+            | Cmd::Share(name)
+            | Cmd::Decode { name, .. }
+            | Cmd::Encode { name, .. }
+            | Cmd::History(name)
+            | Cmd::RevealHistory(name)
+            | Cmd::HistoryDiff(name)
+            | Cmd::RevealHistoryDiff(name)
+            | Cmd::ClearHistory(name)
+            | Cmd::PruneHistory { name, .. }
+            | Cmd::Rollback { name, .. }
+            | Cmd::Restore { name, .. }
+            | Cmd::Tag { name, .. }
+            | Cmd::Untag { name, .. } => Some((name, None)),
+            _ => None,
+        }
+    }
 }
 
 fn parse_cmd<'text>(
@@ -76,14 +418,53 @@ fn parse_cmd<'text>(
         pos,
         &[
             &parse_cmd_set,
+            &parse_cmd_del_query,
             &parse_cmd_del,
+            &parse_cmd_show_json,
             &parse_cmd_show,
+            &parse_cmd_reveal_json,
             &parse_cmd_reveal,
+            &parse_cmd_count,
+            &parse_cmd_list,
             &parse_cmd_copy,
+            &parse_cmd_totp,
+            &parse_cmd_share,
+            &parse_cmd_decode,
+            &parse_cmd_encode,
+            &parse_cmd_tag,
+            &parse_cmd_untag,
+            &parse_cmd_history_limit,
+            &parse_cmd_history_prune,
+            &parse_cmd_history_diff,
             &parse_cmd_history,
+            &parse_cmd_reveal_history_diff,
             &parse_cmd_reveal_history,
             &parse_cmd_rename,
+            &parse_cmd_dup,
+            &parse_cmd_allow_duplicate_names,
+            &parse_cmd_rollback,
+            &parse_cmd_undo,
+            &parse_cmd_redo,
+            &parse_cmd_trash,
+            &parse_cmd_undelete,
+            &parse_cmd_restore,
+            &parse_cmd_empty_trash,
+            &parse_cmd_merge,
+            &parse_cmd_watch,
+            &parse_cmd_unwatch,
+            &parse_cmd_watches,
+            &parse_cmd_import_lastpass,
+            &parse_cmd_import_csv,
+            &parse_cmd_import_keepass,
             &parse_cmd_import,
+            &parse_cmd_export_csv_fields,
+            &parse_cmd_export_csv,
+            &parse_cmd_export_json,
+            &parse_cmd_export,
+            &parse_cmd_size_report,
+            &parse_cmd_check,
+            &parse_cmd_gen,
+            &parse_cmd_gen_words,
         ],
         ParseError::SyntaxError(pos, "cannot parse cmd"),
     )
@@ -93,14 +474,39 @@ fn parse_cmd_set<'text>(
     tokens: &[Token<'text>],
     pos: usize,
 ) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
-    fn check_duplicate_assignments<'text>(assignments: &[Assign<'text>]) -> Option<&'text str> {
+    enum Item<'text> {
+        Assign(Assign<'text>),
+        Generated(Generated<'text>),
+    }
+
+    fn parse_item<'text>(
+        tokens: &[Token<'text>],
+        pos: usize,
+    ) -> Result<(Item<'text>, usize), ParseError<'text>> {
+        // `gen` is not a keyword, so only `gen(` is a generator and `pass = gen` is a value
+        let value = match tokens.get(pos) {
+            Some(Token::Keyword("sensitive")) => pos + 3,
+            _ => pos + 2,
+        };
+        match (tokens.get(value), tokens.get(value + 1)) {
+            (Some(Token::Value("gen")), Some(Token::Symbol("("))) => parse_generated(tokens, pos)
+                .map(|(generated, pos)| (Item::Generated(generated), pos)),
+            _ => parse_assign(tokens, pos).map(|(assign, pos)| (Item::Assign(assign), pos)),
+        }
+    }
+
+    fn check_duplicate_assignments<'text>(items: &[Item<'text>]) -> Option<&'text str> {
         let mut seen = HashSet::new();
 
-        for assign in assignments {
-            if seen.contains(assign.attr) {
-                return Some(assign.attr);
+        for item in items {
+            let attr = match item {
+                Item::Assign(assign) => assign.attr,
+                Item::Generated(generated) => generated.attr,
+            };
+            if seen.contains(attr) {
+                return Some(attr);
             }
-            seen.insert(assign.attr);
+            seen.insert(attr);
         }
 
         None
@@ -110,17 +516,96 @@ fn parse_cmd_set<'text>(
         return Err(ParseError::Expected(Token::Keyword("set"), pos));
     };
 
-    let Some(Token::Value(name)) = tokens.get(pos + 1) else {
-        return Err(ParseError::ExpectedName(pos));
+    // `set where <query> ...` instead of a name sets the fields of every record that matches.
+    // `where` is not a keyword, so `set where` and `set where user = x` still set a record called 'where'
+    let (name, filter, pos) = match tokens.get(pos + 1) {
+        Some(Token::Value("where")) if !starts_assignments(tokens, pos + 2) => {
+            let (filter, pos) = parse_or(tokens, pos + 2)?;
+            (None, Some(filter), pos)
+        }
+        Some(Token::Value(name)) => {
+            let (filter, pos) = parse_where(tokens, pos + 2)?;
+            (Some(*name), filter, pos)
+        }
+        _ => return Err(ParseError::ExpectedName(pos + 1)),
     };
+    let (items, pos) = many(tokens, pos, parse_item);
+    // an expiry that isn't a date says so, instead of the rest being left unparsed
+    if let Err(e @ ParseError::Date(..)) = parse_item(tokens, pos) {
+        return Err(e);
+    }
 
-    let (assignments, pos) = many(tokens, pos + 2, parse_assign);
-
-    if let Some(attr) = check_duplicate_assignments(&assignments) {
+    if let Some(attr) = check_duplicate_assignments(&items) {
         return Err(ParseError::DuplicateAssignments(attr, pos));
     }
 
-    Ok((Cmd::Set { name, assignments }, pos))
+    let Some(name) = name else {
+        let mut assignments = vec![];
+        for item in items {
+            match item {
+                Item::Assign(assign) => assignments.push(assign),
+                Item::Generated(_) => return Err(ParseError::SyntaxError(
+                    pos,
+                    "every record would get the same generated value. use `set <name>` for each",
+                )),
+            }
+        }
+        let filter = filter.expect("`where` was parsed");
+        return Ok((
+            Cmd::SetWhere {
+                filter,
+                assignments,
+            },
+            pos,
+        ));
+    };
+
+    let (mut assignments, mut generated) = (vec![], vec![]);
+    for item in items {
+        match item {
+            Item::Assign(assign) => assignments.push(assign),
+            Item::Generated(item) => generated.push(item),
+        }
+    }
+
+    Ok((
+        Cmd::Set {
+            name,
+            filter,
+            assignments,
+            generated,
+        },
+        pos,
+    ))
+}
+
+/// `where <or>` after a name picks one of the records that share it
+fn parse_where<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Option<Or<'text>>, usize), ParseError<'text>> {
+    match tokens.get(pos) {
+        Some(Token::Value("where")) => match parse_or(tokens, pos + 1) {
+            Ok((filter, pos)) => Ok((Some(filter), pos)),
+            // an attr called 'where', like `set gmail where = x` or `del gmail where user`
+            Err(_)
+                if matches!(tokens.get(pos + 1), Some(Token::Symbol("=")))
+                    || tokens[pos + 1..]
+                        .iter()
+                        .all(|t| matches!(t, Token::Value(_))) =>
+            {
+                Ok((None, pos))
+            }
+            Err(e) => Err(e),
+        },
+        _ => Ok((None, pos)),
+    }
+}
+
+/// whether the assignments of `set` start at `pos`, or there are none
+fn starts_assignments(tokens: &[Token<'_>], pos: usize) -> bool {
+    matches!(tokens.get(pos), None | Some(Token::Keyword("sensitive")))
+        || matches!(tokens.get(pos + 1), Some(Token::Symbol("=")))
 }
 
 fn parse_cmd_del<'text>(
@@ -148,9 +633,54 @@ fn parse_cmd_del<'text>(
         return Err(ParseError::ExpectedName(pos + 1));
     };
 
-    let (attrs, pos) = many(tokens, pos + 2, parse_attr);
+    let (filter, pos) = parse_where(tokens, pos + 2)?;
+    let (attrs, pos) = many(tokens, pos, parse_attr);
+
+    // whatever follows the attrs was meant to be a query (`del user contains`),
+    // so the error of `parse_cmd_del_query` is the one worth showing
+    if pos < tokens.len() {
+        return Err(ParseError::IncompleteParse(pos));
+    }
+
+    Ok((
+        Cmd::Del {
+            name,
+            filter,
+            attrs,
+        },
+        pos,
+    ))
+}
+
+/// a lone name is left to `parse_cmd_del`, which can also delete attrs
+fn parse_cmd_del_query<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let (Some(Token::Keyword("del")) | Some(Token::Keyword("delete"))) = tokens.get(pos) else {
+        return Err(ParseError::ExpectedOneOf(
+            vec![Token::Keyword("del"), Token::Keyword("delete")],
+            pos,
+        ));
+    };
+
+    // `del where <query>` reads better for those who know it from `del <name> where <query>`
+    let start = match tokens.get(pos + 1) {
+        Some(Token::Value("where")) if tokens.get(pos + 2).is_some() => pos + 2,
+        _ => pos + 1,
+    };
+
+    // a keyword that doesn't start a query, like `del prev`, is a misplaced name
+    let query = parse_query(tokens, start).map_err(|e| match e.pos() == start {
+        true if start == pos + 1 => ParseError::ExpectedName(start),
+        true => ParseError::ExpectedFilter(start),
+        false => e,
+    })?;
 
-    Ok((Cmd::Del { name, attrs }, pos))
+    match query {
+        (Query::Name(_), _) => Err(ParseError::ExpectedFilter(start)),
+        (query, pos) => Ok((Cmd::DelQuery(query), pos)),
+    }
 }
 
 fn parse_cmd_show<'text>(
@@ -162,58 +692,61 @@ fn parse_cmd_show<'text>(
     };
 
     let (query, pos) = parse_query(tokens, pos + 1)?;
+    let (order, pos) = parse_order(tokens, pos)?;
 
-    Ok((Cmd::Show(query), pos))
+    Ok((Cmd::Show(query, order), pos))
 }
 
-fn parse_cmd_reveal<'text>(
+fn parse_cmd_show_json<'text>(
     tokens: &[Token<'text>],
     pos: usize,
 ) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
-    let Some(Token::Keyword("reveal")) = tokens.get(pos) else {
-        return Err(ParseError::Expected(Token::Keyword("reveal"), pos));
+    let Some(Token::Keyword("show")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Keyword("show"), pos));
     };
 
-    let (query, pos) = parse_query(tokens, pos + 1)?;
+    let Some(Token::Value("--json")) = tokens.get(pos + 1) else {
+        return Err(ParseError::Expected(Token::Value("--json"), pos + 1));
+    };
 
-    Ok((Cmd::Reveal(query), pos))
+    let (query, pos) = parse_query(tokens, pos + 2)?;
+    let (order, pos) = parse_order(tokens, pos)?;
+
+    Ok((Cmd::ShowJson(query, order), pos))
 }
 
-fn parse_cmd_copy<'text>(
+fn parse_cmd_reveal_json<'text>(
     tokens: &[Token<'text>],
     pos: usize,
 ) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
-    let Some(Token::Keyword("copy")) = tokens.get(pos) else {
-        return Err(ParseError::Expected(Token::Keyword("copy"), pos));
+    let Some(Token::Keyword("reveal")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Keyword("reveal"), pos));
     };
 
-    let Some(Token::Value(name)) = tokens.get(pos + 1) else {
-        return Err(ParseError::ExpectedName(pos + 1));
+    let Some(Token::Value("--json")) = tokens.get(pos + 1) else {
+        return Err(ParseError::Expected(Token::Value("--json"), pos + 1));
     };
 
-    let Some(Token::Value(attr)) = tokens.get(pos + 2) else {
-        return Err(ParseError::ExpectedAttr(pos + 2));
-    };
+    let (query, pos) = parse_query(tokens, pos + 2)?;
+    let (order, pos) = parse_order(tokens, pos)?;
 
-    Ok((Cmd::Copy { name, attr }, pos + 3))
+    Ok((Cmd::RevealJson(query, order), pos))
 }
 
-fn parse_cmd_history<'text>(
+fn parse_cmd_count<'text>(
     tokens: &[Token<'text>],
     pos: usize,
 ) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
-    let Some(Token::Keyword("history")) = tokens.get(pos) else {
-        return Err(ParseError::Expected(Token::Keyword("history"), pos));
+    let Some(Token::Value("count")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("count"), pos));
     };
 
-    let Some(Token::Value(name)) = tokens.get(pos + 1) else {
-        return Err(ParseError::ExpectedName(pos + 1));
-    };
+    let (query, pos) = parse_query(tokens, pos + 1)?;
 
-    Ok((Cmd::History(name), pos + 2))
+    Ok((Cmd::Count(query), pos))
 }
 
-fn parse_cmd_reveal_history<'text>(
+fn parse_cmd_reveal<'text>(
     tokens: &[Token<'text>],
     pos: usize,
 ) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
@@ -221,478 +754,3100 @@ fn parse_cmd_reveal_history<'text>(
         return Err(ParseError::Expected(Token::Keyword("reveal"), pos));
     };
 
-    let Some(Token::Keyword("history")) = tokens.get(pos + 1) else {
-        return Err(ParseError::Expected(Token::Keyword("history"), pos + 1));
+    let (query, pos) = parse_query(tokens, pos + 1)?;
+    let (order, pos) = parse_order(tokens, pos)?;
+
+    Ok((Cmd::Reveal(query, order), pos))
+}
+
+/// how `show` and `reveal` order their records. by name unless sorted
+#[derive(Default)]
+pub struct Order<'text> {
+    pub sort: Option<&'text str>,
+    /// at most this many of the ordered records. every one of them if `None`
+    pub limit: Option<usize>,
+}
+
+fn parse_order<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Order<'text>, usize), ParseError<'text>> {
+    let (sort, pos) = match tokens.get(pos) {
+        Some(Token::Value("sort")) => match tokens.get(pos + 1) {
+            Some(Token::Value(attr)) => (Some(*attr), pos + 2),
+            _ => return Err(ParseError::ExpectedAttr(pos + 1)),
+        },
+        _ => (None, pos),
     };
 
-    let Some(Token::Value(name)) = tokens.get(pos + 2) else {
-        return Err(ParseError::ExpectedName(pos + 2));
+    let (limit, pos) = match tokens.get(pos) {
+        Some(Token::Value("limit")) => match tokens.get(pos + 1) {
+            Some(Token::Value(limit)) => match limit.parse::<usize>() {
+                Ok(limit) if limit > 0 => (Some(limit), pos + 2),
+                _ => {
+                    return Err(ParseError::SyntaxError(
+                        pos + 1,
+                        "expected a limit of 1 or more",
+                    ))
+                }
+            },
+            _ => return Err(ParseError::ExpectedValue(pos + 1)),
+        },
+        _ => (None, pos),
     };
 
-    Ok((Cmd::RevealHistory(name), pos + 3))
+    Ok((Order { sort, limit }, pos))
 }
 
-fn parse_cmd_rename<'text>(
+fn parse_cmd_copy<'text>(
     tokens: &[Token<'text>],
     pos: usize,
 ) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
-    let Some(Token::Keyword("rename")) = tokens.get(pos) else {
-        return Err(ParseError::Expected(Token::Keyword("rename"), pos));
+    let Some(Token::Keyword("copy")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Keyword("copy"), pos));
     };
 
-    let Some(Token::Value(old)) = tokens.get(pos + 1) else {
+    // `copy totp gmail` still copies the gmail attr of the totp record
+    if let (Some(Token::Value("totp")), Some(Token::Value(name)), Some(Token::Value(attr))) = (
+        tokens.get(pos + 1),
+        tokens.get(pos + 2),
+        tokens.get(pos + 3),
+    ) {
+        let (clear_after, pos) = parse_clear_after(tokens, pos + 4);
+        return Ok((
+            Cmd::CopyTotp {
+                name,
+                attr,
+                clear_after,
+            },
+            pos,
+        ));
+    }
+
+    let Some(Token::Value(name)) = tokens.get(pos + 1) else {
         return Err(ParseError::ExpectedName(pos + 1));
     };
 
-    let Some(Token::Value(new)) = tokens.get(pos + 2) else {
-        return Err(ParseError::ExpectedName(pos + 2));
+    let (filter, pos) = parse_where(tokens, pos + 2)?;
+    // the whole record is only cleared after the default, as `copy gmail 10` copies the '10' attr
+    let Some(Token::Value(attr)) = tokens.get(pos) else {
+        return Ok((
+            Cmd::Copy {
+                name,
+                filter,
+                attr: None,
+                clear_after: None,
+            },
+            pos,
+        ));
     };
 
-    Ok((Cmd::Rename(old, new), pos + 3))
+    let (clear_after, pos) = parse_clear_after(tokens, pos + 1);
+    Ok((
+        Cmd::Copy {
+            name,
+            filter,
+            attr: Some(attr),
+            clear_after,
+        },
+        pos,
+    ))
+}
+
+fn parse_clear_after(tokens: &[Token], pos: usize) -> (Option<u64>, usize) {
+    match tokens.get(pos) {
+        Some(Token::Value(secs)) => match secs.parse::<u64>() {
+            Ok(secs) if secs > 0 => (Some(secs), pos + 1),
+            _ => (None, pos),
+        },
+        _ => (None, pos),
+    }
 }
 
-fn parse_cmd_import<'text>(
+fn parse_cmd_totp<'text>(
     tokens: &[Token<'text>],
     pos: usize,
 ) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
-    let Some(Token::Keyword("import")) = tokens.get(pos) else {
-        return Err(ParseError::Expected(Token::Keyword("import"), pos));
+    let Some(Token::Value("totp")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("totp"), pos));
     };
 
-    let Some(Token::Value(fpath)) = tokens.get(pos + 1) else {
-        return Err(ParseError::ExpectedValue(pos + 1));
+    let Some(Token::Value(name)) = tokens.get(pos + 1) else {
+        return Err(ParseError::ExpectedName(pos + 1));
     };
 
-    Ok((Cmd::Import(fpath), pos + 2))
-}
+    let (mut digits, mut period) = (None, None);
+    let mut pos = pos + 2;
+    while let (Some(Token::Value(key @ ("digits" | "period"))), Some(Token::Symbol("="))) =
+        (tokens.get(pos), tokens.get(pos + 1))
+    {
+        let value = match tokens.get(pos + 2) {
+            Some(Token::Value(value)) => value.parse::<u64>().ok().filter(|&value| value > 0),
+            _ => None,
+        };
+        match (*key, value) {
+            ("digits", Some(value)) => digits = Some(value as usize),
+            ("period", Some(value)) => period = Some(value),
+            _ => {
+                return Err(ParseError::SyntaxError(
+                    pos + 2,
+                    "expected a positive number",
+                ))
+            }
+        }
+        pos += 3;
+    }
 
-pub struct Assign<'text> {
-    pub attr: &'text str,
-    pub value: &'text str,
-    pub sensitive: bool,
+    Ok((
+        Cmd::Totp {
+            name,
+            digits,
+            period,
+        },
+        pos,
+    ))
 }
 
-fn parse_assign<'text>(
+fn parse_cmd_share<'text>(
     tokens: &[Token<'text>],
     pos: usize,
-) -> Result<(Assign<'text>, usize), ParseError<'text>> {
-    let (sensitive, pos) = match tokens.get(pos) {
-        Some(Token::Keyword("sensitive")) | Some(Token::Keyword("secret")) => (true, pos + 1),
-        _ => (false, pos),
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("share")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("share"), pos));
     };
 
-    let Some(Token::Value(attr)) = tokens.get(pos) else {
-        return Err(ParseError::ExpectedAttr(pos));
+    let Some(Token::Value(name)) = tokens.get(pos + 1) else {
+        return Err(ParseError::ExpectedName(pos + 1));
     };
 
-    let Some(Token::Symbol("=")) = tokens.get(pos + 1) else {
-        return Err(ParseError::Expected(Token::Symbol("="), pos + 1));
+    Ok((Cmd::Share(name), pos + 2))
+}
+
+fn parse_cmd_list<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("list")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("list"), pos));
     };
 
-    let Some(Token::Value(value)) = tokens.get(pos + 2) else {
-        return Err(ParseError::ExpectedValue(pos + 2));
+    // every record unless told otherwise
+    if tokens.get(pos + 1).is_none() {
+        return Ok((Cmd::List(Query::All), pos + 1));
+    }
+
+    let (query, pos) = parse_query(tokens, pos + 1)?;
+
+    Ok((Cmd::List(query), pos))
+}
+
+fn parse_cmd_decode<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("decode")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("decode"), pos));
     };
 
+    let ((name, attr, encoding, reveal), pos) = parse_codec(tokens, pos + 1)?;
     Ok((
-        Assign {
+        Cmd::Decode {
+            name,
             attr,
-            value,
-            sensitive,
+            encoding,
+            reveal,
         },
-        pos + 3,
+        pos,
     ))
 }
 
-pub enum Query<'text> {
-    Or(Or<'text>),
-    Name(&'text str),
-    All,
+fn parse_cmd_encode<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("encode")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("encode"), pos));
+    };
+
+    let ((name, attr, encoding, reveal), pos) = parse_codec(tokens, pos + 1)?;
+    Ok((
+        Cmd::Encode {
+            name,
+            attr,
+            encoding,
+            reveal,
+        },
+        pos,
+    ))
 }
 
-fn parse_query<'text>(
+/// name, attr, encoding and whether to reveal
+type Codec<'text> = (&'text str, &'text str, Encoding, bool);
+
+/// `<name> <attr> <encoding> reveal?` of `decode` and `encode`
+fn parse_codec<'text>(
     tokens: &[Token<'text>],
     pos: usize,
-) -> Result<(Query<'text>, usize), ParseError<'text>> {
-    match tokens.get(pos) {
-        Some(Token::Keyword("all")) => Ok((Query::All, pos + 1)),
-        Some(Token::Value(val)) => match parse_or(tokens, pos) {
-            Ok((or, pos)) => Ok((Query::Or(or), pos)),
-            Err(_) => Ok((Query::Name(val), pos + 1)),
-        },
-        _ => Err(ParseError::SyntaxError(pos, "unable to parse query")),
+) -> Result<(Codec<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value(name)) = tokens.get(pos) else {
+        return Err(ParseError::ExpectedName(pos));
+    };
+
+    let Some(Token::Value(attr)) = tokens.get(pos + 1) else {
+        return Err(ParseError::ExpectedAttr(pos + 1));
+    };
+
+    let encoding = match tokens.get(pos + 2) {
+        Some(Token::Value("b64")) => Encoding::Base64,
+        Some(Token::Value("hex")) => Encoding::Hex,
+        _ => {
+            return Err(ParseError::ExpectedOneOf(
+                vec![Token::Value("b64"), Token::Value("hex")],
+                pos + 2,
+            ))
+        }
+    };
+
+    match tokens.get(pos + 3) {
+        Some(Token::Keyword("reveal")) => Ok(((name, attr, encoding, true), pos + 4)),
+        _ => Ok(((name, attr, encoding, false), pos + 3)),
     }
 }
 
-pub enum Or<'text> {
-    And(And<'text>),
-    Or(Box<Or<'text>>, And<'text>),
+fn parse_cmd_tag<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("tag")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("tag"), pos));
+    };
+
+    let (name, tags, pos) = parse_tags(tokens, pos + 1)?;
+    Ok((Cmd::Tag { name, tags }, pos))
 }
 
-fn parse_or<'text>(
+fn parse_cmd_untag<'text>(
     tokens: &[Token<'text>],
     pos: usize,
-) -> Result<(Or<'text>, usize), ParseError<'text>> {
-    let (lhs, mut pos) = parse_and(tokens, pos)?;
-    let mut lhs = Or::And(lhs);
-    while let Some(token) = tokens.get(pos) {
-        match token {
-            Token::Keyword("or") => {
-                let (rhs, next_pos) = parse_and(tokens, pos + 1)?;
-                pos = next_pos;
-                lhs = Or::Or(Box::new(lhs), rhs);
-            }
-            _ => break,
-        }
-    }
-    Ok((lhs, pos))
-}
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("untag")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("untag"), pos));
+    };
 
-pub enum And<'text> {
-    Filter(Filter<'text>),
-    And(Box<And<'text>>, Filter<'text>),
+    let (name, tags, pos) = parse_tags(tokens, pos + 1)?;
+    Ok((Cmd::Untag { name, tags }, pos))
 }
 
-fn parse_and<'text>(
+/// `<name> {<value>}+` of `tag` and `untag`
+fn parse_tags<'text>(
     tokens: &[Token<'text>],
     pos: usize,
-) -> Result<(And<'text>, usize), ParseError<'text>> {
-    let (lhs, mut pos) = parse_filter(tokens, pos)?;
-    let mut lhs = And::Filter(lhs);
-    while let Some(token) = tokens.get(pos) {
-        match token {
-            Token::Keyword("and") => {
-                let (rhs, next_pos) = parse_filter(tokens, pos + 1)?;
-                pos = next_pos;
-                lhs = And::And(Box::new(lhs), rhs);
-            }
-            _ => break,
+) -> Result<(&'text str, Vec<&'text str>, usize), ParseError<'text>> {
+    let Some(Token::Value(name)) = tokens.get(pos) else {
+        return Err(ParseError::ExpectedName(pos));
+    };
+
+    let mut tags = vec![];
+    let mut pos = pos + 1;
+    while let Some(Token::Value(tag)) = tokens.get(pos) {
+        // tags are stored comma separated
+        if tag.contains(',') {
+            return Err(ParseError::SyntaxError(pos, "tags can't contain ','"));
         }
+        tags.push(*tag);
+        pos += 1;
     }
-    Ok((lhs, pos))
-}
 
-pub enum Filter<'text> {
-    Contains(Contains<'text>),
-    Matches(Matches<'text>),
-    Cmp(Is<'text>),
-    Parens(Box<Query<'text>>),
+    match tags.is_empty() {
+        true => Err(ParseError::SyntaxError(pos, "expected a tag")),
+        false => Ok((name, tags, pos)),
+    }
 }
 
-fn parse_filter<'text>(
+fn parse_cmd_history<'text>(
     tokens: &[Token<'text>],
     pos: usize,
-) -> Result<(Filter<'text>, usize), ParseError<'text>> {
-    fn parse_parens<'text>(
-        tokens: &[Token<'text>],
-        pos: usize,
-    ) -> Result<(Filter<'text>, usize), ParseError<'text>> {
-        let Some(Token::Symbol("(")) = tokens.get(pos) else {
-            return Err(ParseError::Expected(Token::Symbol("("), pos));
-        };
-        let (query, pos) = parse_query(tokens, pos + 1)?;
-        let Some(Token::Symbol(")")) = tokens.get(pos) else {
-            return Err(ParseError::Expected(Token::Symbol(")"), pos));
-        };
-        Ok((Filter::Parens(Box::new(query)), pos + 1))
-    }
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Keyword("history")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Keyword("history"), pos));
+    };
 
-    combine_parsers(
-        tokens,
-        pos,
-        &[
-            &parse_parens,
-            &parse_contains,
-            &parse_matches,
-            &parse_is,
-        ],
-        ParseError::SyntaxError(pos, "cannot parse filter"),
-    )
-}
+    let Some(Token::Value(name)) = tokens.get(pos + 1) else {
+        return Err(ParseError::ExpectedName(pos + 1));
+    };
 
-pub struct Contains<'text> {
-    pub attr: &'text str,
-    pub substr: &'text str,
+    Ok((Cmd::History(name), pos + 2))
 }
 
-fn parse_contains<'text>(
+fn parse_cmd_undo<'text>(
     tokens: &[Token<'text>],
     pos: usize,
-) -> Result<(Contains<'text>, usize), ParseError<'text>> {
-    let Some(Token::Value(attr)) = tokens.get(pos) else {
-        return Err(ParseError::ExpectedAttr(pos));
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("undo")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("undo"), pos));
     };
 
-    let Some(Token::Keyword("contains")) = tokens.get(pos + 1) else {
-        return Err(ParseError::Expected(Token::Keyword("contains"), pos + 1));
-    };
+    Ok((Cmd::Undo, pos + 1))
+}
 
-    let Some(Token::Value(substr)) = tokens.get(pos + 2) else {
-        return Err(ParseError::ExpectedValue(pos + 2));
+fn parse_cmd_redo<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("redo")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("redo"), pos));
     };
 
-    Ok((Contains { attr, substr }, pos + 3))
+    Ok((Cmd::Redo, pos + 1))
 }
 
-pub struct Matches<'text> {
-    pub attr: &'text str,
-    pub pat: Regex,
+fn parse_cmd_trash<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("trash")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("trash"), pos));
+    };
+
+    Ok((Cmd::Trash, pos + 1))
 }
 
-fn parse_matches<'text>(
+fn parse_cmd_undelete<'text>(
     tokens: &[Token<'text>],
     pos: usize,
-) -> Result<(Matches<'text>, usize), ParseError<'text>> {
-    let Some(Token::Value(attr)) = tokens.get(pos) else {
-        return Err(ParseError::ExpectedAttr(pos));
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("undelete")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("undelete"), pos));
     };
 
-    let (Some(Token::Keyword("matches")) | Some(Token::Keyword("like"))) = tokens.get(pos + 1)
-    else {
-        return Err(ParseError::ExpectedOneOf(
-            vec![Token::Keyword("matches"), Token::Keyword("like")],
-            pos + 1,
-        ));
+    let Some(Token::Value(name)) = tokens.get(pos + 1) else {
+        return Err(ParseError::ExpectedName(pos + 1));
     };
 
-    let Some(Token::Value(pat)) = tokens.get(pos + 2) else {
-        return Err(ParseError::ExpectedValue(pos + 2));
+    Ok((Cmd::Undelete(name), pos + 2))
+}
+
+fn parse_cmd_restore<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("restore")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("restore"), pos));
     };
 
-    let pat = Regex::new(pat).map_err(|_| ParseError::InvalidRegex(pos + 2))?;
+    let Some(Token::Value(name)) = tokens.get(pos + 1) else {
+        return Err(ParseError::ExpectedName(pos + 1));
+    };
 
-    Ok((Matches { attr, pat }, pos + 3))
-}
+    // without an index it is the record in the trash that is brought back
+    let Some(index) = (match tokens.get(pos + 2) {
+        None => return Ok((Cmd::Undelete(name), pos + 2)),
+        Some(Token::Value(index)) => index.parse::<usize>().ok(),
+        _ => None,
+    }) else {
+        return Err(ParseError::SyntaxError(
+            pos + 2,
+            "expected a history entry index",
+        ));
+    };
 
-pub struct Is<'text> {
-    pub attr: &'text str,
-    pub value: &'text str,
+    Ok((Cmd::Restore { name, index }, pos + 3))
 }
 
-fn parse_is<'text>(
+fn parse_cmd_empty_trash<'text>(
     tokens: &[Token<'text>],
     pos: usize,
-) -> Result<(Is<'text>, usize), ParseError<'text>> {
-    let Some(Token::Value(attr)) = tokens.get(pos) else {
-        return Err(ParseError::ExpectedAttr(pos));
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("empty")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("empty"), pos));
     };
 
-    let Some(Token::Keyword("is")) = tokens.get(pos + 1) else {
-        return Err(ParseError::Expected(Token::Keyword("is"), pos + 1));
+    let Some(Token::Value("trash")) = tokens.get(pos + 1) else {
+        return Err(ParseError::Expected(Token::Value("trash"), pos + 1));
     };
 
-    let Some(Token::Value(value)) = tokens.get(pos + 2) else {
-        return Err(ParseError::ExpectedValue(pos + 2));
+    Ok((Cmd::EmptyTrash, pos + 2))
+}
+
+fn parse_cmd_merge<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("merge")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("merge"), pos));
     };
 
-    Ok((Is { attr, value }, pos + 3))
-}
+    let Some(Token::Value(fpath)) = tokens.get(pos + 1) else {
+        return Err(ParseError::ExpectedValue(pos + 1));
+    };
 
-impl<'text> Display for Cmd<'text> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Cmd::Set { name, assignments } => {
-                write!(f, "set '{}'", name)?;
-                for assign in assignments {
-                    write!(f, " {}", assign)?;
-                }
-                Ok(())
-            }
-            Cmd::Del { name, attrs } => {
-                write!(f, "del '{}'", name)?;
-                for attr in attrs {
-                    write!(f, " '{}'", attr)?;
-                }
-                Ok(())
-            }
-            Cmd::Show(q) => write!(f, "show {}", q),
-            Cmd::Reveal(q) => write!(f, "reveal {}", q),
-            Cmd::Copy { name, attr } => write!(f, "copy '{}' '{}'", name, attr),
-            Cmd::History(name) => write!(f, "history '{}'", name),
-            Cmd::RevealHistory(name) => write!(f, "reveal history '{}'", name),
-            Cmd::Rename(old, new) => write!(f, "rename '{}' '{}'", old, new),
-            Cmd::Import(fpath) => write!(f, "import '{}'", fpath),
-        }
-    }
+    Ok((Cmd::Merge(fpath), pos + 2))
 }
 
-impl<'text> Display for Assign<'text> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self.sensitive {
-            true => write!(f, "sensitive {} = '{}'", self.attr, self.value),
-            false => write!(f, "{} = '{}'", self.attr, self.value),
-        }
-    }
+fn parse_cmd_watch<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("watch")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("watch"), pos));
+    };
+
+    let (query, pos) = parse_query(tokens, pos + 1)?;
+
+    Ok((Cmd::Watch(query), pos))
 }
 
-impl<'text> Display for Query<'text> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Query::Or(o) => write!(f, "{}", o),
-            Query::Name(name) => write!(f, "'{}'", name),
-            Query::All => write!(f, "all"),
-        }
-    }
+fn parse_cmd_unwatch<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("unwatch")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("unwatch"), pos));
+    };
+
+    let (query, pos) = parse_query(tokens, pos + 1)?;
+
+    Ok((Cmd::Unwatch(query), pos))
 }
 
-impl<'text> Display for Or<'text> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Or::And(a) => write!(f, "{}", a),
-            Or::Or(lhs, rhs) => write!(f, "({} or {})", lhs, rhs),
-        }
-    }
+fn parse_cmd_watches<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("watches")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("watches"), pos));
+    };
+
+    Ok((Cmd::Watches, pos + 1))
 }
 
-impl<'text> Display for And<'text> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            And::Filter(x) => write!(f, "{}", x),
-            And::And(lhs, rhs) => write!(f, "({} and {})", lhs, rhs),
+fn parse_cmd_history_limit<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Keyword("history")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Keyword("history"), pos));
+    };
+
+    let Some(Token::Value("limit")) = tokens.get(pos + 1) else {
+        return Err(ParseError::Expected(Token::Value("limit"), pos + 1));
+    };
+
+    match tokens.get(pos + 2) {
+        Some(Token::Keyword("set")) => {
+            let Some(limit) = (match tokens.get(pos + 3) {
+                Some(Token::Value(limit)) => limit.parse::<usize>().ok().filter(|&limit| limit > 0),
+                _ => None,
+            }) else {
+                return Err(ParseError::SyntaxError(
+                    pos + 3,
+                    "expected a positive number",
+                ));
+            };
+            Ok((Cmd::SetHistoryLimit(Some(limit)), pos + 4))
+        }
+        Some(Token::Value("clear")) => Ok((Cmd::SetHistoryLimit(None), pos + 3)),
+        Some(Token::Value("show")) | Some(Token::Keyword("show")) => {
+            Ok((Cmd::ShowHistoryLimit, pos + 3))
         }
+        _ => Err(ParseError::SyntaxError(
+            pos + 2,
+            "expected set, clear or show",
+        )),
     }
 }
 
-impl<'text> Display for Filter<'text> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Filter::Contains(c) => write!(f, "{}", c),
-            Filter::Matches(m) => write!(f, "{}", m),
-            Filter::Cmp(c) => write!(f, "{}", c),
-            Filter::Parens(q) => write!(f, "({})", q),
+fn parse_cmd_history_prune<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Keyword("history")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Keyword("history"), pos));
+    };
+
+    match tokens.get(pos + 1) {
+        Some(Token::Value("clear")) => {
+            let Some(Token::Value(name)) = tokens.get(pos + 2) else {
+                return Err(ParseError::ExpectedName(pos + 2));
+            };
+            Ok((Cmd::ClearHistory(name), pos + 3))
         }
+        Some(Token::Value("prune")) => {
+            let Some(Token::Value(name)) = tokens.get(pos + 2) else {
+                return Err(ParseError::ExpectedName(pos + 2));
+            };
+            let Some(keep) = (match tokens.get(pos + 3) {
+                Some(Token::Value(keep)) => keep.parse::<usize>().ok().filter(|&keep| keep > 0),
+                _ => None,
+            }) else {
+                return Err(ParseError::SyntaxError(
+                    pos + 3,
+                    "expected a positive number",
+                ));
+            };
+            Ok((Cmd::PruneHistory { name, keep }, pos + 4))
+        }
+        _ => Err(ParseError::ExpectedOneOf(
+            vec![Token::Value("clear"), Token::Value("prune")],
+            pos + 1,
+        )),
     }
 }
 
-impl<'text> Display for Contains<'text> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} contains '{}'", self.attr, self.substr)
+fn parse_cmd_history_diff<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Keyword("history")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Keyword("history"), pos));
+    };
+
+    let Some(Token::Value("diff")) = tokens.get(pos + 1) else {
+        return Err(ParseError::Expected(Token::Value("diff"), pos + 1));
+    };
+
+    let Some(Token::Value(name)) = tokens.get(pos + 2) else {
+        return Err(ParseError::ExpectedName(pos + 2));
+    };
+
+    Ok((Cmd::HistoryDiff(name), pos + 3))
+}
+
+fn parse_cmd_reveal_history_diff<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Keyword("reveal")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Keyword("reveal"), pos));
+    };
+
+    let Some(Token::Keyword("history")) = tokens.get(pos + 1) else {
+        return Err(ParseError::Expected(Token::Keyword("history"), pos + 1));
+    };
+
+    let Some(Token::Value("diff")) = tokens.get(pos + 2) else {
+        return Err(ParseError::Expected(Token::Value("diff"), pos + 2));
+    };
+
+    let Some(Token::Value(name)) = tokens.get(pos + 3) else {
+        return Err(ParseError::ExpectedName(pos + 3));
+    };
+
+    Ok((Cmd::RevealHistoryDiff(name), pos + 4))
+}
+
+fn parse_cmd_reveal_history<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Keyword("reveal")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Keyword("reveal"), pos));
+    };
+
+    let Some(Token::Keyword("history")) = tokens.get(pos + 1) else {
+        return Err(ParseError::Expected(Token::Keyword("history"), pos + 1));
+    };
+
+    let Some(Token::Value(name)) = tokens.get(pos + 2) else {
+        return Err(ParseError::ExpectedName(pos + 2));
+    };
+
+    Ok((Cmd::RevealHistory(name), pos + 3))
+}
+
+fn parse_cmd_rename<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Keyword("rename")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Keyword("rename"), pos));
+    };
+
+    let (dry_run, pos) = match tokens.get(pos + 1) {
+        Some(Token::Value("--dry-run")) => (true, pos + 1),
+        _ => (false, pos),
+    };
+    match tokens.get(pos + 1) {
+        Some(Token::Keyword("all")) => return parse_batch_rename(tokens, pos + 2, dry_run),
+        _ if dry_run => return Err(ParseError::Expected(Token::Keyword("all"), pos + 1)),
+        _ => {}
+    }
+
+    let Some(Token::Value(old)) = tokens.get(pos + 1) else {
+        return Err(ParseError::ExpectedName(pos + 1));
+    };
+
+    let (filter, pos) = parse_where(tokens, pos + 2)?;
+    let Some(Token::Value(new)) = tokens.get(pos) else {
+        return Err(ParseError::ExpectedName(pos));
+    };
+
+    // a second value makes it the attrs of a field of `old`
+    match tokens.get(pos + 1) {
+        Some(Token::Value(new_attr)) => Ok((
+            Cmd::RenameField {
+                record: old,
+                filter,
+                old_attr: new,
+                new_attr,
+            },
+            pos + 2,
+        )),
+        _ => Ok((Cmd::Rename { old, filter, new }, pos + 1)),
     }
 }
 
-impl<'text> Display for Matches<'text> {
+fn parse_batch_rename<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+    dry_run: bool,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value(pattern)) = tokens.get(pos) else {
+        return Err(ParseError::ExpectedValue(pos));
+    };
+    let pattern = Regex::new(pattern).map_err(|_| ParseError::InvalidRegex(pos))?;
+
+    let Some(Token::Value(replacement)) = tokens.get(pos + 1) else {
+        return Err(ParseError::ExpectedValue(pos + 1));
+    };
+
+    Ok((
+        Cmd::BatchRename {
+            pattern,
+            replacement,
+            dry_run,
+        },
+        pos + 2,
+    ))
+}
+
+fn parse_cmd_dup<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let (Some(Token::Value("dup")) | Some(Token::Value("clone"))) = tokens.get(pos) else {
+        return Err(ParseError::ExpectedOneOf(
+            vec![Token::Value("dup"), Token::Value("clone")],
+            pos,
+        ));
+    };
+
+    let Some(Token::Value(src)) = tokens.get(pos + 1) else {
+        return Err(ParseError::ExpectedName(pos + 1));
+    };
+
+    let Some(Token::Value(dst)) = tokens.get(pos + 2) else {
+        return Err(ParseError::ExpectedName(pos + 2));
+    };
+
+    Ok((Cmd::Dup { src, dst }, pos + 3))
+}
+
+fn parse_cmd_allow_duplicate_names<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("allow-duplicate-names")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(
+            Token::Value("allow-duplicate-names"),
+            pos,
+        ));
+    };
+
+    match tokens.get(pos + 1) {
+        Some(Token::Value("on")) => Ok((Cmd::AllowDuplicateNames(Some(true)), pos + 2)),
+        Some(Token::Value("off")) => Ok((Cmd::AllowDuplicateNames(Some(false)), pos + 2)),
+        None => Ok((Cmd::AllowDuplicateNames(None), pos + 1)),
+        _ => Err(ParseError::ExpectedOneOf(
+            vec![Token::Value("on"), Token::Value("off")],
+            pos + 1,
+        )),
+    }
+}
+
+fn parse_cmd_rollback<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("rollback")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("rollback"), pos));
+    };
+
+    let Some(Token::Value(name)) = tokens.get(pos + 1) else {
+        return Err(ParseError::ExpectedName(pos + 1));
+    };
+
+    let Some(steps) = (match tokens.get(pos + 2) {
+        Some(Token::Value(steps)) => steps.parse::<usize>().ok().filter(|&steps| steps > 0),
+        _ => None,
+    }) else {
+        return Err(ParseError::SyntaxError(
+            pos + 2,
+            "expected a history entry number",
+        ));
+    };
+
+    Ok((Cmd::Rollback { name, steps }, pos + 3))
+}
+
+fn parse_cmd_import<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Keyword("import")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Keyword("import"), pos));
+    };
+
+    let Some(Token::Value(fpath)) = tokens.get(pos + 1) else {
+        return Err(ParseError::ExpectedValue(pos + 1));
+    };
+
+    let (merge, pos) = parse_merge(tokens, pos + 2);
+
+    Ok((Cmd::Import(fpath, merge), pos))
+}
+
+fn parse_cmd_import_lastpass<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Keyword("import")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Keyword("import"), pos));
+    };
+
+    let Some(Token::Value("lastpass")) = tokens.get(pos + 1) else {
+        return Err(ParseError::Expected(Token::Value("lastpass"), pos + 1));
+    };
+
+    let Some(Token::Value(fpath)) = tokens.get(pos + 2) else {
+        return Err(ParseError::ExpectedValue(pos + 2));
+    };
+
+    let (merge, pos) = parse_merge(tokens, pos + 3);
+
+    Ok((Cmd::ImportLastPass(fpath, merge), pos))
+}
+
+fn parse_cmd_import_csv<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Keyword("import")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Keyword("import"), pos));
+    };
+
+    let Some(Token::Value("csv")) = tokens.get(pos + 1) else {
+        return Err(ParseError::Expected(Token::Value("csv"), pos + 1));
+    };
+
+    let Some(Token::Value(fpath)) = tokens.get(pos + 2) else {
+        return Err(ParseError::ExpectedValue(pos + 2));
+    };
+
+    let (merge, pos) = parse_merge(tokens, pos + 3);
+
+    Ok((Cmd::ImportCsv(fpath, merge), pos))
+}
+
+fn parse_cmd_import_keepass<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Keyword("import")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Keyword("import"), pos));
+    };
+
+    let Some(Token::Value("keepass")) = tokens.get(pos + 1) else {
+        return Err(ParseError::Expected(Token::Value("keepass"), pos + 1));
+    };
+
+    let Some(Token::Value(fpath)) = tokens.get(pos + 2) else {
+        return Err(ParseError::ExpectedValue(pos + 2));
+    };
+
+    let (merge, pos) = parse_merge(tokens, pos + 3);
+
+    Ok((Cmd::ImportKeePass(fpath, merge), pos))
+}
+
+/// what to do with the records of an import whose name already exists
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Merge {
+    /// leave the existing record untouched
+    Skip,
+    /// update the existing record like `set` does
+    #[default]
+    Overwrite,
+    /// import under the first free `<name>-2`, `<name>-3`, ...
+    Rename,
+}
+
+fn parse_merge(tokens: &[Token], pos: usize) -> (Merge, usize) {
+    match tokens.get(pos) {
+        Some(Token::Value("skip")) => (Merge::Skip, pos + 1),
+        Some(Token::Value("overwrite")) => (Merge::Overwrite, pos + 1),
+        Some(Token::Keyword("rename")) => (Merge::Rename, pos + 1),
+        _ => (Merge::default(), pos),
+    }
+}
+
+fn parse_cmd_export<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("export")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("export"), pos));
+    };
+
+    let Some(Token::Value(fpath)) = tokens.get(pos + 1) else {
+        return Err(ParseError::ExpectedValue(pos + 1));
+    };
+
+    Ok((Cmd::Export(fpath), pos + 2))
+}
+
+fn parse_cmd_export_csv<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("export")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("export"), pos));
+    };
+
+    let Some(Token::Value("csv")) = tokens.get(pos + 1) else {
+        return Err(ParseError::Expected(Token::Value("csv"), pos + 1));
+    };
+
+    let Some(Token::Value(fpath)) = tokens.get(pos + 2) else {
+        return Err(ParseError::ExpectedValue(pos + 2));
+    };
+
+    Ok((Cmd::ExportCsv(fpath), pos + 3))
+}
+
+fn parse_cmd_export_csv_fields<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("export")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("export"), pos));
+    };
+
+    let Some(Token::Value("csv")) = tokens.get(pos + 1) else {
+        return Err(ParseError::Expected(Token::Value("csv"), pos + 1));
+    };
+
+    let Some(Token::Value("fields")) = tokens.get(pos + 2) else {
+        return Err(ParseError::Expected(Token::Value("fields"), pos + 2));
+    };
+
+    let Some(Token::Value(fpath)) = tokens.get(pos + 3) else {
+        return Err(ParseError::ExpectedValue(pos + 3));
+    };
+
+    Ok((Cmd::ExportCsvFields(fpath), pos + 4))
+}
+
+fn parse_cmd_export_json<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("export")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("export"), pos));
+    };
+
+    let Some(Token::Value("json")) = tokens.get(pos + 1) else {
+        return Err(ParseError::Expected(Token::Value("json"), pos + 1));
+    };
+
+    let Some(Token::Value(fpath)) = tokens.get(pos + 2) else {
+        return Err(ParseError::ExpectedValue(pos + 2));
+    };
+
+    Ok((Cmd::ExportJson(fpath), pos + 3))
+}
+
+fn parse_cmd_size_report<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("size")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("size"), pos));
+    };
+
+    let Some(Token::Value("report")) = tokens.get(pos + 1) else {
+        return Err(ParseError::Expected(Token::Value("report"), pos + 1));
+    };
+
+    Ok((Cmd::SizeReport, pos + 2))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheckKind {
+    /// values held by more than one field
+    Duplicates,
+    /// values that don't look like their kind
+    Kinds,
+    /// record names that are keywords when typed without quotes
+    Names,
+}
+
+impl Display for CheckKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} matches '{}'", self.attr, self.pat)
+        match self {
+            CheckKind::Duplicates => write!(f, "duplicates"),
+            CheckKind::Kinds => write!(f, "kinds"),
+            CheckKind::Names => write!(f, "names"),
+        }
+    }
+}
+
+fn parse_cmd_check<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("check")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("check"), pos));
+    };
+
+    match tokens.get(pos + 1) {
+        Some(Token::Value("duplicates")) => Ok((Cmd::Check(CheckKind::Duplicates), pos + 2)),
+        Some(Token::Value("kinds")) => Ok((Cmd::Check(CheckKind::Kinds), pos + 2)),
+        Some(Token::Value("names")) => Ok((Cmd::Check(CheckKind::Names), pos + 2)),
+        _ => Err(ParseError::ExpectedOneOf(
+            vec![
+                Token::Value("duplicates"),
+                Token::Value("kinds"),
+                Token::Value("names"),
+            ],
+            pos + 1,
+        )),
+    }
+}
+
+fn parse_cmd_gen<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("gen")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("gen"), pos));
+    };
+
+    let len = parse_gen_len(tokens, pos + 1)?;
+
+    let (mut symbols, mut digits, mut ambiguous) = (true, true, true);
+    let mut pos = pos + 2;
+
+    loop {
+        match tokens.get(pos) {
+            Some(Token::Value("nosymbols")) => symbols = false,
+            Some(Token::Value("nodigits")) => digits = false,
+            Some(Token::Value("noambiguous")) => ambiguous = false,
+            _ => break,
+        }
+        pos += 1;
+    }
+
+    Ok((
+        Cmd::Gen {
+            len,
+            symbols,
+            digits,
+            ambiguous,
+        },
+        pos,
+    ))
+}
+
+/// the length of a generated password, at most `MAX_GEN_LEN`
+fn parse_gen_len<'text>(tokens: &[Token<'text>], pos: usize) -> Result<usize, ParseError<'text>> {
+    let Some(Token::Value(len)) = tokens.get(pos) else {
+        return Err(ParseError::SyntaxError(pos, "expected a length"));
+    };
+    match len.parse::<usize>() {
+        Ok(len) if (1..=MAX_GEN_LEN).contains(&len) => Ok(len),
+        Ok(len) if len > MAX_GEN_LEN => Err(ParseError::SyntaxError(
+            pos,
+            "a generated password is at most 1024 characters",
+        )),
+        _ => Err(ParseError::SyntaxError(pos, "expected a length")),
+    }
+}
+
+/// the number of words of a generated passphrase, at most `MAX_GEN_WORDS`
+fn parse_word_count<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<usize, ParseError<'text>> {
+    let Some(Token::Value(count)) = tokens.get(pos) else {
+        return Err(ParseError::SyntaxError(pos, "expected a word count"));
+    };
+    match count.parse::<usize>() {
+        Ok(count) if (1..=MAX_GEN_WORDS).contains(&count) => Ok(count),
+        Ok(count) if count > MAX_GEN_WORDS => Err(ParseError::SyntaxError(
+            pos,
+            "a generated passphrase is at most 64 words",
+        )),
+        _ => Err(ParseError::SyntaxError(pos, "expected a word count")),
+    }
+}
+
+fn parse_cmd_gen_words<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Cmd<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value("gen")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("gen"), pos));
+    };
+
+    let Some(Token::Value("words")) = tokens.get(pos + 1) else {
+        return Err(ParseError::Expected(Token::Value("words"), pos + 1));
+    };
+
+    let count = parse_word_count(tokens, pos + 2)?;
+
+    let Some(Token::Value("sep")) = tokens.get(pos + 3) else {
+        return Ok((Cmd::GenWords { count, sep: "-" }, pos + 3));
+    };
+
+    let Some(Token::Value(sep)) = tokens.get(pos + 4) else {
+        return Err(ParseError::ExpectedValue(pos + 4));
+    };
+
+    Ok((Cmd::GenWords { count, sep }, pos + 5))
+}
+
+#[derive(Clone)]
+pub struct Assign<'text> {
+    pub attr: &'text str,
+    pub value: &'text str,
+    pub sensitive: bool,
+    /// the expiry the field had is kept if `None`
+    pub expires: Option<Expiry<'text>>,
+    /// `type <kind>`. the kind the field had is kept if `None`
+    pub kind: Option<FieldKind>,
+}
+
+/// `expires <span | date>` after an assignment: how long its value is good for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expiry<'text> {
+    /// like `expires 90` (days) or `expires 6m`
+    In(Span),
+    /// like `expires 2025-06-01`. turned into days from when it is set
+    On(&'text str),
+    /// `expires never` takes the expiry away
+    Never,
+}
+
+/// `type <kind>` after an assignment. `type` followed by anything else is an attr
+fn parse_kind(tokens: &[Token], pos: usize) -> (Option<FieldKind>, usize) {
+    let (Some(Token::Value("type")), Some(Token::Value(kind))) =
+        (tokens.get(pos), tokens.get(pos + 1))
+    else {
+        return (None, pos);
+    };
+    match FieldKind::ALL.into_iter().find(|k| k.to_string() == *kind) {
+        Some(kind) => (Some(kind), pos + 2),
+        None => (None, pos),
+    }
+}
+
+/// `expires <span | date>` after an assignment (see `date`). without an `=`,
+/// so that `expires = <value>` is always the attr `expires`
+fn parse_expires<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Option<Expiry<'text>>, usize), ParseError<'text>> {
+    let (Some(Token::Value("expires")), Some(Token::Value(when))) =
+        (tokens.get(pos), tokens.get(pos + 1))
+    else {
+        return Ok((None, pos));
+    };
+    if *when == "never" {
+        return Ok((Some(Expiry::Never), pos + 2));
+    }
+    if let Ok(span) = date::parse_span(when) {
+        return Ok((Some(Expiry::In(span)), pos + 2));
+    }
+    // only the form matters here. the date is placed when the assignment is made
+    let epoch = chrono::DateTime::<chrono::Utc>::default();
+    match date::parse_date_at(when, epoch) {
+        Ok(_) => Ok((Some(Expiry::On(when)), pos + 2)),
+        Err(e @ DateError::Ambiguous(_)) => Err(ParseError::Date(e, pos + 1)),
+        Err(_) => Err(ParseError::Date(DateError::NotASpan(when), pos + 1)),
+    }
+}
+
+fn parse_assign<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Assign<'text>, usize), ParseError<'text>> {
+    let (sensitive, pos) = match tokens.get(pos) {
+        Some(Token::Keyword("sensitive")) | Some(Token::Keyword("secret")) => (true, pos + 1),
+        _ => (false, pos),
+    };
+
+    let Some(Token::Value(attr)) = tokens.get(pos) else {
+        return Err(ParseError::ExpectedAttr(pos));
+    };
+
+    let Some(Token::Symbol("=")) = tokens.get(pos + 1) else {
+        return Err(ParseError::Expected(Token::Symbol("="), pos + 1));
+    };
+
+    let Some(Token::Value(value)) = tokens.get(pos + 2) else {
+        return Err(ParseError::ExpectedValue(pos + 2));
+    };
+
+    // in either order, each at most once
+    let (mut expires, mut kind, mut pos) = (None, None, pos + 3);
+    loop {
+        match (parse_expires(tokens, pos)?, parse_kind(tokens, pos)) {
+            ((Some(e), next), _) if expires.is_none() => (expires, pos) = (Some(e), next),
+            (_, (Some(k), next)) if kind.is_none() => (kind, pos) = (Some(k), next),
+            _ => break,
+        }
+    }
+    Ok((
+        Assign {
+            attr,
+            value,
+            sensitive,
+            expires,
+            kind,
+        },
+        pos,
+    ))
+}
+
+/// an attr whose value is generated when the command is evaluated. always sensitive
+pub struct Generated<'text> {
+    pub attr: &'text str,
+    pub generator: Generator,
+    pub expires: Option<Expiry<'text>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Generator {
+    /// `gen(24)` same as `gen 24`
+    Password(usize),
+    /// `gen(phrase 5)` same as `gen words 5`
+    Phrase(usize),
+}
+
+fn parse_generated<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Generated<'text>, usize), ParseError<'text>> {
+    let pos = match tokens.get(pos) {
+        Some(Token::Keyword("sensitive")) | Some(Token::Keyword("secret")) => pos + 1,
+        _ => pos,
+    };
+
+    let Some(Token::Value(attr)) = tokens.get(pos) else {
+        return Err(ParseError::ExpectedAttr(pos));
+    };
+
+    let Some(Token::Symbol("=")) = tokens.get(pos + 1) else {
+        return Err(ParseError::Expected(Token::Symbol("="), pos + 1));
+    };
+
+    let Some(Token::Value("gen")) = tokens.get(pos + 2) else {
+        return Err(ParseError::Expected(Token::Value("gen"), pos + 2));
+    };
+
+    let Some(Token::Symbol("(")) = tokens.get(pos + 3) else {
+        return Err(ParseError::Expected(Token::Symbol("("), pos + 3));
+    };
+
+    let (phrase, pos) = match tokens.get(pos + 4) {
+        Some(Token::Value("phrase")) => (true, pos + 5),
+        _ => (false, pos + 4),
+    };
+
+    let n = match phrase {
+        true => parse_word_count(tokens, pos)?,
+        false => parse_gen_len(tokens, pos)?,
+    };
+
+    let Some(Token::Symbol(")")) = tokens.get(pos + 1) else {
+        return Err(ParseError::Expected(Token::Symbol(")"), pos + 1));
+    };
+
+    let generator = match phrase {
+        true => Generator::Phrase(n),
+        false => Generator::Password(n),
+    };
+    let (expires, pos) = parse_expires(tokens, pos + 2)?;
+    Ok((
+        Generated {
+            attr,
+            generator,
+            expires,
+        },
+        pos,
+    ))
+}
+
+pub enum Query<'text> {
+    Or(Or<'text>),
+    Name(&'text str),
+    All,
+}
+
+fn parse_query<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Query<'text>, usize), ParseError<'text>> {
+    match tokens.get(pos) {
+        Some(Token::Keyword("all")) => Ok((Query::All, pos + 1)),
+        Some(Token::Value(val)) => match parse_or(tokens, pos) {
+            Ok((or, pos)) => Ok((Query::Or(or), pos)),
+            Err(e) => match tokens.get(pos + 1) {
+                // or the `<order>` of show and reveal
+                None | Some(Token::Symbol(")")) | Some(Token::Value("sort" | "limit")) => {
+                    Ok((Query::Name(val), pos + 1))
+                }
+                _ if e.pos() > pos + 1 => Err(e),
+                // `has .`
+                _ if matches!(e, ParseError::NameAlwaysExists(_)) => Err(e),
+                _ => Err(ParseError::ExpectedFilter(pos + 1)),
+            },
+        },
+        Some(Token::Symbol("(")) => {
+            let (or, pos) = parse_or(tokens, pos)?;
+            Ok((Query::Or(or), pos))
+        }
+        _ => Err(ParseError::SyntaxError(pos, "unable to parse query")),
+    }
+}
+
+pub enum Or<'text> {
+    And(And<'text>),
+    Or(Box<Or<'text>>, And<'text>),
+}
+
+fn parse_or<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Or<'text>, usize), ParseError<'text>> {
+    let (lhs, mut pos) = parse_and(tokens, pos)?;
+    let mut lhs = Or::And(lhs);
+    while let Some(token) = tokens.get(pos) {
+        match token {
+            Token::Keyword("or") => {
+                let (rhs, next_pos) = parse_and(tokens, pos + 1)?;
+                pos = next_pos;
+                lhs = Or::Or(Box::new(lhs), rhs);
+            }
+            _ => break,
+        }
+    }
+    Ok((lhs, pos))
+}
+
+pub enum And<'text> {
+    Filter(Filter<'text>),
+    And(Box<And<'text>>, Filter<'text>),
+}
+
+fn parse_and<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(And<'text>, usize), ParseError<'text>> {
+    let (lhs, mut pos) = parse_filter(tokens, pos)?;
+    let mut lhs = And::Filter(lhs);
+    while let Some(token) = tokens.get(pos) {
+        match token {
+            Token::Keyword("and") => {
+                let (rhs, next_pos) = parse_filter(tokens, pos + 1)?;
+                pos = next_pos;
+                lhs = And::And(Box::new(lhs), rhs);
+            }
+            _ => break,
+        }
+    }
+    Ok((lhs, pos))
+}
+
+pub enum Filter<'text> {
+    Contains(Contains<'text>),
+    StartsWith(StartsWith<'text>),
+    EndsWith(EndsWith<'text>),
+    Matches(Matches<'text>),
+    Fuzzy(Fuzzy<'text>),
+    Cmp(Is<'text>),
+    Compare(Compare<'text>),
+    Parens(Box<Query<'text>>),
+    Not(Box<Filter<'text>>),
+    Has(Has<'text>),
+    Missing(Missing<'text>),
+    TagContains(TagContains<'text>),
+    FieldCount(FieldCount),
+    Expired(Expired),
+}
+
+fn parse_filter<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Filter<'text>, usize), ParseError<'text>> {
+    fn parse_parens<'text>(
+        tokens: &[Token<'text>],
+        pos: usize,
+    ) -> Result<(Filter<'text>, usize), ParseError<'text>> {
+        let Some(Token::Symbol("(")) = tokens.get(pos) else {
+            return Err(ParseError::Expected(Token::Symbol("("), pos));
+        };
+        let (query, pos) = parse_query(tokens, pos + 1)?;
+        let Some(Token::Symbol(")")) = tokens.get(pos) else {
+            return Err(ParseError::Expected(Token::Symbol(")"), pos));
+        };
+        Ok((Filter::Parens(Box::new(query)), pos + 1))
+    }
+
+    fn parse_not<'text>(
+        tokens: &[Token<'text>],
+        pos: usize,
+    ) -> Result<(Filter<'text>, usize), ParseError<'text>> {
+        // not a keyword so that records and attrs can still be called 'not'
+        let Some(Token::Value("not")) = tokens.get(pos) else {
+            return Err(ParseError::Expected(Token::Value("not"), pos));
+        };
+        if let Some(Token::Keyword("all")) = tokens.get(pos + 1) {
+            let all = Filter::Parens(Box::new(Query::All));
+            return Ok((Filter::Not(Box::new(all)), pos + 2));
+        }
+        let (filter, pos) = parse_filter(tokens, pos + 1)?;
+        Ok((Filter::Not(Box::new(filter)), pos))
+    }
+
+    combine_parsers(
+        tokens,
+        pos,
+        &[
+            &parse_parens,
+            &parse_not,
+            &parse_has,
+            &parse_missing,
+            &parse_tag_contains,
+            &parse_contains,
+            &parse_startswith,
+            &parse_endswith,
+            &parse_matches,
+            &parse_fuzzy,
+            &parse_is,
+            &parse_field_count,
+            &parse_compare,
+            // last, so that `expired is x` is still about the attr `expired`
+            &parse_expired,
+        ],
+        ParseError::SyntaxError(pos, "cannot parse filter"),
+    )
+}
+
+pub struct Has<'text> {
+    pub attr: &'text str,
+}
+
+fn parse_has<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Has<'text>, usize), ParseError<'text>> {
+    // not a keyword so that records and attrs can still be called 'has'
+    let Some(Token::Value("has")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("has"), pos));
+    };
+
+    let attr = match tokens.get(pos + 1) {
+        Some(Token::Value(".")) => return Err(ParseError::NameAlwaysExists(pos + 1)),
+        Some(Token::Value(attr)) => attr,
+        _ => return Err(ParseError::ExpectedAttr(pos + 1)),
+    };
+
+    Ok((Has { attr }, pos + 2))
+}
+
+pub struct Missing<'text> {
+    pub attr: &'text str,
+}
+
+fn parse_missing<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Missing<'text>, usize), ParseError<'text>> {
+    // not keywords so that records and attrs can still be called 'missing' or 'lacks'
+    let (Some(Token::Value("missing")) | Some(Token::Value("lacks"))) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("missing"), pos));
+    };
+
+    let attr = match tokens.get(pos + 1) {
+        Some(Token::Value(".")) => return Err(ParseError::NameAlwaysExists(pos + 1)),
+        Some(Token::Value(attr)) => attr,
+        _ => return Err(ParseError::ExpectedAttr(pos + 1)),
+    };
+
+    Ok((Missing { attr }, pos + 2))
+}
+
+pub struct TagContains<'text> {
+    pub tag: &'text str,
+}
+
+fn parse_tag_contains<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(TagContains<'text>, usize), ParseError<'text>> {
+    match tokens.get(pos) {
+        Some(Token::Value(value)) if value.starts_with("tag:") => match &value[4..] {
+            "" => Err(ParseError::SyntaxError(pos, "expected a tag after 'tag:'")),
+            tag => Ok((TagContains { tag }, pos + 1)),
+        },
+        Some(Token::Value("tag")) => {
+            let Some(Token::Keyword("contains")) = tokens.get(pos + 1) else {
+                return Err(ParseError::Expected(Token::Keyword("contains"), pos + 1));
+            };
+            let Some(Token::Value(tag)) = tokens.get(pos + 2) else {
+                return Err(ParseError::ExpectedValue(pos + 2));
+            };
+            Ok((TagContains { tag }, pos + 3))
+        }
+        Some(Token::Value("tagged")) => {
+            let Some(Token::Value(tag)) = tokens.get(pos + 1) else {
+                return Err(ParseError::ExpectedValue(pos + 1));
+            };
+            Ok((TagContains { tag }, pos + 2))
+        }
+        _ => Err(ParseError::Expected(Token::Value("tag"), pos)),
+    }
+}
+
+pub struct Contains<'text> {
+    pub attr: &'text str,
+    pub substr: &'text str,
+    pub case_sensitive: bool,
+}
+
+/// the `cs` between an attr and `contains` or `matches` that makes them case-sensitive.
+/// returns whether it is there and the position after it
+fn parse_case_sensitive(tokens: &[Token], pos: usize) -> (bool, usize) {
+    match tokens.get(pos) {
+        Some(Token::Value("cs")) => (true, pos + 1),
+        _ => (false, pos),
+    }
+}
+
+fn parse_contains<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Contains<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value(attr)) = tokens.get(pos) else {
+        return Err(ParseError::ExpectedAttr(pos));
+    };
+
+    let (case_sensitive, pos) = parse_case_sensitive(tokens, pos + 1);
+
+    let Some(Token::Keyword("contains")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Keyword("contains"), pos));
+    };
+
+    let Some(Token::Value(substr)) = tokens.get(pos + 1) else {
+        return Err(ParseError::ExpectedValue(pos + 1));
+    };
+
+    Ok((
+        Contains {
+            attr,
+            substr,
+            case_sensitive,
+        },
+        pos + 2,
+    ))
+}
+
+pub struct StartsWith<'text> {
+    pub attr: &'text str,
+    pub prefix: &'text str,
+    pub case_sensitive: bool,
+}
+
+fn parse_startswith<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(StartsWith<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value(attr)) = tokens.get(pos) else {
+        return Err(ParseError::ExpectedAttr(pos));
+    };
+
+    let (case_sensitive, pos) = parse_case_sensitive(tokens, pos + 1);
+
+    let Some(Token::Value("startswith")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("startswith"), pos));
+    };
+
+    let Some(Token::Value(prefix)) = tokens.get(pos + 1) else {
+        return Err(ParseError::ExpectedValue(pos + 1));
+    };
+
+    Ok((
+        StartsWith {
+            attr,
+            prefix,
+            case_sensitive,
+        },
+        pos + 2,
+    ))
+}
+
+pub struct EndsWith<'text> {
+    pub attr: &'text str,
+    pub suffix: &'text str,
+    pub case_sensitive: bool,
+}
+
+fn parse_endswith<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(EndsWith<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value(attr)) = tokens.get(pos) else {
+        return Err(ParseError::ExpectedAttr(pos));
+    };
+
+    let (case_sensitive, pos) = parse_case_sensitive(tokens, pos + 1);
+
+    let Some(Token::Value("endswith")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("endswith"), pos));
+    };
+
+    let Some(Token::Value(suffix)) = tokens.get(pos + 1) else {
+        return Err(ParseError::ExpectedValue(pos + 1));
+    };
+
+    Ok((
+        EndsWith {
+            attr,
+            suffix,
+            case_sensitive,
+        },
+        pos + 2,
+    ))
+}
+
+pub struct Matches<'text> {
+    pub attr: &'text str,
+    /// case-insensitive unless `case_sensitive`
+    pub pat: Regex,
+    pub case_sensitive: bool,
+}
+
+fn parse_matches<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Matches<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value(attr)) = tokens.get(pos) else {
+        return Err(ParseError::ExpectedAttr(pos));
+    };
+
+    let (case_sensitive, pos) = parse_case_sensitive(tokens, pos + 1);
+
+    let (Some(Token::Keyword("matches")) | Some(Token::Keyword("like"))) = tokens.get(pos) else {
+        return Err(ParseError::ExpectedOneOf(
+            vec![Token::Keyword("matches"), Token::Keyword("like")],
+            pos,
+        ));
+    };
+
+    let Some(Token::Value(pat)) = tokens.get(pos + 1) else {
+        return Err(ParseError::ExpectedValue(pos + 1));
+    };
+
+    let pat = RegexBuilder::new(pat)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .map_err(|_| ParseError::InvalidRegex(pos + 1))?;
+
+    Ok((
+        Matches {
+            attr,
+            pat,
+            case_sensitive,
+        },
+        pos + 2,
+    ))
+}
+
+pub struct Is<'text> {
+    pub attr: &'text str,
+    pub value: &'text str,
+}
+
+fn parse_is<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Is<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value(attr)) = tokens.get(pos) else {
+        return Err(ParseError::ExpectedAttr(pos));
+    };
+
+    let Some(Token::Keyword("is")) = tokens.get(pos + 1) else {
+        return Err(ParseError::Expected(Token::Keyword("is"), pos + 1));
+    };
+
+    let Some(Token::Value(value)) = tokens.get(pos + 2) else {
+        return Err(ParseError::ExpectedValue(pos + 2));
+    };
+
+    Ok((Is { attr, value }, pos + 3))
+}
+
+pub enum CompareOp {
+    Gt,
+    Lt,
+    Gte,
+    Lte,
+}
+
+/// numeric comparison. the field value and `value` are compared as numbers
+pub struct Compare<'text> {
+    pub attr: &'text str,
+    pub op: CompareOp,
+    pub value: &'text str,
+}
+
+fn parse_compare<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Compare<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value(attr)) = tokens.get(pos) else {
+        return Err(ParseError::ExpectedAttr(pos));
+    };
+
+    let op = parse_compare_op(tokens, pos + 1)?;
+
+    let Some(Token::Value(value)) = tokens.get(pos + 2) else {
+        return Err(ParseError::ExpectedValue(pos + 2));
+    };
+    if value.trim().parse::<f64>().is_err() {
+        return Err(ParseError::SyntaxError(pos + 2, "not a number"));
+    }
+
+    Ok((Compare { attr, op, value }, pos + 3))
+}
+
+fn parse_compare_op<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<CompareOp, ParseError<'text>> {
+    // the words are not keywords so that records and attrs can still be called 'gt' ...
+    match tokens.get(pos) {
+        Some(Token::Symbol(">")) | Some(Token::Value("gt")) => Ok(CompareOp::Gt),
+        Some(Token::Symbol("<")) | Some(Token::Value("lt")) => Ok(CompareOp::Lt),
+        Some(Token::Symbol(">=")) | Some(Token::Value("gte")) => Ok(CompareOp::Gte),
+        Some(Token::Symbol("<=")) | Some(Token::Value("lte")) => Ok(CompareOp::Lte),
+        _ => Err(ParseError::ExpectedOneOf(
+            vec![
+                Token::Symbol(">"),
+                Token::Symbol("<"),
+                Token::Symbol(">="),
+                Token::Symbol("<="),
+                Token::Value("gt"),
+                Token::Value("lt"),
+                Token::Value("gte"),
+                Token::Value("lte"),
+            ],
+            pos,
+        )),
+    }
+}
+
+/// the number of fields of a record, not counting its tags
+pub struct FieldCount {
+    pub op: CompareOp,
+    pub count: usize,
+}
+
+/// `fields <op> <count>`. with anything but a count it is the attr `fields` instead
+fn parse_field_count<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(FieldCount, usize), ParseError<'text>> {
+    let Some(Token::Value("fields")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("fields"), pos));
+    };
+
+    let op = parse_compare_op(tokens, pos + 1)?;
+
+    let Some(count) = (match tokens.get(pos + 2) {
+        Some(Token::Value(count)) => count.parse::<usize>().ok(),
+        _ => None,
+    }) else {
+        return Err(ParseError::ExpectedValue(pos + 2));
+    };
+
+    Ok((FieldCount { op, count }, pos + 3))
+}
+
+/// a value within `max_distance` typos of `target`
+pub struct Fuzzy<'text> {
+    pub attr: &'text str,
+    pub target: &'text str,
+    pub max_distance: usize,
+}
+
+fn parse_fuzzy<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Fuzzy<'text>, usize), ParseError<'text>> {
+    let Some(Token::Value(attr)) = tokens.get(pos) else {
+        return Err(ParseError::ExpectedAttr(pos));
+    };
+
+    let Some(Token::Value("fuzzy")) = tokens.get(pos + 1) else {
+        return Err(ParseError::Expected(Token::Value("fuzzy"), pos + 1));
+    };
+
+    let Some(Token::Value(target)) = tokens.get(pos + 2) else {
+        return Err(ParseError::ExpectedValue(pos + 2));
+    };
+
+    // anything that looks like a number is meant as the distance
+    match tokens.get(pos + 3) {
+        Some(Token::Value(distance)) if distance.parse::<f64>().is_ok() => {
+            match distance.parse::<usize>() {
+                Ok(max_distance) if max_distance > 0 => Ok((
+                    Fuzzy {
+                        attr,
+                        target,
+                        max_distance,
+                    },
+                    pos + 4,
+                )),
+                _ => Err(ParseError::SyntaxError(
+                    pos + 3,
+                    "the distance must be a whole number above 0",
+                )),
+            }
+        }
+        _ => Ok((
+            Fuzzy {
+                attr,
+                target,
+                max_distance: 1,
+            },
+            pos + 3,
+        )),
+    }
+}
+
+/// a field whose value is older than its `expires`
+pub struct Expired;
+
+fn parse_expired<'text>(
+    tokens: &[Token<'text>],
+    pos: usize,
+) -> Result<(Expired, usize), ParseError<'text>> {
+    let Some(Token::Value("expired")) = tokens.get(pos) else {
+        return Err(ParseError::Expected(Token::Value("expired"), pos));
+    };
+    Ok((Expired, pos + 1))
+}
+
+impl<'text> Display for Cmd<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Cmd::Set {
+                name,
+                filter,
+                assignments,
+                generated,
+            } => {
+                write!(f, "set '{}'", name)?;
+                if let Some(filter) = filter {
+                    write!(f, " where {}", filter)?;
+                }
+                for assign in assignments {
+                    write!(f, " {}", assign)?;
+                }
+                for generated in generated {
+                    write!(f, " {}", generated)?;
+                }
+                Ok(())
+            }
+            Cmd::Del {
+                name,
+                filter,
+                attrs,
+            } => {
+                write!(f, "del '{}'", name)?;
+                if let Some(filter) = filter {
+                    write!(f, " where {}", filter)?;
+                }
+                for attr in attrs {
+                    write!(f, " '{}'", attr)?;
+                }
+                Ok(())
+            }
+            Cmd::SetWhere {
+                filter,
+                assignments,
+            } => {
+                write!(f, "set where {}", filter)?;
+                for assign in assignments {
+                    write!(f, " {}", assign)?;
+                }
+                Ok(())
+            }
+            Cmd::DelQuery(q) => write!(f, "del {}", q),
+            Cmd::Show(q, order) => write!(f, "show {}{}", q, order),
+            Cmd::ShowJson(q, order) => write!(f, "show --json {}{}", q, order),
+            Cmd::Reveal(q, order) => write!(f, "reveal {}{}", q, order),
+            Cmd::RevealJson(q, order) => write!(f, "reveal --json {}{}", q, order),
+            Cmd::Count(q) => write!(f, "count {}", q),
+            Cmd::List(q) => write!(f, "list {}", q),
+            Cmd::Copy {
+                name,
+                filter,
+                attr,
+                clear_after,
+            } => {
+                write!(f, "copy '{}'", name)?;
+                if let Some(filter) = filter {
+                    write!(f, " where {}", filter)?;
+                }
+                if let Some(attr) = attr {
+                    write!(f, " '{}'", attr)?;
+                }
+                match clear_after {
+                    Some(secs) => write!(f, " {}", secs),
+                    None => Ok(()),
+                }
+            }
+            Cmd::CopyTotp {
+                name,
+                attr,
+                clear_after,
+            } => {
+                write!(f, "copy totp '{}' '{}'", name, attr)?;
+                match clear_after {
+                    Some(secs) => write!(f, " {}", secs),
+                    None => Ok(()),
+                }
+            }
+            Cmd::Totp {
+                name,
+                digits,
+                period,
+            } => {
+                write!(f, "totp '{}'", name)?;
+                if let Some(digits) = digits {
+                    write!(f, " digits = {}", digits)?;
+                }
+                if let Some(period) = period {
+                    write!(f, " period = {}", period)?;
+                }
+                Ok(())
+            }
+            Cmd::Share(name) => write!(f, "share '{}'", name),
+            Cmd::Decode {
+                name,
+                attr,
+                encoding,
+                reveal,
+            } => {
+                write!(f, "decode '{}' '{}' {}", name, attr, encoding)?;
+                match reveal {
+                    true => write!(f, " reveal"),
+                    false => Ok(()),
+                }
+            }
+            Cmd::Encode {
+                name,
+                attr,
+                encoding,
+                reveal,
+            } => {
+                write!(f, "encode '{}' '{}' {}", name, attr, encoding)?;
+                match reveal {
+                    true => write!(f, " reveal"),
+                    false => Ok(()),
+                }
+            }
+            Cmd::History(name) => write!(f, "history '{}'", name),
+            Cmd::RevealHistory(name) => write!(f, "reveal history '{}'", name),
+            Cmd::HistoryDiff(name) => write!(f, "history diff '{}'", name),
+            Cmd::RevealHistoryDiff(name) => write!(f, "reveal history diff '{}'", name),
+            Cmd::SetHistoryLimit(Some(limit)) => write!(f, "history limit set {}", limit),
+            Cmd::SetHistoryLimit(None) => write!(f, "history limit clear"),
+            Cmd::ShowHistoryLimit => write!(f, "history limit show"),
+            Cmd::ClearHistory(name) => write!(f, "history clear '{}'", name),
+            Cmd::PruneHistory { name, keep } => write!(f, "history prune '{}' {}", name, keep),
+            Cmd::Undo => write!(f, "undo"),
+            Cmd::Redo => write!(f, "redo"),
+            Cmd::Trash => write!(f, "trash"),
+            Cmd::Undelete(name) => write!(f, "undelete '{}'", name),
+            Cmd::Restore { name, index } => write!(f, "restore '{}' {}", name, index),
+            Cmd::EmptyTrash => write!(f, "empty trash"),
+            Cmd::Merge(fpath) => write!(f, "merge '{}'", fpath),
+            Cmd::Watch(query) => write!(f, "watch {}", query),
+            Cmd::Unwatch(query) => write!(f, "unwatch {}", query),
+            Cmd::Watches => write!(f, "watches"),
+            Cmd::Rename { old, filter, new } => {
+                write!(f, "rename '{}'", old)?;
+                if let Some(filter) = filter {
+                    write!(f, " where {}", filter)?;
+                }
+                write!(f, " '{}'", new)
+            }
+            Cmd::BatchRename {
+                pattern,
+                replacement,
+                dry_run,
+            } => {
+                write!(f, "rename")?;
+                if *dry_run {
+                    write!(f, " --dry-run")?;
+                }
+                write!(f, " all '{}' '{}'", pattern, replacement)
+            }
+            Cmd::RenameField {
+                record,
+                filter,
+                old_attr,
+                new_attr,
+            } => {
+                write!(f, "rename '{}'", record)?;
+                if let Some(filter) = filter {
+                    write!(f, " where {}", filter)?;
+                }
+                write!(f, " '{}' '{}'", old_attr, new_attr)
+            }
+            Cmd::Dup { src, dst } => write!(f, "dup '{}' '{}'", src, dst),
+            Cmd::AllowDuplicateNames(allow) => match allow {
+                Some(true) => write!(f, "allow-duplicate-names on"),
+                Some(false) => write!(f, "allow-duplicate-names off"),
+                None => write!(f, "allow-duplicate-names"),
+            },
+            Cmd::Rollback { name, steps } => write!(f, "rollback '{}' {}", name, steps),
+            Cmd::Tag { name, tags } => {
+                write!(f, "tag '{}'", name)?;
+                for tag in tags {
+                    write!(f, " '{}'", tag)?;
+                }
+                Ok(())
+            }
+            Cmd::Untag { name, tags } => {
+                write!(f, "untag '{}'", name)?;
+                for tag in tags {
+                    write!(f, " '{}'", tag)?;
+                }
+                Ok(())
+            }
+            Cmd::Import(fpath, merge) => write!(f, "import '{}'{}", fpath, merge),
+            Cmd::ImportLastPass(fpath, merge) => {
+                write!(f, "import lastpass '{}'{}", fpath, merge)
+            }
+            Cmd::ImportCsv(fpath, merge) => write!(f, "import csv '{}'{}", fpath, merge),
+            Cmd::ImportKeePass(fpath, merge) => write!(f, "import keepass '{}'{}", fpath, merge),
+            Cmd::Export(fpath) => write!(f, "export '{}'", fpath),
+            Cmd::ExportCsv(fpath) => write!(f, "export csv '{}'", fpath),
+            Cmd::ExportCsvFields(fpath) => write!(f, "export csv fields '{}'", fpath),
+            Cmd::ExportJson(fpath) => write!(f, "export json '{}'", fpath),
+            Cmd::SizeReport => write!(f, "size report"),
+            Cmd::Check(kind) => write!(f, "check {}", kind),
+            Cmd::Gen {
+                len,
+                symbols,
+                digits,
+                ambiguous,
+            } => {
+                write!(f, "gen {}", len)?;
+                if !symbols {
+                    write!(f, " nosymbols")?;
+                }
+                if !digits {
+                    write!(f, " nodigits")?;
+                }
+                if !ambiguous {
+                    write!(f, " noambiguous")?;
+                }
+                Ok(())
+            }
+            Cmd::GenWords { count, sep } => match *sep {
+                "-" => write!(f, "gen words {}", count),
+                sep => write!(f, "gen words {} sep '{}'", count, sep),
+            },
+        }
+    }
+}
+
+impl<'text> Display for ParseError<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::SyntaxError(pos, msg) => write!(f, "{} at token {}", msg, pos),
+            ParseError::ExpectedName(pos) => write!(f, "expected a name at token {}", pos),
+            ParseError::ExpectedAttr(pos) => write!(f, "expected an attr at token {}", pos),
+            ParseError::ExpectedValue(pos) => write!(f, "expected a value at token {}", pos),
+            ParseError::Expected(token, pos) => write!(f, "expected `{}` at token {}", token, pos),
+            ParseError::ExpectedOneOf(tokens, pos) => {
+                write!(f, "expected one of")?;
+                for token in tokens {
+                    write!(f, " `{}`", token)?;
+                }
+                write!(f, " at token {}", pos)
+            }
+            ParseError::InvalidRegex(pos) => write!(f, "invalid regex at token {}", pos),
+            ParseError::Date(e, pos) => write!(f, "{} at token {}", e, pos),
+            ParseError::DuplicateAssignments(attr, pos) => {
+                write!(f, "'{}' is assigned more than once at token {}", attr, pos)
+            }
+            ParseError::ExpectedFilter(pos) => write!(
+                f,
+                "expected a filter at token {}. a name must be alone, otherwise use `<attr> contains|matches|is <value>`",
+                pos
+            ),
+            ParseError::NameAlwaysExists(pos) => write!(
+                f,
+                "'.' is the record name which always exists. it can't be used with `has` or `missing` at token {}",
+                pos
+            ),
+            ParseError::IncompleteParse(pos) => write!(f, "unexpected token at token {}", pos),
+        }
+    }
+}
+
+/// an attr as it has to be typed to parse back to itself. quoted when
+/// it would otherwise lex as a keyword like `all` or as several tokens
+struct Attr<'text>(&'text str);
+
+impl<'text> Display for Attr<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match lex(self.0).as_deref() {
+            Ok([Token::Value(value)]) if *value == self.0 => write!(f, "{}", self.0),
+            _ => write!(f, "'{}'", self.0),
+        }
+    }
+}
+
+impl<'text> Display for Assign<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.sensitive {
+            true => write!(f, "sensitive {} = '{}'", Attr(self.attr), self.value)?,
+            false => write!(f, "{} = '{}'", Attr(self.attr), self.value)?,
+        }
+        if let Some(expires) = self.expires {
+            write!(f, " {}", expires)?;
+        }
+        match self.kind {
+            Some(kind) => write!(f, " type {}", kind),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'text> Display for Expiry<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expiry::In(span) => write!(f, "expires {}", span),
+            Expiry::On(date) => write!(f, "expires '{}'", date),
+            Expiry::Never => write!(f, "expires never"),
+        }
+    }
+}
+
+impl<'text> Display for Generated<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.generator {
+            Generator::Password(len) => write!(f, "{} = gen({})", Attr(self.attr), len)?,
+            Generator::Phrase(count) => write!(f, "{} = gen(phrase {})", Attr(self.attr), count)?,
+        }
+        match self.expires {
+            Some(expires) => write!(f, " {}", expires),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'text> Display for Query<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Query::Or(o) => write!(f, "{}", o),
+            Query::Name(name) => write!(f, "'{}'", name),
+            Query::All => write!(f, "all"),
+        }
+    }
+}
+
+impl<'text> Display for Or<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Or::And(a) => write!(f, "{}", a),
+            Or::Or(lhs, rhs) => write!(f, "({} or {})", lhs, rhs),
+        }
+    }
+}
+
+impl<'text> Display for And<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            And::Filter(x) => write!(f, "{}", x),
+            And::And(lhs, rhs) => write!(f, "({} and {})", lhs, rhs),
+        }
+    }
+}
+
+impl<'text> Display for Filter<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Filter::Contains(c) => write!(f, "{}", c),
+            Filter::StartsWith(s) => write!(f, "{}", s),
+            Filter::EndsWith(e) => write!(f, "{}", e),
+            Filter::Matches(m) => write!(f, "{}", m),
+            Filter::Fuzzy(z) => write!(f, "{}", z),
+            Filter::Cmp(c) => write!(f, "{}", c),
+            Filter::Compare(c) => write!(f, "{}", c),
+            Filter::FieldCount(c) => write!(f, "{}", c),
+            Filter::Expired(_) => write!(f, "expired"),
+            Filter::Parens(q) => write!(f, "({})", q),
+            Filter::Has(h) => write!(f, "{}", h),
+            Filter::Missing(m) => write!(f, "{}", m),
+            Filter::TagContains(t) => write!(f, "{}", t),
+            Filter::Not(filter) => match filter.as_ref() {
+                Filter::Parens(_) => write!(f, "not {}", filter),
+                filter => write!(f, "not ({})", filter),
+            },
+        }
+    }
+}
+
+impl<'text> Display for Has<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "has {}", Attr(self.attr))
+    }
+}
+
+impl<'text> Display for TagContains<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "tag contains '{}'", self.tag)
+    }
+}
+
+impl<'text> Display for Missing<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "missing {}", Attr(self.attr))
+    }
+}
+
+impl<'text> Display for Contains<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.case_sensitive {
+            true => write!(f, "{} cs contains '{}'", Attr(self.attr), self.substr),
+            false => write!(f, "{} contains '{}'", Attr(self.attr), self.substr),
+        }
+    }
+}
+
+impl<'text> Display for Fuzzy<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} fuzzy '{}' {}",
+            Attr(self.attr),
+            self.target,
+            self.max_distance
+        )
+    }
+}
+
+impl<'text> Display for StartsWith<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.case_sensitive {
+            true => write!(f, "{} cs startswith '{}'", Attr(self.attr), self.prefix),
+            false => write!(f, "{} startswith '{}'", Attr(self.attr), self.prefix),
+        }
+    }
+}
+
+impl<'text> Display for EndsWith<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.case_sensitive {
+            true => write!(f, "{} cs endswith '{}'", Attr(self.attr), self.suffix),
+            false => write!(f, "{} endswith '{}'", Attr(self.attr), self.suffix),
+        }
+    }
+}
+
+impl<'text> Display for Matches<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.case_sensitive {
+            true => write!(f, "{} cs matches '{}'", Attr(self.attr), self.pat),
+            false => write!(f, "{} matches '{}'", Attr(self.attr), self.pat),
+        }
+    }
+}
+
+impl<'text> Display for Is<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is '{}'", Attr(self.attr), self.value)
+    }
+}
+
+/// written with a leading space and nothing for the default
+/// so that it can follow the path of an import
+impl Display for Merge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Merge::Skip => write!(f, " skip"),
+            Merge::Overwrite => Ok(()),
+            Merge::Rename => write!(f, " rename"),
+        }
+    }
+}
+
+impl Display for CompareOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompareOp::Gt => write!(f, ">"),
+            CompareOp::Lt => write!(f, "<"),
+            CompareOp::Gte => write!(f, ">="),
+            CompareOp::Lte => write!(f, "<="),
+        }
+    }
+}
+
+impl<'text> Display for Compare<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} '{}'", Attr(self.attr), self.op, self.value)
+    }
+}
+
+impl<'text> Display for Order<'text> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(attr) = self.sort {
+            write!(f, " sort '{}'", attr)?;
+        }
+        match self.limit {
+            Some(limit) => write!(f, " limit {}", limit),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Display for FieldCount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "fields {} {}", self.op, self.count)
+    }
+}
+
+impl<'text> From<Expired> for Filter<'text> {
+    fn from(value: Expired) -> Self {
+        Filter::Expired(value)
+    }
+}
+
+impl<'text> From<FieldCount> for Filter<'text> {
+    fn from(value: FieldCount) -> Self {
+        Filter::FieldCount(value)
+    }
+}
+
+impl<'text> From<Has<'text>> for Filter<'text> {
+    fn from(value: Has<'text>) -> Self {
+        Filter::Has(value)
+    }
+}
+
+impl<'text> From<Missing<'text>> for Filter<'text> {
+    fn from(value: Missing<'text>) -> Self {
+        Filter::Missing(value)
+    }
+}
+
+impl<'text> From<TagContains<'text>> for Filter<'text> {
+    fn from(value: TagContains<'text>) -> Self {
+        Filter::TagContains(value)
+    }
+}
+
+impl<'text> From<Contains<'text>> for Filter<'text> {
+    fn from(value: Contains<'text>) -> Self {
+        Filter::Contains(value)
+    }
+}
+
+impl<'text> From<StartsWith<'text>> for Filter<'text> {
+    fn from(value: StartsWith<'text>) -> Self {
+        Filter::StartsWith(value)
+    }
+}
+
+impl<'text> From<EndsWith<'text>> for Filter<'text> {
+    fn from(value: EndsWith<'text>) -> Self {
+        Filter::EndsWith(value)
+    }
+}
+
+impl<'text> From<Fuzzy<'text>> for Filter<'text> {
+    fn from(value: Fuzzy<'text>) -> Self {
+        Filter::Fuzzy(value)
+    }
+}
+
+impl<'text> From<Matches<'text>> for Filter<'text> {
+    fn from(value: Matches<'text>) -> Self {
+        Filter::Matches(value)
+    }
+}
+
+impl<'text> From<Is<'text>> for Filter<'text> {
+    fn from(value: Is<'text>) -> Self {
+        Filter::Cmp(value)
+    }
+}
+
+impl<'text> From<Compare<'text>> for Filter<'text> {
+    fn from(value: Compare<'text>) -> Self {
+        Filter::Compare(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    macro_rules! check {
+        ($f:ident, $src:expr, $expected:expr) => {
+            let tokens = lex($src).expect("** LEX ERROR");
+            let (stmt, pos) = $f(&tokens, 0).expect("** Unable to parse statement");
+            assert_eq!(pos, tokens.len(), "** Unable to parse all Tokens\n{}", stmt);
+            let stmt = format!("{}", stmt);
+            assert_eq!(stmt, $expected);
+        };
+        ($f:ident, $src:expr) => {
+            check!($f, $src, $src)
+        };
+    }
+
+    macro_rules! check_err {
+        ($src:expr, $expected:pat) => {
+            let tokens = lex($src).expect("** LEX ERROR");
+            match parse(&tokens) {
+                Ok(cmd) => panic!("** expected error but parsed `{}`", cmd),
+                Err(e) => assert!(matches!(e, $expected), "** {}\n{:?}", $src, e),
+            }
+        };
+    }
+
+    #[test]
+    fn test_cmd_set() {
+        check!(parse_cmd, "set 'gmail'");
+        check!(
+            parse_cmd,
+            "set 'gmail' user = 'zahash' pass = 'supersecretpass' url = 'mail.google.com'"
+        );
+        check!(
+            parse_cmd,
+            "set 'gmail' user = 'zahash' sensitive pass = 'supersecretpass' url = 'mail.google.com'"
+        );
+        check!(parse_cmd, "set 'gmail' user = 'zahash' pass = gen(24)");
+        check!(
+            parse_cmd,
+            "set gmail sensitive pass = gen( phrase 5 ) user = zahash",
+            "set 'gmail' user = 'zahash' pass = gen(phrase 5)"
+        );
+        check!(
+            parse_cmd,
+            "set 'gen' 'gen' = 'gen(24)'",
+            "set 'gen' gen = 'gen(24)'"
+        );
+
+        check!(
+            parse_cmd,
+            "set where url contains oldcorp.com user = 'me@newcorp.com'",
+            "set where url contains 'oldcorp.com' user = 'me@newcorp.com'"
+        );
+        check!(
+            parse_cmd,
+            "set where user is zahash and not has mfa sensitive pass = x mfa = false",
+            "set where (user is 'zahash' and not (has mfa)) sensitive pass = 'x' mfa = 'false'"
+        );
+
+        check_err!("set gmail pass = gen(0)", ParseError::IncompleteParse(2));
+        check_err!(
+            "set where has url pass = gen(24)",
+            ParseError::SyntaxError(_, _)
+        );
+        check_err!(
+            "set where has url user = a user = b",
+            ParseError::DuplicateAssignments("user", _)
+        );
+        check_err!(
+            "set gmail pass = gen(8) pass = x",
+            ParseError::DuplicateAssignments("pass", _)
+        );
+    }
+
+    #[test]
+    fn test_cmd_del() {
+        check!(parse_cmd, "del 'gmail'");
+        check!(parse_cmd, "delete 'gmail'", "del 'gmail'");
+        check!(parse_cmd, "del 'gmail' 'user' 'pass'");
+    }
+
+    #[test]
+    fn test_cmd_del_query() {
+        check!(parse_cmd, "del all");
+        check!(
+            parse_cmd,
+            "del user contains 'zahash'",
+            "del user contains 'zahash'"
+        );
+        check!(
+            parse_cmd,
+            "delete has totp or missing url",
+            "del (has totp or missing url)"
+        );
+        check!(
+            parse_cmd,
+            "del where url contains example.org",
+            "del url contains 'example.org'"
+        );
+        check!(parse_cmd, "del where all", "del all");
+        // names and attrs are still a plain del
+        check!(parse_cmd, "del 'gmail'");
+        check!(parse_cmd, "del 'gmail' 'contains'");
+        check!(parse_cmd, "del 'gmail' 'url' 'pass'");
+
+        check_err!("del user contains", ParseError::ExpectedValue(3));
+    }
+
+    #[test]
+    fn test_where_as_value() {
+        // a record called 'where' and its attrs
+        check!(parse_cmd, "del where", "del 'where'");
+        check!(parse_cmd, "del where gmail", "del 'where' 'gmail'");
+        check!(
+            parse_cmd,
+            "del gmail where user",
+            "del 'gmail' 'where' 'user'"
+        );
+        check!(parse_cmd, "set where", "set 'where'");
+        check!(parse_cmd, "set where user = x", "set 'where' user = 'x'");
+        check!(parse_cmd, "set gmail where = x", "set 'gmail' where = 'x'");
+        check!(
+            parse_cmd,
+            "set where sensitive pass = x",
+            "set 'where' sensitive pass = 'x'"
+        );
+        check!(parse_cmd, "show where", "show 'where'");
+
+        check!(
+            parse_cmd,
+            "set gmail where user is x pass = y",
+            "set 'gmail' where user is 'x' pass = 'y'"
+        );
+        check_err!("del gmail where user is", ParseError::ExpectedValue(5));
+    }
+
+    #[test]
+    fn test_gen_as_value() {
+        check!(parse_cmd, "set gen gen = gen", "set 'gen' gen = 'gen'");
+        check!(parse_cmd, "show gen", "show 'gen'");
+        check!(parse_cmd, "del gen", "del 'gen'");
+    }
+
+    #[test]
+    fn test_cmd_show() {
+        check!(parse_cmd, "show all");
+        check!(parse_cmd, "show 'gmail'");
+        check!(
+            parse_cmd,
+            "show user is 'a' or user contains 'a' and user matches 'a'",
+            "show (user is 'a' or (user contains 'a' and user matches 'a'))"
+        );
+        check!(
+            parse_cmd,
+            "show user is 'a' and user contains 'a' or user matches 'a'",
+            "show ((user is 'a' and user contains 'a') or user matches 'a')"
+        );
+    }
+
+    #[test]
+    fn test_cmd_reveal() {
+        check!(parse_cmd, "reveal all");
+        check!(parse_cmd, "reveal 'gmail'");
+        check!(
+            parse_cmd,
+            "reveal user is 'a' or user contains 'a' and user matches 'a'",
+            "reveal (user is 'a' or (user contains 'a' and user matches 'a'))"
+        );
+        check!(
+            parse_cmd,
+            "reveal user is 'a' and user contains 'a' or user matches 'a'",
+            "reveal ((user is 'a' and user contains 'a') or user matches 'a')"
+        );
+    }
+
+    #[test]
+    fn test_order() {
+        check!(parse_cmd, "show all sort 'user' limit 10");
+        check!(parse_cmd, "show all sort user", "show all sort 'user'");
+        check!(parse_cmd, "reveal 'gmail' limit 1");
+        check!(
+            parse_cmd,
+            "show --json url contains google sort port limit 3",
+            "show --json url contains 'google' sort 'port' limit 3"
+        );
+        check!(parse_cmd, "reveal --json all sort '.'");
+        // records can still be called 'sort' and 'limit'
+        check!(parse_cmd, "show 'sort'");
+        check!(parse_cmd, "show 'limit' limit 2");
+
+        check_err!("show all sort", ParseError::ExpectedAttr(3));
+        check_err!("show all limit", ParseError::ExpectedValue(3));
+        check_err!("show all limit 0", ParseError::SyntaxError(3, _));
+        check_err!("show all limit ten", ParseError::SyntaxError(3, _));
+        check_err!("show all limit 2 sort user", ParseError::IncompleteParse(4));
+    }
+
+    #[test]
+    fn test_cmd_count() {
+        check!(parse_cmd, "count all");
+        check!(parse_cmd, "count 'gmail'");
+        check!(
+            parse_cmd,
+            "count url contains google and not user is zahash",
+            "count (url contains 'google' and not (user is 'zahash'))"
+        );
+        // a record can still be called 'count'
+        check!(parse_cmd, "show 'count'");
+
+        check_err!("count", ParseError::SyntaxError(1, _));
+        check_err!("count url contains", ParseError::ExpectedValue(3));
+    }
+
+    #[test]
+    fn test_cmd_list() {
+        check!(parse_cmd, "list", "list all");
+        check!(parse_cmd, "list all");
+        check!(parse_cmd, "list 'gmail'");
+        check!(
+            parse_cmd,
+            "list url contains google or has totp",
+            "list (url contains 'google' or has totp)"
+        );
+
+        check_err!("list url contains", ParseError::ExpectedValue(3));
+    }
+
+    #[test]
+    fn test_cmd_json() {
+        check!(parse_cmd, "show --json all");
+        check!(parse_cmd, "reveal --json 'gmail'");
+        check!(
+            parse_cmd,
+            "show --json user is 'a' and url contains 'b'",
+            "show --json (user is 'a' and url contains 'b')"
+        );
+        check!(parse_cmd, "show '--json'");
+    }
+
+    #[test]
+    fn test_cmd_history() {
+        check!(parse_cmd, "history 'gmail'");
+        check!(parse_cmd, "reveal history 'gmail'");
+    }
+
+    #[test]
+    fn test_cmd_rename() {
+        check!(parse_cmd, "rename 'gmail' 'discord'");
+        check!(
+            parse_cmd,
+            "rename gmail where user is zahash gmail2",
+            "rename 'gmail' where user is 'zahash' 'gmail2'"
+        );
+
+        check_err!(
+            "rename gmail where user is zahash",
+            ParseError::ExpectedName(6)
+        );
+    }
+
+    #[test]
+    fn test_cmd_rename_field() {
+        check!(parse_cmd, "rename 'gmail' 'pass' 'password'");
+        check!(
+            parse_cmd,
+            "rename gmail where user is zahash pass password",
+            "rename 'gmail' where user is 'zahash' 'pass' 'password'"
+        );
+
+        check_err!(
+            "rename gmail pass password again",
+            ParseError::IncompleteParse(4)
+        );
+    }
+
+    #[test]
+    fn test_cmd_batch_rename() {
+        check!(parse_cmd, "rename all '\\.com$' '.io'");
+        check!(
+            parse_cmd,
+            "rename --dry-run all '^(.*)-old$' $1",
+            "rename --dry-run all '^(.*)-old$' '$1'"
+        );
+        // a record named 'all'
+        check!(
+            parse_cmd,
+            "rename 'all' everything",
+            "rename 'all' 'everything'"
+        );
+
+        check_err!("rename all", ParseError::ExpectedValue(2));
+        check_err!("rename all '\\.com$'", ParseError::ExpectedValue(3));
+        check_err!("rename all '(' x", ParseError::InvalidRegex(2));
+        check_err!(
+            "rename --dry-run gmail gmail2",
+            ParseError::Expected(Token::Keyword("all"), 2)
+        );
+        check_err!("rename all a b c", ParseError::IncompleteParse(4));
+    }
+
+    #[test]
+    fn test_cmd_dup() {
+        check!(parse_cmd, "dup 'gmail' 'gmail_backup'");
+        check!(parse_cmd, "dup 'dup' 'dup2'");
+        check!(
+            parse_cmd,
+            "clone gmail gmail-alt",
+            "dup 'gmail' 'gmail-alt'"
+        );
+
+        check_err!("dup gmail", ParseError::ExpectedName(2));
+        check_err!("dup gmail a b", ParseError::IncompleteParse(3));
+        check_err!("clone gmail", ParseError::ExpectedName(2));
+    }
+
+    #[test]
+    fn test_where() {
+        check!(
+            parse_cmd,
+            "set gmail where user is zahash pass = gen(24)",
+            "set 'gmail' where user is 'zahash' pass = gen(24)"
+        );
+        check!(
+            parse_cmd,
+            "set gmail where user is zahash and url contains work pass = x",
+            "set 'gmail' where (user is 'zahash' and url contains 'work') pass = 'x'"
+        );
+        check!(
+            parse_cmd,
+            "del gmail where user is zahash",
+            "del 'gmail' where user is 'zahash'"
+        );
+        check!(
+            parse_cmd,
+            "del gmail where tag:work url",
+            "del 'gmail' where tag contains 'work' 'url'"
+        );
+        check!(
+            parse_cmd,
+            "copy gmail where url contains work pass 10",
+            "copy 'gmail' where url contains 'work' 'pass' 10"
+        );
+        // a record called 'where' has to be quoted
+        check!(parse_cmd, "set 'where' user = x", "set 'where' user = 'x'");
+
+        check!(
+            parse_cmd,
+            "copy gmail where user is x",
+            "copy 'gmail' where user is 'x'"
+        );
+
+        check_err!("set gmail where pass = x", ParseError::Expected(_, 4));
+    }
+
+    #[test]
+    fn test_cmd_allow_duplicate_names() {
+        check!(parse_cmd, "allow-duplicate-names on");
+        check!(parse_cmd, "allow-duplicate-names off");
+        check!(parse_cmd, "allow-duplicate-names");
+
+        check_err!(
+            "allow-duplicate-names maybe",
+            ParseError::ExpectedOneOf(_, 1)
+        );
+    }
+
+    #[test]
+    fn test_cmd_history_limit() {
+        check!(parse_cmd, "history limit set 10");
+        check!(parse_cmd, "history limit clear");
+        check!(parse_cmd, "history limit show");
+        // a record can still be called 'limit'
+        check!(parse_cmd, "history limit", "history 'limit'");
+
+        check_err!("history limit set 0", ParseError::IncompleteParse(2));
+        check_err!("history limit set", ParseError::IncompleteParse(2));
+    }
+
+    #[test]
+    fn test_cmd_history_diff() {
+        check!(parse_cmd, "history diff gmail", "history diff 'gmail'");
+        check!(
+            parse_cmd,
+            "reveal history diff 'old mail'",
+            "reveal history diff 'old mail'"
+        );
+        // a record can still be called 'diff'
+        check!(parse_cmd, "history diff", "history 'diff'");
+        check!(parse_cmd, "reveal history diff", "reveal history 'diff'");
+    }
+
+    #[test]
+    fn test_cmd_history_prune() {
+        check!(parse_cmd, "history clear gmail", "history clear 'gmail'");
+        check!(
+            parse_cmd,
+            "history prune gmail 5",
+            "history prune 'gmail' 5"
+        );
+        check!(
+            parse_cmd,
+            "history prune 'old mail' 1",
+            "history prune 'old mail' 1"
+        );
+        // records can still be called 'clear' and 'prune'
+        check!(parse_cmd, "history clear", "history 'clear'");
+        check!(parse_cmd, "history prune", "history 'prune'");
+
+        check_err!("history prune gmail 0", ParseError::IncompleteParse(2));
+        check_err!("history prune gmail", ParseError::IncompleteParse(2));
+    }
+
+    #[test]
+    fn test_cmd_rollback() {
+        check!(parse_cmd, "rollback 'gmail' 2");
+        check!(parse_cmd, "rollback 'rollback' 1");
+
+        check_err!("rollback gmail", ParseError::SyntaxError(2, _));
+        check_err!("rollback gmail 0", ParseError::SyntaxError(2, _));
+        check_err!("rollback gmail latest", ParseError::SyntaxError(2, _));
     }
-}
 
-impl<'text> Display for Is<'text> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} is '{}'", self.attr, self.value)
+    #[test]
+    fn test_cmd_tag() {
+        check!(parse_cmd, "tag 'gmail' 'work' 'social'");
+        check!(parse_cmd, "untag 'gmail' 'social'");
+        check!(parse_cmd, "tag 'tag' 'untag'");
+
+        check_err!("tag gmail", ParseError::SyntaxError(2, _));
+        check_err!("untag gmail", ParseError::SyntaxError(2, _));
+        check_err!("tag gmail 'work,social'", ParseError::SyntaxError(2, _));
+        check_err!("tag", ParseError::ExpectedName(1));
     }
-}
 
-impl<'text> From<Contains<'text>> for Filter<'text> {
-    fn from(value: Contains<'text>) -> Self {
-        Filter::Contains(value)
+    #[test]
+    fn test_cmd_copy() {
+        check!(parse_cmd, "copy 'gmail' 'pass'");
+        check!(parse_cmd, "copy 'gmail' 'pass' 10");
+        check!(parse_cmd, "copy totp 'gmail' 'totp' 5");
+        // the whole record
+        check!(parse_cmd, "copy 'gmail'");
+        check!(parse_cmd, "copy 'gmail' '10'");
+
+        check_err!("copy gmail pass 0", ParseError::IncompleteParse(3));
+        check_err!("copy gmail pass soon", ParseError::IncompleteParse(3));
     }
-}
 
-impl<'text> From<Matches<'text>> for Filter<'text> {
-    fn from(value: Matches<'text>) -> Self {
-        Filter::Matches(value)
+    #[test]
+    fn test_cmd_codec() {
+        check!(parse_cmd, "decode 'aws' 'key' b64");
+        check!(parse_cmd, "decode 'aws' 'key' hex reveal");
+        check!(parse_cmd, "encode 'aws' 'key' b64 reveal");
+        check!(parse_cmd, "encode 'aws' 'key' hex");
+        check!(parse_cmd, "decode 'decode' 'b64' b64");
+
+        check_err!("decode aws", ParseError::ExpectedAttr(2));
+        check_err!("decode aws key", ParseError::ExpectedOneOf(_, 3));
+        check_err!("encode aws key base32", ParseError::ExpectedOneOf(_, 3));
+        check_err!("decode aws key b64 show", ParseError::IncompleteParse(4));
     }
-}
 
-impl<'text> From<Is<'text>> for Filter<'text> {
-    fn from(value: Is<'text>) -> Self {
-        Filter::Cmp(value)
+    #[test]
+    fn test_cmd_import() {
+        check!(parse_cmd, "import '/home/suscobar/passwords.json'");
+        check!(parse_cmd, "import lastpass '/home/suscobar/lastpass.csv'");
+        check!(parse_cmd, "import 'lastpass'");
+        check!(
+            parse_cmd,
+            "import csv '/home/suscobar/Chrome Passwords.csv'"
+        );
+        check!(parse_cmd, "import '/home/suscobar/passwords.txt' skip");
+        check!(parse_cmd, "import 'f.txt' rename");
+        check!(parse_cmd, "import 'f.txt' overwrite", "import 'f.txt'");
+        check!(parse_cmd, "import keepass 'db.xml' skip");
+        check!(parse_cmd, "import 'skip'");
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use pretty_assertions::assert_eq;
+    #[test]
+    fn test_cmd_export() {
+        check!(parse_cmd, "export '/home/suscobar/passwords.txt'");
+        check!(parse_cmd, "export 'csv'");
+        check!(parse_cmd, "export csv '/home/suscobar/passwords.csv'");
+        check!(parse_cmd, "export json '/home/suscobar/passwords.json'");
+        check!(parse_cmd, "export 'json'");
+    }
 
-    macro_rules! check {
-        ($f:ident, $src:expr, $expected:expr) => {
-            let tokens = lex($src).expect("** LEX ERROR");
-            let (stmt, pos) = $f(&tokens, 0).expect("** Unable to parse statement");
-            assert_eq!(pos, tokens.len(), "** Unable to parse all Tokens\n{}", stmt);
-            let stmt = format!("{}", stmt);
-            assert_eq!(stmt, $expected);
-        };
-        ($f:ident, $src:expr) => {
-            check!($f, $src, $src)
-        };
+    #[test]
+    fn test_cmd_export_csv_fields() {
+        check!(parse_cmd, "export csv fields '/home/suscobar/fields.csv'");
+        // a csv export to a file called fields
+        check!(parse_cmd, "export csv 'fields'");
     }
 
     #[test]
-    fn test_cmd_set() {
-        check!(parse_cmd, "set 'gmail'");
+    fn test_export_outside_the_command() {
+        // a command only when it comes first, so records and attrs can be called export
+        check!(parse_cmd, "show export", "show 'export'");
+        check!(parse_cmd, "set export user = x", "set 'export' user = 'x'");
         check!(
             parse_cmd,
-            "set 'gmail' user = 'zahash' pass = 'supersecretpass' url = 'mail.google.com'"
+            "set gmail export = x",
+            "set 'gmail' export = 'x'"
         );
+        check!(parse_cmd, "show export is x", "show export is 'x'");
+        check!(parse_cmd, "del export", "del 'export'");
+    }
+
+    #[test]
+    fn test_cmd_gen() {
+        check!(parse_cmd, "gen 24");
+        check!(parse_cmd, "gen 24 nosymbols");
         check!(
             parse_cmd,
-            "set 'gmail' user = 'zahash' sensitive pass = 'supersecretpass' url = 'mail.google.com'"
+            "gen 8 noambiguous nodigits",
+            "gen 8 nodigits noambiguous"
         );
+
+        check_err!("gen", ParseError::SyntaxError(1, _));
+        check_err!("gen 0", ParseError::SyntaxError(1, _));
+        check_err!("gen long", ParseError::SyntaxError(1, _));
+        check_err!("gen 24 nouppercase", ParseError::IncompleteParse(2));
     }
 
     #[test]
-    fn test_cmd_del() {
-        check!(parse_cmd, "del 'gmail'");
-        check!(parse_cmd, "delete 'gmail'", "del 'gmail'");
-        check!(parse_cmd, "del 'gmail' 'user' 'pass'");
+    fn test_gen_len_limit() {
+        check!(parse_cmd, "gen 1024");
+        check!(parse_cmd, "set 'gmail' pass = gen(1024)");
+        check_err!(
+            "gen 1025",
+            ParseError::SyntaxError(1, "a generated password is at most 1024 characters")
+        );
+        check_err!("gen 99999999999999999999999", ParseError::SyntaxError(1, _));
+        check_err!("set gmail pass = gen(1025)", ParseError::IncompleteParse(2));
     }
 
     #[test]
-    fn test_cmd_show() {
-        check!(parse_cmd, "show all");
-        check!(parse_cmd, "show 'gmail'");
+    fn test_cmd_totp() {
+        check!(parse_cmd, "totp gmail", "totp 'gmail'");
         check!(
             parse_cmd,
-            "show user is 'a' or user contains 'a' and user matches 'a'",
-            "show (user is 'a' or (user contains 'a' and user matches 'a'))"
+            "copy totp gmail totp",
+            "copy totp 'gmail' 'totp'"
         );
+        check!(parse_cmd, "copy totp gmail", "copy 'totp' 'gmail'");
+
         check!(
             parse_cmd,
-            "show user is 'a' and user contains 'a' or user matches 'a'",
-            "show ((user is 'a' and user contains 'a') or user matches 'a')"
+            "totp gmail digits = 8",
+            "totp 'gmail' digits = 8"
+        );
+        check!(
+            parse_cmd,
+            "totp gmail period = 60 digits = 8",
+            "totp 'gmail' digits = 8 period = 60"
         );
+
+        check_err!("totp", ParseError::ExpectedName(1));
+        check_err!("totp gmail digits = 0", ParseError::SyntaxError(4, _));
+        check_err!("totp gmail period = soon", ParseError::SyntaxError(4, _));
+        check_err!("totp gmail digits 8", ParseError::IncompleteParse(2));
+        check_err!("copy totp gmail totp pass", ParseError::IncompleteParse(4));
     }
 
     #[test]
-    fn test_cmd_reveal() {
-        check!(parse_cmd, "reveal all");
-        check!(parse_cmd, "reveal 'gmail'");
-        check!(
-            parse_cmd,
-            "reveal user is 'a' or user contains 'a' and user matches 'a'",
-            "reveal (user is 'a' or (user contains 'a' and user matches 'a'))"
+    fn test_cmd_share() {
+        check!(parse_cmd, "share gmail", "share 'gmail'");
+        check!(parse_cmd, "share 'share'");
+
+        check_err!("share", ParseError::ExpectedName(1));
+    }
+
+    #[test]
+    fn test_cmd_gen_words() {
+        check!(parse_cmd, "gen words 6");
+        check!(parse_cmd, "gen words 6 sep '_'");
+        check!(parse_cmd, "gen words 6 sep -", "gen words 6");
+        check!(parse_cmd, "gen words 4 sep ' '");
+
+        check_err!("gen words", ParseError::SyntaxError(2, _));
+        check_err!("gen words 0", ParseError::SyntaxError(2, _));
+        check_err!("gen words 6 sep", ParseError::ExpectedValue(4));
+    }
+
+    #[test]
+    fn test_gen_words_limit() {
+        check!(parse_cmd, "gen words 64");
+        check!(parse_cmd, "set 'gmail' pass = gen(phrase 64)");
+        check_err!(
+            "gen words 65",
+            ParseError::SyntaxError(2, "a generated passphrase is at most 64 words")
+        );
+        check_err!(
+            "set gmail pass = gen(phrase 65)",
+            ParseError::IncompleteParse(2)
         );
+    }
+
+    #[test]
+    fn test_cmd_size_report() {
+        check!(parse_cmd, "size report");
+    }
+
+    #[test]
+    fn test_cmd_undo() {
+        check!(parse_cmd, "undo");
+        check_err!("undo 2", ParseError::IncompleteParse(1));
+    }
+
+    #[test]
+    fn test_cmd_redo() {
+        check!(parse_cmd, "redo");
+        check_err!("redo 2", ParseError::IncompleteParse(1));
+    }
+
+    #[test]
+    fn test_cmd_trash() {
+        check!(parse_cmd, "trash");
+        check!(parse_cmd, "undelete gmail", "undelete 'gmail'");
+        check!(parse_cmd, "undelete 'old mail'");
+        check!(parse_cmd, "empty trash");
+
+        check_err!("undelete", ParseError::ExpectedName(1));
+        check_err!("undelete all", ParseError::ExpectedName(1));
+        check_err!("undelete gmail 2", ParseError::IncompleteParse(2));
+        check_err!("empty", ParseError::Expected(Token::Value("trash"), 1));
+        check_err!("empty bin", ParseError::Expected(Token::Value("trash"), 1));
+        check_err!("trash gmail", ParseError::IncompleteParse(1));
+    }
+
+    #[test]
+    fn test_cmd_restore() {
+        check!(parse_cmd, "restore gmail 2", "restore 'gmail' 2");
+        check!(parse_cmd, "restore 'old mail' 0");
+
+        check_err!("restore", ParseError::ExpectedName(1));
+        check_err!("restore gmail two", ParseError::SyntaxError(2, _));
+        check_err!("restore gmail -1", ParseError::SyntaxError(2, _));
+        check_err!("restore all 1", ParseError::ExpectedName(1));
+    }
+
+    #[test]
+    fn test_cmd_restore_from_trash() {
+        check!(parse_cmd, "restore gmail", "undelete 'gmail'");
+        check!(parse_cmd, "restore 'old mail'", "undelete 'old mail'");
+    }
+
+    #[test]
+    fn test_cmd_merge() {
+        check!(parse_cmd, "merge '/home/suscobar/other.rg'");
+        check!(parse_cmd, "merge laptop", "merge 'laptop'");
+
+        check_err!("merge", ParseError::ExpectedValue(1));
+        check_err!("merge a b", ParseError::IncompleteParse(2));
+    }
+
+    #[test]
+    fn test_cmd_watch() {
+        check!(parse_cmd, "watch missing totp");
+        check!(parse_cmd, "watch all");
+        check!(parse_cmd, "watch gmail", "watch 'gmail'");
         check!(
             parse_cmd,
-            "reveal user is 'a' and user contains 'a' or user matches 'a'",
-            "reveal ((user is 'a' and user contains 'a') or user matches 'a')"
+            "watch url contains google or has totp",
+            "watch (url contains 'google' or has totp)"
         );
+        check!(parse_cmd, "unwatch missing totp");
+        check!(parse_cmd, "watches");
+
+        check_err!("watch", ParseError::SyntaxError(1, _));
+        check_err!("unwatch", ParseError::SyntaxError(1, _));
+        check_err!("watches all", ParseError::IncompleteParse(1));
     }
 
     #[test]
-    fn test_cmd_history() {
-        check!(parse_cmd, "history 'gmail'");
-        check!(parse_cmd, "reveal history 'gmail'");
+    fn test_cmd_check() {
+        check!(parse_cmd, "check duplicates");
+        check!(parse_cmd, "check kinds");
+
+        check_err!("check dupes", ParseError::ExpectedOneOf(_, 1));
+        check_err!("check duplicates now", ParseError::IncompleteParse(2));
     }
 
     #[test]
-    fn test_cmd_rename() {
-        check!(parse_cmd, "rename 'gmail' 'discord'");
+    fn test_cmd_check_names() {
+        check!(parse_cmd, "check names");
+        check_err!("check name", ParseError::ExpectedOneOf(_, 1));
     }
 
     #[test]
-    fn test_cmd_copy() {
-        check!(parse_cmd, "copy 'gmail' 'pass'");
+    fn test_display_quotes_keyword_attrs() {
+        check!(parse_cmd, "show 'all' is 'x'");
+        check!(parse_cmd, "show has 'prev'");
+        check!(parse_cmd, "show missing 'all'");
+        check!(
+            parse_cmd,
+            "show 'all' contains 'x' and 'is' >= '3'",
+            "show ('all' contains 'x' and 'is' >= '3')"
+        );
+        check!(parse_cmd, "show 'all' fuzzy 'x' 1");
+        check!(parse_cmd, "set 'x' 'all' = '1' sensitive 'prev' = '2'");
+        check!(parse_cmd, "set 'x' 'all' = gen(24)");
+        check!(parse_cmd, "set 'x' where 'all' is 'y' pass = '1'");
+        check!(parse_cmd, "show 'my user' startswith 'x'");
+        check!(parse_cmd, "show allx endswith 'x'");
+        check!(parse_cmd, "show user is 'all'");
     }
 
     #[test]
-    fn test_cmd_import() {
-        check!(parse_cmd, "import '/home/suscobar/passwords.json'");
+    fn test_quoted_keywords() {
+        check!(
+            parse_cmd,
+            "set 'all' 'user' = 'zahash'",
+            "set 'all' user = 'zahash'"
+        );
+        check!(parse_cmd, "show 'all'");
+        check!(parse_cmd, "reveal 'prev'");
+        check!(parse_cmd, "del 'all' 'prev'");
+        check!(parse_cmd, "copy 'all' 'prev'");
+        check!(parse_cmd, "history 'all'");
+        check!(parse_cmd, "reveal history 'prev'");
+        check!(parse_cmd, "rename 'all' 'prev'");
+
+        check_err!("set all", ParseError::ExpectedName(1));
+        check_err!("del prev", ParseError::ExpectedName(1));
+        check_err!("copy all pass", ParseError::ExpectedName(1));
     }
 
     #[test]
     fn test_query() {
         check!(parse_query, "all");
+        check!(parse_query, "(user is 'a')");
+        check!(
+            parse_query,
+            "(user is 'a' or user is 'b') and url is 'c'",
+            "(((user is 'a' or user is 'b')) and url is 'c')"
+        );
         check!(
             parse_query,
             "user is 'a' or user is 'a' and user is 'a'",
@@ -733,12 +3888,323 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_not() {
+        check!(parse_filter, "not (url contains 'google')");
+        check!(
+            parse_filter,
+            "not url contains 'google'",
+            "not (url contains 'google')"
+        );
+        check!(
+            parse_filter,
+            "not not user is 'zahash'",
+            "not (not (user is 'zahash'))"
+        );
+        check!(parse_filter, "not all", "not (all)");
+        check!(parse_filter, "not (all)");
+        check!(
+            parse_cmd,
+            "show user is 'zahash' and not (pass contains '123')",
+            "show (user is 'zahash' and not (pass contains '123'))"
+        );
+        check!(
+            parse_cmd,
+            "show not user is 'a' or user is 'b'",
+            "show (not (user is 'a') or user is 'b')"
+        );
+    }
+
+    #[test]
+    fn test_not_as_value() {
+        check!(parse_cmd, "set not not = x", "set 'not' not = 'x'");
+        check!(parse_cmd, "show not", "show 'not'");
+        check!(parse_cmd, "del not", "del 'not'");
+        check!(parse_filter, "not is x", "not is 'x'");
+        check!(parse_filter, "not not is x", "not (not is 'x')");
+    }
+
+    #[test]
+    fn test_has_missing() {
+        check!(parse_filter, "has totp");
+        check!(parse_filter, "missing url");
+        check!(parse_filter, "lacks pass", "missing pass");
+        check!(
+            parse_cmd,
+            "show has note and lacks pass",
+            "show (has note and missing pass)"
+        );
+        check!(parse_cmd, "show lacks", "show 'lacks'");
+        check!(
+            parse_cmd,
+            "show has totp and not missing url or missing user",
+            "show ((has totp and not (missing url)) or missing user)"
+        );
+        check_err!("show has .", ParseError::NameAlwaysExists(2));
+        check_err!("show missing .", ParseError::NameAlwaysExists(2));
+        check_err!("show lacks .", ParseError::NameAlwaysExists(2));
+        check_err!("show url is x and has", ParseError::ExpectedAttr(6));
+    }
+
+    #[test]
+    fn test_has_missing_as_values() {
+        check!(parse_cmd, "show has", "show 'has'");
+        check!(parse_cmd, "show missing", "show 'missing'");
+        check!(parse_cmd, "set has missing = x", "set 'has' missing = 'x'");
+        check!(parse_cmd, "del has", "del 'has'");
+        check!(parse_filter, "has has");
+        check!(parse_filter, "missing is x", "missing is 'x'");
+    }
+
+    #[test]
+    fn test_tag_contains() {
+        check!(parse_filter, "tag contains 'work'");
+        check!(parse_filter, "tag:work", "tag contains 'work'");
+        check!(
+            parse_cmd,
+            "show tag:work and not tag contains social",
+            "show (tag contains 'work' and not (tag contains 'social'))"
+        );
+        check!(parse_cmd, "show 'tag:work'", "show tag contains 'work'");
+        // without a tag it is just a name
+        check!(parse_cmd, "show 'tag:'");
+        check_err!("show tag contains", ParseError::ExpectedValue(3));
+        check!(parse_filter, "tagged work", "tag contains 'work'");
+        check!(
+            parse_cmd,
+            "show tagged work and url contains google",
+            "show (tag contains 'work' and url contains 'google')"
+        );
+        // like `tag`, on its own it is the name of a record
+        check!(parse_cmd, "show tagged", "show 'tagged'");
+    }
+
     #[test]
     fn test_filter() {
         check!(parse_filter, "url contains 'github'");
+        check!(parse_filter, "url startswith 'https://'");
+        check!(parse_filter, "user endswith '@gmail.com'");
         check!(parse_filter, "user matches '[A-Z]+'");
         check!(parse_filter, "user like '[A-Z]+'", "user matches '[A-Z]+'");
+        check!(parse_filter, "user cs contains 'Bob'");
+        check!(parse_filter, "user cs matches '^[A-Z]+$'");
+        check!(parse_filter, "url cs startswith 'HTTPS://'");
+        check!(parse_filter, "user cs endswith '@Corp.com'");
+        check!(parse_filter, ". startswith git", ". startswith 'git'");
+        check!(parse_filter, ". endswith .com", ". endswith '.com'");
+        check!(
+            parse_cmd,
+            "show url startswith https and not . endswith .com or user cs endswith Bob",
+            "show ((url startswith 'https' and not (. endswith '.com')) or user cs endswith 'Bob')"
+        );
+        check_err!("show url startswith", ParseError::ExpectedValue(3));
+        check!(parse_filter, ". fuzzy gitub 2", ". fuzzy 'gitub' 2");
+        check!(parse_filter, ". fuzzy gitub", ". fuzzy 'gitub' 1");
+        check!(
+            parse_cmd,
+            "show user fuzzy zahsh and . fuzzy gitub 3",
+            "show (user fuzzy 'zahsh' 1 and . fuzzy 'gitub' 3)"
+        );
+        check_err!("show . fuzzy", ParseError::ExpectedValue(3));
+        check_err!("show . fuzzy gitub 0", ParseError::SyntaxError(4, _));
+        check_err!("show . fuzzy gitub -1", ParseError::SyntaxError(4, _));
+        check_err!("show . fuzzy gitub 1.5", ParseError::SyntaxError(4, _));
+        check_err!("show url cs endswith", ParseError::ExpectedValue(4));
+        check!(
+            parse_filter,
+            "user cs like '[A-Z]+'",
+            "user cs matches '[A-Z]+'"
+        );
+        check!(parse_filter, "cs contains 'x'");
+        check!(
+            parse_cmd,
+            "show user cs contains Bob and url contains github",
+            "show (user cs contains 'Bob' and url contains 'github')"
+        );
         check!(parse_filter, "user is 'zahash'");
         check!(parse_filter, "(user is 'zahash')");
+        check!(parse_filter, "port > 8000", "port > '8000'");
+        check!(parse_filter, "port < '8000'");
+        check!(parse_filter, "amount >= '-1.5'");
+        check!(parse_filter, "amount <= '100'");
+        check!(parse_filter, "seats gt 10", "seats > '10'");
+        check!(parse_filter, "seats lt 10", "seats < '10'");
+        check!(parse_filter, "seats gte 10", "seats >= '10'");
+        check!(parse_filter, "seats lte 10", "seats <= '10'");
+    }
+
+    #[test]
+    fn test_startswith_endswith_outside_filters() {
+        // only comparisons right after an attr, elsewhere they are names and attrs
+        check!(parse_cmd, "show startswith", "show 'startswith'");
+        check!(parse_cmd, "show endswith", "show 'endswith'");
+        check!(parse_cmd, "set gmail startswith = x", "set 'gmail' startswith = 'x'");
+        check!(parse_cmd, "set endswith user = x", "set 'endswith' user = 'x'");
+        check!(parse_filter, "startswith endswith x", "startswith endswith 'x'");
+        check!(parse_filter, "endswith is x", "endswith is 'x'");
+    }
+
+    #[test]
+    fn test_comparison_symbols_in_values() {
+        check!(
+            parse_cmd,
+            "set gmail pass = <abc note = >1",
+            "set 'gmail' pass = '<abc' note = '>1'"
+        );
+        check!(parse_filter, "pass is <abc", "pass is '<abc'");
+        check!(parse_filter, ">1 > 1", ">1 > '1'");
+    }
+
+    #[test]
+    fn test_compare_words_as_values() {
+        check!(parse_cmd, "set gt lt = gte", "set 'gt' lt = 'gte'");
+        check!(parse_cmd, "show lte", "show 'lte'");
+        check!(parse_filter, "gt gt 1", "gt > '1'");
+        check!(parse_filter, "lt is gte", "lt is 'gte'");
+        check_err!(
+            "show port > abc",
+            ParseError::SyntaxError(3, "not a number")
+        );
+        check_err!("show port lt 'not a port'", ParseError::SyntaxError(3, _));
+    }
+
+    #[test]
+    fn test_field_count() {
+        check!(parse_filter, "fields < 2");
+        check!(parse_filter, "fields gte 0", "fields >= 0");
+        check!(parse_filter, "fields <= '3'", "fields <= 3");
+        check!(
+            parse_cmd,
+            "show fields lt 2 or not fields > 5",
+            "show (fields < 2 or not (fields > 5))"
+        );
+        // without a count it is an attr called 'fields'
+        check!(parse_filter, "fields > 1.5", "fields > '1.5'");
+        check!(parse_filter, "fields > -1", "fields > '-1'");
+        check!(parse_filter, "fields is 'a'");
+    }
+
+    #[test]
+    fn test_kind() {
+        check!(parse_cmd, "set 'card' number = '4111' type pin");
+        check!(
+            parse_cmd,
+            "set card number = 4111 type number expires 90 user = x",
+            "set 'card' number = '4111' expires 90 type number user = 'x'"
+        );
+        check!(
+            parse_cmd,
+            "set where has pin pin = 1 type pin",
+            "set where has pin pin = '1' type pin"
+        );
+        // anything else is the attr `type`
+        check!(parse_cmd, "set 'card' number = '4111' type = 'pin'");
+        check_err!(
+            "set card number = 4111 type visa",
+            ParseError::IncompleteParse(5)
+        );
+        check_err!(
+            "set card number = 4111 type pin type url",
+            ParseError::IncompleteParse(7)
+        );
+    }
+
+    #[test]
+    fn test_expires() {
+        check!(parse_cmd, "set 'gmail' sensitive pass = 'x' expires 90");
+        check!(parse_cmd, "set 'gmail' pass = 'x' expires never url = 'y'");
+        check!(parse_cmd, "set 'gmail' pass = gen(24) expires 30");
+        check!(
+            parse_cmd,
+            "set where expired pass = x expires 90",
+            "set where expired pass = 'x' expires 90"
+        );
+        check_err!(
+            "set gmail pass = x expires soon",
+            ParseError::Date(DateError::NotASpan("soon"), 6)
+        );
+        check_err!(
+            "set gmail pass = x expires 0",
+            ParseError::Date(DateError::NotASpan("0"), 6)
+        );
+
+        check!(parse_cmd, "show expired");
+        check!(
+            parse_cmd,
+            "show expired and user is x",
+            "show (expired and user is 'x')"
+        );
+        // still an attr when it is compared
+        check!(parse_filter, "expired is 'x'");
+    }
+
+    #[test]
+    fn test_expires_attr() {
+        check!(parse_cmd, "set 'x' a = '1' expires = '90'");
+        check!(parse_cmd, "set 'x' a = '1' expires = 'never'");
+        check!(parse_cmd, "set 'x' expires = '90' a = '1' expires 30");
+        check!(
+            parse_cmd,
+            "set x a = 1 expires 30 expires = 90",
+            "set 'x' a = '1' expires 30 expires = '90'"
+        );
+    }
+
+    #[test]
+    fn test_expires_forms() {
+        check!(parse_cmd, "set 'gmail' pass = 'x' expires 6m");
+        check!(
+            parse_cmd,
+            "set gmail pass = x expires 12weeks",
+            "set 'gmail' pass = 'x' expires 12w"
+        );
+        check!(
+            parse_cmd,
+            "set gmail pass = x expires 90d",
+            "set 'gmail' pass = 'x' expires 90"
+        );
+        check!(parse_cmd, "set 'gmail' pass = 'x' expires '2025-06-01'");
+        check!(
+            parse_cmd,
+            "set gmail pass = x expires 2025-06-01T09:00:00+02:00 type pin",
+            "set 'gmail' pass = 'x' expires '2025-06-01T09:00:00+02:00' type pin"
+        );
+        check_err!(
+            "set gmail pass = x expires 06/01/2025",
+            ParseError::Date(DateError::Ambiguous("06/01/2025"), 6)
+        );
+    }
+
+    #[test]
+    fn test_near_miss() {
+        check_err!(
+            "show gmail extra garbage here",
+            ParseError::ExpectedFilter(2)
+        );
+        check_err!("show gmail and", ParseError::ExpectedFilter(2));
+        check_err!("show (gmail extra)", ParseError::ExpectedFilter(3));
+        check_err!("reveal gmail extra", ParseError::ExpectedFilter(2));
+        check_err!("show user contains", ParseError::ExpectedValue(3));
+        check_err!("show user is", ParseError::ExpectedValue(3));
+        check_err!("show user matches", ParseError::ExpectedValue(3));
+        check_err!("show user like '['", ParseError::InvalidRegex(3));
+        check_err!("show user is a and", ParseError::SyntaxError(5, _));
+        check_err!(
+            "show user is a or url contains",
+            ParseError::ExpectedValue(7)
+        );
+        check_err!(
+            "show (user is a",
+            ParseError::Expected(Token::Symbol(")"), 5)
+        );
+        check_err!("show user is a garbage", ParseError::IncompleteParse(4));
+        check_err!("copy", ParseError::ExpectedName(1));
+        check_err!("set", ParseError::ExpectedName(1));
+        check_err!(
+            "set gmail user = a user = b",
+            ParseError::DuplicateAssignments("user", 8)
+        );
+        check_err!("gmail", ParseError::SyntaxError(0, _));
+        check_err!("import 'f.txt' merge", ParseError::IncompleteParse(2));
     }
 }
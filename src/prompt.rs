@@ -1,10 +1,29 @@
+use crate::agent::{self, Agent, RateLimit};
+use crate::browse;
+use crate::clipboard;
+use crate::clipboard::Osc52;
+use crate::config::{Config, KdfArg};
 use crate::crypt::*;
 use crate::eval::*;
+use crate::keyring::{Keyring, SecretService};
+use crate::lex::lex;
+use crate::parse::{parse, Cmd};
+use crate::render::*;
 use crate::store::Store;
 
+use std::io::IsTerminal;
+use std::num::{NonZeroU32, NonZeroU64};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
 use anyhow::Context;
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+use ignorant::Ignore;
 use rustyline::error::ReadlineError;
+use zeroize::{Zeroize, Zeroizing};
 
 const LOGO: &str = r#"
 ██████   ██████  ██    ██  █████  ██           ██████  ██    ██  █████  ██████  ██████  
@@ -19,16 +38,76 @@ Add, Update:
     set gmail user = sussolini sensitive pass = 'use single quote for spaces' url = mail.google.sus
     set gmail sensitive pass = updatedpassword user = updated_user
 
-Delete whole record: 
+Update every record that matches a query, each keeping its own history:
+    set where url contains oldcorp.com user = 'me@newcorp.com'
+    updated 3 records
+
+Weak sensitive values get a strength warning (turn off with --no-warn):
+    set gmail sensitive pass = hunter2
+    ⚠ pass: strength 0/4 (very weak) – This is similar to a commonly used password.
+
+Sensitive values with surrounding whitespace, a trailing newline or control characters (usually
+pasted by accident) are pointed out without showing them, and trimmed if you say so:
+    set gmail sensitive pass = 'vK7#qz!Lw2 '
+    ⚠ pass: 11 characters with leading or trailing whitespace. trim it? [y/N]
+
+Expiry -- how long a value is good for: days (90), weeks (12w), months (6m), years (1y) or a date
+(2025-06-01). kept when the value changes, until 'expires never'.
+'expired' finds the records with a value older than that:
+    set gmail sensitive pass = hunter2 expires 90
+    set bank pin = 1234 expires 2025-06-01
+    set gmail pass = gen(24)
+    show expired
+
+Kinds -- 'totp' and 'otp' are otp secrets, 'url' a url, 'email' an email address and 'pin' digits.
+other fields can be given a kind (pin, otp, url, email or number), which is kept when the value
+changes. values that don't look like their kind get a warning:
+    set card number = '4111 1111 1111 1111' type pin
+    set gmail email = zahash
+    ⚠ email: doesn't look like an email address
+
+Delete whole record into the trash (shows it and how much history goes with it, and asks first.
+'a' stops asking for the session, so does --yes):
     del gmail
+    'gmail' pass=***** user='sussolini' — 3 earlier versions in its history go with it
 
 Delete fields: 
     del gmail url pass
 
+Delete every record that matches a query into the trash, listing what went (several need a typed
+'yes' first, or --yes. with --exec only --yes allows it):
+    del user contains zahash
+    del where url contains example.org
+    del all
+
+Trash -- deleted records stay there until it is emptied. restore (or undelete) brings the latest one back:
+    trash
+    restore gmail
+    undelete gmail
+    empty trash
+
 Show -- replaces sensitive values with *****:
     show all
     show gmail
     show user is sussolini and (pass contains sus or url matches '.*com')
+    show url startswith https and user endswith '@gmail.com'
+    show port >= 8000 and port lt 9000 and mfa is true
+    show not (url contains google)
+    show has totp or missing url
+    show has note and lacks pass
+    show fields lt 2  (records with fewer than 2 fields, tags aside)
+    show user cs contains Bob  (contains, startswith, endswith and matches ignore case unless 'cs' comes first)
+    show . fuzzy gitub 2  (names at most 2 typos from gitub, 1 when left out)
+
+Sort by an attr instead of the name and show at most some of them (also for reveal):
+    show all sort user limit 10
+    show has port sort port
+
+Show as json (also for every show and reveal with --json):
+    show --json all
+    reveal --json gmail
+
+Show, Reveal and History as a table with one column per attr: --format table
 
 Show (filter by name):
     show . contains gmail
@@ -36,32 +115,809 @@ Show (filter by name):
 Reveal -- works exactly like Show but does not respect sensitivity
     reveal user is sussolini and (pass contains sus or url matches '.*com')
 
+List only the names of the records (all of them, or those that match):
+    list
+    list url contains google
+
+Count the records that match, without showing them:
+    count all
+    count url contains google and user is sussolini
+
 History -- show changes made overtime:
     history gmail
     reveal history gmail
 
+History of only what changed in each entry, fields added (+), removed (-) and changed (~):
+    history diff gmail
+    reveal history diff gmail
+
+Browse the history an entry at a time (↑/↓ move, enter expands, d diffs against the entry
+before, r reveals after asking for the master password once a minute, q quits.
+without a terminal it is listed like 'history'):
+    browse history gmail
+
+Limit the history entries kept per record (50 unless changed. saved with the vault,
+older entries are dropped the next time a record changes):
+    history limit set 20
+    history limit clear
+    history limit show
+
+Drop old history entries of one record (clear keeps only the current state):
+    history prune gmail 5
+    history clear gmail
+
+Attribute your changes in the history to a name (for vaults shared by a team):
+    --author alice  (or set ROYALGUARD_AUTHOR)
+
+Rollback -- restore a record to an entry of its history (1 is the newest):
+    rollback gmail 2
+
+Restore a record to the entry of its history with the index 'history' shows ([0] is the newest).
+the same as rollback, which counts from 1 instead (restore gmail 1 is rollback gmail 2):
+    restore gmail 1
+
+Watch a query for the rest of the session. a line follows every change that makes a record
+start (+) or stop (-) matching or changes one that matches (~):
+    watch missing totp
+    watches
+    unwatch missing totp
+
+Undo the latest change (set, del, rename, import, ... up to the last 20 of this session):
+    undo
+
+Redo what undo took back (until the next change):
+    redo
+
 Rename:
     rename gmail gmail2
 
-Copy field to clipboard:
+Rename every record whose name matches a regex, replacing the match ($1 for a group.
+records whose new name is taken are left as they are. --dry-run only shows the renames):
+    rename all '\.com$' .io
+    rename --dry-run all '^(.*)-old$' $1
+
+Rename a field of a record (keeps its value and sensitivity):
+    rename gmail pass password
+
+Duplicate a record with all its fields (its history starts over). clone does the same:
+    dup gmail gmail_backup
+    clone gmail gmail-alt
+
+Several records with the same name, told apart by their fields (saved with the vault.
+a bare name that matches more than one lists them and refuses, except in show and reveal):
+    allow-duplicate-names on
+    set gmail where user is work@x.com pass = gen(24)
+    copy gmail where user is work@x.com pass
+    del gmail where user is old@x.com
+    rename gmail where user is work@x.com gmail-work
+
+Tags -- kept in the reserved '_tags' field and shown in brackets:
+    tag gmail work social
+    untag gmail social
+    show tag contains work
+    show tag:work
+    show tagged work and url contains google
+
+Copy field to clipboard (cleared after 30s or when quitting unless something else was copied since.
+change with --clear-after or per copy in seconds):
     copy gmail pass
+    copy gmail pass 10
+The whole record, revealed, a field per line:
+    copy gmail
+Without a system clipboard (over ssh) the value is copied through the terminal with an
+OSC 52 escape instead, which isn't cleared automatically (--osc52 always|never to force it)
+
+TOTP code of the 'totp' field, copied until it expires (values starting with otpauth:// are
+stored as sensitive unless --plain-otpauth. their digits, period and algorithm are used):
+    set gmail totp = 'otpauth://totp/Google:sussolini?secret=JBSWY3DPEHPK3PXP'
+    totp gmail
+
+Plain base32 secrets work too, in 'totp', 'otp' or a field of type otp (6 digits every 30s unless overridden):
+    set github sensitive otp = 'JBSW Y3DP EHPK 3PXP'
+    totp github
+    totp github digits = 8 period = 60
+
+Copy the current TOTP code of a field instead of its value:
+    copy totp gmail totp
+
+Share one record: copies it encrypted with a 4 word passphrase that is shown only once.
+Send the copied text and the passphrase (preferably over different channels):
+    share gmail
+
+Receive a shared record from the clipboard (asks for the passphrase). a taken name gets a -2:
+    receive
+
+Copy the base64 or hex decoded value of a field (or encode it), shown instead with 'reveal':
+    decode aws key b64
+    decode aws token hex reveal
+    encode aws key b64
 
-Import:
+Generate a random password of up to 1024 characters (letters, digits and symbols unless excluded):
+    gen 24
+    gen 24 nosymbols
+    gen 24 nodigits noambiguous
+
+Generate a passphrase of up to 64 random words (separated by '-' unless told otherwise).
+the words come from a 2048 word list, so each one adds 11 bits (6 words are 66 bits):
+    gen words 6
+    gen words 6 sep '_'
+
+Generate sensitive values while setting (also in import files, one per line):
+    set gmail user = sussolini pass = gen(24)
+    set gmail pass = gen(phrase 5)
+
+Import (large imports ask before committing. see --help for the import safeguards.
+CTRL-C cancels an import before anything is imported):
     import 'path/to/file.txt'
 
+Import without touching existing records, or import them as 'gmail-2', 'gmail-3', ...
+(works after any of the imports below too):
+    import 'path/to/file.txt' skip
+    import 'path/to/file.txt' rename
+
 Importing requires the below data format. Each line being a new record
 'gmail' user = 'joseph ballin' sensitive pass = 'ни шагу назад, товарищи!'
 'discord' user = 'pablo susscobar' pass = 'plata o plomo'
 
-Change Master Password: chmpw
+Import from a LastPass csv export:
+    import lastpass 'path/to/lastpass.csv'
+
+Import from a Chrome or Firefox csv export:
+    import csv 'path/to/passwords.csv'
+
+Import from a KeePass 2.x xml export. entries with the title of an earlier one get a -2:
+    import keepass 'path/to/db.xml'
+
+Export (same format as import, asks before overwriting an existing file.
+CTRL-C cancels any export before the file is written):
+    export 'path/to/file.txt'
+
+Export to CSV (sensitive values are written in plain text), a row per record or a row per field:
+    export csv 'path/to/file.csv'
+    export csv fields 'path/to/file.csv'
+
+Export to JSON (the current fields of every record, sensitive values in plain text):
+    export json 'path/to/file.json'
+
+Merge another vault file (asks for its master password). records with the same id or name
+get the fields of both, the most recently set value wins where they differ.
+CTRL-C while the file is decrypted cancels before anything is merged:
+    merge 'path/to/other'
+
+Size report -- which records take up the most space in the vault file and what pruning their history saves:
+    size report
+
+Check for the same value in more than one field (sensitive values stay masked):
+    check duplicates
+
+Check for values that don't look like their kind (set with 'type', or told by the attr):
+    check kinds
+
+Check for records named like a keyword ('all', 'prev'..), which only work in quotes:
+    check names
+
+The prompt saves and locks after 5 minutes without input (change with --idle-timeout,
+0 never locks). Enter the master password to unlock.
+
+Every save first copies the vault (still encrypted) to <file>.bak.<timestamp> next to it,
+keeping the newest 5 (change with --backups, 0 makes none).
+
+Change Master Password (one weaker than --min-master-pass-strength, 2/4 unless set, is refused.
+a weak one is warned about and used only if confirmed, as when creating a vault): chmpw
+
+Require a key file besides the master password (a vault without one needs it from its
+next save on. it can't be opened without the key file, so keep a copy somewhere safe):
+    --key-file ~/vault.key
+
+Keep the master password in the OS keyring (needs secret-tool) and open without typing it
+from then on. a kept password that no longer opens the vault is forgotten:
+    --keyring
+
+Open without being able to change anything, to look something up on a shared screen
+(commands that would change the vault are refused and nothing is saved, not even on quitting):
+    --read-only
+A vault saved by a newer royalguard that has something this one doesn't know of (and would drop
+on saving) only opens this way. the error says which version it needs.
+
+Re-encrypt with another key derivation function (tune with --iterations and --argon2-*-cost):
+    rekey argon2id
+    rekey pbkdf2
+
+Vaults over 1 KiB are compressed before they are encrypted (--compress always does it,
+--no-compress never). compress or decompress the vault from now on and save it:
+    compress
+    decompress
+
+Settings used every time go in ~/.config/royalguard/config.toml (or --config). options given
+on the command line or in environment variables win over it:
+    fpath = '/home/sussolini/vaults/main'
+    history_limit = 20
+    kdf = 'argon2id'
+    pbkdf2_iterations = 200000
+    auto_save = false  (quitting asks whether to save instead. locking still saves)
+    author = 'alice'
+The settings this session runs with: config show
+
+Tab completes commands and, after the commands that take one, record names
+
+Usage of a single command: help <command> (for example 'help show')
 "#;
 
+/// usage of each command by the keyword it starts with. shown by 'help <topic>'
+/// and after a command that starts with the keyword fails to parse
+const TOPICS: &[(&str, &str)] = &[
+    (
+        "set",
+        r#"set <name> {where <query>}? {sensitive? <attr> = <value> {expires <span | date | never>}? {type <kind>}?}*
+set where <query> {sensitive? <attr> = <value> {expires <span | date | never>}? {type <kind>}?}*
+    set gmail user = sussolini sensitive pass = 'use single quote for spaces'
+    set gmail sensitive pass = updatedpassword
+    set gmail user = sussolini pass = gen(24)
+    set gmail pass = gen(phrase 5)
+    set gmail where user is sussolini pass = gen(24)
+    set where url contains oldcorp.com user = 'me@newcorp.com'
+    set gmail sensitive pass = hunter2 expires 90
+    set bank pin = 1234 expires 6m
+    set card number = '4111 1111 1111 1111' type pin"#,
+    ),
+    (
+        "del",
+        r#"del <name> {where <query>}? {<attr>}*
+del {all | <query>}
+    del gmail
+    del gmail url pass
+    del gmail where user is sussolini
+    del user contains zahash
+    del where url contains example.org
+    del all"#,
+    ),
+    (
+        "trash",
+        r#"trash
+    trash"#,
+    ),
+    (
+        "undelete",
+        r#"undelete <name>
+    undelete gmail"#,
+    ),
+    (
+        "restore",
+        r#"restore <name> (the latest record of that name in the trash)
+restore <name> <index> (the index 'history' shows, 0 is the newest entry. rollback counts from 1)
+    restore gmail
+    restore gmail 1"#,
+    ),
+    (
+        "empty",
+        r#"empty trash
+    empty trash"#,
+    ),
+    (
+        "show",
+        r#"show --json? {all | <name> | <query>} {sort <attr>}? {limit <count>}?
+    show all
+    show gmail
+    show user is sussolini and (pass contains sus or url matches '.*com')
+    show port >= 8000 and port lt 9000
+    show not (url contains google)
+    show has totp or missing url
+    show has note and lacks pass
+    show fields lt 2
+    show expired
+    show user cs contains Bob
+    show . fuzzy gitub 2
+    show . contains gmail
+    show tag contains work
+    show tag:work
+    show tagged work
+    show all sort user limit 10
+    show --json all"#,
+    ),
+    (
+        "reveal",
+        r#"reveal --json? {all | <name> | <query>} {sort <attr>}? {limit <count>}?
+reveal history <name>
+    reveal gmail
+    reveal user is sussolini and pass contains sus
+    reveal --json gmail
+    reveal history gmail"#,
+    ),
+    (
+        "list",
+        r#"list {all | <name> | <query>}?
+    list
+    list url contains google"#,
+    ),
+    (
+        "count",
+        r#"count {all | <name> | <query>}
+    count all
+    count gmail
+    count url contains google and user is sussolini"#,
+    ),
+    (
+        "history",
+        r#"history <name>
+history diff <name>
+history limit {set <count> | clear | show}
+history prune <name> <count>
+history clear <name>
+    history gmail
+    history diff gmail
+    history limit set 20
+    history limit clear
+    history limit show
+    history prune gmail 5
+    history clear gmail"#,
+    ),
+    (
+        "rollback",
+        r#"rollback <name> <entry> (1 is the newest entry of the history. restore counts from 0)
+    rollback gmail 2"#,
+    ),
+    (
+        "rename",
+        r#"rename <name> {where <query>}? <new name>
+rename <name> {where <query>}? <attr> <new attr>
+rename --dry-run? all <regex> <replacement>
+    rename gmail gmail2
+    rename 'old mail' 'new mail'
+    rename gmail where user is sussolini gmail-work
+    rename gmail pass password
+    rename all '\.com$' .io
+    rename --dry-run all '^(.*)-old$' $1"#,
+    ),
+    (
+        "watch",
+        r#"watch <query>
+    watch missing totp
+    watch url contains google"#,
+    ),
+    (
+        "unwatch",
+        r#"unwatch <query>
+    unwatch missing totp"#,
+    ),
+    (
+        "watches",
+        r#"watches
+    watches"#,
+    ),
+    (
+        "undo",
+        r#"undo
+    undo"#,
+    ),
+    (
+        "redo",
+        r#"redo
+    redo"#,
+    ),
+    (
+        "dup",
+        r#"dup <name> <new name>
+    dup gmail gmail_backup"#,
+    ),
+    (
+        "clone",
+        r#"clone <name> <new name>
+    clone gmail gmail-alt"#,
+    ),
+    (
+        "allow-duplicate-names",
+        r#"allow-duplicate-names {on | off}?
+    allow-duplicate-names on
+    allow-duplicate-names off
+    allow-duplicate-names"#,
+    ),
+    (
+        "copy",
+        r#"copy {totp}? <name> {where <query>}? {<attr> <seconds until cleared>?}?
+    copy gmail pass
+    copy gmail pass 10
+    copy gmail
+    copy gmail where user is sussolini pass
+    copy totp gmail totp"#,
+    ),
+    (
+        "totp",
+        r#"totp <name> {digits = <digits> | period = <seconds>}*
+    totp gmail
+    totp github digits = 8 period = 60"#,
+    ),
+    (
+        "share",
+        r#"share <name>
+    share gmail"#,
+    ),
+    (
+        "decode",
+        r#"decode <name> <attr> {b64 | hex} reveal?
+    decode aws key b64
+    decode aws token hex reveal"#,
+    ),
+    (
+        "encode",
+        r#"encode <name> <attr> {b64 | hex} reveal?
+    encode aws key b64
+    encode aws token hex reveal"#,
+    ),
+    (
+        "tag",
+        r#"tag <name> {<tag>}+
+    tag gmail work social"#,
+    ),
+    (
+        "untag",
+        r#"untag <name> {<tag>}+
+    untag gmail social"#,
+    ),
+    (
+        "import",
+        r#"import {lastpass | csv | keepass}? <path> {skip | overwrite | rename}?
+    import 'path/to/file.txt'
+    import 'path/to/file.txt' skip
+    import lastpass 'path/to/lastpass.csv'
+    import csv 'path/to/passwords.csv' rename
+    import keepass 'path/to/db.xml'"#,
+    ),
+    (
+        "export",
+        r#"export {csv fields? | json}? <path>
+    export 'path/to/file.txt'
+    export csv 'path/to/file.csv'
+    export csv fields 'path/to/file.csv'
+    export json 'path/to/file.json'"#,
+    ),
+    (
+        "merge",
+        r#"merge <path>
+    merge 'path/to/other'"#,
+    ),
+    (
+        "size",
+        r#"size report
+    size report"#,
+    ),
+    (
+        "check",
+        r#"check {duplicates | kinds | names}
+    check duplicates
+    check kinds
+    check names"#,
+    ),
+    (
+        "gen",
+        r#"gen <length> {nosymbols | nodigits | noambiguous}*
+gen words <count> {sep <separator>}?
+    gen 24
+    gen 24 nodigits noambiguous
+    gen words 6
+    gen words 6 sep '_'"#,
+    ),
+];
+
+fn topic(keyword: &str) -> Option<&'static str> {
+    TOPICS
+        .iter()
+        .find(|(k, _)| *k == keyword)
+        .map(|(_, usage)| *usage)
+}
+
+/// the usage of the command that `line` was meant to be
+fn usage_of(line: &str) -> Option<&'static str> {
+    topic(line.split_whitespace().next()?)
+}
+
 /// Royal Guard
 #[derive(Parser)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// settings file read for defaults of the options below.
+    /// defaults to royalguard/config.toml in the config directory (~/.config on linux)
+    #[arg(long, env = "ROYALGUARD_CONFIG")]
+    config: Option<PathBuf>,
+
     /// encrypted data filepath
     #[arg(short, long)]
     fpath: Option<String>,
+
+    /// seconds after which copied values are cleared from the clipboard
+    #[arg(long, default_value_t = 30)]
+    clear_after: u64,
+
+    /// copy through the terminal with an OSC 52 escape sequence. 'auto' only does it
+    /// when there is no system clipboard, like over ssh
+    #[arg(long, value_enum, default_value_t = Osc52::Auto)]
+    osc52: Osc52,
+
+    /// longest line in bytes accepted from an imported file
+    #[arg(long, default_value_t = ImportLimits::default().max_line_len)]
+    max_import_line_len: usize,
+
+    /// most records a single import may add or update
+    #[arg(long, default_value_t = ImportLimits::default().max_records)]
+    max_import_records: usize,
+
+    /// keep control characters in imported names, attrs and values
+    #[arg(long)]
+    keep_control_chars: bool,
+
+    /// ask before committing an import with more records than this
+    #[arg(long, default_value_t = 500)]
+    confirm_import_above: usize,
+
+    /// don't ask before deleting a whole record with 'del' or several with 'del <query>'
+    #[arg(short, long, alias = "force")]
+    yes: bool,
+
+    /// name that the changes made in this session are attributed to in the history.
+    /// kept on this machine, never saved to the vault
+    #[arg(long, env = "ROYALGUARD_AUTHOR")]
+    author: Option<String>,
+
+    /// most history entries kept per record. saved with the vault,
+    /// which keeps 50 unless told otherwise (see 'history limit')
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    history_limit: Option<u64>,
+
+    /// lowest zxcvbn score (0 to 4) a new master password may have, when creating a vault
+    /// and with 'chmpw'. an existing one below it is only warned about
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(0..=4))]
+    min_master_pass_strength: u8,
+
+    /// don't warn about weak sensitive values when setting them
+    #[arg(long)]
+    no_warn: bool,
+
+    /// keep otpauth:// values as typed instead of storing them as sensitive
+    #[arg(long)]
+    plain_otpauth: bool,
+
+    /// most backups of the vault kept next to it (as <file>.bak.<timestamp>). one is made
+    /// before every save from the prompt or --exec. 0 makes none
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    backups: usize,
+
+    /// seconds without input after which the prompt saves and locks until the
+    /// master password is entered again. 0 never locks
+    #[arg(long, default_value_t = 300)]
+    idle_timeout: u64,
+
+    /// evaluate a command, print its output and exit without starting the prompt.
+    /// repeat to run several in order. the master password can also come from stdin or
+    /// the ROYALGUARD_MASTER_PASS environment variable (less safe, other processes of the
+    /// same user may be able to read it)
+    #[arg(long, value_name = "CMD")]
+    exec: Vec<String>,
+
+    /// file containing the master password
+    #[arg(long, alias = "master-pass-file")]
+    pass_file: Option<String>,
+
+    /// file whose content is needed besides the master password to open the vault.
+    /// a vault without one needs it from its next save on. keep a copy somewhere safe,
+    /// the vault can't be opened without it
+    #[arg(long)]
+    key_file: Option<PathBuf>,
+
+    /// how `show`, `reveal` and history are printed
+    #[arg(long, value_enum, default_value_t = Format::Plain)]
+    format: Format,
+
+    /// print the records of `show` and `reveal` as json. same as `--format json`
+    #[arg(long, conflicts_with = "format")]
+    json: bool,
+
+    /// key derivation function used from the next save onwards.
+    /// defaults to the one the file was written with (pbkdf2 for new files)
+    #[arg(long, value_enum)]
+    kdf: Option<KdfArg>,
+
+    /// PBKDF2 iterations used from the next save onwards (100000 unless configured)
+    #[arg(long)]
+    iterations: Option<NonZeroU32>,
+
+    /// Argon2id memory in KiB used from the next save onwards (65536 unless the vault
+    /// has another)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=ARGON2_MAX_M_COST as i64))]
+    argon2_m_cost: Option<u32>,
+
+    /// Argon2id number of passes (3 unless the vault has another)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=ARGON2_MAX_T_COST as i64))]
+    argon2_t_cost: Option<u32>,
+
+    /// Argon2id degree of parallelism (4 unless the vault has another)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=ARGON2_MAX_P_COST as i64))]
+    argon2_p_cost: Option<u32>,
+
+    /// save when quitting and locking. with false, quitting asks whether to save
+    /// unsaved changes (true unless configured)
+    #[arg(long, value_name = "BOOL")]
+    auto_save: Option<bool>,
+
+    /// compress the vault before encrypting it, whatever its size. by default only
+    /// vaults over 1 KiB are
+    #[arg(long, conflicts_with = "no_compress")]
+    compress: bool,
+
+    /// never compress the vault, so that versions from before compression can open it
+    #[arg(long)]
+    no_compress: bool,
+
+    /// refuse every command that would change the vault and never save it,
+    /// not even on quitting or locking. also opens vaults that need a newer royalguard
+    #[arg(long)]
+    read_only: bool,
+
+    /// keep the master password in the OS keyring (through secret-tool) once the vault
+    /// opens, and open it from there without asking the next time. chmpw updates it
+    #[arg(long)]
+    keyring: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// unlock once and answer 'GET <name> <attr>' and 'LIST' from other programs
+    /// of the same user on a unix socket. locks for good after --idle-timeout
+    Agent {
+        /// defaults to royalguard-agent.sock in the runtime directory
+        #[arg(long)]
+        socket: Option<PathBuf>,
+
+        /// ask on the terminal before answering each 'GET'
+        #[arg(long)]
+        confirm: bool,
+
+        /// most requests answered per second. the rest get an error
+        #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+        max_requests_per_sec: u64,
+    },
+    /// check the vault for what royalguard itself never writes (duplicate ids,
+    /// history out of order or from the future, sensitive values unmasked in the history).
+    /// exits with 1 if anything is found
+    Verify {
+        /// correct what can be corrected and save the vault
+        #[arg(long)]
+        fix: bool,
+    },
+}
+
+impl Cli {
+    /// what every command is evaluated with. the prompt also asks about untidy values
+    fn eval_options(&self) -> EvalOptions {
+        EvalOptions {
+            merge_pass: |fpath| {
+                rpassword::prompt_password(format!("master password of '{}': ", fpath)).ok()
+            },
+            strength_warnings: !self.no_warn,
+            sensitive_otpauth: !self.plain_otpauth,
+            import_limits: ImportLimits {
+                max_line_len: self.max_import_line_len,
+                max_records: self.max_import_records,
+                sanitize: !self.keep_control_chars,
+            },
+            ..Default::default()
+        }
+    }
+
+    /// fills in what wasn't given on the command line (or in its environment variables)
+    fn with_config(mut self, config: Config) -> Self {
+        self.fpath = self.fpath.or(config.fpath);
+        self.history_limit = self
+            .history_limit
+            .or(config.history_limit.map(NonZeroU64::get));
+        self.kdf = self.kdf.or(config.kdf);
+        self.iterations = self.iterations.or(config.pbkdf2_iterations);
+        self.auto_save = self.auto_save.or(config.auto_save);
+        self.author = self.author.or(config.author);
+        self
+    }
+
+    fn iterations(&self) -> NonZeroU32 {
+        self.iterations
+            .unwrap_or(NonZeroU32::new(DEFAULT_ITERATIONS).unwrap())
+    }
+
+    fn auto_save(&self) -> bool {
+        self.auto_save.unwrap_or(true)
+    }
+
+    fn compression(&self) -> Compression {
+        match (self.compress, self.no_compress) {
+            (true, _) => Compression::Always,
+            (_, true) => Compression::Never,
+            _ => Compression::Auto,
+        }
+    }
+
+    /// the settings of this session, as they would be written in the config file
+    fn effective_config(&self, fpath: &str, kdf: Kdf, store: &Store) -> Config {
+        let (kdf, iterations) = match kdf {
+            Kdf::Pbkdf2 { iterations } => (KdfArg::Pbkdf2, iterations),
+            Kdf::Argon2id { .. } => (KdfArg::Argon2id, self.iterations()),
+        };
+        Config {
+            fpath: Some(fpath.to_string()),
+            history_limit: store
+                .history_limit()
+                .and_then(|limit| NonZeroU64::new(limit as u64)),
+            kdf: Some(kdf),
+            pbkdf2_iterations: Some(iterations),
+            auto_save: Some(self.auto_save()),
+            author: self.author.clone(),
+        }
+    }
+
+    fn kdf_params(&self, kdf: KdfArg) -> Kdf {
+        match kdf {
+            KdfArg::Pbkdf2 => Kdf::Pbkdf2 {
+                iterations: self.iterations(),
+            },
+            KdfArg::Argon2id => Kdf::Argon2id {
+                m_cost: self.argon2_m_cost.unwrap_or(ARGON2_M_COST),
+                t_cost: self.argon2_t_cost.unwrap_or(ARGON2_T_COST),
+                p_cost: self.argon2_p_cost.unwrap_or(ARGON2_P_COST),
+            },
+        }
+    }
+
+    /// the kdf to save with. `--kdf` picks one with the parameters given for it, otherwise
+    /// the one of the file is kept with the parameters given for it.
+    /// parameters only the other kdf has are refused instead of being ignored
+    fn save_kdf(&self, file_kdf: Kdf) -> anyhow::Result<Kdf> {
+        if let Some(kdf) = self.kdf {
+            return Ok(self.kdf_params(kdf));
+        }
+
+        let argon2_given = [self.argon2_m_cost, self.argon2_t_cost, self.argon2_p_cost]
+            .iter()
+            .any(Option::is_some);
+        match file_kdf {
+            Kdf::Pbkdf2 { iterations } => match argon2_given {
+                true => anyhow::bail!(
+                    "the vault uses pbkdf2. --argon2-m-cost, --argon2-t-cost and --argon2-p-cost only apply with --kdf argon2id"
+                ),
+                false => Ok(Kdf::Pbkdf2 {
+                    iterations: self.iterations.unwrap_or(iterations),
+                }),
+            },
+            Kdf::Argon2id {
+                m_cost,
+                t_cost,
+                p_cost,
+            } => match self.iterations {
+                Some(_) => anyhow::bail!(
+                    "the vault uses argon2id. --iterations only applies with --kdf pbkdf2"
+                ),
+                None => Ok(Kdf::Argon2id {
+                    m_cost: self.argon2_m_cost.unwrap_or(m_cost),
+                    t_cost: self.argon2_t_cost.unwrap_or(t_cost),
+                    p_cost: self.argon2_p_cost.unwrap_or(p_cost),
+                }),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Format {
+    Plain,
+    Json,
+    Table,
+}
+
+impl Format {
+    fn renderer(self) -> &'static dyn Renderer {
+        match self {
+            Format::Plain => &PlainText,
+            Format::Json => &Json,
+            Format::Table => &Table,
+        }
+    }
 }
 
 fn default_fpath() -> anyhow::Result<String> {
@@ -72,50 +928,906 @@ fn default_fpath() -> anyhow::Result<String> {
     Ok(fpath.to_string_lossy().to_string())
 }
 
-fn save(fpath: &str, master_pass: &str, store: &Store) {
+/// backs up the file it overwrites, keeping the newest `backups` of them.
+/// returns whether it was saved
+fn save(
+    fpath: &str,
+    master_pass: &str,
+    key_file: Option<&[u8]>,
+    (kdf, compression): (Kdf, Compression),
+    store: &Store,
+    backups: usize,
+) -> bool {
     println!("saving to '{}' ...", fpath);
-    match dump(fpath, master_pass, store) {
-        Ok(_) => println!("saved successfully!"),
-        Err(e) => eprintln!("!! error while saving: {:?}", e),
+    // the vault itself is written atomically, so a failed backup isn't worth losing the changes over
+    if let Err(e) = backup(fpath, backups) {
+        eprintln!("!! error while backing up: {:?}", e);
+    }
+    match dump(
+        fpath,
+        &*key_source(master_pass, key_file),
+        kdf,
+        compression,
+        store,
+    ) {
+        Ok(_) => {
+            println!("saved successfully!");
+            true
+        }
+        Err(e) => {
+            eprintln!("!! error while saving: {:?}", e);
+            false
+        }
+    }
+}
+
+/// commands handled by the prompt itself rather than `eval`
+/// how long the master password, once given again, lets `browse` reveal without asking
+const REVEAL_GRACE: Duration = Duration::from_secs(60);
+
+const PROMPT_CMDS: &[&str] = &[
+    "help",
+    "clear",
+    "cls",
+    "save",
+    "exit",
+    "quit",
+    "chmpw",
+    "rekey",
+    "receive",
+    "config",
+    "compress",
+    "decompress",
+    "browse",
+];
+
+/// commands whose next word is a record name
+const NAME_CMDS: &[&[&str]] = &[
+    &["set"],
+    &["del"],
+    &["show"],
+    &["reveal"],
+    &["reveal", "history"],
+    &["reveal", "history", "diff"],
+    &["count"],
+    &["list"],
+    &["copy"],
+    &["copy", "totp"],
+    &["totp"],
+    &["share"],
+    &["decode"],
+    &["encode"],
+    &["tag"],
+    &["untag"],
+    &["history"],
+    &["history", "diff"],
+    &["history", "prune"],
+    &["history", "clear"],
+    &["browse", "history"],
+    &["rename"],
+    &["dup"],
+    &["clone"],
+    &["rollback"],
+];
+
+/// tab completion of commands and record names. only the names are kept,
+/// so completing never touches field values
+struct Completion {
+    names: Arc<Mutex<Vec<String>>>,
+}
+
+impl rustyline::completion::Completer for Completion {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete(&line[..pos], &self.names.lock().unwrap()))
+    }
+}
+
+impl rustyline::hint::Hinter for Completion {
+    type Hint = String;
+}
+
+impl rustyline::highlight::Highlighter for Completion {}
+
+impl rustyline::validate::Validator for Completion {}
+
+impl rustyline::Helper for Completion {}
+
+/// where the word being typed at the end of `line` starts and what it could be.
+/// commands at the start of the line, record names after the commands that take one.
+/// matching ignores case and names with whitespace are quoted
+fn complete(line: &str, names: &[String]) -> (usize, Vec<String>) {
+    let start = line.rfind(char::is_whitespace).map(|i| i + 1).unwrap_or(0);
+    let word = line[start..].trim_start_matches('\'').to_lowercase();
+    let before = line[..start].split_whitespace().collect::<Vec<_>>();
+
+    let candidates = match before.as_slice() {
+        [] => TOPICS
+            .iter()
+            .map(|(keyword, _)| *keyword)
+            .chain(PROMPT_CMDS.iter().copied())
+            .filter(|cmd| cmd.starts_with(&word))
+            .map(String::from)
+            .collect(),
+        before if NAME_CMDS.contains(&before) => names
+            .iter()
+            .filter(|name| name.to_lowercase().starts_with(&word))
+            .map(|name| match name.contains(char::is_whitespace) {
+                true => format!("'{}'", name),
+                false => name.clone(),
+            })
+            .collect(),
+        _ => vec![],
+    };
+
+    (start, candidates)
+}
+
+type Editor = rustyline::Editor<Completion, rustyline::history::DefaultHistory>;
+
+/// reads lines on another thread so that waiting for input can time out.
+/// rustyline has no timeout of its own and `readline` blocks.
+struct Input {
+    editor: Arc<Mutex<Editor>>,
+    /// the line still being read after a timeout. the next read picks it up
+    pending: Option<mpsc::Receiver<rustyline::Result<String>>>,
+    /// record names to complete, shared with the editor's `Completion`
+    names: Arc<Mutex<Vec<String>>>,
+}
+
+impl Input {
+    fn new() -> anyhow::Result<Self> {
+        let names = Arc::new(Mutex::new(vec![]));
+        let mut editor = Editor::new()?;
+        editor.set_helper(Some(Completion {
+            names: Arc::clone(&names),
+        }));
+        Ok(Self {
+            editor: Arc::new(Mutex::new(editor)),
+            pending: None,
+            names,
+        })
+    }
+
+    fn editor(&self) -> MutexGuard<'_, Editor> {
+        self.editor.lock().unwrap()
+    }
+
+    /// doesn't need the editor, which is locked while a line is being read
+    fn set_names(&self, names: Vec<String>) {
+        *self.names.lock().unwrap() = names;
+    }
+
+    /// `None` if nothing was entered within `timeout`
+    fn readline(
+        &mut self,
+        prompt: &str,
+        timeout: Option<Duration>,
+    ) -> Option<rustyline::Result<String>> {
+        let line = self.pending.take().unwrap_or_else(|| {
+            let (tx, rx) = mpsc::channel();
+            let (editor, prompt) = (Arc::clone(&self.editor), prompt.to_string());
+            thread::spawn(move || tx.send(editor.lock().unwrap().readline(&prompt)).ignore());
+            rx
+        });
+
+        let received = match timeout {
+            Some(timeout) => match line.recv_timeout(timeout) {
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    self.pending = Some(line);
+                    return None;
+                }
+                received => received.ok(),
+            },
+            None => line.recv().ok(),
+        };
+        Some(received.unwrap_or(Err(ReadlineError::Eof)))
+    }
+}
+
+/// whether to save on the way out. with auto_save off, unsaved changes are only
+/// saved if the user says so
+fn save_on_exit(
+    input: &mut Input,
+    auto_save: bool,
+    store: &Store,
+    saved: &str,
+) -> anyhow::Result<bool> {
+    if store.is_read_only() {
+        return Ok(false);
+    }
+    let unsaved = serde_json::to_string(store)? != saved;
+    Ok(auto_save || (unsaved && confirm(input, "save the unsaved changes?")?))
+}
+
+/// saves (unless read-only), forgets the master password and waits for it to be entered again.
+/// the store stays in memory so a wrong password loses nothing, and the password
+/// is checked against the saved file, so nothing is locked unless the save worked.
+/// returns whether the prompt is usable again (`false` if the user gave up).
+fn lock(
+    input: &mut Input,
+    fpath: &str,
+    master_pass: &mut Zeroizing<String>,
+    key_file: Option<&[u8]>,
+    (kdf, compression): (Kdf, Compression),
+    store: &Store,
+    backups: usize,
+) -> anyhow::Result<bool> {
+    if !store.is_read_only()
+        && !save(
+            fpath,
+            master_pass,
+            key_file,
+            (kdf, compression),
+            store,
+            backups,
+        )
+    {
+        eprintln!("!! not locking because the vault couldn't be saved");
+        return Ok(true);
+    }
+    clipboard::clear_pending();
+    master_pass.zeroize();
+
+    // the prompt is still waiting for a line with the terminal in raw mode
+    print!("\r\nlocked after a while without input. press enter to unlock\r\n");
+    std::io::Write::flush(&mut std::io::stdout())?;
+    match input.readline("", None) {
+        Some(Ok(_)) => {}
+        _ => return Ok(false),
+    }
+
+    loop {
+        let Ok(pass) = rpassword::prompt_password("master password: ").map(Zeroizing::new) else {
+            return Ok(false);
+        };
+        let unlocked = load(fpath, &*key_source(&pass, key_file), kdf);
+        match unlocked {
+            Ok(_) => {
+                *master_pass = pass;
+                println!("unlocked!");
+                return Ok(true);
+            }
+            Err(_) => eprintln!("!! wrong master password"),
+        }
+    }
+}
+
+fn is_import(line: &str) -> bool {
+    let Ok(tokens) = lex(line) else {
+        return false;
+    };
+    matches!(
+        parse(&tokens),
+        Ok(Cmd::Import(..) | Cmd::ImportLastPass(..) | Cmd::ImportCsv(..) | Cmd::ImportKeePass(..))
+    )
+}
+
+/// the master password from `--pass-file` or the deprecated `ROYALGUARD_PASS`
+/// environment variable, otherwise asks for it. it is overwritten in memory once dropped
+fn read_master_pass(pass_file: Option<&str>) -> anyhow::Result<Option<Zeroizing<String>>> {
+    if let Some(pass_file) = pass_file {
+        let pass = Zeroizing::new(
+            std::fs::read_to_string(pass_file)
+                .with_context(|| format!("unable to read master password from '{}'", pass_file))?,
+        );
+        return Ok(Some(trim_line_end(&pass)));
+    }
+
+    if let Ok(pass) = std::env::var("ROYALGUARD_PASS") {
+        eprintln!("warning: ROYALGUARD_PASS is deprecated because environment variables can leak to other processes. use --pass-file instead");
+        return Ok(Some(Zeroizing::new(pass)));
+    }
+
+    Ok(rpassword::prompt_password("master password: ")
+        .ok()
+        .map(Zeroizing::new))
+}
+
+fn trim_line_end(pass: &str) -> Zeroizing<String> {
+    Zeroizing::new(pass.trim_end_matches(['\n', '\r']).to_string())
+}
+
+/// like `read_master_pass` but for `--exec`, which has nobody to ask.
+/// `ROYALGUARD_MASTER_PASS` is only read if it is set, then a line from stdin if it isn't a terminal
+fn read_exec_master_pass(pass_file: Option<&str>) -> anyhow::Result<Option<Zeroizing<String>>> {
+    if pass_file.is_none() {
+        if let Ok(pass) = std::env::var("ROYALGUARD_MASTER_PASS") {
+            return Ok(Some(Zeroizing::new(pass)));
+        }
+
+        let stdin = std::io::stdin();
+        if !stdin.is_terminal() {
+            let mut pass = Zeroizing::new(String::new());
+            stdin.read_line(&mut pass)?;
+            return Ok(Some(trim_line_end(&pass)));
+        }
+    }
+
+    read_master_pass(pass_file)
+}
+
+/// answers on the socket until ctrl-c or until the agent locks
+#[cfg(unix)]
+fn run_agent(agent: &mut Agent, socket: Option<PathBuf>) -> anyhow::Result<()> {
+    let socket = match socket {
+        Some(socket) => socket,
+        None => agent::default_socket()?,
+    };
+    let cancel = crate::cancel::Cancel::default();
+    let _interruptible = crate::cancel::interruptible(&cancel);
+
+    println!(
+        "agent listening on '{}'. stop with CTRL-C",
+        socket.display()
+    );
+    agent::serve(agent, &socket, &cancel)?;
+    println!("agent stopped");
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn run_agent(_: &mut Agent, _: Option<PathBuf>) -> anyhow::Result<()> {
+    anyhow::bail!("the agent needs unix domain sockets, which this platform doesn't have")
+}
+
+/// loads the store and picks the kdf it is saved with.
+/// files keep the kdf they were written with unless `--kdf` (or the config) says otherwise,
+/// in which case the last value is `true` since the file has to be rewritten.
+/// so it is if a key file is given for a file that doesn't need one yet.
+/// a read-only store is never rewritten, and a missing file isn't created for it
+fn open(
+    cli: &Cli,
+    fpath: &str,
+    source: &dyn KeySource,
+    key_file: Option<&[u8]>,
+) -> anyhow::Result<(Store, Kdf, bool)> {
+    if cli.read_only && !Path::new(fpath).exists() {
+        anyhow::bail!(
+            "'{}' doesn't exist, so there is nothing to open read-only",
+            fpath
+        );
+    }
+    let new_file_kdf = cli.kdf_params(cli.kdf.unwrap_or(KdfArg::Pbkdf2));
+    // a vault that needs a newer version can still be read, as long as it isn't saved
+    let (mut store, file_kdf) = match cli.read_only {
+        true => load_any_version(fpath, source, new_file_kdf)?,
+        false => load(fpath, source, new_file_kdf)?,
+    };
+    store.set_author(cli.author.clone());
+    store.set_read_only(cli.read_only);
+    if let Some(limit) = cli.history_limit {
+        store.set_history_limit(Some(limit as usize));
+    }
+    let kdf = cli.save_kdf(file_kdf)?;
+    let adds_key_file = key_file.is_some() && !needs_key_file(fpath);
+    let rekey = !cli.read_only && (kdf != file_kdf || adds_key_file);
+    Ok((store, kdf, rekey))
+}
+
+fn read_key_file(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let key_file = std::fs::read(path)
+        .with_context(|| format!("unable to read key file '{}'", path.display()))?;
+    if key_file.is_empty() {
+        anyhow::bail!("key file '{}' is empty", path.display());
+    }
+    Ok(key_file)
+}
+
+/// evaluates the `--exec` commands in order and saves if any of them changed the store
+/// (or `rekey`). the first failure exits with a non-zero code without saving any of them.
+/// there is nobody to ask before a mass delete, so that fails without `--yes`.
+/// what was copied is cleared before exiting, after the promised delay if they all succeeded
+fn exec(
+    cli: &Cli,
+    fpath: &str,
+    master_pass: &str,
+    key_file: Option<&[u8]>,
+    (mut store, kdf, rekey): (Store, Kdf, bool),
+    renderer: &dyn Renderer,
+) -> anyhow::Result<ExitCode> {
+    let before = serde_json::to_string(&store)?;
+    // there is nobody to ask about untidy values, so they are kept with a warning
+    let options = cli.eval_options();
+
+    for cmd in &cli.exec {
+        if mass_deletion(cmd, &store).is_some() && !cli.yes {
+            eprintln!("!! '{}' deletes several records, which needs --yes", cmd);
+            clipboard::clear_pending();
+            return Ok(ExitCode::FAILURE);
+        }
+        match eval_with(cmd, &mut store, &options) {
+            Ok(eval) => {
+                print(eval, renderer);
+                for notice in store.take_notices() {
+                    println!("{}", notice);
+                }
+            }
+            Err(e) => {
+                print_err(cmd, e);
+                clipboard::clear_pending();
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
+
+    if rekey || serde_json::to_string(&store)? != before {
+        backup(fpath, cli.backups)?;
+        dump(
+            fpath,
+            &*key_source(master_pass, key_file),
+            kdf,
+            cli.compression(),
+            &store,
+        )?;
+    }
+    clipboard::wait_pending();
+    Ok(ExitCode::SUCCESS)
+}
+
+/// when the command didn't parse, what it should have looked like follows the error
+fn print_err(line: &str, e: EvalError) {
+    eprintln!("!! {}", e);
+    if let (EvalError::Parse(_), Some(usage)) = (e, usage_of(line)) {
+        eprintln!("usage:\n{}", usage);
+    }
+}
+
+fn print(eval: Evaluation, renderer: &dyn Renderer) {
+    for line in eval.render(renderer) {
+        println!("{}", line)
+    }
+}
+
+/// question to ask before deleting a whole record. deleting fields is never asked about
+fn deletion(line: &str, store: &Store) -> Option<String> {
+    let tokens = lex(line).ok()?;
+    match parse(&tokens).ok()? {
+        Cmd::Del {
+            name,
+            filter,
+            attrs,
+        } if attrs.is_empty() => match store.pick(name, filter.as_ref()).as_slice() {
+            [record] => {
+                let summary = PlainText.render(Evaluation::Del(Some(record.clone())));
+                Some(format!("{}\ndelete '{}'?", summary.join("\n"), name))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// question to ask before a query deletes several records at once,
+/// which has to be answered by typing out 'yes'
+fn mass_deletion(line: &str, store: &Store) -> Option<String> {
+    let tokens = lex(line).ok()?;
+    match parse(&tokens).ok()? {
+        Cmd::DelQuery(query) => match store.get(query).len() {
+            n if n > 1 => Some(format!("delete {} records?", n)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// the file an export would replace. exports never replace one themselves,
+/// so it is removed once the user agrees to
+fn existing_export(line: &str) -> Option<PathBuf> {
+    let tokens = lex(line).ok()?;
+    match parse(&tokens).ok()? {
+        Cmd::Export(fpath)
+        | Cmd::ExportCsv(fpath)
+        | Cmd::ExportCsvFields(fpath)
+        | Cmd::ExportJson(fpath) => Some(PathBuf::from(fpath)).filter(|fpath| fpath.exists()),
+        _ => None,
+    }
+}
+
+enum Answer {
+    Yes,
+    No,
+    /// yes to this and every other question like it for the rest of the session
+    All,
+}
+
+fn confirm_all(input: &mut Input, question: &str) -> anyhow::Result<Answer> {
+    match input.readline(&format!("{} [y/N/a(ll)] ", question), None) {
+        Some(Ok(answer)) => Ok(match answer.trim() {
+            "y" | "Y" | "yes" => Answer::Yes,
+            "a" | "A" | "all" => Answer::All,
+            _ => Answer::No,
+        }),
+        Some(Err(ReadlineError::Interrupted)) | Some(Err(ReadlineError::Eof)) | None => {
+            Ok(Answer::No)
+        }
+        Some(Err(e)) => Err(e.into()),
+    }
+}
+
+/// rejects a new master password below `min_strength` and warns about one weaker than advised,
+/// which is only used if the user says so then
+fn accept_master_pass(input: &mut Input, pass: &str, min_strength: u8) -> anyhow::Result<bool> {
+    let strength = password_strength(pass);
+    let reason = weakness(pass).map_or(String::new(), |reason| format!(" – {}", reason));
+    if (strength as u8) < min_strength {
+        println!(
+            "!! master password: strength {} is below the minimum of {}/4{}",
+            strength, min_strength, reason
+        );
+        return Ok(false);
+    }
+    if strength >= Strength::ADVISED {
+        return Ok(true);
+    }
+    println!(
+        "⚠ master password: strength {}{}. a longer one, like a passphrase from 'gen words 6', is much harder to guess",
+        strength, reason
+    );
+    confirm(input, "use it anyway?")
+}
+
+/// what zxcvbn says makes `pass` easy to guess, if anything
+fn weakness(pass: &str) -> Option<String> {
+    if pass.chars().count() < 4 {
+        return Some("shorter than 4 characters".into());
+    }
+    let entropy = zxcvbn::zxcvbn(pass, &[]).ok()?;
+    let warning = entropy.feedback().as_ref()?.warning()?;
+    Some(warning.to_string())
+}
+
+/// whether to strip what `set` found around a sensitive value. read straight from stdin,
+/// since the question comes up while the line that asked for it is evaluated
+fn ask_trim(warning: &str) -> bool {
+    print!("{}. trim it? [y/N] ", warning);
+    std::io::Write::flush(&mut std::io::stdout()).ignore();
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).is_ok() && matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+/// like `confirm`, but only 'yes' spelled out is a yes
+fn confirm_typed(input: &mut Input, question: &str) -> anyhow::Result<bool> {
+    match input.readline(&format!("{} type 'yes' to confirm: ", question), None) {
+        Some(Ok(answer)) => Ok(answer.trim() == "yes"),
+        Some(Err(ReadlineError::Interrupted)) | Some(Err(ReadlineError::Eof)) | None => Ok(false),
+        Some(Err(e)) => Err(e.into()),
     }
 }
 
-pub fn run() -> anyhow::Result<()> {
-    let fpath = match Cli::parse().fpath {
+fn confirm(input: &mut Input, question: &str) -> anyhow::Result<bool> {
+    match input.readline(&format!("{} [y/N] ", question), None) {
+        Some(Ok(answer)) => Ok(matches!(answer.trim(), "y" | "Y" | "yes")),
+        Some(Err(ReadlineError::Interrupted)) | Some(Err(ReadlineError::Eof)) | None => Ok(false),
+        Some(Err(e)) => Err(e.into()),
+    }
+}
+
+fn verify(cli: &Cli, fpath: &str, key_file: Option<&[u8]>, fix: bool) -> anyhow::Result<ExitCode> {
+    if !Path::new(fpath).exists() {
+        anyhow::bail!("'{}' doesn't exist", fpath);
+    }
+    if fix && cli.read_only {
+        anyhow::bail!("--fix changes the vault, it can't be used with --read-only");
+    }
+    let Some(master_pass) = read_master_pass(cli.pass_file.as_deref())? else {
+        println!("Bye!");
+        return Ok(ExitCode::SUCCESS);
+    };
+    let (mut store, kdf, _) = open(cli, fpath, &*key_source(&master_pass, key_file), key_file)?;
+
+    let errors = store.verify();
+    for error in &errors {
+        match error.fixable() && !fix {
+            true => println!("!! {} (fixable with --fix)", error),
+            false => println!("!! {}", error),
+        }
+    }
+
+    if fix && errors.iter().any(|e| e.fixable()) {
+        for fixed in store.fix() {
+            println!("fixed {}", fixed);
+        }
+        let settings = (kdf, cli.compression());
+        if !save(fpath, &master_pass, key_file, settings, &store, cli.backups) {
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+
+    let remaining = store.verify();
+    if !remaining.is_empty() {
+        return Ok(ExitCode::FAILURE);
+    }
+    if errors.is_empty() {
+        println!("no problems found");
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+pub fn run() -> anyhow::Result<ExitCode> {
+    let cli = Cli::parse();
+    let config = match &cli.config {
+        Some(path) => Config::load(path)?,
+        None => Config::load_default()?,
+    };
+    let cli = cli.with_config(config);
+    let fpath = match cli.fpath.clone() {
         Some(f) => f,
         None => default_fpath()?,
     };
+    let key_file = cli.key_file.as_deref().map(read_key_file).transpose()?;
+    let key_file = key_file.as_deref();
+    clipboard::set_clear_after(Duration::from_secs(cli.clear_after));
+    clipboard::set_osc52(cli.osc52);
+    // the scheduled clears die with the program, so whatever ends it clears first
+    let _clear = clipboard::ClearOnDrop;
+    crate::cancel::on_exit_signals(|| {
+        clipboard::clear_pending();
+    })?;
+
+    let renderer = match cli.json {
+        true => Format::Json.renderer(),
+        false => cli.format.renderer(),
+    };
+
+    if !cli.exec.is_empty() {
+        let master_pass = read_exec_master_pass(cli.pass_file.as_deref())?
+            .with_context(|| "unable to read master password")?;
+        let opened = open(&cli, &fpath, &*key_source(&master_pass, key_file), key_file)?;
+        return exec(&cli, &fpath, &master_pass, key_file, opened, renderer);
+    }
+
+    if let Some(Command::Agent {
+        socket,
+        confirm,
+        max_requests_per_sec,
+    }) = &cli.command
+    {
+        let Some(master_pass) = read_master_pass(cli.pass_file.as_deref())? else {
+            println!("Bye!");
+            return Ok(ExitCode::SUCCESS);
+        };
+        let (store, _, _) = open(&cli, &fpath, &*key_source(&master_pass, key_file), key_file)?;
+        drop(master_pass);
+
+        let confirm = match confirm {
+            true => Some(Box::new(agent::confirm_on_terminal) as Box<_>),
+            false => None,
+        };
+        let idle_timeout = (cli.idle_timeout > 0).then(|| Duration::from_secs(cli.idle_timeout));
+        let rate_limit = RateLimit::new(*max_requests_per_sec as usize, Duration::from_secs(1));
+        let mut agent = Agent::new(store, rate_limit, idle_timeout, confirm, Instant::now());
+        return run_agent(&mut agent, socket.clone()).map(|_| ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Verify { fix }) = &cli.command {
+        return verify(&cli, &fpath, key_file, *fix);
+    }
 
     println!(env!("CARGO_PKG_VERSION"));
     println!("All data will be saved to file '{}'", fpath);
 
-    let Ok(mut master_pass) = rpassword::prompt_password("master password: ") else {
+    let keyring = SecretService;
+    // what an earlier session with --keyring kept opens the vault without asking
+    let kept = (cli.keyring && Path::new(&fpath).exists())
+        .then(|| keyring.get(&fpath))
+        .flatten();
+    let from_keyring = kept.is_some();
+    let Some(mut master_pass) = (match kept {
+        Some(pass) => Some(pass),
+        None => read_master_pass(cli.pass_file.as_deref())?,
+    }) else {
         println!("Bye!");
-        return Ok(());
+        return Ok(ExitCode::SUCCESS);
     };
 
-    let mut store = load(&fpath, &master_pass)?;
-    let mut editor = rustyline::DefaultEditor::new()?;
+    let mut input = Input::new()?;
+    let existed = Path::new(&fpath).exists();
+    let min_strength = cli.min_master_pass_strength;
+    if !existed && !accept_master_pass(&mut input, &master_pass, min_strength)? {
+        println!("Bye!");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let opened = {
+        let source: Box<dyn KeySource> = match from_keyring {
+            true => Box::new(KeyringCached {
+                keyring: &keyring,
+                fpath: &fpath,
+                key_file,
+            }),
+            false => key_source(&master_pass, key_file),
+        };
+        open(&cli, &fpath, &*source, key_file)
+    };
+    let (mut store, mut kdf, _) = match opened {
+        Err(e) if from_keyring => {
+            keyring.forget(&fpath).ignore();
+            return Err(e.context(
+                "the master password kept in the keyring was forgotten. run again to type it",
+            ));
+        }
+        opened => opened?,
+    };
+    if cli.keyring && !from_keyring {
+        if let Err(e) = keyring.set(&fpath, &master_pass) {
+            println!("⚠ {:#}", e);
+        }
+    }
+    let mut compression = cli.compression();
+    // an existing vault is always opened, so that nobody is locked out of it
+    let strength = password_strength(&master_pass);
+    if existed && (strength as u8) < min_strength {
+        println!(
+            "⚠ master password: strength {} is below the minimum of {}/4. change it with 'chmpw'",
+            strength, min_strength
+        );
+    }
+    if !cli.read_only && key_file.is_some() && !needs_key_file(&fpath) {
+        println!("'{}' will need the key file from the next save on", fpath);
+    }
+    // what the file has, to tell whether quitting without auto_save loses anything
+    let mut saved = serde_json::to_string(&store)?;
+    // answering 'all' stops asking before deletes for the rest of the session
+    let mut yes_to_deletes = cli.yes;
+    // when the master password was last given again to reveal in `browse`
+    let mut reauthorized = None;
+    let idle_timeout = (cli.idle_timeout > 0).then(|| Duration::from_secs(cli.idle_timeout));
 
     println!("{}", LOGO);
     println!(env!("CARGO_PKG_VERSION"));
 
     println!("type 'help' for usage instructions");
-    println!("To Quit, press CTRL-C or CTRL-D or type 'exit' or 'quit' (all updates will be auto saved after quitting)");
-    println!("type 'save' to save current updates manually");
+    match cli.auto_save() {
+        _ if cli.read_only => println!("To Quit, press CTRL-C or CTRL-D or type 'exit' or 'quit' (read-only. nothing can be changed or saved)"),
+        true => println!("To Quit, press CTRL-C or CTRL-D or type 'exit' or 'quit' (all updates will be auto saved after quitting)"),
+        false => println!("To Quit, press CTRL-C or CTRL-D or type 'exit' or 'quit' (auto_save is off. unsaved updates are asked about)"),
+    }
+    if !cli.read_only {
+        println!("type 'save' to save current updates manually");
+    }
+    // only the prompt has someone to ask. `--exec` keeps such values and warns
+    let options = EvalOptions {
+        trim_untidy: ask_trim,
+        ..cli.eval_options()
+    };
+    let prompt = match cli.read_only {
+        true => "[readonly] > ",
+        false => "> ",
+    };
 
     loop {
-        match editor.readline("> ").as_deref() {
-            Ok("clear") | Ok("cls") => editor.clear_screen()?,
+        for notice in store.take_notices() {
+            println!("{}", notice);
+        }
+        input.set_names(store.names());
+        let Some(line) = input.readline(prompt, idle_timeout) else {
+            match lock(
+                &mut input,
+                &fpath,
+                &mut master_pass,
+                key_file,
+                (kdf, compression),
+                &store,
+                cli.backups,
+            )? {
+                true => {
+                    saved = serde_json::to_string(&store)?;
+                    continue;
+                }
+                false => break,
+            }
+        };
+
+        match line.as_deref() {
+            Ok("clear") | Ok("cls") => input.editor().clear_screen()?,
             Ok("help") | Ok("HELP") => println!("{}", HELP),
+            Ok(line) if line.split_whitespace().next() == Some("help") => {
+                let args = line.split_whitespace().skip(1).collect::<Vec<_>>();
+                match <[&str; 1]>::try_from(args).ok().and_then(|[t]| topic(t)) {
+                    Some(usage) => println!("{}", usage),
+                    None => {
+                        let topics = TOPICS.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+                        println!("!! usage: help <{}>", topics.join(" | "));
+                    }
+                }
+            }
             Ok("exit") | Ok("quit") => {
-                save(&fpath, &master_pass, &store);
+                if save_on_exit(&mut input, cli.auto_save(), &store, &saved)? {
+                    save(
+                        &fpath,
+                        &master_pass,
+                        key_file,
+                        (kdf, compression),
+                        &store,
+                        cli.backups,
+                    );
+                }
                 break;
             }
-            Ok("save") => save(&fpath, &master_pass, &store),
+            Ok("save" | "chmpw" | "compress" | "decompress") if cli.read_only => {
+                println!("!! the vault is open read-only");
+            }
+            Ok(line) if cli.read_only && line.split_whitespace().next() == Some("rekey") => {
+                println!("!! the vault is open read-only");
+            }
+            Ok("save") => {
+                if save(
+                    &fpath,
+                    &master_pass,
+                    key_file,
+                    (kdf, compression),
+                    &store,
+                    cli.backups,
+                ) {
+                    saved = serde_json::to_string(&store)?;
+                }
+            }
+            Ok(cmd @ ("compress" | "decompress")) => {
+                compression = match cmd {
+                    "compress" => Compression::Always,
+                    _ => Compression::Never,
+                };
+                if save(
+                    &fpath,
+                    &master_pass,
+                    key_file,
+                    (kdf, compression),
+                    &store,
+                    cli.backups,
+                ) {
+                    saved = serde_json::to_string(&store)?;
+                }
+            }
+            Ok(line) if line.split_whitespace().next() == Some("browse") => {
+                input.editor().add_history_entry(line)?;
+                let Some(name) = browse::target(line) else {
+                    println!("!! usage: browse history <name>");
+                    continue;
+                };
+                if !(std::io::stdin().is_terminal() && std::io::stdout().is_terminal()) {
+                    // what follows `browse` is the `history` command itself
+                    let history = line.trim_start().strip_prefix("browse").unwrap_or(line);
+                    match eval_with(history, &mut store, &options) {
+                        Ok(eval) => print(eval, renderer),
+                        Err(e) => print_err(line, e),
+                    }
+                    continue;
+                }
+                let mut reauth = || {
+                    if reauthorized.is_some_and(|at: Instant| at.elapsed() < REVEAL_GRACE) {
+                        return true;
+                    }
+                    match rpassword::prompt_password("master password to reveal: ")
+                        .map(Zeroizing::new)
+                    {
+                        Ok(pass) if pass == master_pass => {
+                            reauthorized = Some(Instant::now());
+                            true
+                        }
+                        _ => false,
+                    }
+                };
+                // a broken terminal closes the browser, not the vault
+                if let Err(e) = browse::run(browse::Browser::new(&store, &name), &mut reauth) {
+                    eprintln!("!! {}", e);
+                }
+            }
+            Ok(line) if line.split_whitespace().next() == Some("config") => {
+                match line.split_whitespace().skip(1).collect::<Vec<_>>()[..] {
+                    ["show"] => print!("{}", cli.effective_config(&fpath, kdf, &store).to_toml()),
+                    _ => println!("!! usage: config show"),
+                }
+            }
             Ok("chmpw") => {
                 let pw = match rpassword::prompt_password("new master password: ") {
-                    Ok(pw) if !pw.trim().is_empty() => pw,
+                    Ok(pw) if !pw.trim().is_empty() => Zeroizing::new(pw),
                     _ => {
                         println!("abort!");
                         continue;
@@ -123,7 +1835,7 @@ pub fn run() -> anyhow::Result<()> {
                 };
 
                 let pw2 = match rpassword::prompt_password("retype new master password: ") {
-                    Ok(pw2) if !pw2.trim().is_empty() => pw2,
+                    Ok(pw2) if !pw2.trim().is_empty() => Zeroizing::new(pw2),
                     _ => {
                         println!("abort!");
                         continue;
@@ -135,30 +1847,159 @@ pub fn run() -> anyhow::Result<()> {
                     continue;
                 }
 
+                if !accept_master_pass(&mut input, &pw, min_strength)? {
+                    println!("abort!");
+                    continue;
+                }
+
                 master_pass = pw;
                 println!("master password changed successfully!");
+                if cli.keyring {
+                    if let Err(e) = keyring.set(&fpath, &master_pass) {
+                        println!("⚠ {:#}", e);
+                    }
+                }
+            }
+            Ok(line) if line.split_whitespace().next() == Some("rekey") => {
+                let args = line.split_whitespace().skip(1).collect::<Vec<_>>();
+                let Ok([new_kdf]) = <[&str; 1]>::try_from(args) else {
+                    println!("!! usage: rekey pbkdf2 | rekey argon2id");
+                    continue;
+                };
+                let Ok(new_kdf) = KdfArg::from_str(new_kdf, true) else {
+                    println!("!! unknown kdf '{}'. use pbkdf2 or argon2id", new_kdf);
+                    continue;
+                };
+
+                kdf = cli.kdf_params(new_kdf);
+                if save(
+                    &fpath,
+                    &master_pass,
+                    key_file,
+                    (kdf, compression),
+                    &store,
+                    cli.backups,
+                ) {
+                    saved = serde_json::to_string(&store)?;
+                }
+            }
+            Ok("receive") => {
+                let Some(armored) = clipboard::paste() else {
+                    println!("!! unable to read the clipboard");
+                    continue;
+                };
+
+                let passphrase = match rpassword::prompt_password("passphrase: ") {
+                    Ok(passphrase) if !passphrase.trim().is_empty() => passphrase,
+                    _ => {
+                        println!("abort!");
+                        continue;
+                    }
+                };
+
+                match receive(&armored, &passphrase, &mut store) {
+                    Ok(eval) => print(eval, renderer),
+                    Err(e) => eprintln!("!! {}", e),
+                }
             }
             Ok(line) => {
                 if !line.is_empty() {
-                    editor.add_history_entry(line)?;
-                    match eval(line, &mut store) {
-                        Ok(eval) => {
-                            for line in eval.lines() {
-                                println!("{}", line)
+                    input.editor().add_history_entry(line)?;
+                    if let Some(question) = deletion(line, &store).filter(|_| !yes_to_deletes) {
+                        match confirm_all(&mut input, &question)? {
+                            Answer::Yes => {}
+                            Answer::All => yes_to_deletes = true,
+                            Answer::No => {
+                                println!("abort!");
+                                continue;
                             }
                         }
-                        Err(e) => eprintln!("!! {:?}", e),
+                    }
+                    if let Some(question) = mass_deletion(line, &store).filter(|_| !cli.yes) {
+                        if !confirm_typed(&mut input, &question)? {
+                            println!("abort!");
+                            continue;
+                        }
+                    }
+                    if let Some(existing) = existing_export(line) {
+                        let question =
+                            format!("'{}' already exists. overwrite?", existing.display());
+                        if !confirm(&mut input, &question)? {
+                            println!("abort!");
+                            continue;
+                        }
+                        if let Err(e) = std::fs::remove_file(&existing) {
+                            println!("!! unable to remove '{}'. {}", existing.display(), e);
+                            continue;
+                        }
+                    }
+                    if is_import(line) {
+                        // imports run on a copy so that large ones can be reviewed first
+                        let mut staged = store.clone();
+                        match eval_with(line, &mut staged, &options) {
+                            Ok(Evaluation::Import {
+                                imported,
+                                skipped,
+                                renamed,
+                                warnings,
+                            }) => {
+                                for warning in warnings {
+                                    println!("{}", warning);
+                                }
+                                let nrecords = imported + renamed;
+                                let question = format!("import {} records?", nrecords);
+                                if nrecords > cli.confirm_import_above
+                                    && !confirm(&mut input, &question)?
+                                {
+                                    println!("abort!");
+                                    continue;
+                                }
+                                store = staged;
+                                let eval = Evaluation::Import {
+                                    imported,
+                                    skipped,
+                                    renamed,
+                                    warnings: vec![],
+                                };
+                                print(eval, renderer);
+                            }
+                            Ok(eval) => print(eval, renderer),
+                            Err(e) => print_err(line, e),
+                        }
+                        continue;
+                    }
+                    match eval_with(line, &mut store, &options) {
+                        Ok(eval) => print(eval, renderer),
+                        Err(e) => print_err(line, e),
                     }
                 }
             }
             Err(ReadlineError::Interrupted) => {
                 eprintln!("CTRL-C");
-                save(&fpath, &master_pass, &store);
+                if save_on_exit(&mut input, cli.auto_save(), &store, &saved)? {
+                    save(
+                        &fpath,
+                        &master_pass,
+                        key_file,
+                        (kdf, compression),
+                        &store,
+                        cli.backups,
+                    );
+                }
                 break;
             }
             Err(ReadlineError::Eof) => {
                 eprintln!("CTRL-D");
-                save(&fpath, &master_pass, &store);
+                if save_on_exit(&mut input, cli.auto_save(), &store, &saved)? {
+                    save(
+                        &fpath,
+                        &master_pass,
+                        key_file,
+                        (kdf, compression),
+                        &store,
+                        cli.backups,
+                    );
+                }
                 break;
             }
             Err(e) => {
@@ -168,5 +2009,339 @@ pub fn run() -> anyhow::Result<()> {
         }
     }
 
-    Ok(())
+    if clipboard::clear_pending() {
+        println!("clipboard cleared!");
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topics() {
+        // every example in a topic is a valid command of that topic
+        for (keyword, usage) in TOPICS {
+            for example in usage.lines().filter_map(|l| l.strip_prefix("    ")) {
+                let tokens = lex(example).unwrap();
+                assert!(parse(&tokens).is_ok(), "{}", example);
+                assert_eq!(usage_of(example), Some(*usage));
+                assert!(example.starts_with(keyword));
+            }
+        }
+    }
+
+    #[test]
+    fn test_usage_on_parse_failure() {
+        let failures = [
+            "set gmail pass",
+            "del",
+            "show user contains",
+            "reveal history",
+            "count",
+            "list user contains",
+            "history limit set",
+            "rollback gmail",
+            "rename gmail",
+            "rename all '('",
+            "dup gmail",
+            "clone gmail",
+            "undo 2",
+            "redo 2",
+            "trash gmail",
+            "undelete",
+            "restore gmail two",
+            "empty bin",
+            "merge",
+            "watch",
+            "unwatch",
+            "watches all",
+            "allow-duplicate-names maybe",
+            "copy",
+            "totp gmail digits 8",
+            "share",
+            "decode aws key",
+            "encode aws key base32",
+            "tag gmail",
+            "untag gmail 'a,b'",
+            "import 'a.txt' merge",
+            "export 'a.txt' 'b.txt'",
+            "size",
+            "check dupes",
+            "gen words",
+        ];
+
+        for line in failures {
+            let tokens = lex(line).unwrap();
+            assert!(parse(&tokens).is_err(), "{}", line);
+            let keyword = line.split_whitespace().next().unwrap();
+            assert_eq!(usage_of(line), topic(keyword), "{}", line);
+            assert!(usage_of(line).is_some(), "{}", line);
+        }
+
+        // every topic has a failure above
+        for (keyword, _) in TOPICS {
+            assert!(
+                failures.iter().any(|l| l.starts_with(keyword)),
+                "{}",
+                keyword
+            );
+        }
+
+        assert_eq!(usage_of("chmpw"), None);
+        assert_eq!(usage_of(""), None);
+    }
+
+    #[test]
+    fn test_deletion() {
+        let mut store = Store::new();
+        eval(
+            "set gmail user = zahash sensitive pass = 'ни шагу назад'",
+            &mut store,
+        )
+        .unwrap();
+        eval("set github user = zahash", &mut store).unwrap();
+        eval("set github url = github.com", &mut store).unwrap();
+
+        assert_eq!(
+            deletion("del gmail", &store).unwrap(),
+            "'gmail' pass=***** user='zahash'\ndelete 'gmail'?"
+        );
+        assert_eq!(
+            deletion("delete 'github'", &store).unwrap(),
+            "'github' url='github.com' user='zahash' — 1 earlier version in its history goes with it\ndelete 'github'?"
+        );
+        assert_eq!(
+            mass_deletion("del user is zahash", &store).unwrap(),
+            "delete 2 records?"
+        );
+        assert_eq!(
+            mass_deletion("del where has pass or has user", &store).unwrap(),
+            "delete 2 records?"
+        );
+
+        for line in [
+            "del gmail pass",
+            "del twitch",
+            "del user contains sussolini",
+            "del github user",
+            "show gmail",
+            "del",
+        ] {
+            assert_eq!(deletion(line, &store), None, "{}", line);
+            assert_eq!(mass_deletion(line, &store), None, "{}", line);
+        }
+        // a query that deletes just one record isn't asked about
+        assert_eq!(mass_deletion("del has pass", &store), None);
+        assert_eq!(deletion("del user is zahash", &store), None);
+    }
+
+    #[test]
+    fn test_complete() {
+        let names = ["gmail", "GitHub", "discord", "old mail"].map(String::from);
+        let complete = |line| complete(line, &names);
+
+        assert_eq!(complete("sh"), (0, vec!["show".into(), "share".into()]));
+        assert_eq!(complete("SI"), (0, vec!["size".into()]));
+        assert_eq!(complete("ch"), (0, vec!["check".into(), "chmpw".into()]));
+        assert_eq!(complete("chm"), (0, vec!["chmpw".into()]));
+        assert_eq!(
+            complete("show g"),
+            (5, vec!["gmail".into(), "GitHub".into()])
+        );
+        assert_eq!(complete("del GM"), (4, vec!["gmail".into()]));
+        assert_eq!(
+            complete("copy "),
+            (
+                5,
+                ["gmail", "GitHub", "discord", "'old mail'"]
+                    .map(String::from)
+                    .to_vec()
+            )
+        );
+        assert_eq!(complete("copy totp d"), (10, vec!["discord".into()]));
+        assert_eq!(
+            complete("reveal history 'ol"),
+            (15, vec!["'old mail'".into()])
+        );
+        assert_eq!(complete("rename gmail gi"), (13, vec![]));
+
+        // attrs and values are never completed
+        assert_eq!(complete("copy gmail p"), (11, vec![]));
+        assert_eq!(complete("set gmail pass = g"), (17, vec![]));
+        assert_eq!(complete("show user is g"), (13, vec![]));
+        assert_eq!(complete("x"), (0, vec![]));
+    }
+
+    #[test]
+    fn test_with_config() {
+        let config = Config {
+            fpath: Some("/vaults/main".into()),
+            history_limit: NonZeroU64::new(20),
+            kdf: Some(KdfArg::Argon2id),
+            pbkdf2_iterations: NonZeroU32::new(1000),
+            auto_save: Some(false),
+            author: Some("alice".into()),
+        };
+
+        let cli = Cli::parse_from(["royalguard"]).with_config(config.clone());
+        assert_eq!(cli.fpath.as_deref(), Some("/vaults/main"));
+        assert_eq!(cli.history_limit, Some(20));
+        assert_eq!(cli.kdf, Some(KdfArg::Argon2id));
+        assert_eq!(cli.iterations().get(), 1000);
+        assert!(!cli.auto_save());
+        assert_eq!(cli.author.as_deref(), Some("alice"));
+
+        let cli = Cli::parse_from([
+            "royalguard",
+            "-f",
+            "/vaults/other",
+            "--kdf",
+            "pbkdf2",
+            "--auto-save",
+            "true",
+            "--author",
+            "bob",
+        ])
+        .with_config(config);
+        assert_eq!(cli.fpath.as_deref(), Some("/vaults/other"));
+        assert_eq!(cli.history_limit, Some(20));
+        assert_eq!(cli.kdf, Some(KdfArg::Pbkdf2));
+        assert!(cli.auto_save());
+        assert_eq!(cli.author.as_deref(), Some("bob"));
+
+        let cli = Cli::parse_from(["royalguard"]).with_config(Config::default());
+        assert_eq!(cli.kdf, None);
+        assert_eq!(cli.iterations().get(), DEFAULT_ITERATIONS);
+        assert!(cli.auto_save());
+        assert_eq!(
+            cli.effective_config("/vaults/main", cli.kdf_params(KdfArg::Pbkdf2), &Store::new())
+                .to_toml(),
+            "fpath = \"/vaults/main\"\nhistory_limit = 50\nkdf = \"pbkdf2\"\npbkdf2_iterations = 100000\nauto_save = true\n"
+        );
+    }
+
+    #[test]
+    fn test_save_kdf() {
+        let save_kdf = |args: &[&str], file_kdf: Kdf| {
+            Cli::parse_from([&["royalguard"], args].concat()).save_kdf(file_kdf)
+        };
+        let pbkdf2 = |iterations| Kdf::Pbkdf2 {
+            iterations: NonZeroU32::new(iterations).unwrap(),
+        };
+
+        // kept, with the parameters given for it
+        assert_eq!(save_kdf(&[], pbkdf2(7)).unwrap(), pbkdf2(7));
+        assert_eq!(
+            save_kdf(&["--iterations", "9"], pbkdf2(7)).unwrap(),
+            pbkdf2(9)
+        );
+        assert_eq!(
+            save_kdf(&["--argon2-t-cost", "5"], Kdf::argon2id()).unwrap(),
+            Kdf::Argon2id {
+                m_cost: ARGON2_M_COST,
+                t_cost: 5,
+                p_cost: ARGON2_P_COST
+            }
+        );
+        // the parameters of the other kdf aren't ignored
+        assert!(save_kdf(&["--argon2-m-cost", "1024"], pbkdf2(7)).is_err());
+        assert!(save_kdf(&["--iterations", "9"], Kdf::argon2id()).is_err());
+        // unless it is switched to
+        assert_eq!(
+            save_kdf(&["--kdf", "argon2id"], pbkdf2(7)).unwrap(),
+            Kdf::argon2id()
+        );
+        assert_eq!(
+            save_kdf(&["--kdf", "pbkdf2", "--iterations", "9"], Kdf::argon2id()).unwrap(),
+            pbkdf2(9)
+        );
+
+        let too_much = (ARGON2_MAX_M_COST + 1).to_string();
+        assert!(Cli::try_parse_from(["royalguard", "--argon2-m-cost", &too_much]).is_err());
+    }
+
+    #[test]
+    fn test_compression() {
+        let compression = |args: &[&str]| {
+            Cli::try_parse_from([&["royalguard"], args].concat()).map(|cli| cli.compression())
+        };
+        assert_eq!(compression(&[]).unwrap(), Compression::Auto);
+        assert_eq!(compression(&["--compress"]).unwrap(), Compression::Always);
+        assert_eq!(compression(&["--no-compress"]).unwrap(), Compression::Never);
+        assert!(compression(&["--compress", "--no-compress"]).is_err());
+    }
+
+    #[test]
+    fn test_open_needs_newer_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("royalguard");
+        let fpath = fpath.to_str().unwrap();
+        let vault = r#"{"records":[],"version":"99.0.0","min_reader_version":"99.0.0"}"#;
+        let kdf = Kdf::Pbkdf2 {
+            iterations: NonZeroU32::new(1).unwrap(),
+        };
+        let sealed = seal(vault, &Password("master"), kdf, Compression::Never).unwrap();
+        std::fs::write(fpath, sealed).unwrap();
+
+        let e = open(
+            &Cli::parse_from(["royalguard"]),
+            fpath,
+            &Password("master"),
+            None,
+        )
+        .unwrap_err();
+        assert!(e.to_string().contains("needs royalguard 99.0.0 or newer"));
+
+        let cli = Cli::parse_from(["royalguard", "--read-only"]);
+        let (store, _, rekey) = open(&cli, fpath, &Password("master"), None).unwrap();
+        assert!(store.is_read_only());
+        assert!(!rekey);
+    }
+
+    #[test]
+    fn test_save_keeps_argon2id() {
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("royalguard");
+        let fpath = fpath.to_str().unwrap();
+        let argon2id = Kdf::Argon2id {
+            m_cost: 8,
+            t_cost: 1,
+            p_cost: 1,
+        };
+        let vault = r#"{"records":[],"version":"0.5.0"}"#;
+        let sealed = seal(vault, &Password("master"), argon2id, Compression::Never).unwrap();
+        std::fs::write(fpath, sealed).unwrap();
+
+        // without --kdf a save doesn't move the vault back to pbkdf2
+        let cli = Cli::parse_from(["royalguard"]);
+        let (store, kdf, rekey) = open(&cli, fpath, &Password("master"), None).unwrap();
+        assert_eq!(kdf, argon2id);
+        assert!(!rekey);
+        let settings = (kdf, Compression::Never);
+        assert!(save(fpath, "master", None, settings, &store, 0));
+
+        let pbkdf2 = Kdf::Pbkdf2 {
+            iterations: NonZeroU32::new(1).unwrap(),
+        };
+        let (_, kdf) = load(fpath, &Password("master"), pbkdf2).unwrap();
+        assert_eq!(kdf, argon2id);
+    }
+
+    #[test]
+    fn test_min_master_pass_strength() {
+        assert_eq!(Cli::parse_from(["royalguard"]).min_master_pass_strength, 2);
+        let cli = Cli::parse_from(["royalguard", "--min-master-pass-strength", "4"]);
+        assert_eq!(cli.min_master_pass_strength, 4);
+        assert!(Cli::try_parse_from(["royalguard", "--min-master-pass-strength", "5"]).is_err());
+
+        assert_eq!(
+            weakness("a1!").as_deref(),
+            Some("shorter than 4 characters")
+        );
+        assert!(weakness("password").is_some());
+        assert_eq!(weakness("q8#Vz!r2Lp@w9Xk$uT4m"), None);
+    }
 }
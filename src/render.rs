@@ -0,0 +1,775 @@
+use std::collections::BTreeSet;
+
+use chrono::{DateTime, Local};
+use ignorant::Ignore;
+
+use crate::clipboard::Copied;
+use crate::eval::Evaluation;
+use crate::store::DupStatus;
+use crate::store::DuplicateGroup;
+use crate::store::Field;
+use crate::store::FieldChange;
+use crate::store::FieldRenameStatus;
+use crate::store::HistoryDiff;
+use crate::store::HistoryEntry;
+use crate::store::KindMismatch;
+use crate::store::Record;
+use crate::store::RenameStatus;
+use crate::store::SizeReport;
+use crate::store::UndeleteStatus;
+use crate::store::TAGS;
+
+/// turns an evaluation into the lines that are shown to the user.
+/// every output format (and any future front-end) is a `Renderer`.
+pub trait Renderer {
+    fn render(&self, eval: Evaluation) -> Vec<String>;
+}
+
+/// the default human readable output
+pub struct PlainText;
+
+/// `show` and `reveal` as json. everything else falls back to `PlainText`
+pub struct Json;
+
+/// `show`, `reveal` and history as a table with one column per attr.
+/// everything else falls back to `PlainText`
+pub struct Table;
+
+impl Renderer for PlainText {
+    fn render(&self, eval: Evaluation) -> Vec<String> {
+        match eval {
+            Evaluation::Set => vec![],
+            Evaluation::SetWithWarnings(warnings) => warnings,
+            Evaluation::Del(record) => match record {
+                Some(record) => vec![fmt_deleted(record)],
+                None => vec![],
+            },
+            Evaluation::DelFields(record) => match record {
+                Some(record) => vec![fmt_record(record, true)],
+                None => vec![],
+            },
+            Evaluation::DelQuery(records) => {
+                let deleted = match records.len() {
+                    0 => "nothing matched, nothing deleted".to_string(),
+                    1 => "deleted 1 record".to_string(),
+                    n => format!("deleted {} records", n),
+                };
+                (sorted(records).into_iter())
+                    .map(|record| fmt_record(record, true))
+                    .chain([deleted])
+                    .collect()
+            }
+            Evaluation::SetWhere {
+                updated,
+                mut warnings,
+            } => {
+                warnings.push(match updated {
+                    0 => "nothing matched, nothing updated".to_string(),
+                    1 => "updated 1 record".to_string(),
+                    n => format!("updated {} records", n),
+                });
+                warnings
+            }
+            Evaluation::Show(records) => records
+                .into_iter()
+                .map(|record| fmt_record(record, true))
+                .collect(),
+            Evaluation::Reveal(records) => records
+                .into_iter()
+                .map(|record| fmt_record(record, false))
+                .collect(),
+            Evaluation::Count(count) => vec![count.to_string()],
+            Evaluation::List(mut names) => {
+                names.sort();
+                names
+            }
+            Evaluation::Coded(value) => vec![value],
+            Evaluation::Copy(copied) => match copied {
+                Some(copied) => vec![fmt_copied(copied)],
+                None => vec!["Unable to Copy! Try Again!".into()],
+            },
+            Evaluation::Gen(password) => match password {
+                Some(password) => vec![password],
+                None => vec!["Unable to Generate! Try Again!".into()],
+            },
+            Evaluation::Share { passphrase, copied } => match copied {
+                Some(copied) => vec![
+                    fmt_copied(copied),
+                    format!("passphrase (shown only once): {}", passphrase),
+                ],
+                None => vec!["Unable to Copy! Try Again!".into()],
+            },
+            Evaluation::Receive { name, mut warnings } => {
+                warnings.push(format!("received '{}'", name));
+                warnings
+            }
+            Evaluation::Totp {
+                code,
+                remaining,
+                copied,
+            } => {
+                let mut lines = vec![format!("{} (valid for {}s)", code, remaining.as_secs())];
+                lines.extend(copied.map(fmt_copied));
+                lines
+            }
+            Evaluation::History(history) => newest_first(history)
+                .into_iter()
+                .enumerate()
+                .map(|(i, h)| fmt_history(i, h, true))
+                .collect(),
+            Evaluation::RevealHistory(history) => newest_first(history)
+                .into_iter()
+                .enumerate()
+                .map(|(i, h)| fmt_history(i, h, false))
+                .collect(),
+            Evaluation::HistoryDiff(diffs) => fmt_history_diff(diffs, true),
+            Evaluation::RevealHistoryDiff(diffs) => fmt_history_diff(diffs, false),
+            Evaluation::AllowDuplicateNames(allow) => vec![format!(
+                "allow-duplicate-names: {}",
+                if allow { "on" } else { "off" }
+            )],
+            Evaluation::Rename((status, old, new)) => match status {
+                RenameStatus::OldNameNotFound => vec![format!("'{}' not found!", old)],
+                RenameStatus::NewNameAlreadyExists => vec![format!("'{}' already exists!", new)],
+                RenameStatus::Successful => vec!["Renamed!".into()],
+            },
+            Evaluation::BatchRename { renames, .. } if renames.is_empty() => {
+                vec!["nothing to rename".into()]
+            }
+            Evaluation::BatchRename { renames, dry_run } => renames
+                .into_iter()
+                .map(|(old, new, status)| match (status, dry_run) {
+                    (RenameStatus::Successful, false) => {
+                        format!("renamed '{}' to '{}'", old, new)
+                    }
+                    (RenameStatus::Successful, true) => {
+                        format!("would rename '{}' to '{}'", old, new)
+                    }
+                    _ => format!("'{}' not renamed, '{}' already exists!", old, new),
+                })
+                .collect(),
+            Evaluation::RenameField((status, record, old, new)) => match status {
+                FieldRenameStatus::RecordNotFound => vec![format!("'{}' not found!", record)],
+                FieldRenameStatus::OldAttrNotFound => {
+                    vec![format!("'{}' has no '{}'!", record, old)]
+                }
+                FieldRenameStatus::NewAttrAlreadyExists => {
+                    vec![format!("'{}' already has '{}'!", record, new)]
+                }
+                FieldRenameStatus::Successful => vec!["Renamed!".into()],
+            },
+            Evaluation::Dup {
+                status,
+                src,
+                dst,
+                record,
+            } => match (status, record) {
+                (DupStatus::Successful, Some(record)) => vec![fmt_record(record, true)],
+                (DupStatus::DstAlreadyExists, _) => vec![format!("'{}' already exists!", dst)],
+                _ => vec![format!("'{}' not found!", src)],
+            },
+            Evaluation::Rollback(record) => vec![fmt_record(record, true)],
+            Evaluation::Undo(cmd) => match cmd {
+                Some(cmd) => vec![format!("undo: {}", cmd)],
+                None => vec!["nothing to undo".into()],
+            },
+            Evaluation::Redo(cmd) => match cmd {
+                Some(cmd) => vec![format!("redo: {}", cmd)],
+                None => vec!["nothing to redo".into()],
+            },
+            Evaluation::Trash(records) if records.is_empty() => vec!["trash is empty".into()],
+            Evaluation::Trash(records) => records
+                .into_iter()
+                .map(|record| fmt_record(record, true))
+                .collect(),
+            Evaluation::Undelete((status, name)) => match status {
+                UndeleteStatus::NotInTrash => vec![format!("'{}' is not in the trash!", name)],
+                UndeleteStatus::NameAlreadyExists => vec![format!("'{}' already exists!", name)],
+                UndeleteStatus::Successful => vec!["Restored!".into()],
+            },
+            Evaluation::Merge {
+                added,
+                updated,
+                conflicts,
+            } => {
+                let mut lines = vec![match added {
+                    1 => format!("added 1 record, updated {}", updated),
+                    n => format!("added {} records, updated {}", n, updated),
+                }];
+                if !conflicts.is_empty() {
+                    lines.push(format!(
+                        "kept the newest of {} conflicting values:",
+                        conflicts.len()
+                    ));
+                }
+                for group in conflicts.chunk_by(|c1, c2| c1.author == c2.author) {
+                    let fields = group
+                        .iter()
+                        .map(|c| format!("'{}' {}", c.name, c.attr))
+                        .collect::<Vec<_>>();
+                    let author = group[0].author.as_deref().unwrap_or("unknown author");
+                    lines.push(format!("    {}: {}", author, fields.join(", ")));
+                }
+                lines
+            }
+            Evaluation::Watch {
+                query,
+                added,
+                matching,
+            } => match added {
+                true => vec![format!("watching {}: {} matching", query, matching)],
+                false => vec![format!("already watching {}", query)],
+            },
+            Evaluation::Unwatch { query, removed } => match removed {
+                true => vec![format!("stopped watching {}", query)],
+                false => vec![format!("not watching {}", query)],
+            },
+            Evaluation::Watches(watches) if watches.is_empty() => {
+                vec!["nothing is watched".into()]
+            }
+            Evaluation::Watches(watches) => watches
+                .into_iter()
+                .map(|(query, matching)| format!("{}: {} matching", query, matching))
+                .collect(),
+            Evaluation::EmptyTrash(0) => vec!["trash is empty".into()],
+            Evaluation::EmptyTrash(1) => vec!["deleted 1 record for good".into()],
+            Evaluation::EmptyTrash(deleted) => {
+                vec![format!("deleted {} records for good", deleted)]
+            }
+            Evaluation::Tag(record) => vec![fmt_record(record, true)],
+            Evaluation::PruneHistory(0) => vec!["nothing to remove from the history".into()],
+            Evaluation::PruneHistory(1) => vec!["removed 1 history entry".into()],
+            Evaluation::PruneHistory(removed) => {
+                vec![format!("removed {} history entries", removed)]
+            }
+            Evaluation::HistoryLimit(limit) => match limit {
+                Some(limit) => vec![format!("history limit: {} entries per record", limit)],
+                None => vec!["history limit: none, every entry is kept".into()],
+            },
+            Evaluation::Import {
+                imported,
+                skipped,
+                renamed,
+                mut warnings,
+            } => {
+                match skipped {
+                    0 => {}
+                    1 => warnings.push("skipped 1 existing record".into()),
+                    n => warnings.push(format!("skipped {} existing records", n)),
+                }
+                match renamed {
+                    0 => {}
+                    1 => warnings.push("renamed 1 colliding record".into()),
+                    n => warnings.push(format!("renamed {} colliding records", n)),
+                }
+                warnings.push(match imported {
+                    1 => "imported 1 record".to_string(),
+                    n => format!("imported {} records", n),
+                });
+                warnings
+            }
+            Evaluation::Export {
+                exported,
+                mut warnings,
+            } => {
+                warnings.push(match exported {
+                    1 => "exported 1 record".to_string(),
+                    n => format!("exported {} records", n),
+                });
+                warnings
+            }
+            eval @ (Evaluation::ShowJson(_) | Evaluation::RevealJson(_)) => {
+                vec![to_json(&eval).to_string()]
+            }
+            Evaluation::SizeReport(report) => fmt_size_report(report),
+            Evaluation::Duplicates { sensitive, plain } => fmt_duplicates(sensitive, plain),
+            Evaluation::KindMismatches(mismatches) => fmt_kind_mismatches(mismatches),
+            Evaluation::KeywordNames(names) => fmt_keyword_names(names),
+        }
+    }
+}
+
+impl Renderer for Json {
+    fn render(&self, eval: Evaluation) -> Vec<String> {
+        match to_json(&eval) {
+            serde_json::Value::Null => PlainText.render(eval),
+            json => vec![json.to_string()],
+        }
+    }
+}
+
+impl Renderer for Table {
+    fn render(&self, eval: Evaluation) -> Vec<String> {
+        match eval {
+            Evaluation::Show(records) => records_table(records, true),
+            Evaluation::Reveal(records) => records_table(records, false),
+            Evaluation::History(history) => history_table(newest_first(history), true),
+            Evaluation::RevealHistory(history) => history_table(newest_first(history), false),
+            eval => PlainText.render(eval),
+        }
+    }
+}
+
+/// `show` and `reveal` (also with `--json`) as a json array of records in the order they were evaluated in.
+/// like `fmt_fields`, `show` replaces sensitive values (also in history) with `null`.
+/// `count` is a plain number. other evaluations that aren't records have no json form
+/// and are `null`.
+pub fn to_json(eval: &Evaluation) -> serde_json::Value {
+    match eval {
+        Evaluation::Show(records) => records_json(records, true),
+        Evaluation::Reveal(records) => records_json(records, false),
+        Evaluation::ShowJson(records) => records_json(records, true),
+        Evaluation::RevealJson(records) => records_json(records, false),
+        Evaluation::Count(count) => (*count).into(),
+        _ => serde_json::Value::Null,
+    }
+}
+
+fn records_json(records: &[Record], sensitize: bool) -> serde_json::Value {
+    fn mask(fields: &mut serde_json::Value) {
+        for field in fields.as_array_mut().into_iter().flatten() {
+            if field["sensitive"] == true {
+                field["value"] = serde_json::Value::Null;
+            }
+        }
+    }
+
+    records
+        .iter()
+        .map(|record| {
+            let mut json = serde_json::to_value(record).unwrap_or_default();
+            if sensitize {
+                mask(&mut json["fields"]);
+                for history in json["history"].as_array_mut().into_iter().flatten() {
+                    mask(&mut history["fields"]);
+                }
+            }
+            json
+        })
+        .collect()
+}
+
+fn sorted(mut records: Vec<Record>) -> Vec<Record> {
+    records.sort_by(|r1, r2| r1.name.cmp(&r2.name));
+    records
+}
+
+fn newest_first(mut history: Vec<HistoryEntry>) -> Vec<HistoryEntry> {
+    history.sort_by(|h1, h2| h1.datetime.cmp(&h2.datetime).reverse());
+    history
+}
+
+fn fmt_copied(copied: Copied) -> String {
+    match copied {
+        Copied::Native { clear_after } => {
+            format!("Copied! will clear in {}s", clear_after.as_secs())
+        }
+        Copied::Terminal => "Copied via terminal escape! it won't be cleared automatically".into(),
+    }
+}
+
+fn fmt_record(record: Record, sensitize: bool) -> String {
+    use std::fmt::Write;
+
+    let mut buf = String::new();
+    write!(buf, "'{}'", record.name).ignore();
+    fmt_fields(record.fields, sensitize, &mut buf);
+
+    buf
+}
+
+/// the name (and tags) on the first line and then a field per line
+pub fn fmt_record_lines(record: Record, sensitize: bool) -> Vec<String> {
+    let (tags, mut fields): (Vec<_>, Vec<_>) =
+        record.fields.into_iter().partition(|f| f.attr == TAGS);
+    fields.sort_by(|f1, f2| f1.attr.cmp(&f2.attr));
+
+    let mut name = format!("'{}'", record.name);
+    fmt_fields(tags, sensitize, &mut name);
+    let mut lines = vec![name];
+    lines.extend(fields.into_iter().map(|field| {
+        let mut buf = "   ".to_string();
+        fmt_fields(vec![field], sensitize, &mut buf);
+        buf
+    }));
+    lines
+}
+
+/// with how much history goes along, which a record alone doesn't show.
+/// the newest history entry is the record as it is, so it isn't counted
+fn fmt_deleted(record: Record) -> String {
+    let earlier = record.history.entries().len().saturating_sub(1);
+    let record = fmt_record(record, true);
+    match earlier {
+        0 => record,
+        1 => format!("{} — 1 earlier version in its history goes with it", record),
+        n => format!(
+            "{} — {} earlier versions in its history go with it",
+            record, n
+        ),
+    }
+}
+
+/// `n` is the index `restore` takes, 0 for the newest
+pub fn fmt_history(n: usize, history: HistoryEntry, sensitize: bool) -> String {
+    let mut buf = fmt_history_header(n, history.datetime, history.author);
+    fmt_fields(history.fields, sensitize, &mut buf);
+    buf
+}
+
+/// like `fmt_history`, with one field per line below the header
+pub fn fmt_history_expanded(n: usize, history: HistoryEntry, sensitize: bool) -> Vec<String> {
+    let mut fields = history.fields;
+    fields.sort_by(|f1, f2| f1.attr.cmp(&f2.attr));
+
+    let mut lines = vec![fmt_history_header(n, history.datetime, history.author)];
+    lines.extend(fields.into_iter().map(|field| {
+        let mut buf = "   ".to_string();
+        fmt_fields(vec![field], sensitize, &mut buf);
+        buf
+    }));
+    lines
+}
+
+fn fmt_history_header(n: usize, datetime: DateTime<Local>, author: Option<String>) -> String {
+    let datetime = datetime.format("%Y-%m-%d %H:%M %:z");
+    match author {
+        Some(author) => format!("[{}] ({}) ({})", n, datetime, author),
+        None => format!("[{}] ({})", n, datetime),
+    }
+}
+
+/// newest first and numbered like the history, each entry followed by the fields
+/// it added (+), removed (-) and changed (~)
+fn fmt_history_diff(diffs: Vec<HistoryDiff>, sensitize: bool) -> Vec<String> {
+    (diffs.into_iter().rev().enumerate())
+        .flat_map(|(i, diff)| fmt_history_change(i, diff, sensitize))
+        .collect()
+}
+
+/// the header of the history entry with index `n` followed by what it changed
+pub fn fmt_history_change(n: usize, diff: HistoryDiff, sensitize: bool) -> Vec<String> {
+    let value = |field: &Field| match sensitize && field.sensitive {
+        true => "*****".to_string(),
+        false => format!("'{}'", field.value),
+    };
+
+    let mut lines = vec![fmt_history_header(n, diff.datetime, diff.author)];
+    lines.extend(diff.changes.iter().map(|change| match change {
+        FieldChange::Added(field) => format!("    + {}={}", field.attr, value(field)),
+        FieldChange::Removed(field) => format!("    - {}={}", field.attr, value(field)),
+        FieldChange::Changed { before, after } => {
+            format!(
+                "    ~ {}: {} -> {}",
+                after.attr,
+                value(before),
+                value(after)
+            )
+        }
+    }));
+    lines
+}
+
+/// the largest records one per line followed by the rest aggregated,
+/// then the records whose history is worth pruning
+fn fmt_size_report(report: SizeReport) -> Vec<String> {
+    const TOP: usize = 10;
+    const PRUNE_TOP: usize = 3;
+
+    let history = report.records.iter().map(|r| r.history).sum::<usize>();
+
+    let mut lines = vec![format!("{} bytes uncompressed", report.total)];
+    lines.extend(report.records.iter().take(TOP).map(|r| {
+        format!(
+            "'{}' {} bytes (fields {}, history {})",
+            r.name, r.total, r.fields, r.history
+        )
+    }));
+
+    let rest = &report.records[report.records.len().min(TOP)..];
+    if !rest.is_empty() {
+        lines.push(format!(
+            "{} other records {} bytes",
+            rest.len(),
+            rest.iter().map(|r| r.total).sum::<usize>()
+        ));
+    }
+
+    lines.push(format!("{} bytes of overhead", report.overhead));
+    lines.push(format!(
+        "history takes {} of the {} bytes",
+        history, report.total
+    ));
+
+    let prunable = report.records.iter().map(|r| r.prunable).sum::<usize>();
+    if prunable > 0 {
+        lines.push(format!(
+            "pruning every history to the current state saves {} bytes",
+            prunable
+        ));
+        let mut records = report
+            .records
+            .iter()
+            .filter(|r| r.prunable > 0)
+            .collect::<Vec<_>>();
+        records.sort_by(|r1, r2| r2.prunable.cmp(&r1.prunable).then(r1.name.cmp(&r2.name)));
+        lines.extend(records.iter().take(PRUNE_TOP).map(|r| {
+            format!(
+                "    history prune '{}' 1 saves {} bytes",
+                r.name, r.prunable
+            )
+        }));
+    }
+    lines
+}
+
+fn fmt_duplicates(sensitive: Vec<DuplicateGroup>, plain: Vec<DuplicateGroup>) -> Vec<String> {
+    fn fmt_group(group: DuplicateGroup, quote: bool) -> String {
+        let fields = group
+            .fields
+            .iter()
+            .map(|(name, attr)| format!("'{}' {}", name, attr))
+            .collect::<Vec<_>>();
+        let value = match quote {
+            true => format!("'{}'", group.value),
+            false => group.value,
+        };
+        format!("{} in {} fields: {}", value, group.count, fields.join(", "))
+    }
+
+    if sensitive.is_empty() && plain.is_empty() {
+        return vec!["no duplicate values".into()];
+    }
+
+    let mut lines = vec![];
+    if !sensitive.is_empty() {
+        lines.push("sensitive values used more than once:".into());
+        lines.extend(sensitive.into_iter().map(|g| fmt_group(g, false)));
+    }
+    if !plain.is_empty() {
+        lines.push("other values used more than once:".into());
+        lines.extend(plain.into_iter().map(|g| fmt_group(g, true)));
+    }
+    lines
+}
+
+fn fmt_kind_mismatches(mismatches: Vec<KindMismatch>) -> Vec<String> {
+    if mismatches.is_empty() {
+        return vec!["every value looks like its kind".into()];
+    }
+
+    (mismatches.into_iter())
+        .map(|m| {
+            let describe = m.kind.describe();
+            format!("'{}' {}: doesn't look like {}", m.name, m.attr, describe)
+        })
+        .collect()
+}
+
+fn fmt_keyword_names(names: Vec<(String, String)>) -> Vec<String> {
+    if names.is_empty() {
+        return vec!["no record is named like a keyword".into()];
+    }
+
+    (names.into_iter())
+        .map(|(name, rename)| {
+            format!(
+                "'{}' is the keyword {} without quotes. rename '{}' {}",
+                name, name, name, rename
+            )
+        })
+        .collect()
+}
+
+/// tags come first in brackets, apart from the other fields
+fn fmt_fields(mut fields: Vec<Field>, sensitize: bool, buf: &mut String) {
+    use std::fmt::Write;
+
+    if let Some(i) = fields.iter().position(|f| f.attr == TAGS) {
+        let tags = fields.remove(i);
+        let tags = tags.value.split(',').map(str::trim).collect::<Vec<_>>();
+        write!(buf, " [{}]", tags.join(", ")).ignore();
+    }
+    fields.sort_by(|f1, f2| f1.attr.cmp(&f2.attr));
+
+    for field in fields {
+        match sensitize && field.sensitive {
+            true => write!(buf, " {}=*****", field.attr),
+            false => write!(buf, " {}='{}'", field.attr, field.value),
+        }
+        .ignore()
+    }
+}
+
+fn records_table(records: Vec<Record>, sensitize: bool) -> Vec<String> {
+    let rows = records
+        .into_iter()
+        .map(|record| (record.name, record.fields))
+        .collect();
+    table("name", rows, sensitize)
+}
+
+fn history_table(history: Vec<HistoryEntry>, sensitize: bool) -> Vec<String> {
+    let rows = history
+        .into_iter()
+        .enumerate()
+        .map(|(i, h)| {
+            let datetime = h.datetime.format("%Y-%m-%d %H:%M %:z");
+            match h.author {
+                Some(author) => (format!("[{}] {} ({})", i, datetime, author), h.fields),
+                None => (format!("[{}] {}", i, datetime), h.fields),
+            }
+        })
+        .collect();
+    table("entry", rows, sensitize)
+}
+
+/// one row per `(key, fields)` and one column per attr (sorted).
+/// attrs missing from a row are left blank. an empty table has no lines at all
+fn table(key: &str, rows: Vec<(String, Vec<Field>)>, sensitize: bool) -> Vec<String> {
+    if rows.is_empty() {
+        return vec![];
+    }
+
+    let attrs = rows
+        .iter()
+        .flat_map(|(_, fields)| fields.iter().map(|f| f.attr.as_str()))
+        .collect::<BTreeSet<_>>();
+
+    let mut cells = vec![std::iter::once(key)
+        .chain(attrs.iter().copied())
+        .map(String::from)
+        .collect::<Vec<_>>()];
+    cells.extend(rows.iter().map(|(key, fields)| {
+        std::iter::once(key.clone())
+            .chain(
+                attrs
+                    .iter()
+                    .map(|attr| match fields.iter().find(|f| f.attr == *attr) {
+                        Some(field) if sensitize && field.sensitive => "*****".into(),
+                        Some(field) => field.value.clone(),
+                        None => String::new(),
+                    }),
+            )
+            .collect()
+    }));
+
+    let widths = (0..=attrs.len())
+        .map(|col| {
+            cells
+                .iter()
+                .map(|row| row[col].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+
+    let mut lines = cells
+        .into_iter()
+        .map(|row| {
+            row.iter()
+                .zip(&widths)
+                .map(|(cell, &width)| format!("{:width$}", cell, width = width))
+                .collect::<Vec<_>>()
+                .join(" | ")
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+    lines.insert(
+        1,
+        widths
+            .iter()
+            .map(|&width| "-".repeat(width))
+            .collect::<Vec<_>>()
+            .join("-+-"),
+    );
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::eval;
+    use crate::store::Store;
+    use pretty_assertions::assert_eq;
+
+    fn store() -> Store {
+        let mut store = Store::new();
+        for cmd in [
+            "set gmail user = zahash sensitive pass = gpass",
+            "set discord user = 'pablo susscobar' url = discord.com",
+        ] {
+            eval(cmd, &mut store).unwrap();
+        }
+        store
+    }
+
+    #[test]
+    fn test_json() {
+        let mut store = store();
+
+        let lines = Json.render(eval("show gmail", &mut store).unwrap());
+        let json = serde_json::from_str::<serde_json::Value>(&lines[0]).unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(json[0]["name"], "gmail");
+        assert!(json[0]["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|field| field["attr"] == "pass" && field["value"].is_null()));
+
+        assert_eq!(
+            Json.render(eval("rename gmail discord", &mut store).unwrap()),
+            ["'discord' already exists!"]
+        );
+        assert_eq!(
+            to_json(&eval("show user is zahash", &mut store).unwrap()),
+            serde_json::json!([json[0]])
+        );
+        assert!(to_json(&eval("set twitch", &mut store).unwrap()).is_null());
+    }
+
+    #[test]
+    fn test_table() {
+        let mut store = store();
+
+        assert_eq!(
+            Table.render(eval("show all", &mut store).unwrap()),
+            [
+                "name    | pass  | url         | user",
+                "--------+-------+-------------+----------------",
+                "discord |       | discord.com | pablo susscobar",
+                "gmail   | ***** |             | zahash",
+            ]
+        );
+        assert_eq!(
+            Table.render(eval("reveal gmail", &mut store).unwrap()),
+            [
+                "name  | pass  | user",
+                "------+-------+-------",
+                "gmail | gpass | zahash",
+            ]
+        );
+        assert!(Table
+            .render(eval("show twitch", &mut store).unwrap())
+            .is_empty());
+
+        store.set_author(Some("pablo".into()));
+        eval("set gmail pass = newpass", &mut store).unwrap();
+        let lines = Table.render(eval("reveal history gmail", &mut store).unwrap());
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("entry"));
+        assert!(lines[2].starts_with("[0] "));
+        assert!(lines[2].ends_with(" (pablo) | newpass | zahash"));
+        assert!(lines[3].starts_with("[1] "));
+        assert!(lines[3].ends_with("| gpass   | zahash"));
+        assert!(!lines[3].contains("pablo"));
+
+        assert_eq!(
+            Table.render(eval("rename gmail discord", &mut store).unwrap()),
+            ["'discord' already exists!"]
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use base64::Engine;
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::crypt::{seal, unseal, Compression, Kdf, Password};
+use crate::eval::passphrase;
+use crate::store::{Field, Record};
+
+/// marks the text on the clipboard as a share so that `receive`
+/// can tell it apart from whatever else might have been copied
+const PREFIX: &str = "royalguard-share:";
+
+const PASSPHRASE_WORDS: usize = 4;
+
+/// a single record on its way to someone else. history is never shared.
+#[derive(Serialize, Deserialize)]
+pub struct Shared {
+    pub name: String,
+    pub fields: Vec<Field>,
+    pub created: DateTime<Local>,
+}
+
+impl Shared {
+    /// shares are meant to be received right away. old ones have probably been
+    /// sitting in a chat log for anyone to try their luck with
+    pub fn is_stale(&self, now: DateTime<Local>) -> bool {
+        now - self.created > chrono::Duration::days(1)
+    }
+}
+
+/// the armored `record`, created `now`, and the freshly generated passphrase it is encrypted with
+pub fn share(record: Record, now: DateTime<Local>) -> anyhow::Result<(String, String)> {
+    let passphrase =
+        passphrase(PASSPHRASE_WORDS, " ").ok_or_else(|| anyhow::anyhow!("Passphrase Error."))?;
+    let shared = Shared {
+        name: record.name,
+        fields: record.fields,
+        created: now,
+    };
+
+    Ok((armor(&shared, &passphrase)?, passphrase))
+}
+
+pub fn receive(armored: &str, passphrase: &str) -> anyhow::Result<Shared> {
+    let sealed = armored
+        .trim()
+        .strip_prefix(PREFIX)
+        .ok_or_else(|| anyhow::anyhow!("not a royalguard share"))?;
+    let sealed = base64::engine::general_purpose::STANDARD
+        .decode(sealed)
+        .map_err(|_| anyhow::anyhow!("the share is corrupted"))?;
+    let (plain_text, _) = unseal(&sealed, &Password(passphrase.trim()))
+        .ok_or_else(|| anyhow::anyhow!("wrong passphrase or the share was tampered with"))?;
+
+    Ok(serde_json::from_slice(&plain_text)?)
+}
+
+/// the passphrase is short enough to read out loud, so argon2id makes up for it
+fn armor(shared: &Shared, passphrase: &str) -> anyhow::Result<String> {
+    let sealed = seal(
+        &serde_json::to_string(shared)?,
+        &Password(passphrase),
+        Kdf::argon2id(),
+        // shares stay readable by versions from before compression
+        Compression::Never,
+    )?;
+    Ok(format!(
+        "{}{}",
+        PREFIX,
+        base64::engine::general_purpose::STANDARD.encode(sealed)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypt::MAGIC_V2;
+    use crate::store::FieldKind;
+    use pretty_assertions::assert_eq;
+
+    fn shared(created: DateTime<Local>) -> Shared {
+        Shared {
+            name: "gmail".into(),
+            fields: vec![Field {
+                attr: "pass".into(),
+                value: "ни шагу назад".into(),
+                sensitive: true,
+                expires: None,
+                kind: FieldKind::Unknown,
+            }],
+            created,
+        }
+    }
+
+    fn sealed(armored: &str) -> Vec<u8> {
+        base64::engine::general_purpose::STANDARD
+            .decode(armored.strip_prefix(PREFIX).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_share() {
+        let record = Record {
+            id: uuid::Uuid::new_v4(),
+            name: "gmail".into(),
+            fields: shared(Local::now()).fields,
+            history: Default::default(),
+        };
+        let (armored, passphrase) = share(record.clone(), Local::now()).unwrap();
+        assert_eq!(passphrase.split(' ').count(), PASSPHRASE_WORDS);
+        assert_ne!(passphrase, share(record, Local::now()).unwrap().1);
+
+        let received = receive(&armored, &passphrase).unwrap();
+        assert_eq!(received.name, "gmail");
+        assert_eq!(received.fields, shared(Local::now()).fields);
+    }
+
+    #[test]
+    fn test_armor() {
+        let armored = armor(&shared(Local::now()), "abandon ability able about").unwrap();
+        assert!(armored.starts_with(PREFIX));
+        assert!(!armored.contains('\n'));
+
+        // same layout as the vault file, always with argon2id
+        let sealed = sealed(&armored);
+        assert_eq!(&sealed[..4], MAGIC_V2);
+        assert_eq!(
+            Kdf::from_bytes(&sealed[4..4 + Kdf::LEN]),
+            Some(Kdf::argon2id())
+        );
+
+        // surrounding whitespace from chat clients doesn't matter
+        let received = receive(&format!("  {}\n", armored), "abandon ability able about").unwrap();
+        assert_eq!(received.name, "gmail");
+        assert_eq!(received.fields, shared(Local::now()).fields);
+        assert!(!received.is_stale(received.created));
+    }
+
+    #[test]
+    fn test_tamper() {
+        let armored = armor(&shared(Local::now()), "abandon ability able about").unwrap();
+
+        let mut sealed = sealed(&armored);
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        let tampered = format!(
+            "{}{}",
+            PREFIX,
+            base64::engine::general_purpose::STANDARD.encode(sealed)
+        );
+
+        for (armored, passphrase) in [
+            (tampered.as_str(), "abandon ability able about"),
+            (armored.as_str(), "abandon ability able above"),
+            (&armored[PREFIX.len()..], "abandon ability able about"),
+            (&armored[..armored.len() - 3], "abandon ability able about"),
+        ] {
+            assert!(receive(armored, passphrase).is_err());
+        }
+    }
+
+    #[test]
+    fn test_stale() {
+        let created = DateTime::parse_from_rfc3339("2024-01-01T09:00:00+00:00")
+            .unwrap()
+            .with_timezone(&Local);
+        let armored = armor(&shared(created), "abandon ability able about").unwrap();
+
+        let received = receive(&armored, "abandon ability able about").unwrap();
+        assert_eq!(received.created, created);
+        assert!(!received.is_stale(created + chrono::Duration::hours(23)));
+        assert!(received.is_stale(created + chrono::Duration::hours(25)));
+    }
+}
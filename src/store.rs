@@ -1,16 +1,194 @@
+use std::cell::OnceCell;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+
 use chrono::prelude::*;
-use serde::{Deserialize, Serialize};
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::value::RawValue;
 use uuid::Uuid;
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 use crate::{
+    date,
     eval::Cond,
-    parse::{Assign, Query},
+    parse::{Assign, Expiry, Or, Query},
 };
 
+/// the reserved attr with the comma separated tags of a record.
+/// only `tag` and `untag` change it
+pub const TAGS: &str = "_tags";
+
+/// history entries kept per record unless the vault says otherwise
+pub const DEFAULT_HISTORY_LIMIT: usize = 50;
+
+/// most changes that `undo` can take back
+pub const UNDO_LIMIT: usize = 20;
+
+/// the oldest version that can open what this one saves without dropping some of it.
+/// raise it to the version that adds anything older ones don't know of
+/// (like `Field::expires` and `Field::kind`)
+pub const MIN_READER_VERSION: &str = "0.5.0";
+
+fn default_history_limit() -> Option<usize> {
+    Some(DEFAULT_HISTORY_LIMIT)
+}
+
+/// where the store gets the time its history entries are made at
+pub trait Clock: std::fmt::Debug + Send + Sync {
+    fn now(&self) -> DateTime<Local>;
+}
+
+/// the time of the machine. what every store uses unless told otherwise
+#[derive(Debug)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "StoreFile")]
 pub struct Store {
     records: Vec<Record>,
     version: String,
+
+    /// what the vault was saved with. always saved as `MIN_READER_VERSION`,
+    /// since a vault that needs more can't be saved (see `newer_reader_needed`)
+    #[serde(serialize_with = "serialize_min_reader_version")]
+    min_reader_version: Option<String>,
+
+    /// most history entries kept per record. `None` keeps all of them
+    history_limit: Option<usize>,
+
+    /// several records may share a name, told apart with `where`. off unless turned on
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    allow_duplicate_names: bool,
+
+    /// deleted records, oldest first, until `undelete` brings one back or `empty_trash`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    trash: Vec<Record>,
+
+    /// positions of the records in `records` by name. not saved, rebuilt on load
+    #[serde(skip)]
+    index: HashMap<String, Vec<usize>>,
+
+    /// who the history entries of this session are attributed to.
+    /// a setting of the machine, never saved to the vault
+    #[serde(skip)]
+    author: Option<String>,
+
+    /// the store before each of the latest changes, oldest first. never saved
+    #[serde(skip)]
+    undo: VecDeque<Snapshot>,
+
+    /// the store after each change that was taken back, latest undo last. never saved
+    #[serde(skip)]
+    redo: Vec<Snapshot>,
+
+    /// queries whose matches are reported when they change. never saved
+    #[serde(skip)]
+    watches: Vec<Watch>,
+
+    /// what changed for the watches, until the prompt prints it
+    #[serde(skip)]
+    notices: Vec<String>,
+
+    /// commands that would change it are refused (`--read-only`). never saved
+    #[serde(skip)]
+    read_only: bool,
+
+    /// when the changes of this session happen. never saved
+    #[serde(skip)]
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Watch {
+    /// as it is shown, which parses back to the same query
+    pub query: String,
+    /// what it matched when last checked
+    pub matched: Vec<Record>,
+}
+
+/// everything a command can change, and the command
+#[derive(Debug, Clone)]
+struct Snapshot {
+    cmd: String,
+    records: Vec<Record>,
+    history_limit: Option<usize>,
+    allow_duplicate_names: bool,
+    trash: Vec<Record>,
+}
+
+impl Snapshot {
+    fn matches(&self, store: &Store) -> bool {
+        fn same(before: &[Record], after: &[Record]) -> bool {
+            before.len() == after.len()
+                && (before.iter())
+                    .zip(after)
+                    .all(|(before, after)| before.same_as(after))
+        }
+
+        self.history_limit == store.history_limit
+            && self.allow_duplicate_names == store.allow_duplicate_names
+            && same(&self.records, &store.records)
+            && same(&self.trash, &store.trash)
+    }
+}
+
+/// what is actually saved to the vault file
+#[derive(Deserialize)]
+struct StoreFile {
+    records: Vec<Record>,
+    version: String,
+
+    /// missing in vaults from before it was saved, which any version can open
+    #[serde(default)]
+    min_reader_version: Option<String>,
+
+    /// missing in vaults from before the limit existed. `null` if it was cleared
+    #[serde(default = "default_history_limit")]
+    history_limit: Option<usize>,
+
+    #[serde(default)]
+    allow_duplicate_names: bool,
+
+    /// missing in vaults from before the trash existed
+    #[serde(default)]
+    trash: Vec<Record>,
+}
+
+impl From<StoreFile> for Store {
+    fn from(file: StoreFile) -> Self {
+        let mut store = Self {
+            records: file.records,
+            version: file.version,
+            min_reader_version: file.min_reader_version,
+            history_limit: file.history_limit,
+            allow_duplicate_names: file.allow_duplicate_names,
+            trash: file.trash,
+            index: HashMap::new(),
+            author: None,
+            undo: VecDeque::new(),
+            redo: vec![],
+            watches: vec![],
+            notices: vec![],
+            read_only: false,
+            clock: Arc::new(SystemClock),
+        };
+        store.reindex();
+        store
+    }
+}
+
+fn serialize_min_reader_version<S: Serializer>(
+    _: &Option<String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(MIN_READER_VERSION)
 }
 
 pub enum RenameStatus {
@@ -19,96 +197,1056 @@ pub enum RenameStatus {
     Successful,
 }
 
+pub enum UndeleteStatus {
+    NotInTrash,
+    NameAlreadyExists,
+    Successful,
+}
+
+pub enum FieldRenameStatus {
+    RecordNotFound,
+    OldAttrNotFound,
+    NewAttrAlreadyExists,
+    Successful,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DupStatus {
+    SrcNotFound,
+    DstAlreadyExists,
+    Successful,
+}
+
 impl<'text> Store {
     pub fn new() -> Self {
         Self {
             records: vec![],
             version: env!("CARGO_PKG_VERSION").to_string(),
+            min_reader_version: Some(MIN_READER_VERSION.into()),
+            history_limit: default_history_limit(),
+            allow_duplicate_names: false,
+            trash: vec![],
+            index: HashMap::new(),
+            author: None,
+            undo: VecDeque::new(),
+            redo: vec![],
+            watches: vec![],
+            notices: vec![],
+            read_only: false,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    pub fn set_author(&mut self, author: Option<String>) {
+        self.author = author;
+    }
+
+    /// so that tests can say when things happen
+    #[cfg(test)]
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// the time of the store's clock, for whatever depends on it outside the store
+    pub fn now(&self) -> DateTime<Local> {
+        self.clock.now()
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// the version the vault needs when this one is older, so that saving it would drop
+    /// what this one doesn't know of. one that can't be made sense of is needed too
+    pub fn newer_reader_needed(&self) -> Option<&str> {
+        let required = self.min_reader_version.as_deref()?;
+        let this = semver::Version::parse(env!("CARGO_PKG_VERSION")).ok()?;
+        match semver::Version::parse(required) {
+            Ok(version) if version <= this => None,
+            _ => Some(required),
+        }
+    }
+
+    pub fn history_limit(&self) -> Option<usize> {
+        self.history_limit
+    }
+
+    /// older entries are trimmed from each record the next time it changes
+    pub fn set_history_limit(&mut self, limit: Option<usize>) {
+        self.history_limit = limit;
+    }
+
+    pub fn allow_duplicate_names(&self) -> bool {
+        self.allow_duplicate_names
+    }
+
+    /// can't be turned off while names are still shared. those names are the `Err`
+    pub fn set_allow_duplicate_names(&mut self, allow: bool) -> Result<(), Vec<String>> {
+        let mut shared = self
+            .index
+            .iter()
+            .filter(|(_, positions)| positions.len() > 1)
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        shared.sort();
+
+        match allow || shared.is_empty() {
+            true => {
+                self.allow_duplicate_names = allow;
+                Ok(())
+            }
+            false => Err(shared),
+        }
+    }
+
+    /// remembers the store as it is before `cmd` changes it.
+    /// only the latest `UNDO_LIMIT` are kept
+    pub fn checkpoint(&mut self, cmd: String) {
+        let snapshot = self.snapshot(cmd);
+        self.push_undo(snapshot);
+    }
+
+    /// forgets the latest checkpoint if the command didn't change anything after all.
+    /// otherwise the changes that were taken back can't be redone anymore
+    pub fn forget_unchanged(&mut self) {
+        match self
+            .undo
+            .back()
+            .is_some_and(|snapshot| snapshot.matches(self))
+        {
+            true => {
+                self.undo.pop_back();
+            }
+            false => self.redo.clear(),
+        }
+    }
+
+    /// puts the store back the way it was before the latest change,
+    /// history included. returns the command that made the change
+    pub fn undo(&mut self) -> Option<String> {
+        let snapshot = self.undo.pop_back()?;
+        self.redo.push(self.snapshot(snapshot.cmd.clone()));
+        Some(self.apply(snapshot))
+    }
+
+    /// makes the latest change that `undo` took back again.
+    /// returns the command that made the change
+    pub fn redo(&mut self) -> Option<String> {
+        let snapshot = self.redo.pop()?;
+        let before = self.snapshot(snapshot.cmd.clone());
+        self.push_undo(before);
+        Some(self.apply(snapshot))
+    }
+
+    /// returns whether the query wasn't watched already
+    pub fn watch(&mut self, query: String, matched: Vec<Record>) -> bool {
+        if self.watches.iter().any(|watch| watch.query == query) {
+            return false;
+        }
+        self.watches.push(Watch { query, matched });
+        true
+    }
+
+    /// returns whether the query was watched
+    pub fn unwatch(&mut self, query: &str) -> bool {
+        let before = self.watches.len();
+        self.watches.retain(|watch| watch.query != query);
+        self.watches.len() < before
+    }
+
+    /// in the order they were added
+    pub fn watches(&self) -> &[Watch] {
+        &self.watches
+    }
+
+    /// remembers what the `i`th watch matches now and what changed about it
+    pub fn rewatch(&mut self, i: usize, matched: Vec<Record>, notice: String) {
+        self.watches[i].matched = matched;
+        self.notices.push(notice);
+    }
+
+    pub fn take_notices(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.notices)
+    }
+
+    fn snapshot(&self, cmd: String) -> Snapshot {
+        Snapshot {
+            cmd,
+            records: self.records.clone(),
+            history_limit: self.history_limit,
+            allow_duplicate_names: self.allow_duplicate_names,
+            trash: self.trash.clone(),
+        }
+    }
+
+    /// only the latest `UNDO_LIMIT` are kept
+    fn push_undo(&mut self, snapshot: Snapshot) {
+        if self.undo.len() == UNDO_LIMIT {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(snapshot);
+    }
+
+    /// returns the command of the snapshot
+    fn apply(&mut self, snapshot: Snapshot) -> String {
+        self.records = snapshot.records;
+        self.history_limit = snapshot.history_limit;
+        self.allow_duplicate_names = snapshot.allow_duplicate_names;
+        self.trash = snapshot.trash;
+        self.reindex();
+        snapshot.cmd
+    }
+
+    fn reindex(&mut self) {
+        self.index.clear();
+        for (i, record) in self.records.iter().enumerate() {
+            self.index.entry(record.name.clone()).or_default().push(i);
+        }
+    }
+
+    /// the records named `name` that match `filter`
+    fn positions(&self, name: &str, filter: Option<&Or<'text>>) -> Vec<usize> {
+        let now = self.clock.now();
+        self.index
+            .get(name)
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|&i| filter.is_none_or(|filter| filter.test(&self.records[i], now)))
+            .collect()
+    }
+
+    /// the only record named `name` that matches `filter`. `None` if it is ambiguous
+    fn position(&self, name: &str, filter: Option<&Or<'text>>) -> Option<usize> {
+        match self.positions(name, filter).as_slice() {
+            &[i] => Some(i),
+            _ => None,
         }
     }
 
+    fn find(&self, name: &str) -> Option<&Record> {
+        self.position(name, None).map(|i| &self.records[i])
+    }
+
+    fn find_mut(&mut self, name: &str) -> Option<&mut Record> {
+        self.position(name, None).map(|i| &mut self.records[i])
+    }
+
+    /// every record named `name` that matches `filter`. more than one is ambiguous
+    pub fn pick(&self, name: &str, filter: Option<&Or<'text>>) -> Vec<Record> {
+        self.positions(name, filter)
+            .into_iter()
+            .map(|i| self.records[i].clone())
+            .collect()
+    }
+
     pub fn get(&self, query: Query<'text>) -> Vec<Record> {
+        let now = self.clock.now();
         match query {
             Query::All => self.records.clone(),
-            Query::Name(name) => {
-                Vec::from_iter(self.records.iter().find(|r| r.name == name).cloned())
-            }
+            Query::Name(name) => self.pick(name, None),
             Query::Or(cond) => self
                 .records
                 .iter()
-                .filter(|data| cond.test(data))
+                .filter(|data| cond.test(data, now))
                 .cloned()
                 .collect(),
         }
     }
 
-    pub fn set(&mut self, name: &'text str, assignments: Vec<Assign<'text>>) {
-        let record = match self.records.iter_mut().find(|r| r.name == name) {
-            Some(r) => r,
+    /// how many records `get` would return, without cloning them
+    pub fn count(&self, query: Query<'text>) -> usize {
+        let now = self.clock.now();
+        match query {
+            Query::All => self.records.len(),
+            Query::Name(name) => self.positions(name, None).len(),
+            Query::Or(cond) => self
+                .records
+                .iter()
+                .filter(|data| cond.test(data, now))
+                .count(),
+        }
+    }
+
+    /// the names of the records `get` would return, without cloning the records
+    pub fn names_matching(&self, query: Query<'text>) -> Vec<String> {
+        let name = |record: &Record| record.name.clone();
+        let now = self.clock.now();
+        match query {
+            Query::All => self.records.iter().map(name).collect(),
+            Query::Name(n) => self
+                .positions(n, None)
+                .into_iter()
+                .map(|i| name(&self.records[i]))
+                .collect(),
+            Query::Or(cond) => self
+                .records
+                .iter()
+                .filter(|data| cond.test(data, now))
+                .map(name)
+                .collect(),
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names = self.index.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.index.contains_key(name)
+    }
+
+    /// updates the record named `name` that matches `filter`, otherwise adds a new one
+    pub fn set(
+        &mut self,
+        name: &'text str,
+        filter: Option<&Or<'text>>,
+        assignments: Vec<Assign<'text>>,
+    ) {
+        let i = match self.position(name, filter) {
+            Some(i) => i,
             None => {
                 self.records.push(Record {
                     id: Uuid::new_v4(),
                     name: name.to_string(),
                     fields: vec![],
-                    history: vec![],
+                    history: History::default(),
                 });
-                self.records.last_mut().unwrap()
+                self.index
+                    .entry(name.to_string())
+                    .or_default()
+                    .push(self.records.len() - 1);
+                self.records.len() - 1
             }
         };
+        self.assign(i, assignments);
+    }
+
+    /// `set` on every record that matches `filter`, each with a history entry of its own.
+    /// returns how many there were
+    pub fn set_where(&mut self, filter: &Or<'text>, assignments: Vec<Assign<'text>>) -> usize {
+        let now = self.clock.now();
+        let matching = (0..self.records.len())
+            .filter(|&i| filter.test(&self.records[i], now))
+            .collect::<Vec<_>>();
+        for &i in &matching {
+            self.assign(i, assignments.clone());
+        }
+        matching.len()
+    }
+
+    fn assign(&mut self, i: usize, assignments: Vec<Assign<'text>>) {
+        let now = self.clock.now();
+        let record = &mut self.records[i];
 
         for Assign {
             attr,
             value,
             sensitive,
+            expires,
+            kind,
         } in assignments
         {
+            let old = record.fields.iter().find(|f| f.attr == attr);
+            let (kept, kept_kind) = (old.and_then(|f| f.expires), old.map(|f| f.kind));
             record.fields.retain(|f| f.attr != attr);
             record.fields.push(Field {
                 attr: attr.to_string(),
                 value: value.to_string(),
                 sensitive,
+                expires: match expires {
+                    Some(Expiry::In(span)) => Some(days_until(now, span.after(now))),
+                    Some(Expiry::On(date)) => {
+                        let until = date::parse_date(date, &*self.clock).ok();
+                        Some(days_until(now, until))
+                    }
+                    Some(Expiry::Never) => None,
+                    None => kept,
+                },
+                kind: kind.or(kept_kind).unwrap_or_default(),
             });
         }
 
-        record.update_history();
+        record.update_history(self.clock.now(), self.author.as_deref(), self.history_limit);
     }
 
-    pub fn rename(&mut self, old: &str, new: &str) -> RenameStatus {
-        if self.records.iter().any(|r| r.name == new) {
+    pub fn rename(&mut self, old: &str, filter: Option<&Or<'text>>, new: &str) -> RenameStatus {
+        if !self.allow_duplicate_names && self.contains(new) {
             return RenameStatus::NewNameAlreadyExists;
         };
 
-        let Some(record) = self.records.iter_mut().find(|r| r.name == old) else {
+        let Some(i) = self.position(old, filter) else {
             return RenameStatus::OldNameNotFound;
         };
 
-        record.name = new.into();
+        self.records[i].name = new.into();
+        self.reindex();
         RenameStatus::Successful
     }
 
+    /// renames every record whose name `pattern` matches, replacing the matches with
+    /// `replacement` (`$1` for a group). a record whose new name is taken is left as is
+    /// and the others are still renamed
+    pub fn batch_rename(
+        &mut self,
+        pattern: &Regex,
+        replacement: &str,
+    ) -> Vec<(String, String, RenameStatus)> {
+        let renames = self.batch_renames(pattern, replacement);
+        for (i, _, new, status) in &renames {
+            if let RenameStatus::Successful = status {
+                self.records[*i].name = new.clone();
+            }
+        }
+        self.reindex();
+
+        renames
+            .into_iter()
+            .map(|(_, old, new, status)| (old, new, status))
+            .collect()
+    }
+
+    /// what `batch_rename` would do, without doing it
+    pub fn preview_batch_rename(
+        &self,
+        pattern: &Regex,
+        replacement: &str,
+    ) -> Vec<(String, String, RenameStatus)> {
+        self.batch_renames(pattern, replacement)
+            .into_iter()
+            .map(|(_, old, new, status)| (old, new, status))
+            .collect()
+    }
+
+    /// the position, old and new name of each record to rename, in the order of the names.
+    /// a name is taken once an earlier record is renamed to it and free once renamed away from
+    fn batch_renames(
+        &self,
+        pattern: &Regex,
+        replacement: &str,
+    ) -> Vec<(usize, String, String, RenameStatus)> {
+        let mut taken = self.index.keys().cloned().collect::<HashSet<_>>();
+        let mut renames = vec![];
+
+        for old in self.names() {
+            let new = pattern.replace_all(&old, replacement).into_owned();
+            if new == old {
+                continue;
+            }
+
+            for &i in &self.index[&old] {
+                let status = match !self.allow_duplicate_names && taken.contains(&new) {
+                    true => RenameStatus::NewNameAlreadyExists,
+                    false => {
+                        taken.remove(&old);
+                        taken.insert(new.clone());
+                        RenameStatus::Successful
+                    }
+                };
+                renames.push((i, old.clone(), new.clone(), status));
+            }
+        }
+
+        renames
+    }
+
+    pub fn rename_field(
+        &mut self,
+        name: &str,
+        filter: Option<&Or<'text>>,
+        old: &str,
+        new: &str,
+    ) -> FieldRenameStatus {
+        let Some(i) = self.position(name, filter) else {
+            return FieldRenameStatus::RecordNotFound;
+        };
+
+        let record = &mut self.records[i];
+        if !record.fields.iter().any(|f| f.attr == old) {
+            return FieldRenameStatus::OldAttrNotFound;
+        }
+        if record.fields.iter().any(|f| f.attr == new) {
+            return FieldRenameStatus::NewAttrAlreadyExists;
+        }
+
+        for field in record.fields.iter_mut().filter(|f| f.attr == old) {
+            field.attr = new.into();
+        }
+        record.update_history(self.clock.now(), self.author.as_deref(), self.history_limit);
+        FieldRenameStatus::Successful
+    }
+
+    /// copies every field of `src` into a new record named `dst`, whose history
+    /// starts over from the copy
+    pub fn duplicate(&mut self, src: &str, dst: &str) -> DupStatus {
+        if !self.allow_duplicate_names && self.contains(dst) {
+            return DupStatus::DstAlreadyExists;
+        }
+
+        let Some(i) = self.position(src, None) else {
+            return DupStatus::SrcNotFound;
+        };
+
+        let mut record = Record {
+            id: Uuid::new_v4(),
+            name: dst.to_string(),
+            fields: self.records[i].fields.clone(),
+            history: History::default(),
+        };
+        record.update_history(self.clock.now(), self.author.as_deref(), self.history_limit);
+
+        self.records.push(record);
+        self.index
+            .entry(dst.to_string())
+            .or_default()
+            .push(self.records.len() - 1);
+        DupStatus::Successful
+    }
+
     pub fn history(&self, name: &str) -> Vec<HistoryEntry> {
-        match self.records.iter().find(|r| r.name == name) {
-            Some(record) => record.history.clone(),
+        match self.find(name) {
+            Some(record) => record.history.entries().to_vec(),
             None => vec![],
         }
     }
 
-    pub fn remove(&mut self, name: &str) -> Option<Record> {
-        let record = self.records.iter().find(|r| r.name == name).cloned();
-        self.records.retain(|r| r.name != name);
-        record
+    /// every history entry of `name` as the changes it made, oldest first.
+    /// the oldest entry adds all of its fields
+    pub fn history_diff(&self, name: &str) -> Vec<HistoryDiff> {
+        let mut history = self.history(name);
+        history.sort_by_key(|h| h.datetime);
+
+        let mut before = vec![];
+        history
+            .into_iter()
+            .map(|h| {
+                let changes = diff_fields(&before, &h.fields);
+                before = h.fields;
+                HistoryDiff {
+                    datetime: h.datetime,
+                    author: h.author,
+                    changes,
+                }
+            })
+            .collect()
+    }
+
+    /// keeps only the `keep` newest history entries, the newest being the current state.
+    /// how many entries were dropped, `None` if there is no such record
+    pub fn prune_history(&mut self, name: &str, keep: usize) -> Option<usize> {
+        let (clock, author) = (self.clock.clone(), self.author.clone());
+        let record = self.find_mut(name)?;
+        record.update_history(clock.now(), author.as_deref(), None);
+        let history = record.history.entries_mut();
+        let removed = history.len().saturating_sub(keep);
+        history.drain(..removed);
+        Some(removed)
+    }
+
+    /// restores the fields of the `steps`th newest history entry (1 is the current state).
+    /// the restored state becomes the newest entry, so a rollback can be rolled back too.
+    pub fn rollback(&mut self, name: &str, steps: usize) -> Option<Record> {
+        let (clock, author, limit) = (self.clock.clone(), self.author.clone(), self.history_limit);
+        let record = self.find_mut(name)?;
+        let history = record.history.entries();
+        let i = history.len().checked_sub(steps)?;
+        record.fields = history[i].fields.clone();
+        record.update_history(clock.now(), author.as_deref(), limit);
+        Some(record.clone())
+    }
+
+    /// restores the fields of the history entry `index` places from the newest (0 is the
+    /// current state), the index `history` shows. like `rollback`, the restored state
+    /// becomes the newest entry instead of the history being rewritten
+    pub fn restore(&mut self, name: &str, index: usize) -> Option<Record> {
+        self.rollback(name, index.checked_add(1)?)
+    }
+
+    /// moves every record that matches `query` to the trash
+    pub fn remove_query(&mut self, query: Query<'text>) -> Vec<Record> {
+        let now = self.clock.now();
+        let (removed, kept): (Vec<_>, _) = std::mem::take(&mut self.records)
+            .into_iter()
+            .partition(|record| query.test(record, now));
+        self.records = kept;
+        self.trash.extend(removed.iter().cloned());
+        self.reindex();
+        removed
+    }
+
+    /// moves the record to the trash
+    pub fn remove(&mut self, name: &str, filter: Option<&Or<'text>>) -> Option<Record> {
+        let i = self.position(name, filter)?;
+        let record = self.records.remove(i);
+        self.trash.push(record.clone());
+        self.reindex();
+        Some(record)
+    }
+
+    /// the deleted records, oldest first
+    pub fn trash(&self) -> Vec<Record> {
+        self.trash.clone()
+    }
+
+    /// brings back the latest deleted record named `name`, history included
+    pub fn undelete(&mut self, name: &str) -> UndeleteStatus {
+        let Some(i) = self.trash.iter().rposition(|record| record.name == name) else {
+            return UndeleteStatus::NotInTrash;
+        };
+        if !self.allow_duplicate_names && self.contains(name) {
+            return UndeleteStatus::NameAlreadyExists;
+        }
+
+        let record = self.trash.remove(i);
+        self.records.push(record);
+        self.reindex();
+        UndeleteStatus::Successful
+    }
+
+    /// deletes the trashed records for good. returns how many there were
+    pub fn empty_trash(&mut self) -> usize {
+        std::mem::take(&mut self.trash).len()
     }
 
-    pub fn remove_attrs(&mut self, name: &str, attrs: &[&str]) -> Option<Record> {
-        if let Some(record) = self.records.iter_mut().find(|r| r.name == name) {
-            record.fields.retain(|f| !attrs.contains(&f.attr.as_str()));
-            record.update_history();
-            return Some(record.clone());
+    /// adds the records of `other` that aren't here and unions the fields and histories of
+    /// the ones that are. records are the same if they have the same id, otherwise the same
+    /// name unless several records share it. the trash of `other` is left out
+    pub fn merge(&mut self, other: Store) -> MergeReport {
+        let sorted = |fields: &[Field]| {
+            let mut fields = fields.to_vec();
+            fields.sort_by(|f1, f2| f1.attr.cmp(&f2.attr));
+            fields
+        };
+        let (clock, author, limit) = (self.clock.clone(), self.author.clone(), self.history_limit);
+        let mut report = MergeReport {
+            added: 0,
+            updated: 0,
+            conflicts: vec![],
+        };
+
+        for theirs in other.records {
+            let i = (self.records.iter().position(|r| r.id == theirs.id)).or_else(|| {
+                match self.positions(&theirs.name, None).as_slice() {
+                    &[i] => Some(i),
+                    _ => None,
+                }
+            });
+            let Some(i) = i else {
+                self.records.push(theirs);
+                self.reindex();
+                report.added += 1;
+                continue;
+            };
+
+            let record = &mut self.records[i];
+            let before = sorted(&record.fields);
+            let conflicts = record.merge(&theirs);
+            record.update_history(clock.now(), author.as_deref(), limit);
+
+            if sorted(&record.fields) != before {
+                report.updated += 1;
+            }
+            report
+                .conflicts
+                .extend(conflicts.into_iter().map(|(attr, author)| MergeConflict {
+                    name: record.name.clone(),
+                    attr,
+                    author,
+                }));
+        }
+
+        report.conflicts.sort_by(|c1, c2| {
+            (c1.author.is_none(), &c1.author, &c1.name, &c1.attr).cmp(&(
+                c2.author.is_none(),
+                &c2.author,
+                &c2.name,
+                &c2.attr,
+            ))
+        });
+        report
+    }
+
+    /// attributes the bytes of the serialized store to each record.
+    /// the same serialization as the vault file is used, only without the encryption.
+    pub fn size_report(&self) -> SizeReport {
+        fn len<T: Serialize>(value: &T) -> usize {
+            serde_json::to_vec(value).map_or(0, |bytes| bytes.len())
+        }
+
+        let empty = Store {
+            records: vec![],
+            version: self.version.clone(),
+            min_reader_version: self.min_reader_version.clone(),
+            history_limit: self.history_limit,
+            allow_duplicate_names: self.allow_duplicate_names,
+            trash: self.trash.clone(),
+            index: HashMap::new(),
+            author: None,
+            undo: VecDeque::new(),
+            redo: vec![],
+            watches: vec![],
+            notices: vec![],
+            read_only: false,
+            clock: self.clock.clone(),
+        };
+
+        let now = self.clock.now();
+        let mut records = self
+            .records
+            .iter()
+            .map(|record| {
+                // what `history prune <name> 1` would leave behind
+                let mut pruned = record.clone();
+                pruned.update_history(now, self.author.as_deref(), Some(1));
+                let total = len(record);
+                RecordSize {
+                    name: record.name.clone(),
+                    total,
+                    fields: len(&record.fields),
+                    history: len(&record.history),
+                    prunable: total.saturating_sub(len(&pruned)),
+                }
+            })
+            .collect::<Vec<_>>();
+        records.sort_by(|r1, r2| r2.total.cmp(&r1.total).then(r1.name.cmp(&r2.name)));
+
+        SizeReport {
+            total: len(self),
+            // the rest of the store plus the commas between records
+            overhead: len(&empty) + self.records.len().saturating_sub(1),
+            records,
+        }
+    }
+
+    /// sensitive values held by more than one field, like a password used twice
+    pub fn find_duplicate_sensitive_values(&self) -> Vec<DuplicateGroup> {
+        self.duplicate_values(true)
+    }
+
+    pub fn find_duplicate_values(&self) -> Vec<DuplicateGroup> {
+        self.duplicate_values(false)
+    }
+
+    /// the fields with the same value, most fields first. tags are left out
+    fn duplicate_values(&self, sensitive: bool) -> Vec<DuplicateGroup> {
+        let mut fields = HashMap::<&str, Vec<(String, String)>>::new();
+        for record in &self.records {
+            for field in &record.fields {
+                if field.sensitive == sensitive && field.attr != TAGS && !field.value.is_empty() {
+                    fields
+                        .entry(&field.value)
+                        .or_default()
+                        .push((record.name.clone(), field.attr.clone()));
+                }
+            }
+        }
+
+        let mut groups = fields
+            .into_iter()
+            .filter(|(_, fields)| fields.len() > 1)
+            .map(|(value, mut fields)| {
+                fields.sort();
+                DuplicateGroup {
+                    value: match sensitive {
+                        true => "*****".into(),
+                        false => value.into(),
+                    },
+                    count: fields.len(),
+                    fields,
+                }
+            })
+            .collect::<Vec<_>>();
+        groups.sort_by(|g1, g2| g2.count.cmp(&g1.count).then(g1.fields.cmp(&g2.fields)));
+        groups
+    }
+
+    /// the fields whose value doesn't look like their kind, stored or told by the attr.
+    /// sorted by name and attr
+    pub fn find_kind_mismatches(&self) -> Vec<KindMismatch> {
+        let mut mismatches = (self.records.iter())
+            .flat_map(|record| {
+                (record.fields.iter())
+                    .filter(|field| !field.kind().accepts(&field.value))
+                    .map(|field| KindMismatch {
+                        name: record.name.clone(),
+                        attr: field.attr.clone(),
+                        kind: field.kind(),
+                    })
+            })
+            .collect::<Vec<_>>();
+        mismatches.sort_by(|m1, m2| (&m1.name, &m1.attr).cmp(&(&m2.name, &m2.attr)));
+        mismatches
+    }
+
+    /// adds the `tags` that `name` doesn't have yet
+    pub fn tag(&mut self, name: &str, tags: &[&str]) -> Option<Record> {
+        self.retag(name, |current| {
+            for tag in tags {
+                if !current.iter().any(|t| t == tag) {
+                    current.push(tag.to_string());
+                }
+            }
+        })
+    }
+
+    pub fn untag(&mut self, name: &str, tags: &[&str]) -> Option<Record> {
+        self.retag(name, |current| {
+            current.retain(|t| !tags.contains(&t.as_str()))
+        })
+    }
+
+    /// rewrites the `TAGS` field with what `f` makes of the current tags.
+    /// the field is removed along with the last tag
+    fn retag(&mut self, name: &str, f: impl FnOnce(&mut Vec<String>)) -> Option<Record> {
+        let (clock, author, limit) = (self.clock.clone(), self.author.clone(), self.history_limit);
+        let record = self.find_mut(name)?;
+        let current = record
+            .tags()
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        let mut tags = current.clone();
+        f(&mut tags);
+
+        if tags != current {
+            record.fields.retain(|f| f.attr != TAGS);
+            if !tags.is_empty() {
+                record.fields.push(Field {
+                    attr: TAGS.into(),
+                    value: tags.join(","),
+                    sensitive: false,
+                    expires: None,
+                    kind: FieldKind::Unknown,
+                });
+            }
+            record.update_history(clock.now(), author.as_deref(), limit);
+        }
+        Some(record.clone())
+    }
+
+    pub fn remove_attrs(
+        &mut self,
+        name: &str,
+        filter: Option<&Or<'text>>,
+        attrs: &[&str],
+    ) -> Option<Record> {
+        let i = self.position(name, filter)?;
+        let record = &mut self.records[i];
+        record.fields.retain(|f| !attrs.contains(&f.attr.as_str()));
+        record.update_history(self.clock.now(), self.author.as_deref(), self.history_limit);
+        Some(record.clone())
+    }
+
+    /// anything the store itself never produces, as far as it can tell.
+    /// records in the trash count for duplicate ids
+    pub fn verify(&self) -> Vec<IntegrityError> {
+        self.verify_at(self.clock.now())
+    }
+
+    fn verify_at(&self, now: DateTime<Local>) -> Vec<IntegrityError> {
+        let latest = now + chrono::Duration::minutes(CLOCK_SKEW_MINUTES);
+        let mut errors = vec![];
+
+        let mut ids = HashSet::new();
+        for record in self.records.iter().chain(&self.trash) {
+            if !ids.insert(record.id) {
+                errors.push(IntegrityError::DuplicateId {
+                    name: record.name.clone(),
+                    id: record.id,
+                });
+            }
+        }
+
+        for record in &self.records {
+            let name = || record.name.clone();
+            if !record.history.is_readable() {
+                errors.push(IntegrityError::UnreadableHistory { name: name() });
+                continue;
+            }
+            let entries = record.history.entries();
+            if entries.windows(2).any(|w| w[0].datetime > w[1].datetime) {
+                errors.push(IntegrityError::UnorderedHistory { name: name() });
+            }
+            if let Some(entry) = entries.iter().find(|h| h.datetime > latest) {
+                errors.push(IntegrityError::FutureHistory {
+                    name: name(),
+                    datetime: entry.datetime,
+                });
+            }
+            errors.extend(
+                record
+                    .unmasked_in_history()
+                    .into_iter()
+                    .map(|attr| IntegrityError::UnmaskedInHistory { name: name(), attr }),
+            );
+        }
+
+        errors
+    }
+
+    /// corrects what `verify` finds that can be corrected without guessing
+    /// and returns what that was
+    pub fn fix(&mut self) -> Vec<IntegrityError> {
+        let fixed = (self.verify().into_iter())
+            .filter(IntegrityError::fixable)
+            .collect::<Vec<_>>();
+
+        let mut ids = HashSet::new();
+        for record in self.records.iter_mut().chain(&mut self.trash) {
+            if !ids.insert(record.id) {
+                record.id = Uuid::new_v4();
+            }
+        }
+
+        for record in &mut self.records {
+            if !record.history.is_readable() {
+                continue;
+            }
+            let entries = record.history.entries();
+            let unordered = entries.windows(2).any(|w| w[0].datetime > w[1].datetime);
+            let attrs = record.unmasked_in_history();
+            // the rest are left as the raw json they were loaded from
+            if !unordered && attrs.is_empty() {
+                continue;
+            }
+            let fields = record.fields.clone();
+            let history = record.history.entries_mut();
+            history.sort_by_key(|h| h.datetime);
+            for field in history.iter_mut().flat_map(|h| &mut h.fields) {
+                if attrs.contains(&field.attr)
+                    && fields
+                        .iter()
+                        .any(|f| f.attr == field.attr && f.value == field.value)
+                {
+                    field.sensitive = true;
+                }
+            }
+        }
+
+        fixed
+    }
+}
+
+/// how far ahead of this machine's clock a history entry may be
+/// (made on one whose clock runs ahead) before `verify` finds it suspicious
+pub const CLOCK_SKEW_MINUTES: i64 = 10;
+
+/// something wrong with a record that `verify` found
+#[derive(Debug, PartialEq)]
+pub enum IntegrityError {
+    /// another record, maybe one in the trash, has the same id
+    DuplicateId { name: String, id: Uuid },
+    /// the history isn't valid, so it looks empty
+    UnreadableHistory { name: String },
+    /// the entries aren't oldest first
+    UnorderedHistory { name: String },
+    /// an entry is from later than now (allowing for `CLOCK_SKEW_MINUTES`)
+    FutureHistory {
+        name: String,
+        datetime: DateTime<Local>,
+    },
+    /// a sensitive field whose current value isn't sensitive in an older entry,
+    /// so `history` shows it
+    UnmaskedInHistory { name: String, attr: String },
+}
+
+impl IntegrityError {
+    /// whether `fix` corrects it
+    pub fn fixable(&self) -> bool {
+        match self {
+            IntegrityError::DuplicateId { .. }
+            | IntegrityError::UnorderedHistory { .. }
+            | IntegrityError::UnmaskedInHistory { .. } => true,
+            IntegrityError::UnreadableHistory { .. } | IntegrityError::FutureHistory { .. } => {
+                false
+            }
         }
-        None
     }
 }
 
+impl std::fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IntegrityError::DuplicateId { name, id } => {
+                write!(f, "'{}': id {} is used by another record too", name, id)
+            }
+            IntegrityError::UnreadableHistory { name } => {
+                write!(f, "'{}': the history can't be read", name)
+            }
+            IntegrityError::UnorderedHistory { name } => {
+                write!(f, "'{}': the history entries are out of order", name)
+            }
+            IntegrityError::FutureHistory { name, datetime } => write!(
+                f,
+                "'{}': a history entry is from the future ({})",
+                name,
+                datetime.format("%Y-%m-%d %H:%M %:z")
+            ),
+            IntegrityError::UnmaskedInHistory { name, attr } => write!(
+                f,
+                "'{}': the current value of sensitive '{}' isn't sensitive in the history",
+                name, attr
+            ),
+        }
+    }
+}
+
+pub struct SizeReport {
+    pub total: usize,
+    pub overhead: usize,
+    /// largest first
+    pub records: Vec<RecordSize>,
+}
+
+/// what `merge` did
+pub struct MergeReport {
+    pub added: usize,
+    /// records whose fields changed. ones that only got more history aren't counted
+    pub updated: usize,
+    /// by author, unknown authors last
+    pub conflicts: Vec<MergeConflict>,
+}
+
+/// a field that both stores have with different values
+pub struct MergeConflict {
+    pub name: String,
+    pub attr: String,
+    /// who set the value that was kept, if the history tells
+    pub author: Option<String>,
+}
+
+/// fields of any records that hold the same value
+pub struct DuplicateGroup {
+    /// `*****` if the value is sensitive
+    pub value: String,
+    pub count: usize,
+    /// the record names and attrs, sorted
+    pub fields: Vec<(String, String)>,
+}
+
+/// a field whose value doesn't look like its kind. the value isn't kept, it may be sensitive
+pub struct KindMismatch {
+    pub name: String,
+    pub attr: String,
+    pub kind: FieldKind,
+}
+
+/// `fields` and `history` are part of `total`. the rest is the id, name and keys
+pub struct RecordSize {
+    pub name: String,
+    pub total: usize,
+    pub fields: usize,
+    pub history: usize,
+    /// saved by pruning the history down to the current state
+    pub prunable: usize,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Record {
     pub id: Uuid,
@@ -116,43 +1254,745 @@ pub struct Record {
     pub fields: Vec<Field>,
 
     #[serde(default)]
-    pub history: Vec<HistoryEntry>,
+    pub history: History,
 }
 
 impl Record {
-    pub fn update_history(&mut self) {
-        self.history.sort_by(|h1, h2| h1.datetime.cmp(&h2.datetime));
-        match self.history.last_mut() {
-            Some(history) => {
-                history.fields.sort_by(|f1, f2| f1.attr.cmp(&f2.attr));
+    /// sensitive fields whose current value is in the history without being sensitive
+    fn unmasked_in_history(&self) -> BTreeSet<String> {
+        let entries = self.history.entries().iter();
+        (entries.flat_map(|h| &h.fields))
+            .filter(|old| !old.sensitive)
+            .filter(|old| {
+                (self.fields.iter())
+                    .any(|f| f.sensitive && f.attr == old.attr && f.value == old.value)
+            })
+            .map(|old| old.attr.clone())
+            .collect()
+    }
+
+    pub fn tags(&self) -> Vec<&str> {
+        match self.fields.iter().find(|f| f.attr == TAGS) {
+            Some(field) => field
+                .value
+                .split(',')
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// the same fields in any order and the same history
+    fn same_as(&self, other: &Record) -> bool {
+        let sorted = |fields: &[Field]| {
+            let mut fields = fields.to_vec();
+            fields.sort_by(|f1, f2| f1.attr.cmp(&f2.attr));
+            fields
+        };
+
+        self.id == other.id
+            && self.name == other.name
+            && sorted(&self.fields) == sorted(&other.fields)
+            && self.history.same_as(&other.history)
+    }
+
+    /// unions the fields and the histories. where both have a field with different values
+    /// the one set most recently is kept, and sensitive stays sensitive. tags are unioned.
+    /// returns the conflicting attrs with who set the kept value
+    fn merge(&mut self, theirs: &Record) -> Vec<(String, Option<String>)> {
+        let mut conflicts = vec![];
+
+        for field in &theirs.fields {
+            let Some(ours) = self.fields.iter_mut().find(|f| f.attr == field.attr) else {
+                self.fields.push(field.clone());
+                continue;
+            };
+            ours.sensitive |= field.sensitive;
+
+            if ours.attr == TAGS {
+                let mut tags = ours.value.split(',').collect::<Vec<_>>();
+                for tag in field.value.split(',') {
+                    if !tags.contains(&tag) {
+                        tags.push(tag);
+                    }
+                }
+                ours.value = tags.join(",");
+            } else if ours.value != field.value {
+                let our_change = self.history.set_at(ours);
+                let their_change = theirs.history.set_at(field);
+                let newer = their_change.map(|h| h.datetime) > our_change.map(|h| h.datetime);
+                let kept = match newer {
+                    true => their_change,
+                    false => our_change,
+                };
+                conflicts.push((field.attr.clone(), kept.and_then(|h| h.author.clone())));
+                if newer {
+                    ours.value = field.value.clone();
+                }
+            }
+        }
+
+        let history = self.history.entries_mut();
+        for entry in theirs.history.entries() {
+            if !history
+                .iter()
+                .any(|h| h.datetime == entry.datetime && h.fields == entry.fields)
+            {
+                history.push(entry.clone());
+            }
+        }
+        history.sort_by_key(|h| h.datetime);
+
+        conflicts
+    }
+
+    /// adds the current fields as a new entry if they changed and
+    /// then keeps only the `limit` newest entries
+    pub fn update_history(
+        &mut self,
+        now: DateTime<Local>,
+        author: Option<&str>,
+        limit: Option<usize>,
+    ) {
+        let history = self.history.entries_mut();
+        history.sort_by_key(|h| h.datetime);
+        match history.last_mut() {
+            Some(last) => {
+                last.fields.sort_by(|f1, f2| f1.attr.cmp(&f2.attr));
                 self.fields.sort_by(|f1, f2| f1.attr.cmp(&f2.attr));
-                if history.fields != self.fields {
-                    self.history.push(HistoryEntry::new(self.fields.clone()))
+                if last.fields != self.fields {
+                    history.push(HistoryEntry::new(now, self.fields.clone(), author))
                 }
             }
-            None => self.history.push(HistoryEntry::new(self.fields.clone())),
+            None => history.push(HistoryEntry::new(now, self.fields.clone(), author)),
+        }
+
+        if let Some(limit) = limit {
+            history.drain(..history.len().saturating_sub(limit));
         }
     }
 }
 
+/// the history of a record. most sessions never look at it, so it is kept as the raw
+/// json from the vault file and only parsed when first needed.
+/// until it is modified it is saved back byte for byte.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    raw: Option<Box<RawValue>>,
+    entries: OnceCell<Vec<HistoryEntry>>,
+}
+
+impl History {
+    /// oldest first. a history that can't be parsed is empty
+    pub fn entries(&self) -> &[HistoryEntry] {
+        self.entries.get_or_init(|| match &self.raw {
+            Some(raw) => serde_json::from_str(raw.get()).unwrap_or_default(),
+            None => vec![],
+        })
+    }
+
+    /// entries are only ever added or trimmed from the front, so comparing the
+    /// newest is enough. neither is parsed while still the raw json of the vault
+    fn same_as(&self, other: &History) -> bool {
+        match (&self.raw, &other.raw) {
+            (Some(raw), Some(other)) => raw.get() == other.get(),
+            _ => {
+                let (entries, other) = (self.entries(), other.entries());
+                entries.len() == other.len()
+                    && entries.last().map(|h| h.datetime) == other.last().map(|h| h.datetime)
+            }
+        }
+    }
+
+    /// the entry that gave `field` its current value, as far back as the history goes.
+    /// `None` if the newest entry doesn't have it
+    fn set_at(&self, field: &Field) -> Option<&HistoryEntry> {
+        self.entries()
+            .iter()
+            .rev()
+            .take_while(|h| {
+                (h.fields.iter()).any(|f| f.attr == field.attr && f.value == field.value)
+            })
+            .last()
+    }
+
+    fn is_readable(&self) -> bool {
+        match &self.raw {
+            Some(raw) => serde_json::from_str::<Vec<HistoryEntry>>(raw.get()).is_ok(),
+            None => true,
+        }
+    }
+
+    fn entries_mut(&mut self) -> &mut Vec<HistoryEntry> {
+        self.entries();
+        self.raw = None;
+        self.entries.get_mut().expect("parsed by entries()")
+    }
+}
+
+impl Serialize for History {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.raw {
+            Some(raw) => raw.serialize(serializer),
+            None => self.entries().serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for History {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self {
+            raw: Some(Box::<RawValue>::deserialize(deserializer)?),
+            entries: OnceCell::new(),
+        })
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Field {
     pub attr: String,
     pub value: String,
     pub sensitive: bool,
+    /// days after which the value should be changed (see `show expired`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u32>,
+    /// given with `type <kind>`. see `kind` for the one that counts
+    #[serde(default, skip_serializing_if = "FieldKind::is_unknown")]
+    pub kind: FieldKind,
+}
+
+impl Field {
+    /// the given kind, or else the one the attr tells
+    pub fn kind(&self) -> FieldKind {
+        match self.kind {
+            FieldKind::Unknown => FieldKind::infer(&self.attr),
+            kind => kind,
+        }
+    }
+}
+
+/// what a value is, which decides what it should look like and what can be done with it
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldKind {
+    #[default]
+    Unknown,
+    /// digits, like a pin or a card number. spaces between them are fine
+    Pin,
+    /// a totp secret, as an otpauth:// uri or base32
+    Otp,
+    Url,
+    Email,
+    Number,
+}
+
+impl FieldKind {
+    pub const ALL: [FieldKind; 5] = [
+        FieldKind::Pin,
+        FieldKind::Otp,
+        FieldKind::Url,
+        FieldKind::Email,
+        FieldKind::Number,
+    ];
+
+    /// the kind of a field that wasn't given one, going by the attr
+    pub fn infer(attr: &str) -> Self {
+        match attr.to_lowercase().as_str() {
+            "totp" | "otp" => FieldKind::Otp,
+            "url" => FieldKind::Url,
+            "email" => FieldKind::Email,
+            "pin" => FieldKind::Pin,
+            _ => FieldKind::Unknown,
+        }
+    }
+
+    pub fn is_unknown(&self) -> bool {
+        *self == FieldKind::Unknown
+    }
+
+    /// whether `value` looks like this kind. anything goes for `Unknown`
+    pub fn accepts(self, value: &str) -> bool {
+        let value = value.trim();
+        match self {
+            FieldKind::Unknown => true,
+            FieldKind::Pin => {
+                let digits = value.replace(' ', "");
+                !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+            }
+            FieldKind::Otp => {
+                // 80 bits is the shortest secret in common use
+                let secret = value.replace(' ', "").to_uppercase();
+                let secret = secret.trim_end_matches('=');
+                value.starts_with("otpauth://")
+                    || (secret.len() >= 16
+                        && (secret.chars())
+                            .all(|c| c.is_ascii_uppercase() || ('2'..='7').contains(&c)))
+            }
+            FieldKind::Url => {
+                let has_scheme = value
+                    .split_once("://")
+                    .is_some_and(|(scheme, rest)| !scheme.is_empty() && !rest.is_empty());
+                !value.contains(char::is_whitespace) && (has_scheme || value.contains('.'))
+            }
+            FieldKind::Email => match value.split_once('@') {
+                Some((local, domain)) => {
+                    !local.is_empty()
+                        && !domain.contains('@')
+                        && !value.contains(char::is_whitespace)
+                        && (domain.split('.').count() > 1)
+                        && domain.split('.').all(|part| !part.is_empty())
+                }
+                None => false,
+            },
+            FieldKind::Number => value.parse::<f64>().is_ok_and(f64::is_finite),
+        }
+    }
+
+    /// what a value of this kind is, for warnings about ones that don't look like it
+    pub fn describe(self) -> &'static str {
+        match self {
+            FieldKind::Unknown => "anything",
+            FieldKind::Pin => "a pin (digits only)",
+            FieldKind::Otp => "an otp secret (an otpauth:// uri or base32)",
+            FieldKind::Url => "a url",
+            FieldKind::Email => "an email address",
+            FieldKind::Number => "a number",
+        }
+    }
 }
 
+impl std::fmt::Display for FieldKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldKind::Unknown => write!(f, "unknown"),
+            FieldKind::Pin => write!(f, "pin"),
+            FieldKind::Otp => write!(f, "otp"),
+            FieldKind::Url => write!(f, "url"),
+            FieldKind::Email => write!(f, "email"),
+            FieldKind::Number => write!(f, "number"),
+        }
+    }
+}
+
+/// values are overwritten before their memory is freed, so that they don't linger in it
+/// (or in swap and core dumps). the attr isn't a secret
+impl Drop for Field {
+    fn drop(&mut self) {
+        self.value.zeroize();
+    }
+}
+
+impl ZeroizeOnDrop for Field {}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryEntry {
     pub datetime: DateTime<Local>,
     pub fields: Vec<Field>,
+
+    /// `None` for entries made without an author set and for ones from older versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
 }
 
 impl HistoryEntry {
-    pub fn new(fields: Vec<Field>) -> Self {
+    pub fn new(datetime: DateTime<Local>, fields: Vec<Field>, author: Option<&str>) -> Self {
         Self {
-            datetime: Local::now(),
+            datetime,
             fields,
+            author: author.map(String::from),
+        }
+    }
+}
+
+/// what an entry of the history changed compared to the one before it
+#[derive(Clone)]
+pub struct HistoryDiff {
+    pub datetime: DateTime<Local>,
+    pub author: Option<String>,
+    /// sorted by attr
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldChange {
+    Added(Field),
+    Removed(Field),
+    /// the value, the sensitivity or both
+    Changed {
+        before: Field,
+        after: Field,
+    },
+}
+
+/// whole days from `now` until `until`, rounded up (0 once passed, `u32::MAX` for `None`)
+fn days_until(now: DateTime<Local>, until: Option<DateTime<Local>>) -> u32 {
+    let Some(until) = until else {
+        return u32::MAX;
+    };
+    let seconds = (until - now).num_seconds().max(0);
+    u32::try_from((seconds + 86399) / 86400).unwrap_or(u32::MAX)
+}
+
+/// the changes from `before` to `after`, sorted by attr
+pub fn diff_fields(before: &[Field], after: &[Field]) -> Vec<FieldChange> {
+    let find = |fields: &[Field], attr: &str| fields.iter().find(|f| f.attr == attr).cloned();
+    let attrs = (before.iter().chain(after))
+        .map(|f| f.attr.as_str())
+        .collect::<BTreeSet<_>>();
+
+    attrs
+        .into_iter()
+        .filter_map(|attr| match (find(before, attr), find(after, attr)) {
+            (None, Some(after)) => Some(FieldChange::Added(after)),
+            (Some(before), None) => Some(FieldChange::Removed(before)),
+            (Some(before), Some(after)) if before != after => {
+                Some(FieldChange::Changed { before, after })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// a time that only moves when told to
+#[cfg(test)]
+#[derive(Debug)]
+pub struct MockClock(std::sync::Mutex<DateTime<Local>>);
+
+#[cfg(test)]
+impl MockClock {
+    /// any date `date::parse_date` takes, like `2024-01-01` or `2024-01-01T09:00:00+00:00`
+    pub fn at(date: &str) -> Arc<Self> {
+        let now = date::parse_date(date, &SystemClock).unwrap();
+        Arc::new(Self(std::sync::Mutex::new(now)))
+    }
+
+    pub fn advance(&self, by: chrono::Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Local> {
+        *self.0.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const VAULT: &str = r#"{"records":[{"id":"6c3a4a8e-4d2b-4b7e-9d55-1f6f0d7c2a11","name":"gmail","fields":[{"attr":"user","value":"zahash","sensitive":false}],"history":[ {"datetime":"2023-11-04T10:00:00+05:30","fields":[{"attr":"user","value":"zahash","sensitive":false}],"unknown":1} ]}],"version":"0.5.0","min_reader_version":"0.5.0","history_limit":50}"#;
+
+    #[test]
+    fn test_lazy_history() {
+        let store = serde_json::from_str::<Store>(VAULT).unwrap();
+        assert!(store.records[0].history.entries.get().is_none());
+
+        // saved verbatim, unknown keys and whitespace included
+        assert_eq!(serde_json::to_string(&store).unwrap(), VAULT);
+        assert!(store.records[0].history.entries.get().is_none());
+        // `show --json` goes through serde_json::Value
+        let json = serde_json::to_value(&store).unwrap();
+        assert_eq!(json["records"][0]["history"][0]["unknown"], 1);
+
+        let history = store.history("gmail");
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].fields, store.records[0].fields);
+        // reading doesn't change what is saved
+        assert_eq!(serde_json::to_string(&store).unwrap(), VAULT);
+    }
+
+    #[test]
+    fn test_field_zeroize() {
+        fn assert_zeroize_on_drop<T: ZeroizeOnDrop>() {}
+        assert_zeroize_on_drop::<Field>();
+
+        // saved and loaded the same as before it was zeroized on drop
+        let field = Field {
+            attr: "pass".into(),
+            value: "gpass".into(),
+            sensitive: true,
+            expires: None,
+            kind: FieldKind::Unknown,
+        };
+        let json = serde_json::to_string(&field).unwrap();
+        assert_eq!(json, r#"{"attr":"pass","value":"gpass","sensitive":true}"#);
+        assert_eq!(serde_json::from_str::<Field>(&json).unwrap(), field);
+
+        let mut store = serde_json::from_str::<Store>(VAULT).unwrap();
+        store.records[0].fields =
+            serde_json::from_str(r#"[{"attr":"user","value":"zahash","sensitive":false}]"#)
+                .unwrap();
+        assert_eq!(serde_json::to_string(&store).unwrap(), VAULT);
+    }
+
+    #[test]
+    fn test_lazy_history_update() {
+        let mut store = serde_json::from_str::<Store>(VAULT).unwrap();
+        store.set(
+            "gmail",
+            None,
+            vec![Assign {
+                attr: "pass",
+                value: "gpass",
+                sensitive: true,
+                expires: None,
+                kind: None,
+            }],
+        );
+
+        let saved = serde_json::to_string(&store).unwrap();
+        assert!(!saved.contains("unknown"));
+
+        let loaded = serde_json::from_str::<Store>(&saved).unwrap();
+        let history = loaded.history("gmail");
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].fields, store.records[0].fields);
+
+        // records from before history existed
+        let store = serde_json::from_str::<Store>(
+            r#"{"records":[{"id":"6c3a4a8e-4d2b-4b7e-9d55-1f6f0d7c2a11","name":"gmail","fields":[]}],"version":"0.1.0"}"#,
+        )
+        .unwrap();
+        assert!(store.history("gmail").is_empty());
+        assert_eq!(store.history_limit(), Some(DEFAULT_HISTORY_LIMIT));
+        assert!(serde_json::to_string(&store)
+            .unwrap()
+            .contains(r#""history":[]"#));
+    }
+
+    #[test]
+    fn test_clock() {
+        let assign = |value| Assign {
+            attr: "pass",
+            value,
+            sensitive: true,
+            expires: None,
+            kind: None,
+        };
+
+        let clock = MockClock::at("2024-01-01T09:00:00+00:00");
+        let mut store = Store::new();
+        store.set_clock(clock.clone());
+
+        store.set("gmail", None, vec![assign("first")]);
+        clock.advance(chrono::Duration::days(3));
+        store.set("gmail", None, vec![assign("second")]);
+
+        let history = store.history("gmail");
+        assert_eq!(history.len(), 2);
+        assert_eq!(
+            history[0].datetime,
+            DateTime::parse_from_rfc3339("2024-01-01T09:00:00+00:00").unwrap()
+        );
+        assert_eq!(
+            history[1].datetime,
+            DateTime::parse_from_rfc3339("2024-01-04T09:00:00+00:00").unwrap()
+        );
+
+        // the history is checked against the same clock, not the machine's
+        assert_eq!(store.verify(), vec![]);
+    }
+
+    #[test]
+    fn test_legacy_tags() {
+        // records from before tags existed have no `_tags` field
+        let mut store = serde_json::from_str::<Store>(VAULT).unwrap();
+        assert!(store.records[0].tags().is_empty());
+
+        let record = store.tag("gmail", &["work", "email"]).unwrap();
+        assert_eq!(record.tags(), vec!["work", "email"]);
+        let record = store.untag("gmail", &["work"]).unwrap();
+        assert_eq!(record.tags(), vec!["email"]);
+
+        let loaded =
+            serde_json::from_str::<Store>(&serde_json::to_string(&store).unwrap()).unwrap();
+        assert_eq!(loaded.records[0].tags(), vec!["email"]);
+    }
+
+    #[test]
+    fn test_verify() {
+        let store = serde_json::from_str::<Store>(VAULT).unwrap();
+        assert_eq!(store.verify(), vec![]);
+
+        let vault = r#"{"records":[
+            {"id":"6c3a4a8e-4d2b-4b7e-9d55-1f6f0d7c2a11","name":"gmail","fields":[{"attr":"pass","value":"gpass","sensitive":true}],"history":[
+                {"datetime":"2023-11-05T10:00:00+05:30","fields":[{"attr":"pass","value":"gpass","sensitive":false}]},
+                {"datetime":"2023-11-04T10:00:00+05:30","fields":[{"attr":"pass","value":"old","sensitive":false}]}]},
+            {"id":"6c3a4a8e-4d2b-4b7e-9d55-1f6f0d7c2a11","name":"twitch","fields":[],"history":[
+                {"datetime":"2023-11-06T10:05:00+05:30","fields":[]}]},
+            {"id":"0b1e6c1e-3f43-4f3c-8a5a-2d9e6b1c7f22","name":"discord","fields":[],"history":{"oops":1}}
+        ],"version":"0.4.9","history_limit":50}"#;
+        let mut store = serde_json::from_str::<Store>(vault).unwrap();
+        let now = DateTime::parse_from_rfc3339("2023-11-06T10:00:00+05:30").unwrap();
+        let errors = store.verify_at(now.with_timezone(&Local));
+        assert_eq!(errors.len(), 4);
+        assert_eq!(
+            errors[0].to_string(),
+            "'twitch': id 6c3a4a8e-4d2b-4b7e-9d55-1f6f0d7c2a11 is used by another record too"
+        );
+        assert_eq!(
+            errors[1],
+            IntegrityError::UnorderedHistory {
+                name: "gmail".into()
+            }
+        );
+        assert_eq!(
+            errors[2],
+            IntegrityError::UnmaskedInHistory {
+                name: "gmail".into(),
+                attr: "pass".into()
+            }
+        );
+        assert_eq!(
+            errors[3],
+            IntegrityError::UnreadableHistory {
+                name: "discord".into()
+            }
+        );
+        // within the clock skew tolerance
+        let errors = store.verify_at(now.with_timezone(&Local) - chrono::Duration::minutes(30));
+        assert!(matches!(
+            &errors[3], IntegrityError::FutureHistory { name, .. } if name == "twitch"
+        ));
+
+        assert_eq!(store.fix().len(), 3);
+        assert_eq!(
+            store.verify_at(now.with_timezone(&Local)),
+            vec![IntegrityError::UnreadableHistory {
+                name: "discord".into()
+            }]
+        );
+        assert_ne!(store.records[0].id, store.records[1].id);
+        let history = store.history("gmail");
+        assert_eq!(history[0].fields[0].value, "old");
+        assert!(!history[0].fields[0].sensitive);
+        assert!(history[1].fields[0].sensitive);
+    }
+
+    #[test]
+    fn test_min_reader_version() {
+        let vault = |min_reader_version: &str| {
+            let vault = VAULT.replace(r#""min_reader_version":"0.5.0","#, min_reader_version);
+            serde_json::from_str::<Store>(&vault).unwrap()
+        };
+
+        // saved by versions from before it, or by ones that need no newer version than this
+        assert_eq!(vault("").newer_reader_needed(), None);
+        for older in ["0.1.0", "0.4.9", env!("CARGO_PKG_VERSION")] {
+            let store = vault(&format!(r#""min_reader_version":"{}","#, older));
+            assert_eq!(store.newer_reader_needed(), None);
+        }
+        // newer ones, and what this version can't tell
+        for newer in ["0.6.0", "1.0.0-beta.1", "99.0.0", "soon"] {
+            let store = vault(&format!(r#""min_reader_version":"{}","#, newer));
+            assert_eq!(store.newer_reader_needed(), Some(newer));
         }
+
+        // always saved as what this version needs
+        let saved = serde_json::to_value(vault("")).unwrap();
+        assert_eq!(saved["min_reader_version"], MIN_READER_VERSION);
+        assert_eq!(
+            serde_json::to_value(Store::new()).unwrap()["min_reader_version"],
+            MIN_READER_VERSION
+        );
+    }
+
+    #[test]
+    fn test_field_kind_infer() {
+        assert_eq!(FieldKind::infer("totp"), FieldKind::Otp);
+        assert_eq!(FieldKind::infer("OTP"), FieldKind::Otp);
+        assert_eq!(FieldKind::infer("url"), FieldKind::Url);
+        assert_eq!(FieldKind::infer("email"), FieldKind::Email);
+        assert_eq!(FieldKind::infer("pin"), FieldKind::Pin);
+        assert_eq!(FieldKind::infer("pass"), FieldKind::Unknown);
+        assert_eq!(FieldKind::infer("urls"), FieldKind::Unknown);
+
+        let field = |attr: &str, kind| Field {
+            attr: attr.into(),
+            value: "x".into(),
+            sensitive: false,
+            expires: None,
+            kind,
+        };
+        assert_eq!(field("url", FieldKind::Unknown).kind(), FieldKind::Url);
+        assert_eq!(field("url", FieldKind::Number).kind(), FieldKind::Number);
+        assert_eq!(field("card", FieldKind::Pin).kind(), FieldKind::Pin);
+
+        // only a given kind is saved
+        let json = serde_json::to_string(&field("url", FieldKind::Unknown)).unwrap();
+        assert_eq!(json, r#"{"attr":"url","value":"x","sensitive":false}"#);
+        let json = serde_json::to_string(&field("card", FieldKind::Pin)).unwrap();
+        assert_eq!(
+            json,
+            r#"{"attr":"card","value":"x","sensitive":false,"kind":"pin"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Field>(&json).unwrap().kind,
+            FieldKind::Pin
+        );
+    }
+
+    #[test]
+    fn test_field_kind_accepts() {
+        let accepts = |kind: FieldKind, values: &[&str]| {
+            (values.iter())
+                .map(|value| kind.accepts(value))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            accepts(FieldKind::Pin, &["1234", "4111 1111 1111 1111", "12a4", ""]),
+            [true, true, false, false]
+        );
+        assert_eq!(
+            accepts(
+                FieldKind::Otp,
+                &[
+                    "otpauth://totp/Google:sussolini?secret=JBSWY3DPEHPK3PXP",
+                    "JBSW Y3DP EHPK 3PXP",
+                    "jbswy3dpehpk3pxp====",
+                    "JBSWY3DP",
+                    "JBSWY3DPEHPK3PX1"
+                ]
+            ),
+            [true, true, true, false, false]
+        );
+        assert_eq!(
+            accepts(
+                FieldKind::Url,
+                &[
+                    "https://github.com",
+                    "mail.google.com",
+                    "localhost",
+                    "a b.com",
+                    "://x"
+                ]
+            ),
+            [true, true, false, false, false]
+        );
+        assert_eq!(
+            accepts(
+                FieldKind::Email,
+                &[
+                    "zahash@gmail.com",
+                    "zahash",
+                    "@gmail.com",
+                    "a@b",
+                    "a@@b.com",
+                    "a b@c.com",
+                    "a@b..com"
+                ]
+            ),
+            [true, false, false, false, false, false, false]
+        );
+        assert_eq!(
+            accepts(FieldKind::Number, &["42", " -1.5 ", "1e3", "5k", "NaN"]),
+            [true, true, true, false, false]
+        );
+        assert_eq!(accepts(FieldKind::Unknown, &["", "anything"]), [true, true]);
     }
 }